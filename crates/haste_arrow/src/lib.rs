@@ -0,0 +1,226 @@
+//! arrow record batches of projected entity fields and game events; can be written as arrow ipc
+//! streams or files (feather v2) and loaded directly with polars / pandas (`read_ipc`).
+
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, BooleanArray, FixedSizeListArray, Float32Array, Int32Array, Int64Array, MapBuilder,
+    NullArray, StringArray, StringBuilder, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Float32Type, Schema};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::{FileWriter, StreamWriter};
+use arrow::record_batch::RecordBatch;
+use haste_core::entities::fkey_from_dotted_path;
+use haste_core::fieldvalue::FieldValue;
+use haste_core::fxhash;
+use haste_core::gameevents::EventValue;
+use haste_core::parser::{Context, Visitor};
+use haste_core::sink::{Event, Sink, TickEvents};
+
+// projection
+// ----
+
+/// collects values of the given fields of all entities of the given class at the end of each
+/// tick.
+pub struct ProjectionRecorder {
+    class_hash: u64,
+    paths: Vec<String>,
+    keys: Vec<u64>,
+    ticks: Vec<i32>,
+    indices: Vec<i32>,
+    columns: Vec<Vec<Option<FieldValue>>>,
+}
+
+impl ProjectionRecorder {
+    /// field paths are dot separated (for example `CBodyComponent.m_cellX`).
+    pub fn new<P: Into<String>>(class_name: &str, paths: impl IntoIterator<Item = P>) -> Self {
+        let paths: Vec<String> = paths.into_iter().map(Into::into).collect();
+        Self {
+            class_hash: fxhash::hash_bytes(class_name.as_bytes()),
+            keys: paths
+                .iter()
+                .map(|path| fkey_from_dotted_path(path))
+                .collect(),
+            columns: vec![Vec::new(); paths.len()],
+            paths,
+            ticks: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// builds record batch with `tick` and `index` (entity index) columns followed by a column
+    /// per field. column types are inferred from the first non-missing value; vectors become
+    /// fixed size lists of floats; values of mismatching types are treated as missing.
+    pub fn finish(self) -> Result<RecordBatch, ArrowError> {
+        let mut fields = vec![
+            Field::new("tick", DataType::Int32, false),
+            Field::new("index", DataType::Int32, false),
+        ];
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(self.ticks)),
+            Arc::new(Int32Array::from(self.indices)),
+        ];
+        for (path, values) in self.paths.into_iter().zip(self.columns.iter()) {
+            let array = build_field_value_array(values);
+            fields.push(Field::new(path, array.data_type().clone(), true));
+            arrays.push(array);
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+    }
+}
+
+impl Visitor for ProjectionRecorder {
+    fn on_tick_end(&mut self, ctx: &Context) -> anyhow::Result<()> {
+        let Some(entities) = ctx.entities() else {
+            return Ok(());
+        };
+        for (_, entity) in entities.iter() {
+            if !entity.serializer_name_heq(self.class_hash) {
+                continue;
+            }
+            self.ticks.push(ctx.tick());
+            self.indices.push(entity.index());
+            for (key, column) in self.keys.iter().zip(self.columns.iter_mut()) {
+                column.push(entity.get(key).cloned());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn vector_values(value: &Option<FieldValue>, size: usize) -> Option<Vec<Option<f32>>> {
+    let values: &[f32] = match value {
+        Some(FieldValue::Vector2(v)) | Some(FieldValue::QAnglePitchYaw(v)) => v,
+        Some(FieldValue::Vector3(v)) | Some(FieldValue::QAngle(v)) => v,
+        Some(FieldValue::Vector4(v)) => v,
+        _ => return None,
+    };
+    (values.len() == size).then(|| values.iter().copied().map(Some).collect())
+}
+
+fn build_field_value_array(values: &[Option<FieldValue>]) -> ArrayRef {
+    let Some(first) = values.iter().flatten().next() else {
+        return Arc::new(NullArray::new(values.len()));
+    };
+
+    macro_rules! primitive {
+        ($array:ty, $variant:ident) => {
+            Arc::new(<$array>::from_iter(values.iter().map(|v| match v {
+                Some(FieldValue::$variant(v)) => Some(*v),
+                _ => None,
+            })))
+        };
+    }
+
+    let vector_size = match first {
+        FieldValue::I64(_) => return primitive!(Int64Array, I64),
+        FieldValue::U64(_) => return primitive!(UInt64Array, U64),
+        FieldValue::F32(_) => return primitive!(Float32Array, F32),
+        FieldValue::Bool(_) => return primitive!(BooleanArray, Bool),
+        FieldValue::String(_) => {
+            return Arc::new(StringArray::from_iter(values.iter().map(|v| match v {
+                Some(FieldValue::String(v)) => Some(v.as_ref()),
+                _ => None,
+            })));
+        }
+        FieldValue::Vector2(_) | FieldValue::QAnglePitchYaw(_) => 2,
+        FieldValue::Vector3(_) | FieldValue::QAngle(_) => 3,
+        FieldValue::Vector4(_) => 4,
+    };
+    Arc::new(
+        FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            values.iter().map(|v| vector_values(v, vector_size)),
+            vector_size as i32,
+        ),
+    )
+}
+
+// game events
+// ----
+
+/// [`Sink`] that collects game events; use with [`haste_core::sink::SinkVisitor`].
+#[derive(Default)]
+pub struct GameEventRecorder {
+    ticks: Vec<i32>,
+    names: Vec<Box<str>>,
+    data: Vec<Vec<(Box<str>, EventValue)>>,
+}
+
+impl GameEventRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// builds record batch with `tick`, `name` and `data` columns. `data` is a map of strings.
+    pub fn finish(self) -> Result<RecordBatch, ArrowError> {
+        let mut data_builder = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+        for data in self.data.iter() {
+            for (key, value) in data.iter() {
+                data_builder.keys().append_value(key);
+                data_builder
+                    .values()
+                    .append_value(event_value_to_string(value));
+            }
+            data_builder.append(true)?;
+        }
+        let data_array = data_builder.finish();
+
+        let schema = Schema::new(vec![
+            Field::new("tick", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("data", data_array.data_type().clone(), false),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(Int32Array::from(self.ticks)),
+                Arc::new(StringArray::from_iter_values(
+                    self.names.iter().map(AsRef::<str>::as_ref),
+                )),
+                Arc::new(data_array),
+            ],
+        )
+    }
+}
+
+fn event_value_to_string(value: &EventValue) -> String {
+    match value {
+        EventValue::String(v) => v.to_string(),
+        EventValue::F32(v) => v.to_string(),
+        EventValue::I32(v) => v.to_string(),
+        EventValue::Bool(v) => v.to_string(),
+        EventValue::U64(v) => v.to_string(),
+    }
+}
+
+impl Sink for GameEventRecorder {
+    fn on_tick_events(&mut self, tick_events: &TickEvents) -> anyhow::Result<()> {
+        for event in tick_events.events.iter() {
+            if let Event::GameEvent { name, data } = event {
+                self.ticks.push(tick_events.tick);
+                self.names.push(name.clone());
+                self.data.push(data.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+// ipc
+// ----
+
+/// writes the batch in arrow ipc streaming format (`.arrows`).
+pub fn write_ipc_stream<W: Write>(wtr: W, batch: &RecordBatch) -> Result<(), ArrowError> {
+    let mut writer = StreamWriter::try_new(wtr, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()
+}
+
+/// writes the batch in arrow ipc file format (`.arrow`; also known as feather v2).
+pub fn write_ipc_file<W: Write>(wtr: W, batch: &RecordBatch) -> Result<(), ArrowError> {
+    let mut writer = FileWriter::try_new(wtr, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()
+}
@@ -4,11 +4,13 @@ use haste_core::demofile::DEMO_RECORD_BUFFER_SIZE;
 use haste_core::demostream::{
     CmdHeader, DecodeCmdError, DemoStream, ReadCmdError, ReadCmdHeaderError,
 };
-use valveprotos::common::{CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables};
+use valveprotos::common::{
+    CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables, CDemoStringTables,
+};
 
 use crate::demostream::{
     decode_cmd_class_info, decode_cmd_full_packet, decode_cmd_packet, decode_cmd_send_tables,
-    read_cmd_header, scan_for_last_tick,
+    decode_cmd_string_tables, packet_data, read_cmd_header, scan_for_last_tick,
 };
 
 /// allows to read recorded broadcasts.
@@ -90,11 +92,21 @@ impl<R: Read + Seek> DemoStream for BroadcastFile<R> {
         decode_cmd_class_info(data)
     }
 
+    #[inline(always)]
+    fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError> {
+        decode_cmd_string_tables(data)
+    }
+
     #[inline(always)]
     fn decode_cmd_packet(data: &[u8]) -> Result<CDemoPacket, DecodeCmdError> {
         decode_cmd_packet(data)
     }
 
+    #[inline(always)]
+    fn packet_data(data: &[u8]) -> Result<&[u8], DecodeCmdError> {
+        packet_data(data)
+    }
+
     #[inline(always)]
     fn decode_cmd_full_packet(data: &[u8]) -> Result<CDemoFullPacket, DecodeCmdError> {
         decode_cmd_full_packet(data)
@@ -114,3 +126,47 @@ impl<R: Read + Seek> DemoStream for BroadcastFile<R> {
         Ok(self.total_ticks.unwrap())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use haste_core::bitwriter::BitWriter;
+    use haste_core::parser::Parser;
+    use haste_core::protobackend::ProtoMessage;
+    use valveprotos::common::{CsvcMsgServerInfo, EDemoCommands, SvcMessages};
+
+    use super::*;
+
+    // NOTE: see read_cmd_header in demostream.rs
+    fn write_cmd(buf: &mut Vec<u8>, cmd: EDemoCommands, tick: u32, body: &[u8]) {
+        buf.push(cmd as u8);
+        buf.extend_from_slice(&tick.to_le_bytes());
+        buf.push(0);
+        buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        buf.extend_from_slice(body);
+    }
+
+    #[test]
+    fn test_parse_packet() -> anyhow::Result<()> {
+        let server_info = CsvcMsgServerInfo {
+            tick_interval: Some(1.0 / 64.0),
+            ..Default::default()
+        }
+        .encode_message_to_vec();
+        let mut bw = BitWriter::new();
+        bw.write_ubitvar(SvcMessages::SvcServerInfo as u32);
+        bw.write_uvarint32(server_info.len() as u32);
+        bw.write_bytes(&server_info);
+
+        // NOTE: body of the packet cmd is packet data as is, it is not wrapped into CDemoPacket.
+        let mut broadcast = Vec::new();
+        write_cmd(&mut broadcast, EDemoCommands::DemPacket, 1, bw.as_bytes());
+
+        let mut parser = Parser::from_stream(BroadcastFile::start_reading(Cursor::new(broadcast)))?;
+        parser.run_to_end()?;
+        assert_eq!(parser.context().tick_interval(), 1.0 / 64.0);
+
+        Ok(())
+    }
+}
@@ -9,11 +9,13 @@ use haste_core::demostream::{
     CmdHeader, DecodeCmdError, DemoStream, ReadCmdError, ReadCmdHeaderError,
 };
 use serde::Deserialize;
-use valveprotos::common::{CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables};
+use valveprotos::common::{
+    CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables, CDemoStringTables,
+};
 
 use crate::demostream::{
     decode_cmd_class_info, decode_cmd_full_packet, decode_cmd_packet, decode_cmd_send_tables,
-    read_cmd_header, scan_for_last_tick,
+    decode_cmd_string_tables, packet_data, read_cmd_header, scan_for_last_tick,
 };
 use crate::httpclient::HttpClient;
 
@@ -46,6 +48,65 @@ use crate::httpclient::HttpClient;
 
 const MAX_DELTAFRAME_RETRIES: u32 = 5;
 
+/// what to do when a delta fragment is missing while the relay already has newer fragments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GapPolicy {
+    /// keep retrying the missing fragment (up to
+    /// [`BroadcastHttpOptions::max_deltaframe_retries`]), then stop.
+    #[default]
+    Wait,
+    /// resume from the latest fragment of the relay (full fragment first). fragments within the
+    /// gap are lost; consumers should expect discontinuity in state (for example entities that
+    /// were created / deleted within the gap).
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct BroadcastHttpOptions {
+    /// how many times a delta fragment that is not available yet (404) is re-requested before
+    /// the stream is considered to be over.
+    pub max_deltaframe_retries: u32,
+    /// how many times a request that failed for other reasons (http client errors, timeouts, 5xx)
+    /// is retried before the error is returned.
+    pub max_request_retries: u32,
+    /// delay before the first retry; none means keyframe interval (that is what the game does).
+    pub retry_delay: Option<Duration>,
+    /// retry delay is multiplied by this after each retry; 1.0 means constant delay.
+    pub retry_backoff: f32,
+    /// upper bound of retry delay when backing off.
+    pub max_retry_delay: Duration,
+    /// timeout of a single fragment request; none means whatever http client does.
+    ///
+    /// NOTE: requires `tokio` feature.
+    pub fragment_timeout: Option<Duration>,
+    /// when set, sync is re-requested at this interval while streaming delta fragments; keeps
+    /// [`BroadcastHttp::sync_response`] (delays, receive age) up to date.
+    pub keepalive_interval: Option<Duration>,
+    pub gap_policy: GapPolicy,
+}
+
+impl Default for BroadcastHttpOptions {
+    fn default() -> Self {
+        Self {
+            max_deltaframe_retries: MAX_DELTAFRAME_RETRIES,
+            max_request_retries: 0,
+            retry_delay: None,
+            retry_backoff: 1.0,
+            max_retry_delay: Duration::from_secs(60),
+            fragment_timeout: None,
+            keepalive_interval: None,
+            gap_policy: GapPolicy::default(),
+        }
+    }
+}
+
+async fn sleep(dur: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(dur).await;
+    #[cfg(not(feature = "tokio"))]
+    compile_error!("AAAAGH! can't sleep");
+}
+
 // from wiresharking deadlock:
 //   GET /tv/18895867/sync HTTP/1.1\r\n
 //   user-agent: Valve/Steam HTTP Client 1.0 (1422450)\r\n
@@ -107,7 +168,7 @@ pub struct SyncResponse {
     pub protocol: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum FragmentType {
     Delta,
     Full,
@@ -126,19 +187,38 @@ pub enum BroadcastHttpClientError<HttpClientError: Error + Send + Sync + 'static
     StatusCode(http::StatusCode),
     #[error("could not deserialize json")]
     JsonError(#[source] serde_json::Error),
+    #[error("request timed out")]
+    Timeout,
+}
+
+impl<HttpClientError: Error + Send + Sync + 'static> BroadcastHttpClientError<HttpClientError> {
+    /// errors that may go away if request is retried.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::HttpClientError(_) | Self::Timeout => true,
+            Self::StatusCode(status_code) => status_code.is_server_error(),
+            Self::BuildRequestError(_) | Self::JsonError(_) => false,
+        }
+    }
 }
 
 struct BroadcasHttpClient<'client, C: HttpClient + 'client> {
     http_client: C,
     base_url: String,
+    fragment_timeout: Option<Duration>,
     _marker: PhantomData<&'client ()>,
 }
 
 impl<'client, C: HttpClient + 'client> BroadcasHttpClient<'client, C> {
-    fn new(http_client: C, base_url: impl Into<String>) -> Self {
+    fn new(
+        http_client: C,
+        base_url: impl Into<String>,
+        fragment_timeout: Option<Duration>,
+    ) -> Self {
         Self {
             http_client,
             base_url: base_url.into(),
+            fragment_timeout,
             _marker: PhantomData,
         }
     }
@@ -192,7 +272,16 @@ impl<'client, C: HttpClient + 'client> BroadcasHttpClient<'client, C> {
             FragmentType::Full => "full",
         };
         let url = format!("{}/{}/{}", self.base_url, fragment, path);
-        Ok(self.get(&url).await?.into_body()?)
+        let Some(fragment_timeout) = self.fragment_timeout else {
+            return Ok(self.get(&url).await?.into_body()?);
+        };
+        #[cfg(feature = "tokio")]
+        return match tokio::time::timeout(fragment_timeout, self.get(&url)).await {
+            Ok(response) => Ok(response?.into_body()?),
+            Err(_elapsed) => Err(BroadcastHttpClientError::Timeout),
+        };
+        #[cfg(not(feature = "tokio"))]
+        compile_error!("AAAAGH! can't time out");
     }
 }
 
@@ -206,6 +295,7 @@ enum StreamState {
     Start,
     Fullframe,
     Deltaframes {
+        // NOTE: counts 404s of the current fragment.
         num_retries: u32,
         fetch_after: Instant,
         catchup: bool,
@@ -226,6 +316,8 @@ pub struct BroadcastHttp<'client, C: HttpClient + 'client> {
     stream_state: StreamState,
     stream_buffer: StreamBuffer,
     total_ticks: Option<i32>,
+    options: BroadcastHttpOptions,
+    last_sync: Instant,
 }
 
 impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
@@ -233,7 +325,16 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
         http_client: C,
         base_url: impl Into<String>,
     ) -> Result<Self, BroadcastHttpClientError<C::Error>> {
-        let client = BroadcasHttpClient::new(http_client, base_url);
+        Self::start_streaming_with_options(http_client, base_url, BroadcastHttpOptions::default())
+            .await
+    }
+
+    pub async fn start_streaming_with_options(
+        http_client: C,
+        base_url: impl Into<String>,
+        options: BroadcastHttpOptions,
+    ) -> Result<Self, BroadcastHttpClientError<C::Error>> {
+        let client = BroadcasHttpClient::new(http_client, base_url, options.fragment_timeout);
 
         let sync_response = client.get_sync().await?;
 
@@ -246,6 +347,8 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
             stream_state: StreamState::Start,
             stream_buffer: StreamBuffer::Last(None),
             total_ticks: None,
+            options,
+            last_sync: Instant::now(),
         })
     }
 
@@ -254,7 +357,21 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
         http_client: C,
         base_url: impl Into<String>,
     ) -> Result<Self, BroadcastHttpClientError<C::Error>> {
-        let mut this = Self::start_streaming(http_client, base_url).await?;
+        Self::start_streaming_and_buffer_with_options(
+            http_client,
+            base_url,
+            BroadcastHttpOptions::default(),
+        )
+        .await
+    }
+
+    /// see [`Self::start_streaming_and_buffer`].
+    pub async fn start_streaming_and_buffer_with_options(
+        http_client: C,
+        base_url: impl Into<String>,
+        options: BroadcastHttpOptions,
+    ) -> Result<Self, BroadcastHttpClientError<C::Error>> {
+        let mut this = Self::start_streaming_with_options(http_client, base_url, options).await?;
         this.stream_buffer = StreamBuffer::Seekable(Cursor::default());
         Ok(this)
     }
@@ -263,6 +380,58 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
         &self.sync_response
     }
 
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let base = self.options.retry_delay.unwrap_or(self.keyframe_interval);
+        let secs = base.as_secs_f32() * self.options.retry_backoff.powi(attempt as i32);
+        Duration::try_from_secs_f32(secs)
+            .unwrap_or(self.options.max_retry_delay)
+            .min(self.options.max_retry_delay)
+    }
+
+    /// retries transient errors, see [`BroadcastHttpOptions::max_request_retries`].
+    async fn get_fragment(
+        &self,
+        typ: FragmentType,
+    ) -> Result<Bytes, BroadcastHttpClientError<C::Error>> {
+        let mut attempt = 0;
+        loop {
+            match self.client.get_fragment(self.stream_fragment, typ).await {
+                Err(err) if err.is_transient() && attempt < self.options.max_request_retries => {
+                    log::debug!(
+                        "could not get fragment {} ({:?}), retrying: {}",
+                        self.stream_fragment,
+                        typ,
+                        err
+                    );
+                    sleep(self.retry_delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn sync(&mut self) -> Result<(), BroadcastHttpClientError<C::Error>> {
+        self.sync_response = self.client.get_sync().await?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    // see BroadcastHttpOptions::keepalive_interval.
+    async fn maybe_keepalive(&mut self) {
+        let Some(keepalive_interval) = self.options.keepalive_interval else {
+            return;
+        };
+        if self.last_sync.elapsed() < keepalive_interval {
+            return;
+        }
+        // NOTE: keepalive is best effort; failing to sync must not end the stream.
+        if let Err(err) = self.sync().await {
+            log::warn!("could not sync: {}", err);
+            self.last_sync = Instant::now();
+        }
+    }
+
     async fn handle_start(&mut self) -> Result<Bytes, BroadcastHttpClientError<C::Error>> {
         // bool CDemoStreamHttp::OnSync( int nResync )
         // DevMsg( "Broadcast: Buffering stream tick %d fragment %d signup fragment %d\n", m_SyncResponse.nStartTick, m_SyncResponse.nSignupFragment, m_SyncResponse.nSignupFragment );
@@ -276,10 +445,7 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
     }
 
     async fn handle_fullframe(&mut self) -> Result<Bytes, BroadcastHttpClientError<C::Error>> {
-        let full = self
-            .client
-            .get_fragment(self.stream_fragment, FragmentType::Full)
-            .await?;
+        let full = self.get_fragment(FragmentType::Full).await?;
 
         self.stream_state = StreamState::Deltaframes {
             num_retries: 0,
@@ -316,19 +482,13 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
                     continue;
                 }
 
-                let sleep_dur = fetch_after.duration_since(Instant::now());
-                #[cfg(feature = "tokio")]
-                tokio::time::sleep(sleep_dur).await;
-                #[cfg(not(feature = "tokio"))]
-                compile_error!("AAAAGH! can't sleep");
+                sleep(fetch_after.duration_since(Instant::now())).await;
             }
 
+            self.maybe_keepalive().await;
+
             let start = Instant::now();
-            match self
-                .client
-                .get_fragment(self.stream_fragment, FragmentType::Delta)
-                .await
-            {
+            match self.get_fragment(FragmentType::Delta).await {
                 Ok(delta) => {
                     // NOTE: when state transitions from StreamState::Fullframe into
                     // StreamState::Deltaframes stream_fragment must not be incremented. both, full
@@ -346,7 +506,24 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
                 }
 
                 Err(BroadcastHttpClientError::StatusCode(http::StatusCode::NOT_FOUND)) => {
-                    if num_retries >= MAX_DELTAFRAME_RETRIES {
+                    // NOTE: if relay is already past the missing fragment it's a gap (the
+                    // fragment will never show up), otherwise the fragment is not produced yet.
+                    if self.options.gap_policy == GapPolicy::Skip {
+                        self.sync().await?;
+                        if self.sync_response.fragment > self.stream_fragment {
+                            log::warn!(
+                                "fragment {} is missing, skipping to {}",
+                                self.stream_fragment,
+                                self.sync_response.fragment
+                            );
+                            self.stream_fragment = self.sync_response.fragment;
+                            self.stream_state = StreamState::Fullframe;
+                            log::debug!("entering state: {:?}", self.stream_state);
+                            return self.handle_fullframe().await;
+                        }
+                    }
+
+                    if num_retries >= self.options.max_deltaframe_retries {
                         return Err(BroadcastHttpClientError::StatusCode(
                             http::StatusCode::NOT_FOUND,
                         ));
@@ -354,7 +531,7 @@ impl<'client, C: HttpClient + 'client> BroadcastHttp<'client, C> {
 
                     self.stream_state = StreamState::Deltaframes {
                         num_retries: num_retries + 1,
-                        fetch_after: start + self.keyframe_interval,
+                        fetch_after: start + self.retry_delay(num_retries),
                         catchup: false,
                     };
                     log::debug!("entering state: {:?}", self.stream_state);
@@ -545,11 +722,21 @@ impl<'client, C: HttpClient + 'client> DemoStream for BroadcastHttp<'client, C>
         decode_cmd_class_info(data)
     }
 
+    #[inline(always)]
+    fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError> {
+        decode_cmd_string_tables(data)
+    }
+
     #[inline(always)]
     fn decode_cmd_packet(data: &[u8]) -> Result<CDemoPacket, DecodeCmdError> {
         decode_cmd_packet(data)
     }
 
+    #[inline(always)]
+    fn packet_data(data: &[u8]) -> Result<&[u8], DecodeCmdError> {
+        packet_data(data)
+    }
+
     #[inline(always)]
     fn decode_cmd_full_packet(data: &[u8]) -> Result<CDemoFullPacket, DecodeCmdError> {
         decode_cmd_full_packet(data)
@@ -1,9 +1,9 @@
 use std::io::{Read, SeekFrom};
 
 use haste_core::demostream::{CmdHeader, DecodeCmdError, DemoStream, ReadCmdHeaderError};
-use prost::Message;
+use haste_core::protobackend::ProtoMessage;
 use valveprotos::common::{
-    CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables, EDemoCommands,
+    CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables, CDemoStringTables, EDemoCommands,
 };
 
 // cmd header
@@ -70,7 +70,12 @@ pub(crate) fn decode_cmd_send_tables(data: &[u8]) -> Result<CDemoSendTables, Dec
 
 #[inline(always)]
 pub(crate) fn decode_cmd_class_info(data: &[u8]) -> Result<CDemoClassInfo, DecodeCmdError> {
-    CDemoClassInfo::decode(data).map_err(DecodeCmdError::DecodeProtobufError)
+    CDemoClassInfo::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+}
+
+#[inline(always)]
+pub(crate) fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError> {
+    CDemoStringTables::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
 }
 
 #[inline(always)]
@@ -82,6 +87,12 @@ pub(crate) fn decode_cmd_packet(data: &[u8]) -> Result<CDemoPacket, DecodeCmdErr
     })
 }
 
+/// NOTE: packet cmds of broadcasts are not wrapped into CDemoPacket, cmd body is packet data.
+#[inline(always)]
+pub(crate) fn packet_data(data: &[u8]) -> Result<&[u8], DecodeCmdError> {
+    Ok(data)
+}
+
 #[inline(always)]
 pub(crate) fn decode_cmd_full_packet(_data: &[u8]) -> Result<CDemoFullPacket, DecodeCmdError> {
     // NOTE: broadcasts don't seem to contain full packets
@@ -4,5 +4,7 @@ pub(crate) mod demostream;
 mod httpclient;
 
 pub use broadcastfile::BroadcastFile;
-pub use broadcasthttp::{default_headers, BroadcastHttp, BroadcastHttpClientError};
+pub use broadcasthttp::{
+    default_headers, BroadcastHttp, BroadcastHttpClientError, BroadcastHttpOptions, GapPolicy,
+};
 pub use httpclient::HttpClient;
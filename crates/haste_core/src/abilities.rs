@@ -0,0 +1,224 @@
+//! dota 2 abilities and items of units. units (heroes, creeps, etc.) don't carry ability and item
+//! state, they hold handles of ability and item entities in `m_hAbilities` and
+//! `m_Inventory.m_hItems` fixed arrays.
+
+use crate::entities::{
+    ehandle_to_index, fkey_from_path, is_ehandle_valid, Entity, EntityContainer,
+};
+use crate::fxhash;
+
+pub const MAX_ABILITIES: usize = 35;
+/// inventory (0..6), backpack (6..9), stash (9..15), teleport scroll (15) and neutral item (16)
+/// slots and a couple of spare ones.
+pub const MAX_ITEMS: usize = 19;
+
+const ABILITIES_KEY: u64 = fkey_from_path(&["m_hAbilities"]);
+const ITEMS_KEY: u64 = fkey_from_path(&["m_Inventory", "m_hItems"]);
+
+const LEVEL_KEY: u64 = fkey_from_path(&["m_iLevel"]);
+const COOLDOWN_KEY: u64 = fkey_from_path(&["m_fCooldown"]);
+const COOLDOWN_LENGTH_KEY: u64 = fkey_from_path(&["m_flCooldownLength"]);
+const MANA_COST_KEY: u64 = fkey_from_path(&["m_iManaCost"]);
+const ABILITY_CHARGES_KEY: u64 = fkey_from_path(&["m_nAbilityCurrentCharges"]);
+const ITEM_CHARGES_KEY: u64 = fkey_from_path(&["m_iCurrentCharges"]);
+
+// NOTE: elements of fixed arrays are keyed the same way as elements of dynamic arrays; see
+// entities::fkey_from_dotted_path.
+#[inline]
+fn element_key(array_key: u64, slot: usize) -> u64 {
+    fxhash::add_u64_to_hash(array_key, fxhash::add_u64_to_hash(0, slot as u64))
+}
+
+/// typed view of an ability or an item entity. fields that the entity does not have are none.
+#[derive(Debug, Clone, Copy)]
+pub struct AbilityView<'a> {
+    pub slot: usize,
+    pub handle: u32,
+    pub entity: &'a Entity,
+}
+
+impl AbilityView<'_> {
+    #[inline]
+    pub fn index(&self) -> i32 {
+        self.entity.index()
+    }
+
+    #[inline]
+    pub fn level(&self) -> Option<i32> {
+        self.entity.get_value(&LEVEL_KEY)
+    }
+
+    /// game time at which the cooldown ends; see also [`Self::cooldown_length`].
+    #[inline]
+    pub fn cooldown(&self) -> Option<f32> {
+        self.entity.get_value(&COOLDOWN_KEY)
+    }
+
+    #[inline]
+    pub fn cooldown_length(&self) -> Option<f32> {
+        self.entity.get_value(&COOLDOWN_LENGTH_KEY)
+    }
+
+    #[inline]
+    pub fn mana_cost(&self) -> Option<i32> {
+        self.entity.get_value(&MANA_COST_KEY)
+    }
+
+    /// current charges of items (for example wards, dust) and of abilities with charges.
+    pub fn charges(&self) -> Option<i32> {
+        self.entity
+            .get_value(&ITEM_CHARGES_KEY)
+            .or_else(|| self.entity.get_value(&ABILITY_CHARGES_KEY))
+    }
+}
+
+fn view<'a>(
+    unit: &'a Entity,
+    entities: &'a EntityContainer,
+    array_key: u64,
+    slot: usize,
+) -> Option<AbilityView<'a>> {
+    let handle: u32 = unit.get_value(&element_key(array_key, slot))?;
+    if !is_ehandle_valid(handle) {
+        return None;
+    }
+    let entity = entities.get(&ehandle_to_index(handle))?;
+    Some(AbilityView {
+        slot,
+        handle,
+        entity,
+    })
+}
+
+fn slots<'a>(
+    unit: &'a Entity,
+    entities: &'a EntityContainer,
+    array_key: u64,
+    len: usize,
+) -> impl Iterator<Item = AbilityView<'a>> + 'a {
+    (0..len).filter_map(move |slot| view(unit, entities, array_key, slot))
+}
+
+/// abilities of the unit, skipping empty slots (and slots whose entities don't exist).
+pub fn abilities<'a>(
+    unit: &'a Entity,
+    entities: &'a EntityContainer,
+) -> impl Iterator<Item = AbilityView<'a>> + 'a {
+    slots(unit, entities, ABILITIES_KEY, MAX_ABILITIES)
+}
+
+/// items of the unit, skipping empty slots (and slots whose entities don't exist).
+pub fn items<'a>(
+    unit: &'a Entity,
+    entities: &'a EntityContainer,
+) -> impl Iterator<Item = AbilityView<'a>> + 'a {
+    slots(unit, entities, ITEMS_KEY, MAX_ITEMS)
+}
+
+pub fn ability_in_slot<'a>(
+    unit: &'a Entity,
+    entities: &'a EntityContainer,
+    slot: usize,
+) -> Option<AbilityView<'a>> {
+    view(unit, entities, ABILITIES_KEY, slot)
+}
+
+pub fn item_in_slot<'a>(
+    unit: &'a Entity,
+    entities: &'a EntityContainer,
+    slot: usize,
+) -> Option<AbilityView<'a>> {
+    view(unit, entities, ITEMS_KEY, slot)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fieldvalue::FieldValue;
+    use crate::game::EngineConstants;
+    use crate::parser::Parser;
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    #[test]
+    fn test_abilities_and_items() -> anyhow::Result<()> {
+        let inventory = SyntheticClass::new("CDOTA_UnitInventory")
+            .field("m_hItems", SyntheticFieldType::UInt64Array(3));
+        let classes = vec![
+            SyntheticClass::new("CDOTA_Unit_Hero_Toy")
+                .field("m_hAbilities", SyntheticFieldType::UInt64Array(3))
+                .field("m_Inventory", SyntheticFieldType::Pointer(inventory)),
+            SyntheticClass::new("CDOTA_Ability_Toy")
+                .field("m_iLevel", SyntheticFieldType::Int32)
+                .field("m_fCooldown", SyntheticFieldType::Float32)
+                .field("m_iManaCost", SyntheticFieldType::Int32),
+            SyntheticClass::new("CDOTA_Item_Toy")
+                .field("m_iCurrentCharges", SyntheticFieldType::Int32),
+        ];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+
+        let invalid_handle = EngineConstants::DOTA2.invalid_ehandle() as u64;
+        // NOTE: handles of entities #2 and #3 (serial numbers are 0). slot 2 of abilities points
+        // to entity that does not exist.
+        wtr.create(
+            1,
+            "CDOTA_Unit_Hero_Toy",
+            &[
+                ("m_hAbilities.0", FieldValue::U64(2)),
+                ("m_hAbilities.1", FieldValue::U64(invalid_handle)),
+                ("m_hAbilities.2", FieldValue::U64(9)),
+                ("m_Inventory.m_hItems.0", FieldValue::U64(invalid_handle)),
+                ("m_Inventory.m_hItems.1", FieldValue::U64(3)),
+                ("m_Inventory.m_hItems.2", FieldValue::U64(invalid_handle)),
+            ],
+        )?;
+        wtr.create(
+            2,
+            "CDOTA_Ability_Toy",
+            &[
+                ("m_iLevel", FieldValue::I64(2)),
+                ("m_fCooldown", FieldValue::F32(12.5)),
+                ("m_iManaCost", FieldValue::I64(90)),
+            ],
+        )?;
+        wtr.create(
+            3,
+            "CDOTA_Item_Toy",
+            &[("m_iCurrentCharges", FieldValue::I64(3))],
+        )?;
+        wtr.write_tick(1)?;
+
+        let mut parser = Parser::from_stream(wtr.finish_into_demo_file()?)?;
+        parser.run_to_end()?;
+
+        let entities = parser
+            .context()
+            .entities()
+            .ok_or_else(|| anyhow::anyhow!("no entities"))?;
+        let hero = entities
+            .get(&1)
+            .ok_or_else(|| anyhow::anyhow!("no entity #1"))?;
+
+        let abilities: Vec<_> = abilities(hero, entities).collect();
+        assert_eq!(abilities.len(), 1);
+        let ability = &abilities[0];
+        assert_eq!((ability.slot, ability.index()), (0, 2));
+        assert_eq!(ability.level(), Some(2));
+        assert_eq!(ability.cooldown(), Some(12.5));
+        assert_eq!(ability.mana_cost(), Some(90));
+        // NOTE: fields that the entity does not have.
+        assert_eq!(ability.cooldown_length(), None);
+        assert_eq!(ability.charges(), None);
+        assert!(ability_in_slot(hero, entities, 1).is_none());
+        assert!(ability_in_slot(hero, entities, 2).is_none());
+
+        let items: Vec<_> = items(hero, entities).collect();
+        assert_eq!(items.len(), 1);
+        let item = &items[0];
+        assert_eq!((item.slot, item.index()), (1, 3));
+        assert_eq!(item.charges(), Some(3));
+        assert_eq!(item.level(), None);
+        assert!(item_in_slot(hero, entities, 0).is_none());
+
+        Ok(())
+    }
+}
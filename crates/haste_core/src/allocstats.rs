@@ -0,0 +1,264 @@
+//! allocation accounting; counts allocations (and bytes) per subsystem of the parser. gives hard
+//! data when chasing questions like "why does this demo take 3gb".
+//!
+//! a library can't install a global allocator, that is up to the binary:
+//!
+//! ```ignore
+//! use haste::allocstats::{self, CountingAllocator};
+//!
+//! #[global_allocator]
+//! static ALLOC: CountingAllocator = CountingAllocator::new(std::alloc::System);
+//!
+//! // parse...
+//! for (subsystem, stats) in allocstats::snapshot().iter() {
+//!     println!("{}: {} allocations, {} bytes", subsystem.name(), stats.allocations, stats.allocated_bytes);
+//! }
+//! ```
+//!
+//! NOTE: allocations are attributed to the innermost subsystem that is active on the current
+//! thread at the moment of allocation. visitor callbacks that are invoked while a subsystem is
+//! active (for example [`crate::parser::Visitor::on_entity`] is called during entity handling)
+//! count towards that subsystem. deallocations are attributed the same way, thus they are not
+//! necessarily counted against the subsystem that allocated the memory.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Subsystem {
+    /// anything that is not covered by other subsystems (including code outside of haste).
+    Other = 0,
+    /// entity creation, updates and deletion (excluding decoding of field values).
+    Entities,
+    /// string table creation and updates.
+    StringTables,
+    /// decoding of entity field values.
+    FieldValues,
+    /// decoding of protobuf messages.
+    Protobuf,
+}
+
+impl Subsystem {
+    pub const ALL: [Self; 5] = [
+        Self::Other,
+        Self::Entities,
+        Self::StringTables,
+        Self::FieldValues,
+        Self::Protobuf,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Other => "other",
+            Self::Entities => "entities",
+            Self::StringTables => "stringtables",
+            Self::FieldValues => "fieldvalues",
+            Self::Protobuf => "protobuf",
+        }
+    }
+}
+
+// counters
+// ----
+
+struct Counters {
+    allocations: AtomicU64,
+    allocated_bytes: AtomicU64,
+    deallocations: AtomicU64,
+    deallocated_bytes: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_COUNTERS: Counters = Counters {
+    allocations: AtomicU64::new(0),
+    allocated_bytes: AtomicU64::new(0),
+    deallocations: AtomicU64::new(0),
+    deallocated_bytes: AtomicU64::new(0),
+};
+
+static COUNTERS: [Counters; Subsystem::ALL.len()] = [ZERO_COUNTERS; Subsystem::ALL.len()];
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // NOTE: const initialized, has no destructor; accessing it from within the allocator does not
+    // allocate.
+    static CURRENT: Cell<Subsystem> = const { Cell::new(Subsystem::Other) };
+}
+
+#[inline]
+fn current() -> Subsystem {
+    // NOTE: thread local may already be gone during thread teardown.
+    CURRENT.try_with(Cell::get).unwrap_or(Subsystem::Other)
+}
+
+#[inline]
+fn record_alloc(size: usize) {
+    let counters = &COUNTERS[current() as usize];
+    counters.allocations.fetch_add(1, Ordering::Relaxed);
+    counters
+        .allocated_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
+    let live = LIVE_BYTES.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+    PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+}
+
+#[inline]
+fn record_dealloc(size: usize) {
+    let counters = &COUNTERS[current() as usize];
+    counters.deallocations.fetch_add(1, Ordering::Relaxed);
+    counters
+        .deallocated_bytes
+        .fetch_add(size as u64, Ordering::Relaxed);
+    LIVE_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+}
+
+// scopes
+// ----
+
+/// restores previous subsystem when dropped.
+pub struct SubsystemScope {
+    prev: Subsystem,
+}
+
+impl Drop for SubsystemScope {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.prev));
+    }
+}
+
+/// attributes allocations that happen on the current thread to `subsystem` until the returned
+/// scope is dropped; scopes nest.
+#[inline]
+pub fn scope(subsystem: Subsystem) -> SubsystemScope {
+    let prev = CURRENT
+        .try_with(|current| current.replace(subsystem))
+        .unwrap_or(Subsystem::Other);
+    SubsystemScope { prev }
+}
+
+// allocator
+// ----
+
+/// wraps an allocator and counts what goes through it; see module docs.
+pub struct CountingAllocator<A> {
+    inner: A,
+}
+
+impl<A> CountingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    // NOTE: counted as deallocation of the old block and allocation of the new one.
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record_dealloc(layout.size());
+            record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+// tallies
+// ----
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubsystemStats {
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    pub deallocations: u64,
+    pub deallocated_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// indexed by `Subsystem as usize`; see [`Self::get`] and [`Self::iter`].
+    pub subsystems: [SubsystemStats; Subsystem::ALL.len()],
+    /// bytes that are allocated at the moment of the snapshot.
+    pub live_bytes: u64,
+    /// max of live bytes since start (or the last [`reset`]).
+    pub peak_live_bytes: u64,
+}
+
+impl AllocStats {
+    pub fn get(&self, subsystem: Subsystem) -> &SubsystemStats {
+        &self.subsystems[subsystem as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Subsystem, &SubsystemStats)> {
+        Subsystem::ALL.into_iter().zip(self.subsystems.iter())
+    }
+
+    pub fn total(&self) -> SubsystemStats {
+        self.subsystems
+            .iter()
+            .fold(SubsystemStats::default(), |acc, stats| SubsystemStats {
+                allocations: acc.allocations + stats.allocations,
+                allocated_bytes: acc.allocated_bytes + stats.allocated_bytes,
+                deallocations: acc.deallocations + stats.deallocations,
+                deallocated_bytes: acc.deallocated_bytes + stats.deallocated_bytes,
+            })
+    }
+}
+
+/// all zeros if [`CountingAllocator`] is not installed as the global allocator.
+pub fn snapshot() -> AllocStats {
+    let mut stats = AllocStats {
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+        peak_live_bytes: PEAK_LIVE_BYTES.load(Ordering::Relaxed),
+        ..Default::default()
+    };
+    for (dst, src) in stats.subsystems.iter_mut().zip(COUNTERS.iter()) {
+        *dst = SubsystemStats {
+            allocations: src.allocations.load(Ordering::Relaxed),
+            allocated_bytes: src.allocated_bytes.load(Ordering::Relaxed),
+            deallocations: src.deallocations.load(Ordering::Relaxed),
+            deallocated_bytes: src.deallocated_bytes.load(Ordering::Relaxed),
+        };
+    }
+    stats
+}
+
+/// zeroes counters; peak is reset to the current number of live bytes (live bytes themselves are
+/// not reset, memory that is allocated stays allocated).
+pub fn reset() {
+    for counters in COUNTERS.iter() {
+        counters.allocations.store(0, Ordering::Relaxed);
+        counters.allocated_bytes.store(0, Ordering::Relaxed);
+        counters.deallocations.store(0, Ordering::Relaxed);
+        counters.deallocated_bytes.store(0, Ordering::Relaxed);
+    }
+    PEAK_LIVE_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
@@ -2,19 +2,73 @@ pub use bitbuf::OverflowError as BitReaderOverflowError;
 use dungers::bitbuf;
 
 // public/coordsize.h
-const COORD_INTEGER_BITS: usize = 14;
-const COORD_FRACTIONAL_BITS: usize = 5;
-const COORD_DENOMINATOR: f32 = (1 << COORD_FRACTIONAL_BITS) as f32;
-const COORD_RESOLUTION: f32 = 1.0 / COORD_DENOMINATOR;
+pub(crate) const COORD_INTEGER_BITS: usize = 14;
+pub(crate) const COORD_FRACTIONAL_BITS: usize = 5;
+pub(crate) const COORD_DENOMINATOR: f32 = (1 << COORD_FRACTIONAL_BITS) as f32;
+pub(crate) const COORD_RESOLUTION: f32 = 1.0 / COORD_DENOMINATOR;
+pub(crate) const COORD_FRACTIONAL_BITS_MP_LOWPRECISION: usize = 3;
+pub(crate) const COORD_DENOMINATOR_LOWPRECISION: f32 =
+    (1 << COORD_FRACTIONAL_BITS_MP_LOWPRECISION) as f32;
+pub(crate) const COORD_RESOLUTION_LOWPRECISION: f32 = 1.0 / COORD_DENOMINATOR_LOWPRECISION;
 
 // public/coordsize.h
-const NORMAL_FRACTIONAL_BITS: usize = 11;
-const NORMAL_DENOMINATOR: f32 = ((1 << (NORMAL_FRACTIONAL_BITS)) - 1) as f32;
-const NORMAL_RESOLUTION: f32 = 1.0 / (NORMAL_DENOMINATOR);
+pub(crate) const NORMAL_FRACTIONAL_BITS: usize = 11;
+pub(crate) const NORMAL_DENOMINATOR: f32 = ((1 << (NORMAL_FRACTIONAL_BITS)) - 1) as f32;
+pub(crate) const NORMAL_RESOLUTION: f32 = 1.0 / (NORMAL_DENOMINATOR);
+
+/// integer types that can be read with [`BitReader::read_int`].
+pub trait BitInt: Copy {
+    const BITS: usize;
+    const SIGNED: bool;
+
+    /// truncating conversion (same as `as`).
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_bit_int {
+    ($($ty:ty => $signed:expr),+) => {
+        $(
+            impl BitInt for $ty {
+                const BITS: usize = <$ty>::BITS as usize;
+                const SIGNED: bool = $signed;
+
+                #[inline(always)]
+                fn from_u64(value: u64) -> Self {
+                    value as $ty
+                }
+            }
+        )+
+    }
+}
+
+impl_bit_int! {
+    u8 => false,
+    u16 => false,
+    u32 => false,
+    u64 => false,
+    i8 => true,
+    i16 => true,
+    i32 => true,
+    i64 => true
+}
+
+/// interprets lowest `num_bits` of `value` as a two's complement signed integer.
+#[inline(always)]
+pub fn sign_extend(value: u64, num_bits: usize) -> i64 {
+    match num_bits {
+        0 => 0,
+        64.. => value as i64,
+        _ => {
+            let shift = 64 - num_bits;
+            ((value << shift) as i64) >> shift
+        }
+    }
+}
 
 // BitRead is a port of valve's CBitRead(or/and old_bf_read) from valve's tier1 lib.
 pub struct BitReader<'a> {
     inner: bitbuf::BitReader<'a>,
+    num_bits: usize,
     did_check_overflow: bool,
 }
 
@@ -47,6 +101,7 @@ impl<'a> BitReader<'a> {
     pub fn new(data: &'a [u8]) -> Self {
         Self {
             inner: bitbuf::BitReader::new(data),
+            num_bits: data.len() * 8,
             did_check_overflow: false,
         }
     }
@@ -57,12 +112,49 @@ impl<'a> BitReader<'a> {
         self.inner.num_bits_left()
     }
 
+    /// same as [`Self::num_bits_left`].
+    #[inline(always)]
+    pub fn bits_remaining(&self) -> usize {
+        self.num_bits_left()
+    }
+
+    /// number of bits that were read so far. can be used to measure how many bits something
+    /// took on the wire.
+    #[inline(always)]
+    pub fn bits_consumed(&self) -> usize {
+        self.num_bits.saturating_sub(self.num_bits_left())
+    }
+
     /// delegated from [dungers::bitbuf::BitReader].
     #[inline(always)]
     pub fn read_ubit64(&mut self, num_bits: usize) -> u64 {
         unsafe { self.inner.read_ubit64_unchecked(num_bits) }
     }
 
+    /// reads `num_bits` into an integer of the given type; values of signed types are sign
+    /// extended (`num_bits` is the width of the value on the wire, not of `T`).
+    ///
+    /// ```ignore
+    /// let a: u16 = br.read_int(11);
+    /// let b: i8 = br.read_int(5);
+    /// ```
+    #[inline(always)]
+    pub fn read_int<T: BitInt>(&mut self, num_bits: usize) -> T {
+        debug_assert!(num_bits <= T::BITS, "{num_bits} bits don't fit");
+        let value = self.read_ubit64(num_bits);
+        if T::SIGNED {
+            T::from_u64(sign_extend(value, num_bits) as u64)
+        } else {
+            T::from_u64(value)
+        }
+    }
+
+    /// reads `num_bits` as a two's complement signed integer.
+    #[inline(always)]
+    pub fn read_sbit64(&mut self, num_bits: usize) -> i64 {
+        sign_extend(self.read_ubit64(num_bits), num_bits)
+    }
+
     /// delegated from [dungers::bitbuf::BitReader].
     #[inline(always)]
     pub fn read_bool(&mut self) -> bool {
@@ -85,6 +177,17 @@ impl<'a> BitReader<'a> {
         unsafe { self.inner.read_bytes_unchecked(buf) }
     }
 
+    /// advances past `num_bits` without copying them anywhere.
+    pub fn skip_bits(&mut self, mut num_bits: usize) {
+        while num_bits >= 64 {
+            self.read_ubit64(64);
+            num_bits -= 64;
+        }
+        if num_bits > 0 {
+            self.read_ubit64(num_bits);
+        }
+    }
+
     #[inline]
     pub fn is_overflowed(&mut self) -> Result<(), BitReaderOverflowError> {
         self.did_check_overflow = true;
@@ -126,6 +229,7 @@ impl<'a> BitReader<'a> {
     // Y set -> read 4
     // X set -> read 8
     // X + Y set -> read 28
+    /// reads valve's variable-length integer (6 bit header, up to 32 bits total).
     #[inline(always)]
     pub fn read_ubitvar(&mut self) -> u32 {
         let ret = self.read_ubit64(6);
@@ -143,6 +247,8 @@ impl<'a> BitReader<'a> {
         f32::from_bits(self.read_ubit64(32) as u32)
     }
 
+    /// reads a world coordinate in `-16384..16384` range with 1/32 resolution; zero is encoded
+    /// with 2 bits.
     pub fn read_bitcoord(&mut self) -> f32 {
         let mut value: f32 = 0.0;
 
@@ -180,6 +286,7 @@ impl<'a> BitReader<'a> {
         value
     }
 
+    /// reads a float in `-1..=1` range (sign bit + 11 bits of fraction).
     pub fn read_bitnormal(&mut self) -> f32 {
         // read the sign bit
         let signbit = self.read_bool();
@@ -198,6 +305,31 @@ impl<'a> BitReader<'a> {
         value
     }
 
+    /// reads a coordinate within a cell (see [`crate::entities`] `*_coord_from_cell` functions).
+    /// integral coords have no fractional part; low precision coords have 3 bits of fraction
+    /// instead of 5.
+    ///
+    /// public/bitbuf.h (`bf_read::ReadBitCellCoord`)
+    pub fn read_bitcellcoord(
+        &mut self,
+        num_bits: usize,
+        integral: bool,
+        low_precision: bool,
+    ) -> f32 {
+        if integral {
+            return self.read_ubit64(num_bits) as f32;
+        }
+
+        let intval = self.read_ubit64(num_bits);
+        if low_precision {
+            let fractval = self.read_ubit64(COORD_FRACTIONAL_BITS_MP_LOWPRECISION);
+            intval as f32 + (fractval as f32 * COORD_RESOLUTION_LOWPRECISION)
+        } else {
+            let fractval = self.read_ubit64(COORD_FRACTIONAL_BITS);
+            intval as f32 + (fractval as f32 * COORD_RESOLUTION)
+        }
+    }
+
     pub fn read_bitvec3coord(&mut self) -> [f32; 3] {
         let mut fa = [0f32; 3];
 
@@ -308,6 +440,117 @@ impl<'a> BitReader<'a> {
     }
 }
 
+// checked
+// ----
+
+/// fallible variant of [`BitReader`]; each read fails immediately on overflow instead of returning
+/// garbage that is checked (with [`BitReader::is_overflowed`]) later. it borrows a regular
+/// reader, thus the two can be mixed: checked reads for values that come from the wire and are
+/// used as sizes / indices, regular reads everywhere else.
+///
+/// NOTE: in safe mode (see `ParserOptions::safe_mode`) parser uses it for type and size prefixes
+/// of packet messages, for skipping / reading their bodies, for entity headers and for picking
+/// field path ops (operands of ops are checked with [`BitReader::is_overflowed`] right after each
+/// op). field values are not read with it, those reads are checked once the message was read.
+/// [`crate::packetmessages`] always uses it for type and size prefixes and bodies.
+pub struct CheckedBitReader<'r, 'a> {
+    inner: &'r mut BitReader<'a>,
+}
+
+impl<'a> BitReader<'a> {
+    #[inline]
+    pub fn checked(&mut self) -> CheckedBitReader<'_, 'a> {
+        CheckedBitReader { inner: self }
+    }
+}
+
+// NOTE: reads are delegated to safe (checked) methods of the underlying
+// [`dungers::bitbuf::BitReader`]; they check bounds before reading.
+impl<'r, 'a> CheckedBitReader<'r, 'a> {
+    // NOTE: error is handed over to the caller which counts as overflow check; regular reader must
+    // not complain about missing check when it'll be dropped.
+    #[inline(always)]
+    fn surface<T>(
+        &mut self,
+        result: Result<T, BitReaderOverflowError>,
+    ) -> Result<T, BitReaderOverflowError> {
+        if result.is_err() {
+            self.inner.did_check_overflow = true;
+        }
+        result
+    }
+
+    #[inline]
+    pub fn num_bits_left(&self) -> usize {
+        self.inner.num_bits_left()
+    }
+
+    #[inline]
+    pub fn read_ubit64(&mut self, num_bits: usize) -> Result<u64, BitReaderOverflowError> {
+        let result = self.inner.inner.read_ubit64(num_bits);
+        self.surface(result)
+    }
+
+    #[inline]
+    pub fn read_int<T: BitInt>(&mut self, num_bits: usize) -> Result<T, BitReaderOverflowError> {
+        debug_assert!(num_bits <= T::BITS, "{num_bits} bits don't fit");
+        let value = self.read_ubit64(num_bits)?;
+        Ok(if T::SIGNED {
+            T::from_u64(sign_extend(value, num_bits) as u64)
+        } else {
+            T::from_u64(value)
+        })
+    }
+
+    #[inline]
+    pub fn read_bool(&mut self) -> Result<bool, BitReaderOverflowError> {
+        let result = self.inner.inner.read_bool();
+        self.surface(result)
+    }
+
+    #[inline]
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), BitReaderOverflowError> {
+        let result = self.inner.inner.read_bytes(buf);
+        self.surface(result)
+    }
+
+    /// checked counterpart of [`BitReader::skip_bits`].
+    pub fn skip_bits(&mut self, mut num_bits: usize) -> Result<(), BitReaderOverflowError> {
+        while num_bits >= 64 {
+            self.read_ubit64(64)?;
+            num_bits -= 64;
+        }
+        if num_bits > 0 {
+            self.read_ubit64(num_bits)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn read_uvarint32(&mut self) -> Result<u32, BitReaderOverflowError> {
+        let result = self.inner.inner.read_uvarint32();
+        self.surface(result)
+    }
+
+    #[inline]
+    pub fn read_uvarint64(&mut self) -> Result<u64, BitReaderOverflowError> {
+        let result = self.inner.inner.read_uvarint64();
+        self.surface(result)
+    }
+
+    /// checked counterpart of [`BitReader::read_ubitvar`].
+    pub fn read_ubitvar(&mut self) -> Result<u32, BitReaderOverflowError> {
+        let ret = self.read_ubit64(6)?;
+        let ret = match ret & (16 | 32) {
+            16 => (ret & 15) | (self.read_ubit64(4)? << 4),
+            32 => (ret & 15) | (self.read_ubit64(8)? << 4),
+            48 => (ret & 15) | (self.read_ubit64(32 - 4)? << 4),
+            _ => ret,
+        };
+        Ok(ret as u32)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -322,4 +565,134 @@ mod test {
         assert_eq!(&out, &buf);
         assert_eq!(num_chars, buf.len() - 1);
     }
+
+    #[test]
+    fn test_read_int() -> Result<(), BitReaderOverflowError> {
+        // NOTE: bits are read from lowest to highest; 0b101 (5 as u3, -3 as i3), then 0b11111
+        // (31 as u5, -1 as i5).
+        let buf = [0b1111_1101, 0b1111_1111];
+        let mut br = BitReader::new(&buf);
+        assert_eq!(br.read_int::<u8>(3), 5);
+        assert_eq!(br.read_int::<i8>(5), -1);
+        assert_eq!(br.read_int::<i32>(3), -1);
+        assert_eq!(br.read_int::<u16>(5), 31);
+        br.is_overflowed()?;
+
+        assert_eq!(sign_extend(0b011, 3), 3);
+        assert_eq!(sign_extend(0b100, 3), -4);
+        assert_eq!(sign_extend(u64::MAX, 64), -1);
+        assert_eq!(sign_extend(u64::MAX, 0), 0);
+        Ok(())
+    }
+
+    // packs (value, num_bits) pairs from lowest to highest bit, the way the reader reads them.
+    fn pack(fields: &[(u64, usize)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut bit = 0;
+        for &(value, num_bits) in fields {
+            for i in 0..num_bits {
+                if bit / 8 == buf.len() {
+                    buf.push(0);
+                }
+                buf[bit / 8] |= (((value >> i) & 1) as u8) << (bit % 8);
+                bit += 1;
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn test_read_ubitvar() -> Result<(), BitReaderOverflowError> {
+        // 5 fits into the header; 40 is 8 | (2 << 4) with 4 bit tail (Y set); 300 is 12 | (18 <<
+        // 4) with 8 bit tail (X set); u32::MAX has 28 bit tail (X + Y set).
+        let buf = pack(&[
+            (5, 6),
+            (8 | 16, 6),
+            (2, 4),
+            (12 | 32, 6),
+            (18, 8),
+            (15 | 48, 6),
+            ((u32::MAX >> 4) as u64, 28),
+        ]);
+        let mut br = BitReader::new(&buf);
+        assert_eq!(br.read_ubitvar(), 5);
+        assert_eq!(br.read_ubitvar(), 40);
+        assert_eq!(br.read_ubitvar(), 300);
+        assert_eq!(br.read_ubitvar(), u32::MAX);
+        br.is_overflowed()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_bits() -> Result<(), BitReaderOverflowError> {
+        let buf = pack(&[(1, 3), (0, 64), (0, 64), (0b101, 3), (7, 3)]);
+        let mut br = BitReader::new(&buf);
+        br.skip_bits(3);
+        br.skip_bits(128);
+        assert_eq!(br.read_ubit64(3), 0b101);
+        let num_bits_left = br.num_bits_left();
+        assert!(br.checked().skip_bits(num_bits_left + 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_bitcoord() -> Result<(), BitReaderOverflowError> {
+        let buf = pack(&[
+            // zero: no int, no fract
+            (0, 1),
+            (0, 1),
+            // -3.5: int, fract, sign, int - 1, fract / resolution
+            (1, 1),
+            (1, 1),
+            (1, 1),
+            (2, COORD_INTEGER_BITS),
+            (16, COORD_FRACTIONAL_BITS),
+            // 0.25: fract only, positive
+            (0, 1),
+            (1, 1),
+            (0, 1),
+            (8, COORD_FRACTIONAL_BITS),
+        ]);
+        let mut br = BitReader::new(&buf);
+        assert_eq!(br.read_bitcoord(), 0.0);
+        assert_eq!(br.read_bitcoord(), -3.5);
+        assert_eq!(br.read_bitcoord(), 0.25);
+        br.is_overflowed()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_bitnormal() -> Result<(), BitReaderOverflowError> {
+        let buf = pack(&[
+            (0, 1),
+            (2047, NORMAL_FRACTIONAL_BITS),
+            (1, 1),
+            (2047, NORMAL_FRACTIONAL_BITS),
+            (0, 1),
+            (0, NORMAL_FRACTIONAL_BITS),
+        ]);
+        let mut br = BitReader::new(&buf);
+        assert_eq!(br.read_bitnormal(), 1.0);
+        assert_eq!(br.read_bitnormal(), -1.0);
+        assert_eq!(br.read_bitnormal(), 0.0);
+        br.is_overflowed()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_bitcellcoord() -> Result<(), BitReaderOverflowError> {
+        let buf = pack(&[
+            (12, 7),
+            (12, 7),
+            (8, COORD_FRACTIONAL_BITS),
+            (12, 7),
+            (4, COORD_FRACTIONAL_BITS_MP_LOWPRECISION),
+        ]);
+        let mut br = BitReader::new(&buf);
+        assert_eq!(br.read_bitcellcoord(7, true, false), 12.0);
+        assert_eq!(br.read_bitcellcoord(7, false, false), 12.25);
+        assert_eq!(br.read_bitcellcoord(7, false, true), 12.5);
+        br.is_overflowed()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,500 @@
+//! counterpart of [`crate::bitreader::BitReader`]; each `write_*` method produces exactly what the
+//! corresponding `read_*` method expects. bits are written from lowest to highest.
+
+use crate::bitreader::{
+    COORD_DENOMINATOR, COORD_DENOMINATOR_LOWPRECISION, COORD_FRACTIONAL_BITS,
+    COORD_FRACTIONAL_BITS_MP_LOWPRECISION, COORD_INTEGER_BITS, COORD_RESOLUTION,
+    NORMAL_DENOMINATOR, NORMAL_FRACTIONAL_BITS, NORMAL_RESOLUTION,
+};
+use crate::varint;
+
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    num_bits: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(num_bytes: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(num_bytes),
+            num_bits: 0,
+        }
+    }
+
+    #[inline]
+    pub fn num_bits_written(&self) -> usize {
+        self.num_bits
+    }
+
+    /// written bytes; unused bits of the last byte are zeroed.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.num_bits = 0;
+    }
+
+    /// bits of `value` above `num_bits` are ignored.
+    pub fn write_ubit64(&mut self, value: u64, num_bits: usize) {
+        debug_assert!(num_bits <= 64);
+
+        let mut value = if num_bits < 64 {
+            value & ((1 << num_bits) - 1)
+        } else {
+            value
+        };
+        let mut num_bits = num_bits;
+        while num_bits > 0 {
+            let bit_offset = self.num_bits % 8;
+            if bit_offset == 0 {
+                self.buf.push(0);
+            }
+            let n = (8 - bit_offset).min(num_bits);
+            let last = self.buf.len() - 1;
+            self.buf[last] |= ((value & ((1 << n) - 1)) as u8) << bit_offset;
+            value >>= n;
+            num_bits -= n;
+            self.num_bits += n;
+        }
+    }
+
+    /// two's complement, truncated to `num_bits`; see [`crate::bitreader::BitReader::read_sbit64`].
+    #[inline]
+    pub fn write_sbit64(&mut self, value: i64, num_bits: usize) {
+        self.write_ubit64(value as u64, num_bits)
+    }
+
+    #[inline]
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_ubit64(value as u64, 1)
+    }
+
+    #[inline]
+    pub fn write_byte(&mut self, value: u8) {
+        self.write_ubit64(value as u64, 8)
+    }
+
+    /// writes `num_bits` bits of `buf`; counterpart of
+    /// [`crate::bitreader::BitReader::read_bits`].
+    pub fn write_bits(&mut self, buf: &[u8], num_bits: usize) {
+        debug_assert!(num_bits <= buf.len() * 8);
+
+        let num_bytes = num_bits / 8;
+        self.write_bytes(&buf[..num_bytes]);
+        let rem = num_bits % 8;
+        if rem > 0 {
+            self.write_ubit64(buf[num_bytes] as u64, rem);
+        }
+    }
+
+    pub fn write_bytes(&mut self, buf: &[u8]) {
+        if self.num_bits % 8 == 0 {
+            self.buf.extend_from_slice(buf);
+            self.num_bits += buf.len() * 8;
+        } else {
+            buf.iter().for_each(|byte| self.write_byte(*byte));
+        }
+    }
+
+    pub fn write_uvarint32(&mut self, value: u32) {
+        self.write_uvarint64(value as u64)
+    }
+
+    pub fn write_uvarint64(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.write_byte((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.write_byte(value as u8)
+    }
+
+    /// zigzag encoded.
+    pub fn write_varint32(&mut self, value: i32) {
+        self.write_uvarint32(varint::zigzag_encode32(value))
+    }
+
+    /// zigzag encoded.
+    pub fn write_varint64(&mut self, value: i64) {
+        self.write_uvarint64(varint::zigzag_encode64(value))
+    }
+
+    /// public/tier1/bitbuf.h (`bf_write::WriteUBitVar`)
+    pub fn write_ubitvar(&mut self, value: u32) {
+        let value = value as u64;
+        if value < 0x10 {
+            self.write_ubit64(value, 6);
+        } else if value < 0x100 {
+            self.write_ubit64((value & 15) | 16, 6);
+            self.write_ubit64(value >> 4, 4);
+        } else if value < 0x1000 {
+            self.write_ubit64((value & 15) | 32, 6);
+            self.write_ubit64(value >> 4, 8);
+        } else {
+            self.write_ubit64((value & 15) | 48, 6);
+            self.write_ubit64(value >> 4, 32 - 4);
+        }
+    }
+
+    pub fn write_ubitvarfp(&mut self, value: u32) {
+        let value = value as u64;
+        if value < (1 << 2) {
+            self.write_bool(true);
+            self.write_ubit64(value, 2);
+        } else if value < (1 << 4) {
+            self.write_ubit64(0b10, 2);
+            self.write_ubit64(value, 4);
+        } else if value < (1 << 10) {
+            self.write_ubit64(0b100, 3);
+            self.write_ubit64(value, 10);
+        } else if value < (1 << 17) {
+            self.write_ubit64(0b1000, 4);
+            self.write_ubit64(value, 17);
+        } else {
+            self.write_ubit64(0, 4);
+            self.write_ubit64(value, 31);
+        }
+    }
+
+    #[inline]
+    pub fn write_bitfloat(&mut self, value: f32) {
+        self.write_ubit64(value.to_bits() as u64, 32)
+    }
+
+    /// public/tier1/bitbuf.h (`bf_write::WriteBitCoord`)
+    pub fn write_bitcoord(&mut self, value: f32) {
+        let signbit = value <= -COORD_RESOLUTION;
+        let intval = value.abs() as u64;
+        let fractval =
+            ((value * COORD_DENOMINATOR) as i64).unsigned_abs() & (COORD_DENOMINATOR as u64 - 1);
+
+        self.write_bool(intval != 0);
+        self.write_bool(fractval != 0);
+
+        if intval != 0 || fractval != 0 {
+            self.write_bool(signbit);
+            if intval != 0 {
+                // adjust the integers from [1..MAX_COORD_VALUE] to [0..MAX_COORD_VALUE-1]
+                self.write_ubit64(intval - 1, COORD_INTEGER_BITS);
+            }
+            if fractval != 0 {
+                self.write_ubit64(fractval, COORD_FRACTIONAL_BITS);
+            }
+        }
+    }
+
+    /// public/tier1/bitbuf.h (`bf_write::WriteBitNormal`)
+    pub fn write_bitnormal(&mut self, value: f32) {
+        let signbit = value <= -NORMAL_RESOLUTION;
+        let fractval = ((value.abs() * NORMAL_DENOMINATOR) as u64).min(NORMAL_DENOMINATOR as u64);
+
+        self.write_bool(signbit);
+        self.write_ubit64(fractval, NORMAL_FRACTIONAL_BITS);
+    }
+
+    /// public/tier1/bitbuf.h (`bf_write::WriteBitCellCoord`)
+    pub fn write_bitcellcoord(
+        &mut self,
+        value: f32,
+        num_bits: usize,
+        integral: bool,
+        low_precision: bool,
+    ) {
+        self.write_ubit64(value as u64, num_bits);
+        if integral {
+            return;
+        }
+
+        if low_precision {
+            let fractval = ((value * COORD_DENOMINATOR_LOWPRECISION) as u64)
+                & (COORD_DENOMINATOR_LOWPRECISION as u64 - 1);
+            self.write_ubit64(fractval, COORD_FRACTIONAL_BITS_MP_LOWPRECISION);
+        } else {
+            let fractval = ((value * COORD_DENOMINATOR) as u64) & (COORD_DENOMINATOR as u64 - 1);
+            self.write_ubit64(fractval, COORD_FRACTIONAL_BITS);
+        }
+    }
+
+    pub fn write_bitvec3coord(&mut self, value: [f32; 3]) {
+        let flags = value.map(|v| v.abs() >= COORD_RESOLUTION);
+        flags.iter().for_each(|flag| self.write_bool(*flag));
+        for (v, flag) in value.iter().zip(flags) {
+            if flag {
+                self.write_bitcoord(*v);
+            }
+        }
+    }
+
+    /// z is not written, only its sign; see [`crate::bitreader::BitReader::read_bitvec3normal`].
+    pub fn write_bitvec3normal(&mut self, value: [f32; 3]) {
+        let xflag = value[0].abs() >= NORMAL_RESOLUTION;
+        let yflag = value[1].abs() >= NORMAL_RESOLUTION;
+
+        self.write_bool(xflag);
+        self.write_bool(yflag);
+
+        if xflag {
+            self.write_bitnormal(value[0]);
+        }
+        if yflag {
+            self.write_bitnormal(value[1]);
+        }
+
+        self.write_bool(value[2] <= -NORMAL_RESOLUTION);
+    }
+
+    pub fn write_bitangle(&mut self, value: f32, num_bits: usize) {
+        let shift = (1u64 << num_bits) as f32;
+        let d = ((value / 360.0) * shift) as i64 as u64;
+        self.write_ubit64(d, num_bits);
+    }
+
+    /// writes the string followed by a null-terminator; `value` must not contain nulls.
+    pub fn write_string(&mut self, value: &[u8]) {
+        debug_assert!(!value.contains(&0));
+        self.write_bytes(value);
+        self.write_byte(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::bitreader::{BitReader, BitReaderOverflowError, COORD_RESOLUTION_LOWPRECISION};
+
+    #[test]
+    fn test_round_trip() -> Result<(), BitReaderOverflowError> {
+        let mut bw = BitWriter::new();
+        bw.write_ubit64(5, 3);
+        bw.write_sbit64(-3, 7);
+        bw.write_bool(true);
+        bw.write_ubit64(u64::MAX, 64);
+        bw.write_bits(&[0xab, 0b101], 11);
+        bw.write_bytes(b"haste");
+        bw.write_uvarint32(300);
+        bw.write_uvarint64(u64::MAX);
+        bw.write_varint32(-150);
+        bw.write_varint64(i64::MIN);
+        for value in [0, 15, 16, 255, 256, 4095, 4096, u32::MAX] {
+            bw.write_ubitvar(value);
+        }
+        for value in [
+            0,
+            3,
+            4,
+            15,
+            16,
+            1023,
+            1024,
+            (1 << 17) - 1,
+            1 << 17,
+            i32::MAX as u32,
+        ] {
+            bw.write_ubitvarfp(value);
+        }
+        bw.write_bitfloat(-1.5);
+        for value in [0.0, -3.5, 0.25, 16383.96875] {
+            bw.write_bitcoord(value);
+        }
+        for value in [0.0, 1.0, -1.0] {
+            bw.write_bitnormal(value);
+        }
+        bw.write_bitcellcoord(12.0, 7, true, false);
+        bw.write_bitcellcoord(12.25, 7, false, false);
+        bw.write_bitcellcoord(12.5, 7, false, true);
+        bw.write_bitvec3coord([1.5, 0.0, -2.0]);
+        bw.write_bitvec3normal([0.0, 0.0, -1.0]);
+        bw.write_bitangle(90.0, 8);
+        bw.write_string(b"Out, out, brief candle!");
+
+        let buf = bw.into_bytes();
+        let mut br = BitReader::new(&buf);
+        assert_eq!(br.read_ubit64(3), 5);
+        assert_eq!(br.read_sbit64(7), -3);
+        assert!(br.read_bool());
+        assert_eq!(br.read_ubit64(64), u64::MAX);
+        let mut bits = [0u8; 2];
+        br.read_bits(&mut bits, 11);
+        assert_eq!(bits, [0xab, 0b101]);
+        let mut bytes = [0u8; 5];
+        br.read_bytes(&mut bytes);
+        assert_eq!(&bytes, b"haste");
+        assert_eq!(br.read_uvarint32(), 300);
+        assert_eq!(br.read_uvarint64(), u64::MAX);
+        assert_eq!(br.read_varint32(), -150);
+        assert_eq!(br.read_varint64(), i64::MIN);
+        for value in [0, 15, 16, 255, 256, 4095, 4096, u32::MAX] {
+            assert_eq!(br.read_ubitvar(), value);
+        }
+        for value in [
+            0,
+            3,
+            4,
+            15,
+            16,
+            1023,
+            1024,
+            (1 << 17) - 1,
+            1 << 17,
+            i32::MAX as u32,
+        ] {
+            assert_eq!(br.read_ubitvarfp(), value);
+        }
+        assert_eq!(br.read_bitfloat(), -1.5);
+        for value in [0.0, -3.5, 0.25, 16383.96875] {
+            assert_eq!(br.read_bitcoord(), value);
+        }
+        for value in [0.0, 1.0, -1.0] {
+            assert_eq!(br.read_bitnormal(), value);
+        }
+        assert_eq!(br.read_bitcellcoord(7, true, false), 12.0);
+        assert_eq!(br.read_bitcellcoord(7, false, false), 12.25);
+        assert_eq!(br.read_bitcellcoord(7, false, true), 12.5);
+        assert_eq!(br.read_bitvec3coord(), [1.5, 0.0, -2.0]);
+        assert_eq!(br.read_bitvec3normal(), [0.0, 0.0, -1.0]);
+        assert_eq!(br.read_bitangle(8), 90.0);
+        let mut out = [0u8; 32];
+        let num_chars = br.read_string(&mut out, false);
+        assert_eq!(&out[..num_chars], b"Out, out, brief candle!");
+        br.is_overflowed()?;
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_varint_round_trip(
+            a in any::<u32>(),
+            b in any::<u64>(),
+            c in any::<i32>(),
+            d in any::<i64>(),
+            e in any::<u32>(),
+            f in 0..(1u32 << 31),
+        ) {
+            let mut bw = BitWriter::new();
+            bw.write_uvarint32(a);
+            bw.write_uvarint64(b);
+            bw.write_varint32(c);
+            bw.write_varint64(d);
+            bw.write_ubitvar(e);
+            bw.write_ubitvarfp(f);
+
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            prop_assert_eq!(br.read_uvarint32(), a);
+            prop_assert_eq!(br.read_uvarint64(), b);
+            prop_assert_eq!(br.read_varint32(), c);
+            prop_assert_eq!(br.read_varint64(), d);
+            prop_assert_eq!(br.read_ubitvar(), e);
+            prop_assert_eq!(br.read_ubitvarfp(), f);
+            br.is_overflowed()?;
+        }
+
+        #[test]
+        fn prop_bitcoord_round_trip(value in -16383.99f32..16383.99) {
+            let mut bw = BitWriter::new();
+            bw.write_bitcoord(value);
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            let decoded = br.read_bitcoord();
+            br.is_overflowed()?;
+
+            // NOTE: fraction is truncated (towards zero).
+            prop_assert!((value - decoded).abs() < COORD_RESOLUTION, "{value} -> {decoded}");
+            prop_assert!(decoded.abs() <= value.abs(), "{value} -> {decoded}");
+
+            // decoded values are representable exactly
+            let mut bw = BitWriter::new();
+            bw.write_bitcoord(decoded);
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            prop_assert_eq!(br.read_bitcoord(), decoded);
+            br.is_overflowed()?;
+        }
+
+        #[test]
+        fn prop_bitvec3coord_round_trip(value in prop::array::uniform3(-16383.99f32..16383.99)) {
+            let mut bw = BitWriter::new();
+            bw.write_bitvec3coord(value);
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            let decoded = br.read_bitvec3coord();
+            br.is_overflowed()?;
+
+            for (v, d) in value.iter().zip(decoded) {
+                prop_assert!((v - d).abs() < COORD_RESOLUTION, "{value:?} -> {decoded:?}");
+            }
+        }
+
+        #[test]
+        fn prop_bitnormal_round_trip(value in -1.0f32..=1.0) {
+            let mut bw = BitWriter::new();
+            bw.write_bitnormal(value);
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            let decoded = br.read_bitnormal();
+            br.is_overflowed()?;
+
+            prop_assert!(
+                (value - decoded).abs() <= NORMAL_RESOLUTION + f32::EPSILON,
+                "{value} -> {decoded}"
+            );
+        }
+
+        #[test]
+        fn prop_bitcellcoord_round_trip(
+            num_bits in 1..=16usize,
+            integral in any::<bool>(),
+            low_precision in any::<bool>(),
+            t in 0.0f32..1.0,
+        ) {
+            let value = t * (1 << num_bits) as f32;
+
+            let mut bw = BitWriter::new();
+            bw.write_bitcellcoord(value, num_bits, integral, low_precision);
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            let decoded = br.read_bitcellcoord(num_bits, integral, low_precision);
+            br.is_overflowed()?;
+
+            let resolution = if integral {
+                1.0
+            } else if low_precision {
+                COORD_RESOLUTION_LOWPRECISION
+            } else {
+                COORD_RESOLUTION
+            };
+            prop_assert!(decoded <= value, "{value} -> {decoded}");
+            prop_assert!(value - decoded < resolution, "{value} -> {decoded}");
+        }
+
+        #[test]
+        fn prop_bitangle_round_trip(num_bits in 1..=20usize, value in 0.0f32..360.0) {
+            let mut bw = BitWriter::new();
+            bw.write_bitangle(value, num_bits);
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            let decoded = br.read_bitangle(num_bits);
+            br.is_overflowed()?;
+
+            let resolution = 360.0 / (1u64 << num_bits) as f32;
+            // NOTE: tolerance accounts for the error of f32 multiplication in the reader.
+            prop_assert!(
+                (value - decoded).abs() <= resolution + 1e-4,
+                "{value} -> {decoded} ({num_bits} bits)"
+            );
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! what the broadcast (gotv / source tv) camera was showing. director camera changes arrive as
+//! `hltv_chase`, `hltv_fixed` and `hltv_cameraman` game events; observer state of spectating
+//! players is networked in `m_pObserverServices` of player pawns.
+//!
+//! NOTE: dota 2 does not seem to network director camera events in regular replays (only in live
+//! broadcasts of tournament lobbies); absence of changes is not an error.
+
+use crate::entities::{ehandle_to_index, fkey_from_path, is_ehandle_valid, Entity};
+use crate::gameevents::{EventValue, GameEvent};
+
+const OBSERVER_MODE_KEY: u64 = fkey_from_path(&["m_pObserverServices", "m_iObserverMode"]);
+const OBSERVER_TARGET_KEY: u64 = fkey_from_path(&["m_pObserverServices", "m_hObserverTarget"]);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraShot {
+    /// camera follows `target1` (entity index), looking at `target2` if it's not 0.
+    Chase {
+        target1: i32,
+        target2: i32,
+        distance: f32,
+        theta: f32,
+        phi: f32,
+        inertia: f32,
+        in_eye: bool,
+    },
+    /// camera is placed at the given position.
+    Fixed {
+        position: [f32; 3],
+        theta: f32,
+        phi: f32,
+        offset: f32,
+        fov: f32,
+        target: i32,
+    },
+    /// human cameraman took over the broadcast; `index` is what the event carries (entity index
+    /// or user id, depending on game).
+    Cameraman { index: i32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraChange {
+    pub tick: i32,
+    pub shot: CameraShot,
+}
+
+/// observer state of a spectating player pawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObserverState {
+    /// `ObserverMode_t` value (for example in cs2 0 is none, 2 is in eye, 3 is chase, 4 is
+    /// roaming).
+    pub mode: i32,
+    /// index of the entity being observed.
+    pub target: Option<i32>,
+}
+
+impl ObserverState {
+    /// none if the entity does not have observer services.
+    pub fn from_entity(entity: &Entity) -> Option<Self> {
+        let mode = entity.get_value(&OBSERVER_MODE_KEY)?;
+        let target = entity
+            .get_value::<u32>(&OBSERVER_TARGET_KEY)
+            .filter(|handle| is_ehandle_valid(*handle))
+            .map(ehandle_to_index);
+        Some(Self { mode, target })
+    }
+}
+
+fn get_i32(event: &GameEvent, key: &str) -> i32 {
+    match event.get(key) {
+        Some(EventValue::I32(v)) => *v,
+        Some(EventValue::U64(v)) => *v as i32,
+        Some(EventValue::Bool(v)) => *v as i32,
+        _ => 0,
+    }
+}
+
+fn get_f32(event: &GameEvent, key: &str) -> f32 {
+    match event.get(key) {
+        Some(EventValue::F32(v)) => *v,
+        Some(EventValue::I32(v)) => *v as f32,
+        _ => 0.0,
+    }
+}
+
+impl CameraShot {
+    /// none for events that are not camera events.
+    pub fn from_game_event(event: &GameEvent) -> Option<Self> {
+        match event.name() {
+            "hltv_chase" => Some(Self::Chase {
+                target1: get_i32(event, "target1"),
+                target2: get_i32(event, "target2"),
+                distance: get_f32(event, "distance"),
+                theta: get_f32(event, "theta"),
+                phi: get_f32(event, "phi"),
+                inertia: get_f32(event, "inertia"),
+                in_eye: get_i32(event, "ineye") != 0,
+            }),
+            "hltv_fixed" => Some(Self::Fixed {
+                position: [
+                    get_f32(event, "posx"),
+                    get_f32(event, "posy"),
+                    get_f32(event, "posz"),
+                ],
+                theta: get_f32(event, "theta"),
+                phi: get_f32(event, "phi"),
+                offset: get_f32(event, "offset"),
+                fov: get_f32(event, "fov"),
+                target: get_i32(event, "target"),
+            }),
+            "hltv_cameraman" => Some(Self::Cameraman {
+                index: get_i32(event, "index").max(get_i32(event, "userid")),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// log of director camera changes. feed it with decoded game events (see
+/// [`crate::gameevents::GameEventList::decode`]); other events are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct CameraTracker {
+    changes: Vec<CameraChange>,
+}
+
+impl CameraTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, tick: i32, event: &GameEvent) {
+        if let Some(shot) = CameraShot::from_game_event(event) {
+            self.changes.push(CameraChange { tick, shot });
+        }
+    }
+
+    /// camera changes, oldest first.
+    #[inline]
+    pub fn changes(&self) -> &[CameraChange] {
+        &self.changes
+    }
+
+    /// shot that was active at the given tick.
+    pub fn shot_at(&self, tick: i32) -> Option<&CameraShot> {
+        let n = self.changes.partition_point(|change| change.tick <= tick);
+        self.changes
+            .get(n.checked_sub(1)?)
+            .map(|change| &change.shot)
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+}
@@ -0,0 +1,572 @@
+//! append-only log of entity changes (tick, entity, field key, new value) in a compact binary
+//! format, and a reader for it. state of entities at any tick can be re-materialized from the log
+//! without re-parsing the demo (see [`ChangeLogReader::read_until`]).
+//!
+//! format: `HASTECL1` magic followed by records; each record starts with a tag byte.
+//!
+//! - tick: zigzag varint tick; applies to records that follow.
+//! - create: varint entity index, u64 (little endian) serializer name hash; resets state of the
+//! entity. followed by field records of all fields.
+//! - delete: varint entity index.
+//! - field: varint entity index, u64 (little endian) field key, value (tag byte followed by
+//! varints for integers, little endian f32s for floats and vectors, varint length prefixed utf-8
+//! for strings).
+//!
+//! NOTE: leaving pvs is not a change of state, it is not recorded.
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::io::{self, Read, Write};
+
+use nohash::NoHashHasher;
+
+use crate::entities::{DeltaHeader, Entity};
+use crate::entityhistory::EntityState;
+use crate::fieldvalue::FieldValue;
+use crate::replaydiff::field_values_eq;
+use crate::varint::{self, ReadVarintError};
+
+pub const CHANGE_LOG_MAGIC: &[u8; 8] = b"HASTECL1";
+
+const TAG_TICK: u8 = 0;
+const TAG_CREATE: u8 = 1;
+const TAG_DELETE: u8 = 2;
+const TAG_FIELD: u8 = 3;
+
+const VALUE_TAG_I64: u8 = 0;
+const VALUE_TAG_U64: u8 = 1;
+const VALUE_TAG_F32: u8 = 2;
+const VALUE_TAG_BOOL: u8 = 3;
+const VALUE_TAG_VECTOR3: u8 = 4;
+const VALUE_TAG_VECTOR2: u8 = 5;
+const VALUE_TAG_VECTOR4: u8 = 6;
+const VALUE_TAG_QANGLE: u8 = 7;
+const VALUE_TAG_QANGLE_PITCH_YAW: u8 = 8;
+const VALUE_TAG_STRING: u8 = 9;
+
+type FieldMap = HashMap<u64, FieldValue, BuildHasherDefault<NoHashHasher<u64>>>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChangeLogError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    ReadVarintError(#[from] ReadVarintError),
+    #[error("invalid change log magic")]
+    InvalidMagic,
+    #[error("invalid record tag {0}")]
+    InvalidRecordTag(u8),
+    #[error("invalid value tag {0}")]
+    InvalidValueTag(u8),
+    #[error("invalid string value")]
+    InvalidString,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChangeRecord {
+    Create {
+        tick: i32,
+        index: i32,
+        serializer_name_hash: u64,
+    },
+    Delete {
+        tick: i32,
+        index: i32,
+    },
+    Field {
+        tick: i32,
+        index: i32,
+        key: u64,
+        value: FieldValue,
+    },
+}
+
+impl ChangeRecord {
+    pub fn tick(&self) -> i32 {
+        match self {
+            Self::Create { tick, .. } | Self::Delete { tick, .. } | Self::Field { tick, .. } => {
+                *tick
+            }
+        }
+    }
+
+    pub fn index(&self) -> i32 {
+        match self {
+            Self::Create { index, .. } | Self::Delete { index, .. } | Self::Field { index, .. } => {
+                *index
+            }
+        }
+    }
+}
+
+// write
+// ----
+
+fn write_f32s<W: Write>(mut wtr: W, values: &[f32]) -> Result<(), io::Error> {
+    for value in values {
+        wtr.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_value<W: Write>(mut wtr: W, value: &FieldValue) -> Result<(), io::Error> {
+    match value {
+        FieldValue::I64(v) => {
+            wtr.write_all(&[VALUE_TAG_I64])?;
+            varint::write_varint64(wtr, *v)?;
+        }
+        FieldValue::U64(v) => {
+            wtr.write_all(&[VALUE_TAG_U64])?;
+            varint::write_uvarint64(wtr, *v)?;
+        }
+        FieldValue::F32(v) => {
+            wtr.write_all(&[VALUE_TAG_F32])?;
+            write_f32s(wtr, &[*v])?;
+        }
+        FieldValue::Bool(v) => {
+            wtr.write_all(&[VALUE_TAG_BOOL, *v as u8])?;
+        }
+        FieldValue::Vector3(v) => {
+            wtr.write_all(&[VALUE_TAG_VECTOR3])?;
+            write_f32s(wtr, v)?;
+        }
+        FieldValue::Vector2(v) => {
+            wtr.write_all(&[VALUE_TAG_VECTOR2])?;
+            write_f32s(wtr, v)?;
+        }
+        FieldValue::Vector4(v) => {
+            wtr.write_all(&[VALUE_TAG_VECTOR4])?;
+            write_f32s(wtr, v)?;
+        }
+        FieldValue::QAngle(v) => {
+            wtr.write_all(&[VALUE_TAG_QANGLE])?;
+            write_f32s(wtr, v)?;
+        }
+        FieldValue::QAnglePitchYaw(v) => {
+            wtr.write_all(&[VALUE_TAG_QANGLE_PITCH_YAW])?;
+            write_f32s(wtr, v)?;
+        }
+        FieldValue::String(v) => {
+            wtr.write_all(&[VALUE_TAG_STRING])?;
+            varint::write_uvarint64(&mut wtr, v.len() as u64)?;
+            wtr.write_all(v.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// feed it with entity updates from [`crate::parser::Visitor::on_entity`]:
+///
+/// ```ignore
+/// fn on_entity(&mut self, ctx: &Context, delta_header: DeltaHeader, entity: &Entity) -> Result<()> {
+///     self.change_log.record(ctx.tick(), delta_header, entity)?;
+///     Ok(())
+/// }
+/// ```
+///
+/// only fields that changed are written; last written state of each entity is kept to compute
+/// changes.
+pub struct ChangeLogWriter<W: Write> {
+    wtr: W,
+    tick: Option<i32>,
+    // NOTE: keyed by entity index; values are serializer name hash and last written values.
+    state: HashMap<i32, (u64, FieldMap), BuildHasherDefault<NoHashHasher<i32>>>,
+}
+
+impl<W: Write> ChangeLogWriter<W> {
+    pub fn start_writing(mut wtr: W) -> Result<Self, io::Error> {
+        wtr.write_all(CHANGE_LOG_MAGIC)?;
+        Ok(Self {
+            wtr,
+            tick: None,
+            state: HashMap::default(),
+        })
+    }
+
+    fn write_tick(&mut self, tick: i32) -> Result<(), io::Error> {
+        if self.tick != Some(tick) {
+            self.wtr.write_all(&[TAG_TICK])?;
+            varint::write_varint32(&mut self.wtr, tick)?;
+            self.tick = Some(tick);
+        }
+        Ok(())
+    }
+
+    /// writes the record as is, bypassing change tracking of [`Self::record`]; do not mix the two
+    /// for the same entity.
+    pub fn write_record(&mut self, record: &ChangeRecord) -> Result<(), io::Error> {
+        self.write_tick(record.tick())?;
+        match record {
+            ChangeRecord::Create {
+                index,
+                serializer_name_hash,
+                ..
+            } => {
+                self.wtr.write_all(&[TAG_CREATE])?;
+                varint::write_uvarint32(&mut self.wtr, *index as u32)?;
+                self.wtr.write_all(&serializer_name_hash.to_le_bytes())?;
+            }
+            ChangeRecord::Delete { index, .. } => {
+                self.wtr.write_all(&[TAG_DELETE])?;
+                varint::write_uvarint32(&mut self.wtr, *index as u32)?;
+            }
+            ChangeRecord::Field {
+                index, key, value, ..
+            } => {
+                self.write_field(*index, *key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_field(&mut self, index: i32, key: u64, value: &FieldValue) -> Result<(), io::Error> {
+        self.wtr.write_all(&[TAG_FIELD])?;
+        varint::write_uvarint32(&mut self.wtr, index as u32)?;
+        self.wtr.write_all(&key.to_le_bytes())?;
+        write_value(&mut self.wtr, value)
+    }
+
+    pub fn record(
+        &mut self,
+        tick: i32,
+        delta_header: DeltaHeader,
+        entity: &Entity,
+    ) -> Result<(), io::Error> {
+        let index = entity.index();
+        if delta_header == DeltaHeader::DELETE {
+            if self.state.remove(&index).is_some() {
+                self.write_record(&ChangeRecord::Delete { tick, index })?;
+            }
+            return Ok(());
+        }
+        if delta_header == DeltaHeader::LEAVE {
+            return Ok(());
+        }
+
+        let serializer_name_hash = entity.serializer().serializer_name.hash;
+        // NOTE: recording can start mid-demo (after a seek); first update of an entity that was
+        // not seen yet is written as a create.
+        let is_create = delta_header == DeltaHeader::CREATE
+            || self
+                .state
+                .get(&index)
+                .map_or(true, |(prev_hash, _)| *prev_hash != serializer_name_hash);
+        if is_create {
+            self.write_record(&ChangeRecord::Create {
+                tick,
+                index,
+                serializer_name_hash,
+            })?;
+            let fields: FieldMap = entity.iter().map(|(k, v)| (*k, v.clone())).collect();
+            for (key, value) in fields.iter() {
+                self.write_field(index, *key, value)?;
+            }
+            self.state.insert(index, (serializer_name_hash, fields));
+            return Ok(());
+        }
+
+        let mut changed: Vec<(u64, FieldValue)> = Vec::new();
+        if let Some((_, fields)) = self.state.get_mut(&index) {
+            for (key, value) in entity.iter() {
+                let is_changed = fields
+                    .get(key)
+                    .map_or(true, |prev| !field_values_eq(prev, value, 0.0));
+                if is_changed {
+                    fields.insert(*key, value.clone());
+                    changed.push((*key, value.clone()));
+                }
+            }
+        }
+        if !changed.is_empty() {
+            self.write_tick(tick)?;
+            for (key, value) in changed.iter() {
+                self.write_field(index, *key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.wtr.flush()
+    }
+
+    /// flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        self.wtr.flush()?;
+        Ok(self.wtr)
+    }
+}
+
+// read
+// ----
+
+fn read_u8<R: Read>(mut rdr: R) -> Result<u8, io::Error> {
+    let mut buf = [0u8; 1];
+    rdr.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u64_le<R: Read>(mut rdr: R) -> Result<u64, io::Error> {
+    let mut buf = [0u8; 8];
+    rdr.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f32s<R: Read, const N: usize>(mut rdr: R) -> Result<[f32; N], io::Error> {
+    let mut values = [0f32; N];
+    let mut buf = [0u8; 4];
+    for value in values.iter_mut() {
+        rdr.read_exact(&mut buf)?;
+        *value = f32::from_le_bytes(buf);
+    }
+    Ok(values)
+}
+
+fn read_value<R: Read>(mut rdr: R) -> Result<FieldValue, ChangeLogError> {
+    let value = match read_u8(&mut rdr)? {
+        VALUE_TAG_I64 => FieldValue::I64(varint::read_varint64(rdr)?.0),
+        VALUE_TAG_U64 => FieldValue::U64(varint::read_uvarint64(rdr)?.0),
+        VALUE_TAG_F32 => FieldValue::F32(read_f32s::<_, 1>(rdr)?[0]),
+        VALUE_TAG_BOOL => FieldValue::Bool(read_u8(rdr)? != 0),
+        VALUE_TAG_VECTOR3 => FieldValue::Vector3(read_f32s(rdr)?),
+        VALUE_TAG_VECTOR2 => FieldValue::Vector2(read_f32s(rdr)?),
+        VALUE_TAG_VECTOR4 => FieldValue::Vector4(read_f32s(rdr)?),
+        VALUE_TAG_QANGLE => FieldValue::QAngle(read_f32s(rdr)?),
+        VALUE_TAG_QANGLE_PITCH_YAW => FieldValue::QAnglePitchYaw(read_f32s(rdr)?),
+        VALUE_TAG_STRING => {
+            let (len, _) = varint::read_uvarint64(&mut rdr)?;
+            let mut buf = Vec::new();
+            rdr.take(len).read_to_end(&mut buf)?;
+            if buf.len() as u64 != len {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let string = String::from_utf8(buf).map_err(|_| ChangeLogError::InvalidString)?;
+            FieldValue::String(string.into_boxed_str())
+        }
+        tag => return Err(ChangeLogError::InvalidValueTag(tag)),
+    };
+    Ok(value)
+}
+
+/// state of entities materialized from change records.
+#[derive(Debug, Default)]
+pub struct ChangeLogState {
+    entities: HashMap<i32, EntityState, BuildHasherDefault<NoHashHasher<i32>>>,
+}
+
+impl ChangeLogState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, record: &ChangeRecord) {
+        match record {
+            ChangeRecord::Create {
+                index,
+                serializer_name_hash,
+                ..
+            } => {
+                self.entities.insert(
+                    *index,
+                    EntityState {
+                        serializer_name_hash: *serializer_name_hash,
+                        fields: Default::default(),
+                    },
+                );
+            }
+            ChangeRecord::Delete { index, .. } => {
+                self.entities.remove(index);
+            }
+            ChangeRecord::Field {
+                index, key, value, ..
+            } => {
+                if let Some(entity) = self.entities.get_mut(index) {
+                    entity.fields.insert(*key, value.clone());
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, index: &i32) -> Option<&EntityState> {
+        self.entities.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&i32, &EntityState)> {
+        self.entities.iter()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+enum Entry {
+    Tick(i32),
+    Record(ChangeRecord),
+}
+
+pub struct ChangeLogReader<R: Read> {
+    rdr: R,
+    tick: i32,
+}
+
+impl<R: Read> ChangeLogReader<R> {
+    pub fn start_reading(mut rdr: R) -> Result<Self, ChangeLogError> {
+        let mut magic = [0u8; 8];
+        rdr.read_exact(&mut magic)?;
+        if &magic != CHANGE_LOG_MAGIC {
+            return Err(ChangeLogError::InvalidMagic);
+        }
+        Ok(Self { rdr, tick: 0 })
+    }
+
+    /// tick of the last tick record that was read.
+    #[inline]
+    pub fn tick(&self) -> i32 {
+        self.tick
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Entry>, ChangeLogError> {
+        let mut tag = [0u8; 1];
+        if self.rdr.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        let tick = self.tick;
+        let rdr = &mut self.rdr;
+        let entry = match tag[0] {
+            TAG_TICK => Entry::Tick(varint::read_varint32(rdr)?.0),
+            TAG_CREATE => Entry::Record(ChangeRecord::Create {
+                tick,
+                index: varint::read_uvarint32(&mut *rdr)?.0 as i32,
+                serializer_name_hash: read_u64_le(rdr)?,
+            }),
+            TAG_DELETE => Entry::Record(ChangeRecord::Delete {
+                tick,
+                index: varint::read_uvarint32(rdr)?.0 as i32,
+            }),
+            TAG_FIELD => Entry::Record(ChangeRecord::Field {
+                tick,
+                index: varint::read_uvarint32(&mut *rdr)?.0 as i32,
+                key: read_u64_le(&mut *rdr)?,
+                value: read_value(rdr)?,
+            }),
+            tag => return Err(ChangeLogError::InvalidRecordTag(tag)),
+        };
+        Ok(Some(entry))
+    }
+
+    /// none at the end of the log.
+    pub fn next_record(&mut self) -> Result<Option<ChangeRecord>, ChangeLogError> {
+        loop {
+            match self.read_entry()? {
+                Some(Entry::Tick(tick)) => self.tick = tick,
+                Some(Entry::Record(record)) => return Ok(Some(record)),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// applies records up to and including the given tick to the state; reading stops at the
+    /// first record of a later tick (which remains unread). call repeatedly with increasing ticks
+    /// to walk through the log.
+    pub fn read_until(
+        &mut self,
+        tick: i32,
+        state: &mut ChangeLogState,
+    ) -> Result<(), ChangeLogError> {
+        if self.tick > tick {
+            return Ok(());
+        }
+        loop {
+            match self.read_entry()? {
+                Some(Entry::Tick(next_tick)) => {
+                    self.tick = next_tick;
+                    if next_tick > tick {
+                        return Ok(());
+                    }
+                }
+                Some(Entry::Record(record)) => state.apply(&record),
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), ChangeLogError> {
+        let records = vec![
+            ChangeRecord::Create {
+                tick: 1,
+                index: 7,
+                serializer_name_hash: 0xdead_beef,
+            },
+            ChangeRecord::Field {
+                tick: 1,
+                index: 7,
+                key: 1,
+                value: FieldValue::I64(-42),
+            },
+            ChangeRecord::Field {
+                tick: 1,
+                index: 7,
+                key: 2,
+                value: FieldValue::String("npc_dota_hero_axe".into()),
+            },
+            ChangeRecord::Field {
+                tick: 5,
+                index: 7,
+                key: 3,
+                value: FieldValue::Vector3([1.0, -2.5, 3.25]),
+            },
+            ChangeRecord::Field {
+                tick: 5,
+                index: 7,
+                key: 1,
+                value: FieldValue::I64(-41),
+            },
+            ChangeRecord::Delete { tick: 9, index: 7 },
+        ];
+
+        let mut wtr = ChangeLogWriter::start_writing(Vec::new())?;
+        for record in records.iter() {
+            wtr.write_record(record)?;
+        }
+        let buf = wtr.finish()?;
+
+        let mut rdr = ChangeLogReader::start_reading(buf.as_slice())?;
+        for expected in records.iter() {
+            let record = rdr.next_record()?;
+            assert_eq!(format!("{record:?}"), format!("{:?}", Some(expected)));
+        }
+        assert!(rdr.next_record()?.is_none());
+
+        let mut rdr = ChangeLogReader::start_reading(buf.as_slice())?;
+        let mut state = ChangeLogState::new();
+        rdr.read_until(4, &mut state)?;
+        let entity = state.get(&7);
+        assert!(entity.is_some_and(|entity| entity.fields.len() == 2));
+        rdr.read_until(5, &mut state)?;
+        let value = state.get(&7).and_then(|entity| entity.get(&1));
+        assert_eq!(format!("{value:?}"), "Some(I64(-41))");
+        rdr.read_until(9, &mut state)?;
+        assert!(state.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_magic() {
+        let result = ChangeLogReader::start_reading(b"HASTECL0".as_slice());
+        assert!(matches!(result, Err(ChangeLogError::InvalidMagic)));
+    }
+}
@@ -0,0 +1,248 @@
+//! cs2 round state (round number, phase, freeze time), bomb state and team scores / economy.
+//! gamerules live in `CCSGameRulesProxy`, scores in `CCSTeam`, money in `CCSPlayerController`
+//! and the bomb, once it's planted, in `CPlantedC4`.
+//!
+//! NOTE: haste does not ship cs2 protobufs (see [`crate::game::Game::has_protobufs`]); entities
+//! are decoded with common protobufs, that is all this module needs.
+
+use crate::entities::{fkey_from_path, Entity, EntityContainer};
+use crate::fxhash;
+
+pub const GAMERULES_ENTITY: u64 = fxhash::hash_bytes(b"CCSGameRulesProxy");
+pub const TEAM_ENTITY: u64 = fxhash::hash_bytes(b"CCSTeam");
+pub const PLAYER_CONTROLLER_ENTITY: u64 = fxhash::hash_bytes(b"CCSPlayerController");
+pub const PLANTED_C4_ENTITY: u64 = fxhash::hash_bytes(b"CPlantedC4");
+
+/// teams as networked in `m_iTeamNum`.
+pub const TEAM_SPECTATOR: u8 = 1;
+pub const TEAM_TERRORIST: u8 = 2;
+pub const TEAM_COUNTER_TERRORIST: u8 = 3;
+
+fn find(entities: &EntityContainer, serializer_name_hash: u64) -> Option<&Entity> {
+    entities
+        .iter()
+        .map(|(_, entity)| entity)
+        .find(|entity| entity.serializer_name_heq(serializer_name_hash))
+}
+
+// rounds
+// ----
+
+const TOTAL_ROUNDS_PLAYED_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_totalRoundsPlayed"]);
+const WARMUP_PERIOD_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_bWarmupPeriod"]);
+const FREEZE_PERIOD_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_bFreezePeriod"]);
+const GAME_PHASE_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_gamePhase"]);
+const ROUND_WIN_REASON_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_eRoundWinReason"]);
+const ROUND_START_TIME_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_fRoundStartTime"]);
+const ROUND_TIME_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_iRoundTime"]);
+const BOMB_PLANTED_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_bBombPlanted"]);
+const BOMB_DROPPED_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_bBombDropped"]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RoundPhase {
+    Warmup,
+    /// players can buy but can't move.
+    FreezeTime,
+    Live,
+    /// round was won, next one did not start yet.
+    Over,
+}
+
+/// state of the current round from gamerules.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundState {
+    /// 1-based number of the current round (rounds played + 1).
+    pub round: i32,
+    pub phase: RoundPhase,
+    /// `gamephase_t` value (for example 2 is first half, 3 is second half, 5 is match ended).
+    pub game_phase: Option<i32>,
+    /// `RoundEndReason_t` value of the last round that ended.
+    pub round_win_reason: Option<i32>,
+    /// time (server clock, seconds) at which freeze time ended.
+    pub round_start_time: Option<f32>,
+    /// duration of the round (excluding freeze time) in seconds.
+    pub round_time: Option<i32>,
+}
+
+impl RoundState {
+    pub fn from_game_rules(entity: &Entity) -> Self {
+        let get_bool = |key: &u64| entity.get_value::<bool>(key).unwrap_or(false);
+        let total_rounds_played: i32 = entity.get_value(&TOTAL_ROUNDS_PLAYED_KEY).unwrap_or(0);
+        let round_win_reason: Option<i32> = entity.get_value(&ROUND_WIN_REASON_KEY);
+
+        // NOTE: win reason is reset to 0 (unknown) once the next round starts.
+        let phase = if get_bool(&WARMUP_PERIOD_KEY) {
+            RoundPhase::Warmup
+        } else if get_bool(&FREEZE_PERIOD_KEY) {
+            RoundPhase::FreezeTime
+        } else if round_win_reason.is_some_and(|reason| reason != 0) {
+            RoundPhase::Over
+        } else {
+            RoundPhase::Live
+        };
+
+        Self {
+            round: total_rounds_played + 1,
+            phase,
+            game_phase: entity.get_value(&GAME_PHASE_KEY),
+            round_win_reason,
+            round_start_time: entity.get_value(&ROUND_START_TIME_KEY),
+            round_time: entity.get_value(&ROUND_TIME_KEY),
+        }
+    }
+}
+
+/// none if gamerules entity does not exist (yet).
+pub fn round_state(entities: &EntityContainer) -> Option<RoundState> {
+    find(entities, GAMERULES_ENTITY).map(RoundState::from_game_rules)
+}
+
+// bomb
+// ----
+
+const BOMB_TICKING_KEY: u64 = fkey_from_path(&["m_bBombTicking"]);
+const BOMB_SITE_KEY: u64 = fkey_from_path(&["m_nBombSite"]);
+const C4_BLOW_KEY: u64 = fkey_from_path(&["m_flC4Blow"]);
+const BEING_DEFUSED_KEY: u64 = fkey_from_path(&["m_bBeingDefused"]);
+const DEFUSE_COUNT_DOWN_KEY: u64 = fkey_from_path(&["m_flDefuseCountDown"]);
+const BOMB_DEFUSED_KEY: u64 = fkey_from_path(&["m_bBombDefused"]);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BombState {
+    /// carried by a terrorist (or there's no bomb in the game mode).
+    Carried,
+    Dropped,
+    Planted {
+        /// index of the bomb site (0 is a, 1 is b).
+        site: Option<i32>,
+        /// time (server clock, seconds) at which the bomb explodes.
+        explode_time: Option<f32>,
+        /// time (server clock, seconds) at which the defuse completes; none if no one is
+        /// defusing.
+        defuse_time: Option<f32>,
+    },
+    Defused,
+    Exploded,
+}
+
+impl BombState {
+    /// `planted_c4` is `CPlantedC4` entity, if exists.
+    pub fn from_entities(game_rules: &Entity, planted_c4: Option<&Entity>) -> Self {
+        let get_bool = |entity: &Entity, key: &u64| entity.get_value::<bool>(key).unwrap_or(false);
+
+        if let Some(c4) = planted_c4 {
+            if get_bool(c4, &BOMB_DEFUSED_KEY) {
+                return Self::Defused;
+            }
+            // NOTE: planted c4 stops ticking when it explodes or gets defused; the entity lingers
+            // until the next round.
+            if !get_bool(c4, &BOMB_TICKING_KEY) {
+                return Self::Exploded;
+            }
+            return Self::Planted {
+                site: c4.get_value(&BOMB_SITE_KEY),
+                explode_time: c4.get_value(&C4_BLOW_KEY),
+                defuse_time: get_bool(c4, &BEING_DEFUSED_KEY)
+                    .then(|| c4.get_value(&DEFUSE_COUNT_DOWN_KEY))
+                    .flatten(),
+            };
+        }
+
+        if get_bool(game_rules, &BOMB_PLANTED_KEY) {
+            // NOTE: gamerules may arrive before planted c4 entity within the same tick.
+            Self::Planted {
+                site: None,
+                explode_time: None,
+                defuse_time: None,
+            }
+        } else if get_bool(game_rules, &BOMB_DROPPED_KEY) {
+            Self::Dropped
+        } else {
+            Self::Carried
+        }
+    }
+}
+
+/// none if gamerules entity does not exist (yet).
+pub fn bomb_state(entities: &EntityContainer) -> Option<BombState> {
+    let game_rules = find(entities, GAMERULES_ENTITY)?;
+    Some(BombState::from_entities(
+        game_rules,
+        find(entities, PLANTED_C4_ENTITY),
+    ))
+}
+
+// teams
+// ----
+
+const TEAM_NUM_KEY: u64 = fkey_from_path(&["m_iTeamNum"]);
+const SCORE_KEY: u64 = fkey_from_path(&["m_iScore"]);
+const CLAN_NAME_KEY: u64 = fkey_from_path(&["m_szClanTeamname"]);
+
+const ACCOUNT_KEY: u64 = fkey_from_path(&["m_pInGameMoneyServices", "m_iAccount"]);
+const START_ACCOUNT_KEY: u64 = fkey_from_path(&["m_pInGameMoneyServices", "m_iStartAccount"]);
+const CASH_SPENT_THIS_ROUND_KEY: u64 =
+    fkey_from_path(&["m_pInGameMoneyServices", "m_iCashSpentThisRound"]);
+const TOTAL_CASH_SPENT_KEY: u64 = fkey_from_path(&["m_pInGameMoneyServices", "m_iTotalCashSpent"]);
+
+/// score from `CCSTeam`, money summed over `CCSPlayerController`s of the team.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TeamState {
+    pub team: u8,
+    pub score: Option<i32>,
+    /// none if team does not have a name (for example in matchmaking).
+    pub clan_name: Option<String>,
+    pub num_players: usize,
+    /// money that players have now.
+    pub money: i32,
+    /// money that players had at the start of the round.
+    pub start_money: i32,
+    pub cash_spent_this_round: i32,
+    pub total_cash_spent: i32,
+}
+
+/// terrorists and counter-terrorists, in that order; teams that don't have a `CCSTeam` entity
+/// (yet) are omitted.
+pub fn team_states(entities: &EntityContainer) -> Vec<TeamState> {
+    let mut teams: Vec<TeamState> = entities
+        .iter()
+        .map(|(_, entity)| entity)
+        .filter(|entity| entity.serializer_name_heq(TEAM_ENTITY))
+        .filter_map(|entity| {
+            let team: u8 = entity.get_value(&TEAM_NUM_KEY)?;
+            if team != TEAM_TERRORIST && team != TEAM_COUNTER_TERRORIST {
+                return None;
+            }
+            Some(TeamState {
+                team,
+                score: entity.get_value(&SCORE_KEY),
+                clan_name: entity
+                    .get_value::<String>(&CLAN_NAME_KEY)
+                    .filter(|clan_name| !clan_name.is_empty()),
+                ..Default::default()
+            })
+        })
+        .collect();
+    teams.sort_unstable_by_key(|team| team.team);
+
+    for controller in entities
+        .iter()
+        .map(|(_, entity)| entity)
+        .filter(|entity| entity.serializer_name_heq(PLAYER_CONTROLLER_ENTITY))
+    {
+        let Some(team) = controller
+            .get_value::<u8>(&TEAM_NUM_KEY)
+            .and_then(|team_num| teams.iter_mut().find(|team| team.team == team_num))
+        else {
+            continue;
+        };
+        let get = |key: &u64| -> i32 { controller.get_value(key).unwrap_or(0) };
+        team.num_players += 1;
+        team.money += get(&ACCOUNT_KEY);
+        team.start_money += get(&START_ACCOUNT_KEY);
+        team.cash_spent_this_round += get(&CASH_SPENT_THIS_ROUND_KEY);
+        team.total_cash_spent += get(&TOTAL_CASH_SPENT_KEY);
+    }
+
+    teams
+}
@@ -0,0 +1,295 @@
+//! deadlock match clock, per-player souls / stats and objectives (guardians, walkers, base
+//! guardians, shrines and patrons). field names have nothing in common with dota 2, see
+//! [`crate::gameclock`], [`crate::playerstats`] and [`crate::matchinfo`] for dota 2 counterparts.
+//!
+//! NOTE: deadlock is in active development and renames things often; field names are what recent
+//! demos network. values that are missing (older / newer demos) are none.
+
+use valveprotos::common::{CnetMsgTick, NetMessages};
+
+use crate::entities::{fkey_from_path, Entity, EntityContainer};
+use crate::fxhash;
+use crate::gameclock::DEADLOCK_GAMERULES_ENTITY;
+use crate::protobackend::{DecodeError, ProtoMessage};
+
+pub const PLAYER_CONTROLLER_ENTITY: u64 = fxhash::hash_bytes(b"CCitadelPlayerController");
+
+/// teams as networked in `m_iTeamNum`.
+pub const TEAM_AMBER: u8 = 2;
+pub const TEAM_SAPPHIRE: u8 = 3;
+
+// match clock
+// ----
+
+const GAME_STATE_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_eGameState"]);
+const GAME_START_TIME_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_flGameStartTime"]);
+const GAME_PAUSED_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_bGamePaused"]);
+const MATCH_CLOCK_AT_LAST_UPDATE_KEY: u64 =
+    fkey_from_path(&["m_pGameRules", "m_flMatchClockAtLastUpdate"]);
+const MATCH_CLOCK_UPDATE_TICK_KEY: u64 =
+    fkey_from_path(&["m_pGameRules", "m_nMatchClockUpdateTick"]);
+
+/// deadlock's in-game clock. unlike dota 2, deadlock networks the clock itself (its value as of
+/// the tick at which it was last updated; it is updated on pauses and unpauses), thus no need to
+/// reconstruct it from game start time and paused ticks (which is what
+/// [`crate::gameclock::GameClock`] does; that works too, numbers are the same).
+///
+/// feed it with packets from [`crate::parser::Visitor::on_packet`] and entities from
+/// [`crate::parser::Visitor::on_entity`], and set tick interval once it's known (on
+/// `DemSyncTick` cmd, see [`crate::parser::Context::tick_interval`]).
+#[derive(Debug, Clone, Default)]
+pub struct MatchClock {
+    tick_interval: Option<f32>,
+    net_tick: u32,
+    game_state: Option<u32>,
+    game_start_time: f32,
+    game_paused: bool,
+    clock_at_last_update: Option<f32>,
+    clock_update_tick: i32,
+}
+
+impl MatchClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_tick_interval(&mut self, tick_interval: f32) {
+        self.tick_interval = Some(tick_interval);
+    }
+
+    /// `NetTick` packets are handled, other packets are ignored.
+    pub fn update_from_packet(&mut self, packet_type: u32, data: &[u8]) -> Result<(), DecodeError> {
+        if packet_type == NetMessages::NetTick as u32 {
+            if let Some(net_tick) = CnetMsgTick::decode_message(data)?.tick {
+                self.net_tick = net_tick;
+            }
+        }
+        Ok(())
+    }
+
+    /// gamerules entity is handled, other entities are ignored.
+    pub fn update_from_entity(&mut self, entity: &Entity) {
+        if !entity.serializer_name_heq(DEADLOCK_GAMERULES_ENTITY) {
+            return;
+        }
+
+        if let Some(game_state) = entity.get_value(&GAME_STATE_KEY) {
+            self.game_state = Some(game_state);
+        }
+        if let Some(game_start_time) = entity.get_value(&GAME_START_TIME_KEY) {
+            self.game_start_time = game_start_time;
+        }
+        if let Some(game_paused) = entity.get_value(&GAME_PAUSED_KEY) {
+            self.game_paused = game_paused;
+        }
+        if let Some(clock_at_last_update) = entity.get_value(&MATCH_CLOCK_AT_LAST_UPDATE_KEY) {
+            self.clock_at_last_update = Some(clock_at_last_update);
+        }
+        if let Some(clock_update_tick) = entity.get_value(&MATCH_CLOCK_UPDATE_TICK_KEY) {
+            self.clock_update_tick = clock_update_tick;
+        }
+    }
+
+    #[inline]
+    pub fn net_tick(&self) -> u32 {
+        self.net_tick
+    }
+
+    /// `EGameState` value of citadel gamerules (for example 7 is game in progress, 8 is post
+    /// game).
+    #[inline]
+    pub fn game_state(&self) -> Option<u32> {
+        self.game_state
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.game_paused
+    }
+
+    // NOTE: 0.001 is an arbitrary number; nothing special. see gameclock.
+    #[inline]
+    pub fn has_started(&self) -> bool {
+        self.game_start_time > 0.001
+    }
+
+    /// match time in seconds as of the current net tick (what the clock at the top of the screen
+    /// shows); none if the game has not started yet (or tick interval is unknown). the clock stops
+    /// while the game is paused.
+    pub fn match_time(&self) -> Option<f32> {
+        if !self.has_started() {
+            return None;
+        }
+        let clock_at_last_update = self.clock_at_last_update?;
+        if self.game_paused {
+            return Some(clock_at_last_update);
+        }
+        let elapsed_ticks = self.net_tick as i32 - self.clock_update_tick;
+        Some(clock_at_last_update + elapsed_ticks.max(0) as f32 * self.tick_interval?)
+    }
+}
+
+// players
+// ----
+
+const TEAM_KEY: u64 = fkey_from_path(&["m_iTeamNum"]);
+const PLAYER_SLOT_KEY: u64 = fkey_from_path(&["m_unLobbyPlayerSlot"]);
+const HERO_ID_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_nHeroID"]);
+const LEVEL_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iLevel"]);
+const NET_WORTH_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iGoldNetWorth"]);
+const KILLS_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iPlayerKills"]);
+const DEATHS_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iDeaths"]);
+const ASSISTS_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iPlayerAssists"]);
+const LAST_HITS_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iLastHits"]);
+const DENIES_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_iDenies"]);
+const ASSIGNED_LANE_KEY: u64 = fkey_from_path(&["m_PlayerDataGlobal", "m_nAssignedLane"]);
+
+/// player state from `CCitadelPlayerController` (controllers outlive pawns, they exist for the
+/// whole match, also while the hero is dead).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    pub entity_index: i32,
+    pub team: Option<u8>,
+    pub player_slot: Option<u32>,
+    pub hero_id: Option<u32>,
+    pub level: Option<i32>,
+    /// total souls collected (spent and unspent).
+    pub net_worth: Option<i32>,
+    pub kills: Option<i32>,
+    pub deaths: Option<i32>,
+    pub assists: Option<i32>,
+    pub last_hits: Option<i32>,
+    pub denies: Option<i32>,
+    /// lane number the player was assigned to at the start of the match.
+    pub assigned_lane: Option<i32>,
+}
+
+impl PlayerStats {
+    pub fn from_player_controller(entity: &Entity) -> Self {
+        Self {
+            entity_index: entity.index(),
+            team: entity.get_value(&TEAM_KEY),
+            player_slot: entity.get_value(&PLAYER_SLOT_KEY),
+            hero_id: entity.get_value(&HERO_ID_KEY),
+            level: entity.get_value(&LEVEL_KEY),
+            net_worth: entity.get_value(&NET_WORTH_KEY),
+            kills: entity.get_value(&KILLS_KEY),
+            deaths: entity.get_value(&DEATHS_KEY),
+            assists: entity.get_value(&ASSISTS_KEY),
+            last_hits: entity.get_value(&LAST_HITS_KEY),
+            denies: entity.get_value(&DENIES_KEY),
+            assigned_lane: entity.get_value(&ASSIGNED_LANE_KEY),
+        }
+    }
+}
+
+/// stats of all players that have a hero, ordered by entity index.
+pub fn player_stats(entities: &EntityContainer) -> Vec<PlayerStats> {
+    let mut players: Vec<PlayerStats> = entities
+        .iter()
+        .map(|(_, entity)| entity)
+        .filter(|entity| entity.serializer_name_heq(PLAYER_CONTROLLER_ENTITY))
+        .map(PlayerStats::from_player_controller)
+        .filter(|player| player.hero_id.is_some_and(|hero_id| hero_id != 0))
+        .collect();
+    players.sort_unstable_by_key(|player| player.entity_index);
+    players
+}
+
+// objectives
+// ----
+
+const HEALTH_KEY: u64 = fkey_from_path(&["m_iHealth"]);
+const MAX_HEALTH_KEY: u64 = fkey_from_path(&["m_iMaxHealth"]);
+const LIFE_STATE_KEY: u64 = fkey_from_path(&["m_lifeState"]);
+const LANE_KEY: u64 = fkey_from_path(&["m_iLane"]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectiveKind {
+    /// lane tier 1 guardian.
+    Guardian,
+    /// lane tier 2 walker.
+    Walker,
+    /// base guardian (in front of the shrines).
+    BaseGuardian,
+    Shrine,
+    Patron,
+}
+
+// NOTE: base guardians share the class with lane guardians in older demos; in those they are
+// reported as guardians.
+const OBJECTIVES: &[(u64, ObjectiveKind)] = &[
+    (
+        fxhash::hash_bytes(b"CNPC_TrooperBoss"),
+        ObjectiveKind::Guardian,
+    ),
+    (
+        fxhash::hash_bytes(b"CNPC_Boss_Tier2"),
+        ObjectiveKind::Walker,
+    ),
+    (
+        fxhash::hash_bytes(b"CNPC_BaseDefenseSentry"),
+        ObjectiveKind::BaseGuardian,
+    ),
+    (
+        fxhash::hash_bytes(b"CNPC_BarrackBoss"),
+        ObjectiveKind::Shrine,
+    ),
+    (
+        fxhash::hash_bytes(b"CNPC_Boss_Tier3"),
+        ObjectiveKind::Patron,
+    ),
+];
+
+impl ObjectiveKind {
+    pub fn from_entity(entity: &Entity) -> Option<Self> {
+        OBJECTIVES
+            .iter()
+            .find(|(serializer_name_hash, _)| entity.serializer_name_heq(*serializer_name_hash))
+            .map(|(_, kind)| *kind)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectiveState {
+    pub entity_index: i32,
+    pub kind: ObjectiveKind,
+    pub team: Option<u8>,
+    /// lane number; none for objectives that don't belong to a lane (patrons, shrines).
+    pub lane: Option<i32>,
+    pub health: Option<i32>,
+    pub max_health: Option<i32>,
+    /// `m_lifeState` being 0 (alive).
+    pub is_alive: bool,
+}
+
+impl ObjectiveState {
+    /// none if the entity is not an objective.
+    pub fn from_entity(entity: &Entity) -> Option<Self> {
+        let kind = ObjectiveKind::from_entity(entity)?;
+        let health: Option<i32> = entity.get_value(&HEALTH_KEY);
+        let life_state: Option<u8> = entity.get_value(&LIFE_STATE_KEY);
+        Some(Self {
+            entity_index: entity.index(),
+            kind,
+            team: entity.get_value(&TEAM_KEY),
+            lane: entity.get_value(&LANE_KEY),
+            health,
+            max_health: entity.get_value(&MAX_HEALTH_KEY),
+            is_alive: life_state.map_or(health.is_some_and(|health| health > 0), |life_state| {
+                life_state == 0
+            }),
+        })
+    }
+}
+
+/// states of all objectives that exist at the moment, ordered by entity index. destroyed
+/// objectives get deleted (sooner or later), count what's missing to know what was destroyed.
+pub fn objective_states(entities: &EntityContainer) -> Vec<ObjectiveState> {
+    let mut objectives: Vec<ObjectiveState> = entities
+        .iter()
+        .filter_map(|(_, entity)| ObjectiveState::from_entity(entity))
+        .collect();
+    objectives.sort_unstable_by_key(|objective| objective.entity_index);
+    objectives
+}
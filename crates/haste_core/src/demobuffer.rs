@@ -0,0 +1,534 @@
+use std::io::{self, SeekFrom};
+
+use valveprotos::common::{
+    CDemoClassInfo, CDemoFileInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables,
+    CDemoStringTables, EDemoCommands,
+};
+
+use crate::demofile::{DemoHeader, DemoHeaderError, DEMO_HEADER_ID, DEMO_HEADER_ID_SIZE};
+use crate::demostream::{CmdHeader, DecodeCmdError, DemoStream, ReadCmdError, ReadCmdHeaderError};
+use crate::protobackend::ProtoMessage;
+use crate::varint;
+
+const DEMO_HEADER_SIZE: usize = DEMO_HEADER_ID_SIZE + 2 * size_of::<i32>();
+
+// NOTE: varints are at most 5 bytes long; varint is complete when there's a byte without
+// continuation bit.
+#[inline]
+fn has_complete_uvarint32(buf: &[u8]) -> bool {
+    buf.len() >= 5 || buf.iter().any(|b| b & 0x80 == 0)
+}
+
+#[inline]
+fn incomplete() -> ReadCmdHeaderError {
+    ReadCmdHeaderError::IoError(io::ErrorKind::UnexpectedEof.into())
+}
+
+/// in-memory demo stream that can be fed incrementally (for example with chunks of a http
+/// response). does not rely on any io, thus can be used in environments where there's no file
+/// system (wasm).
+///
+/// [`DemoStream::read_cmd_header`] only succeeds if the entire cmd (header and body) is
+/// buffered, and [`DemoStream::is_at_eof`] reports true when there's no complete cmd available.
+/// this means that parser can be run after each [`DemoBuffer::feed`] to consume everything that
+/// have arrived so far.
+///
+/// running out of data is not the end of the stream though ([`DemoStream::is_finished`]), the
+/// rest of the tick may still arrive. the stream is finished once file info cmd (the last cmd of
+/// a demo) was read, or after [`DemoBuffer::finish`] (for demos that lack file info, for example
+/// truncated ones).
+///
+/// fed data is never discarded so that seeking remains possible.
+#[derive(Debug)]
+pub struct DemoBuffer {
+    data: Vec<u8>,
+    pos: usize,
+    buf: Vec<u8>,
+    demo_header: Option<DemoHeader>,
+    finished: bool,
+}
+
+impl DemoBuffer {
+    pub fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            pos: DEMO_HEADER_SIZE,
+            buf: Vec::new(),
+            demo_header: None,
+            finished: false,
+        }
+    }
+
+    /// marks that nothing will be fed anymore.
+    #[inline]
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// appends the chunk; validates demo header as soon as enough bytes have arrived.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), DemoHeaderError> {
+        self.data.extend_from_slice(chunk);
+
+        if self.demo_header.is_none() && self.data.len() >= DEMO_HEADER_SIZE {
+            let mut demofilestamp = [0u8; DEMO_HEADER_ID_SIZE];
+            demofilestamp.copy_from_slice(&self.data[..DEMO_HEADER_ID_SIZE]);
+            if demofilestamp != DEMO_HEADER_ID {
+                return Err(DemoHeaderError::InvalidDemoFileStamp { got: demofilestamp });
+            }
+
+            let mut buf = [0u8; size_of::<i32>()];
+            buf.copy_from_slice(&self.data[DEMO_HEADER_ID_SIZE..DEMO_HEADER_ID_SIZE + 4]);
+            let fileinfo_offset = i32::from_le_bytes(buf);
+            buf.copy_from_slice(&self.data[DEMO_HEADER_ID_SIZE + 4..DEMO_HEADER_SIZE]);
+            let spawngroups_offset = i32::from_le_bytes(buf);
+
+            self.demo_header = Some(DemoHeader {
+                demofilestamp,
+                fileinfo_offset,
+                spawngroups_offset,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// returns None if not enough data have been fed yet.
+    #[inline]
+    pub fn demo_header(&self) -> Option<&DemoHeader> {
+        self.demo_header.as_ref()
+    }
+
+    /// number of bytes fed so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn peek_cmd_header(&self, pos: usize) -> Result<CmdHeader, ReadCmdHeaderError> {
+        if self.demo_header.is_none() {
+            return Err(incomplete());
+        }
+
+        let mut rdr = self.data.get(pos..).ok_or_else(incomplete)?;
+        let mut read_uvarint32 = || {
+            if !has_complete_uvarint32(rdr) {
+                return Err(incomplete());
+            }
+            varint::read_uvarint32(&mut rdr).map_err(ReadCmdHeaderError::from)
+        };
+
+        let (cmd_raw, cmd_n) = read_uvarint32()?;
+        let (tick, tick_n) = read_uvarint32()?;
+        let (body_size, body_size_n) = read_uvarint32()?;
+
+        if rdr.len() < body_size as usize {
+            return Err(incomplete());
+        }
+
+        const DEM_IS_COMPRESSED: u32 = EDemoCommands::DemIsCompressed as u32;
+        let body_compressed = cmd_raw & DEM_IS_COMPRESSED == DEM_IS_COMPRESSED;
+        let cmd = if body_compressed {
+            cmd_raw & !DEM_IS_COMPRESSED
+        } else {
+            cmd_raw
+        };
+
+        Ok(CmdHeader {
+            cmd: EDemoCommands::try_from(cmd as i32).map_err(|_| {
+                ReadCmdHeaderError::UnknownCmd {
+                    raw: cmd_raw,
+                    uncompressed: cmd,
+                }
+            })?,
+            body_compressed,
+            // NOTE: see DemoFile::read_cmd_header for why casting u32 to i32 is okay.
+            tick: tick as i32,
+            body_size,
+            size: (cmd_n + tick_n + body_size_n) as u8,
+        })
+    }
+
+    /// returns None if file info have not arrived yet (it is located at the very end of demos).
+    pub fn file_info(&mut self) -> Result<Option<CDemoFileInfo>, anyhow::Error> {
+        let Some(demo_header) = self.demo_header.as_ref() else {
+            return Ok(None);
+        };
+        let pos = demo_header.fileinfo_offset as usize;
+        let cmd_header = match self.peek_cmd_header(pos) {
+            Ok(cmd_header) => cmd_header,
+            Err(ReadCmdHeaderError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let backup = self.pos;
+        self.pos = pos + cmd_header.size as usize;
+        let file_info = self
+            .read_cmd(&cmd_header)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| CDemoFileInfo::decode_message(data).map_err(anyhow::Error::from));
+        self.pos = backup;
+
+        file_info.map(Some)
+    }
+}
+
+impl Default for DemoBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DemoStream for DemoBuffer {
+    // stream ops
+    // ----
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.data.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => (self.pos as u64).checked_add_signed(offset),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos as usize;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    #[inline]
+    fn stream_position(&mut self) -> Result<u64, io::Error> {
+        Ok(self.pos as u64)
+    }
+
+    #[inline]
+    fn stream_len(&mut self) -> Result<u64, io::Error> {
+        Ok(self.data.len() as u64)
+    }
+
+    /// returns true if there's no complete cmd at the current position.
+    fn is_at_eof(&mut self) -> Result<bool, io::Error> {
+        match self.peek_cmd_header(self.pos) {
+            Err(ReadCmdHeaderError::IoError(err)) => Ok(err.kind() == io::ErrorKind::UnexpectedEof),
+            _ => Ok(false),
+        }
+    }
+
+    fn is_finished(&mut self) -> Result<bool, io::Error> {
+        if !self.is_at_eof()? {
+            return Ok(false);
+        }
+        if self.finished {
+            return Ok(true);
+        }
+        // NOTE: demos that are being recorded have no file info offset yet.
+        let Some(fileinfo_offset) = self
+            .demo_header
+            .as_ref()
+            .map(|demo_header| demo_header.fileinfo_offset as usize)
+            .filter(|fileinfo_offset| *fileinfo_offset > 0)
+        else {
+            return Ok(false);
+        };
+        match self.peek_cmd_header(fileinfo_offset) {
+            Ok(cmd_header) => Ok(self.pos
+                >= fileinfo_offset + cmd_header.size as usize + cmd_header.body_size as usize),
+            Err(_) => Ok(false),
+        }
+    }
+
+    // cmd header
+    // ----
+
+    fn read_cmd_header(&mut self) -> Result<CmdHeader, ReadCmdHeaderError> {
+        let cmd_header = self.peek_cmd_header(self.pos)?;
+        self.pos += cmd_header.size as usize;
+        Ok(cmd_header)
+    }
+
+    // cmd
+    // ----
+
+    fn read_cmd(&mut self, cmd_header: &CmdHeader) -> Result<&[u8], ReadCmdError> {
+        let end = self.pos + cmd_header.body_size as usize;
+        let body = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        self.pos = end;
+
+        if cmd_header.body_compressed {
+            let decompress_len = snap::raw::decompress_len(body)?;
+            if self.buf.len() < decompress_len {
+                self.buf.resize(decompress_len, 0);
+            }
+            snap::raw::Decoder::new().decompress(body, &mut self.buf)?;
+            Ok(&self.buf[..decompress_len])
+        } else {
+            Ok(body)
+        }
+    }
+
+    #[inline(always)]
+    fn decode_cmd_send_tables(data: &[u8]) -> Result<CDemoSendTables, DecodeCmdError> {
+        CDemoSendTables::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    #[inline(always)]
+    fn decode_cmd_class_info(data: &[u8]) -> Result<CDemoClassInfo, DecodeCmdError> {
+        CDemoClassInfo::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    #[inline(always)]
+    fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError> {
+        CDemoStringTables::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    #[inline(always)]
+    fn decode_cmd_packet(data: &[u8]) -> Result<CDemoPacket, DecodeCmdError> {
+        CDemoPacket::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    #[inline(always)]
+    fn decode_cmd_full_packet(data: &[u8]) -> Result<CDemoFullPacket, DecodeCmdError> {
+        CDemoFullPacket::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    // other
+    // ----
+
+    fn start_position(&self) -> u64 {
+        DEMO_HEADER_SIZE as u64
+    }
+
+    fn total_ticks(&mut self) -> Result<i32, anyhow::Error> {
+        self.file_info()?
+            .map(|file_info| file_info.playback_ticks())
+            .ok_or_else(|| anyhow::anyhow!("file info is not available yet"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::demofile::DemoFile;
+    use crate::entities::{fkey_from_path, DeltaHeader, Entity};
+    use crate::fieldvalue::FieldValue;
+    use crate::parser::{Context, Parser, Visitor};
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    #[derive(Default)]
+    struct TickEndRecorder {
+        tick_ends: Vec<i32>,
+    }
+
+    impl Visitor for TickEndRecorder {
+        fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+            self.tick_ends.push(ctx.tick());
+            Ok(())
+        }
+    }
+
+    fn toy_demo() -> Result<Vec<u8>> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(90))])?;
+        wtr.write_tick(2)?;
+        wtr.write_tick(3)?;
+        Ok(wtr.finish()?)
+    }
+
+    fn expected_tick_ends(data: &[u8]) -> Result<Vec<i32>> {
+        let demo_file = DemoFile::start_reading(Cursor::new(data))?;
+        let mut parser = Parser::from_stream_with_visitor(demo_file, TickEndRecorder::default())?;
+        parser.run_to_end()?;
+        Ok(parser.into_visitor().tick_ends)
+    }
+
+    #[test]
+    fn test_tick_does_not_end_before_all_of_its_cmds_arrive() -> Result<()> {
+        let data = toy_demo()?;
+        let expected = expected_tick_ends(&data)?;
+
+        // NOTE: stop and file info cmds belong to the tick of the last packet; split right before
+        // them.
+        let mut demo_file = DemoFile::start_reading(Cursor::new(data.as_slice()))?;
+        let split = loop {
+            let position = demo_file.stream_position()? as usize;
+            let cmd_header = demo_file.read_cmd_header()?;
+            if cmd_header.cmd == EDemoCommands::DemStop {
+                break position;
+            }
+            demo_file.skip_cmd(&cmd_header)?;
+        };
+
+        let mut parser =
+            Parser::from_stream_with_visitor(DemoBuffer::new(), TickEndRecorder::default())?;
+        parser.demo_stream_mut().feed(&data[..split])?;
+        parser.run_to_end()?;
+        assert_eq!(
+            parser.visitor().tick_ends,
+            expected[..expected.len() - 1].to_vec()
+        );
+        assert!(!parser.demo_stream_mut().is_finished()?);
+
+        parser.demo_stream_mut().feed(&data[split..])?;
+        parser.run_to_end()?;
+        assert_eq!(parser.visitor().tick_ends, expected);
+        assert!(parser.demo_stream_mut().is_finished()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_finish() -> Result<()> {
+        let data = toy_demo()?;
+        let expected = expected_tick_ends(&data)?;
+
+        // NOTE: file info never arrives; the last tick ends once the buffer is finished.
+        let demo_file = DemoFile::start_reading(Cursor::new(data.as_slice()))?;
+        let fileinfo_offset = demo_file.demo_header().fileinfo_offset as usize;
+        let mut parser =
+            Parser::from_stream_with_visitor(DemoBuffer::new(), TickEndRecorder::default())?;
+        parser.demo_stream_mut().feed(&data[..fileinfo_offset])?;
+        parser.run_to_end()?;
+        assert_eq!(
+            parser.visitor().tick_ends,
+            expected[..expected.len() - 1].to_vec()
+        );
+
+        parser.demo_stream_mut().finish();
+        parser.run_to_end()?;
+        assert_eq!(parser.visitor().tick_ends, expected);
+
+        Ok(())
+    }
+
+    // NOTE: not a real message type.
+    const TOY_PACKET_TYPE: u32 = 1000;
+
+    #[derive(Default)]
+    struct EventRecorder {
+        events: Vec<String>,
+    }
+
+    impl Visitor for EventRecorder {
+        fn on_entity(
+            &mut self,
+            ctx: &Context,
+            delta_header: DeltaHeader,
+            entity: &Entity,
+        ) -> Result<()> {
+            let health: Option<i32> = entity.get_value(&fkey_from_path(&["m_iHealth"]));
+            self.events.push(format!(
+                "[{}] entity {delta_header:?} #{} {health:?}",
+                ctx.tick(),
+                entity.index()
+            ));
+            Ok(())
+        }
+
+        fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+            if packet_type == TOY_PACKET_TYPE {
+                self.events
+                    .push(format!("[{}] packet {data:?}", ctx.tick()));
+            }
+            Ok(())
+        }
+
+        fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+            self.events.push(format!("[{}] tick end", ctx.tick()));
+            Ok(())
+        }
+    }
+
+    fn busy_demo() -> Result<Vec<u8>> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.create(2, "CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        wtr.create(3, "CToyEntity", &[])?;
+        wtr.write_tick(1)?;
+        wtr.packet_message(TOY_PACKET_TYPE, b"toy".to_vec());
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(90))])?;
+        wtr.leave(3)?;
+        wtr.write_tick(2)?;
+        // NOTE: full packets are skipped unless parser seeks; they still must be read over.
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(90))])?;
+        wtr.create(2, "CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        wtr.create(3, "CToyEntity", &[])?;
+        wtr.write_full_packet(3)?;
+        wtr.update(1, &[("m_bAlive", FieldValue::Bool(true))])?;
+        wtr.delete(2)?;
+        wtr.write_tick(4)?;
+        wtr.create(4, "CToyEntity", &[("m_iHealth", FieldValue::I64(10))])?;
+        wtr.write_tick(5)?;
+        Ok(wtr.finish()?)
+    }
+
+    fn events_of_demo_file(data: &[u8]) -> Result<Vec<String>> {
+        let demo_file = DemoFile::start_reading(Cursor::new(data))?;
+        let mut parser = Parser::from_stream_with_visitor(demo_file, EventRecorder::default())?;
+        parser.run_to_end()?;
+        Ok(parser.into_visitor().events)
+    }
+
+    /// feeds chunks of the given sizes (cycled) and runs parser after each one.
+    fn events_of_demo_buffer(data: &[u8], chunk_sizes: &[usize]) -> Result<Vec<String>> {
+        let mut parser =
+            Parser::from_stream_with_visitor(DemoBuffer::new(), EventRecorder::default())?;
+        let mut rest = data;
+        for chunk_size in chunk_sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, tail) = rest.split_at((*chunk_size).min(rest.len()));
+            parser.demo_stream_mut().feed(chunk)?;
+            parser.run_to_end()?;
+            rest = tail;
+        }
+        assert!(parser.demo_stream_mut().is_finished()?);
+        Ok(parser.into_visitor().events)
+    }
+
+    #[test]
+    fn test_feed_byte_by_byte() -> Result<()> {
+        let data = busy_demo()?;
+        let expected = events_of_demo_file(&data)?;
+        assert!(expected.len() > 10);
+        assert_eq!(events_of_demo_buffer(&data, &[1])?, expected);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_feed_random_chunks(
+            chunk_sizes in prop::collection::vec(1usize..256, 1..32),
+        ) {
+            let data = busy_demo().unwrap();
+            let expected = events_of_demo_file(&data).unwrap();
+            prop_assert_eq!(events_of_demo_buffer(&data, &chunk_sizes).unwrap(), expected);
+        }
+    }
+}
@@ -1,13 +1,13 @@
 use std::io::{self, Read, Seek, SeekFrom};
 
-use dungers::varint;
-use prost;
-use prost::Message;
 use valveprotos::common::{
-    CDemoClassInfo, CDemoFileInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables, EDemoCommands,
+    CDemoClassInfo, CDemoFileInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables,
+    CDemoStringTables, EDemoCommands,
 };
 
 use crate::demostream::{CmdHeader, DecodeCmdError, DemoStream, ReadCmdError, ReadCmdHeaderError};
+use crate::protobackend::ProtoMessage;
+use crate::varint;
 
 // #define DEMO_RECORD_BUFFER_SIZE 2*1024*1024
 //
@@ -19,8 +19,8 @@ pub const DEMO_RECORD_BUFFER_SIZE: usize = 2 * 1024 * 1024;
 // #define DEMO_HEADER_ID "HL2DEMO"
 //
 // NOTE: strings in c/cpp are null terminated.
-const DEMO_HEADER_ID_SIZE: usize = 8;
-const DEMO_HEADER_ID: [u8; DEMO_HEADER_ID_SIZE] = *b"PBDEMS2\0";
+pub(crate) const DEMO_HEADER_ID_SIZE: usize = 8;
+pub(crate) const DEMO_HEADER_ID: [u8; DEMO_HEADER_ID_SIZE] = *b"PBDEMS2\0";
 
 // NOTE: naming is based on stuff from demofile.h of valve's demoinfo2 thing.
 #[derive(Debug, Clone)]
@@ -38,7 +38,7 @@ pub enum DemoHeaderError {
     InvalidDemoFileStamp { got: [u8; DEMO_HEADER_ID_SIZE] },
 }
 
-fn read_demo_header<R: Read>(mut rdr: R) -> Result<DemoHeader, DemoHeaderError> {
+pub(crate) fn read_demo_header<R: Read>(mut rdr: R) -> Result<DemoHeader, DemoHeaderError> {
     let mut demofilestamp = [0u8; DEMO_HEADER_ID_SIZE];
     rdr.read_exact(&mut demofilestamp)?;
     if demofilestamp != DEMO_HEADER_ID {
@@ -96,7 +96,7 @@ impl<R: Read + Seek> DemoFile<R> {
 
             self.seek(SeekFrom::Start(self.demo_header.fileinfo_offset as u64))?;
             let cmd_header = self.read_cmd_header()?;
-            self.file_info = Some(CDemoFileInfo::decode(self.read_cmd(&cmd_header)?)?);
+            self.file_info = Some(CDemoFileInfo::decode_message(self.read_cmd(&cmd_header)?)?);
 
             self.seek(SeekFrom::Start(backup))?;
         }
@@ -195,22 +195,27 @@ impl<R: Read + Seek> DemoStream for DemoFile<R> {
 
     #[inline(always)]
     fn decode_cmd_send_tables(data: &[u8]) -> Result<CDemoSendTables, DecodeCmdError> {
-        CDemoSendTables::decode(data).map_err(DecodeCmdError::DecodeProtobufError)
+        CDemoSendTables::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
     }
 
     #[inline(always)]
     fn decode_cmd_class_info(data: &[u8]) -> Result<CDemoClassInfo, DecodeCmdError> {
-        CDemoClassInfo::decode(data).map_err(DecodeCmdError::DecodeProtobufError)
+        CDemoClassInfo::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    #[inline(always)]
+    fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError> {
+        CDemoStringTables::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
     }
 
     #[inline(always)]
     fn decode_cmd_packet(data: &[u8]) -> Result<CDemoPacket, DecodeCmdError> {
-        CDemoPacket::decode(data).map_err(DecodeCmdError::DecodeProtobufError)
+        CDemoPacket::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
     }
 
     #[inline(always)]
     fn decode_cmd_full_packet(data: &[u8]) -> Result<CDemoFullPacket, DecodeCmdError> {
-        CDemoFullPacket::decode(data).map_err(DecodeCmdError::DecodeProtobufError)
+        CDemoFullPacket::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
     }
 
     // other
@@ -0,0 +1,250 @@
+use std::io::{self, Read, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use valveprotos::common::EDemoCommands;
+
+use crate::demostream::{DemoStream, ReadCmdHeaderError};
+
+// NOTE: the index is stored in a sidecar file next to the demo, see [`DemoIndex::sidecar_path`].
+// layout (everything is little endian):
+// - magic (8 bytes)
+// - version (u32)
+// - length of the demo stream (u64); allows to detect stale indices
+// - total ticks (i32)
+// - number of entries (u32)
+// - entries; tick (i32), position (u64)
+const DEMO_INDEX_MAGIC: [u8; 8] = *b"HSTIDX\0\0";
+const DEMO_INDEX_VERSION: u32 = 1;
+const DEMO_INDEX_SIDECAR_EXTENSION: &str = "hidx";
+
+#[derive(thiserror::Error, Debug)]
+pub enum DemoIndexError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    ReadCmdHeaderError(#[from] ReadCmdHeaderError),
+    #[error("invalid demo index magic (got {got:?})")]
+    InvalidMagic { got: [u8; 8] },
+    #[error("unsupported demo index version (got {got}; want {DEMO_INDEX_VERSION})")]
+    UnsupportedVersion { got: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DemoIndexEntry {
+    pub tick: i32,
+    /// position of the cmd header within the demo stream.
+    pub position: u64,
+}
+
+/// positions of full packets within a demo stream.
+#[derive(Debug, Clone, Default)]
+pub struct DemoIndex {
+    stream_len: u64,
+    total_ticks: i32,
+    entries: Vec<DemoIndexEntry>,
+}
+
+impl DemoIndex {
+    /// scans cmd headers of the whole stream (bodies are skipped). stream position is restored
+    /// afterwards.
+    pub fn build<D: DemoStream>(demo_stream: &mut D) -> Result<Self, DemoIndexError> {
+        let backup = demo_stream.stream_position()?;
+        let stream_len = demo_stream.stream_len()?;
+
+        demo_stream.seek(SeekFrom::Start(demo_stream.start_position()))?;
+
+        let mut entries = Vec::new();
+        let mut total_ticks = 0;
+        loop {
+            let position = demo_stream.stream_position()?;
+            match demo_stream.read_cmd_header() {
+                Ok(cmd_header) => {
+                    if cmd_header.cmd == EDemoCommands::DemFullPacket {
+                        entries.push(DemoIndexEntry {
+                            tick: cmd_header.tick,
+                            position,
+                        });
+                    }
+                    total_ticks = total_ticks.max(cmd_header.tick);
+                    demo_stream.skip_cmd(&cmd_header)?;
+                }
+                Err(_) if demo_stream.is_at_eof().unwrap_or_default() => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        demo_stream.seek(SeekFrom::Start(backup))?;
+
+        Ok(Self {
+            stream_len,
+            total_ticks,
+            entries,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, mut wtr: W) -> Result<(), io::Error> {
+        wtr.write_all(&DEMO_INDEX_MAGIC)?;
+        wtr.write_all(&DEMO_INDEX_VERSION.to_le_bytes())?;
+        wtr.write_all(&self.stream_len.to_le_bytes())?;
+        wtr.write_all(&self.total_ticks.to_le_bytes())?;
+        wtr.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for entry in self.entries.iter() {
+            wtr.write_all(&entry.tick.to_le_bytes())?;
+            wtr.write_all(&entry.position.to_le_bytes())?;
+        }
+        wtr.flush()
+    }
+
+    pub fn read_from<R: Read>(mut rdr: R) -> Result<Self, DemoIndexError> {
+        let mut magic = [0u8; 8];
+        rdr.read_exact(&mut magic)?;
+        if magic != DEMO_INDEX_MAGIC {
+            return Err(DemoIndexError::InvalidMagic { got: magic });
+        }
+
+        let mut buf4 = [0u8; 4];
+        let mut buf8 = [0u8; 8];
+
+        rdr.read_exact(&mut buf4)?;
+        let version = u32::from_le_bytes(buf4);
+        if version != DEMO_INDEX_VERSION {
+            return Err(DemoIndexError::UnsupportedVersion { got: version });
+        }
+
+        rdr.read_exact(&mut buf8)?;
+        let stream_len = u64::from_le_bytes(buf8);
+
+        rdr.read_exact(&mut buf4)?;
+        let total_ticks = i32::from_le_bytes(buf4);
+
+        rdr.read_exact(&mut buf4)?;
+        let len = u32::from_le_bytes(buf4) as usize;
+
+        // NOTE: len comes from the file; a corrupt one must not be able to request a huge
+        // allocation, the vec grows as entries are actually read.
+        let mut entries = Vec::with_capacity(len.min(4096));
+        for _ in 0..len {
+            rdr.read_exact(&mut buf4)?;
+            let tick = i32::from_le_bytes(buf4);
+            rdr.read_exact(&mut buf8)?;
+            let position = u64::from_le_bytes(buf8);
+            entries.push(DemoIndexEntry { tick, position });
+        }
+
+        Ok(Self {
+            stream_len,
+            total_ticks,
+            entries,
+        })
+    }
+
+    /// path of the sidecar index file for the given demo file (`match.dem` -> `match.dem.hidx`).
+    pub fn sidecar_path(demo_path: impl AsRef<Path>) -> PathBuf {
+        let mut path = demo_path.as_ref().as_os_str().to_owned();
+        path.push(".");
+        path.push(DEMO_INDEX_SIDECAR_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// returns false if the index was built for a stream of different length.
+    pub fn is_fresh<D: DemoStream>(&self, demo_stream: &mut D) -> Result<bool, io::Error> {
+        Ok(self.stream_len == demo_stream.stream_len()?)
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[DemoIndexEntry] {
+        &self.entries
+    }
+
+    #[inline]
+    pub fn total_ticks(&self) -> i32 {
+        self.total_ticks
+    }
+
+    /// finds the last full packet at or before the given tick.
+    pub fn find_full_packet(&self, tick: i32) -> Option<&DemoIndexEntry> {
+        let n = self.entries.partition_point(|entry| entry.tick <= tick);
+        n.checked_sub(1).map(|i| &self.entries[i])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    fn build_index() -> anyhow::Result<DemoIndex> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[])?;
+        wtr.write_tick(1)?;
+        wtr.write_full_packet(2)?;
+        wtr.write_tick(3)?;
+        wtr.write_full_packet(4)?;
+        let mut demo_file = wtr.finish_into_demo_file()?;
+        Ok(DemoIndex::build(&mut demo_file)?)
+    }
+
+    fn write_index(index: &DemoIndex) -> Result<Vec<u8>, io::Error> {
+        let mut buf = Vec::new();
+        index.write_to(&mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let index = build_index()?;
+        assert_eq!(
+            index
+                .entries()
+                .iter()
+                .map(|entry| entry.tick)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(index.total_ticks(), 4);
+
+        let got = DemoIndex::read_from(write_index(&index)?.as_slice())?;
+        assert_eq!(got.stream_len, index.stream_len);
+        assert_eq!(got.total_ticks(), index.total_ticks());
+        assert_eq!(got.entries(), index.entries());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated() -> anyhow::Result<()> {
+        let buf = write_index(&build_index()?)?;
+        for len in [0, 4, 12, buf.len() - 1] {
+            assert!(matches!(
+                DemoIndex::read_from(&buf[..len]),
+                Err(DemoIndexError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+            ));
+        }
+
+        // NOTE: number of entries is way off; must fail with an error rather than attempt to
+        // allocate space for all of them.
+        let mut buf = buf;
+        let len_offset = DEMO_INDEX_MAGIC.len() + 4 + 8 + 4;
+        buf[len_offset..len_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            DemoIndex::read_from(buf.as_slice()),
+            Err(DemoIndexError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_magic() -> anyhow::Result<()> {
+        let mut buf = write_index(&build_index()?)?;
+        buf[0] = b'X';
+        assert!(matches!(
+            DemoIndex::read_from(buf.as_slice()),
+            Err(DemoIndexError::InvalidMagic { got }) if got[0] == b'X'
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! some capture setups append multiple demos to one file / stream. each demo ends with
+//! `CDemoStop` (possibly followed by `CDemoFileInfo`) and the next one starts with its own demo
+//! header. [`find_demos`] locates them; [`DemoSegment`] confines a reader to one of them so that
+//! it can be fed to [`crate::demofile::DemoFile`] (offsets within a demo, for example file info
+//! offset, are relative to its own header).
+//!
+//! ```ignore
+//! let mut file = File::open(path)?;
+//! for span in find_demos(BufReader::new(&mut file))? {
+//!     let segment = DemoSegment::new(BufReader::new(file.try_clone()?), span)?;
+//!     let mut parser = Parser::from_stream_with_visitor(DemoFile::start_reading(segment)?, visitor)?;
+//!     parser.run_to_end()?;
+//! }
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use valveprotos::common::EDemoCommands;
+
+use crate::demofile::{read_demo_header, DemoHeaderError, DEMO_HEADER_ID, DEMO_HEADER_ID_SIZE};
+use crate::varint::{self, ReadVarintError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum FindDemosError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    DemoHeaderError(#[from] DemoHeaderError),
+    #[error(transparent)]
+    ReadVarintError(#[from] ReadVarintError),
+}
+
+/// byte range of a demo within a stream; `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DemoSpan {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl DemoSpan {
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+// NOTE: reads up to header id size bytes and seeks back.
+fn is_at_demo_header<R: Read + Seek>(rdr: &mut R, pos: u64) -> Result<bool, io::Error> {
+    let mut buf = [0u8; DEMO_HEADER_ID_SIZE];
+    let is_at_demo_header = match rdr.read_exact(&mut buf) {
+        Ok(()) => buf == DEMO_HEADER_ID,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err),
+    };
+    rdr.seek(SeekFrom::Start(pos))?;
+    Ok(is_at_demo_header)
+}
+
+/// scans the stream from the beginning (which must be a demo header) and returns spans of all
+/// demos in it. only cmd headers are read, bodies are skipped.
+///
+/// last demo that does not end with `CDemoStop` (for example if it's being recorded) spans to the
+/// end of the stream.
+pub fn find_demos<R: Read + Seek>(mut rdr: R) -> Result<Vec<DemoSpan>, FindDemosError> {
+    const DEM_IS_COMPRESSED: u32 = EDemoCommands::DemIsCompressed as u32;
+    const DEM_STOP: u32 = EDemoCommands::DemStop as u32;
+
+    let stream_len = rdr.seek(SeekFrom::End(0))?;
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < stream_len {
+        rdr.seek(SeekFrom::Start(start))?;
+        read_demo_header(&mut rdr)?;
+
+        let mut pos = rdr.stream_position()?;
+        let mut did_stop = false;
+        let end = loop {
+            if pos >= stream_len {
+                break stream_len;
+            }
+            // NOTE: cmds that follow stop belong to the same demo unless they're a header of the
+            // next one.
+            if did_stop && is_at_demo_header(&mut rdr, pos)? {
+                break pos;
+            }
+
+            let (cmd_raw, _) = varint::read_uvarint32(&mut rdr)?;
+            let (_tick, _) = varint::read_uvarint32(&mut rdr)?;
+            let (body_size, _) = varint::read_uvarint32(&mut rdr)?;
+            pos = rdr.seek(SeekFrom::Current(body_size as i64))?;
+
+            did_stop |= cmd_raw & !DEM_IS_COMPRESSED == DEM_STOP;
+        };
+
+        spans.push(DemoSpan { start, end });
+        start = end;
+    }
+
+    Ok(spans)
+}
+
+/// reader that is confined to a span of the inner reader; positions are relative to the start of
+/// the span.
+#[derive(Debug)]
+pub struct DemoSegment<R> {
+    inner: R,
+    span: DemoSpan,
+    pos: u64,
+}
+
+impl<R: Seek> DemoSegment<R> {
+    pub fn new(mut inner: R, span: DemoSpan) -> Result<Self, io::Error> {
+        inner.seek(SeekFrom::Start(span.start))?;
+        Ok(Self {
+            inner,
+            span,
+            pos: 0,
+        })
+    }
+}
+
+impl<R> DemoSegment<R> {
+    #[inline]
+    pub fn span(&self) -> DemoSpan {
+        self.span
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DemoSegment<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.span.len().saturating_sub(self.pos);
+        let n = (buf.len() as u64).min(remaining) as usize;
+        let n = self.inner.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for DemoSegment<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.span.len().checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        let Some(target) = target else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        };
+        self.inner.seek(SeekFrom::Start(self.span.start + target))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn push_demo(buf: &mut Vec<u8>, cmds: &[(EDemoCommands, &[u8])]) -> Result<(), io::Error> {
+        buf.extend_from_slice(&DEMO_HEADER_ID);
+        buf.extend_from_slice(&[0; 8]);
+        for (cmd, body) in cmds {
+            varint::write_uvarint32(&mut *buf, *cmd as u32)?;
+            varint::write_uvarint32(&mut *buf, 0)?;
+            varint::write_uvarint32(&mut *buf, body.len() as u32)?;
+            buf.extend_from_slice(body);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_demos() -> Result<(), FindDemosError> {
+        let mut buf = Vec::new();
+        push_demo(
+            &mut buf,
+            &[
+                (EDemoCommands::DemSyncTick, &[]),
+                (EDemoCommands::DemStop, &[]),
+                (EDemoCommands::DemFileInfo, &[1, 2, 3]),
+            ],
+        )?;
+        let first_len = buf.len() as u64;
+        push_demo(&mut buf, &[(EDemoCommands::DemSyncTick, &[4, 5])])?;
+
+        let spans = find_demos(Cursor::new(&buf))?;
+        assert_eq!(
+            spans,
+            vec![
+                DemoSpan {
+                    start: 0,
+                    end: first_len
+                },
+                DemoSpan {
+                    start: first_len,
+                    end: buf.len() as u64
+                },
+            ]
+        );
+
+        let mut segment = DemoSegment::new(Cursor::new(&buf), spans[1])?;
+        let mut data = Vec::new();
+        segment.read_to_end(&mut data)?;
+        assert_eq!(data, &buf[first_len as usize..]);
+        assert_eq!(segment.seek(SeekFrom::End(0))?, spans[1].len());
+
+        Ok(())
+    }
+}
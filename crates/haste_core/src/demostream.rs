@@ -1,10 +1,14 @@
 use std::io::{self, SeekFrom};
 
-use dungers::varint;
 use valveprotos::common::{
-    CDemoClassInfo, CDemoFullPacket, CDemoPacket, CDemoSendTables, EDemoCommands,
+    CDemoAnimationData, CDemoAnimationHeader, CDemoClassInfo, CDemoFullPacket, CDemoPacket,
+    CDemoSendTables, CDemoStringTables, EDemoCommands,
 };
 
+use crate::protobackend::ProtoMessage;
+use crate::protoscan::{self, ProtoScanError, CMD_PACKET_DATA_FIELD};
+use crate::varint;
+
 #[derive(Debug, Clone)]
 pub struct CmdHeader {
     pub cmd: EDemoCommands,
@@ -39,7 +43,9 @@ pub enum ReadCmdError {
 #[derive(thiserror::Error, Debug)]
 pub enum DecodeCmdError {
     #[error(transparent)]
-    DecodeProtobufError(#[from] prost::DecodeError),
+    DecodeProtobufError(#[from] crate::protobackend::DecodeError),
+    #[error(transparent)]
+    ProtoScanError(#[from] ProtoScanError),
 }
 
 // TODO: is there a way to restrict (idk if this is a correct word) DemoStream trait so that it'll
@@ -73,6 +79,13 @@ pub trait DemoStream {
         Ok(self.stream_position()? == self.stream_len()?)
     }
 
+    /// true if the stream is at its real end; nothing will follow. streams that grow (see
+    /// [`crate::demobuffer::DemoBuffer`]) are at eof whenever they run out of data, but are not
+    /// finished until the rest of the demo arrives.
+    fn is_finished(&mut self) -> Result<bool, io::Error> {
+        self.is_at_eof()
+    }
+
     // cmd header
     // ----
 
@@ -98,7 +111,7 @@ pub trait DemoStream {
     // SyncTick (empty msg)
     fn decode_cmd_send_tables(data: &[u8]) -> Result<CDemoSendTables, DecodeCmdError>;
     fn decode_cmd_class_info(data: &[u8]) -> Result<CDemoClassInfo, DecodeCmdError>;
-    // fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError>;
+    fn decode_cmd_string_tables(data: &[u8]) -> Result<CDemoStringTables, DecodeCmdError>;
     fn decode_cmd_packet(data: &[u8]) -> Result<CDemoPacket, DecodeCmdError>;
     // SignonPacket (same as Packet)
     // fn decode_cmd_console_cmd(data: &[u8]) -> Result<CDemoConsoleCmd, DecodeCmdError>;
@@ -108,8 +121,26 @@ pub trait DemoStream {
     fn decode_cmd_full_packet(data: &[u8]) -> Result<CDemoFullPacket, DecodeCmdError>;
     // fn decode_cmd_save_game(data: &[u8]) -> Result<CDemoSaveGame, DecodeCmdError>;
     // fn decode_cmd_spawn_groups(data: &[u8]) -> Result<CDemoSpawnGroups, DecodeCmdError>;
-    // fn decode_cmd_animation_data(data: &[u8]) -> Result<CDemoAnimationData, DecodeCmdError>;
-    // fn decode_cmd_animation_header(data: &[u8]) -> Result<CDemoAnimationHeader, DecodeCmdError>;
+
+    /// data of packet (and signon packet) cmd; same as [`CDemoPacket::data`] of
+    /// [`Self::decode_cmd_packet`], but without decoding (and copying) it. empty if cmd has no
+    /// data.
+    #[inline(always)]
+    fn packet_data(data: &[u8]) -> Result<&[u8], DecodeCmdError> {
+        Ok(protoscan::find_len_field(data, CMD_PACKET_DATA_FIELD)?.unwrap_or_default())
+    }
+
+    // NOTE: animation cmds are only present in newer deadlock demos; thus default impls.
+
+    #[inline(always)]
+    fn decode_cmd_animation_data(data: &[u8]) -> Result<CDemoAnimationData, DecodeCmdError> {
+        CDemoAnimationData::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
+
+    #[inline(always)]
+    fn decode_cmd_animation_header(data: &[u8]) -> Result<CDemoAnimationHeader, DecodeCmdError> {
+        CDemoAnimationHeader::decode_message(data).map_err(DecodeCmdError::DecodeProtobufError)
+    }
     // Max
     // IsCompressed (flag)
 
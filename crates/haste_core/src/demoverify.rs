@@ -0,0 +1,132 @@
+use std::fmt;
+use std::io::{self, SeekFrom};
+
+use valveprotos::common::EDemoCommands;
+
+use crate::demofile::DEMO_RECORD_BUFFER_SIZE;
+use crate::demostream::{DemoStream, ReadCmdError, ReadCmdHeaderError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyReport {
+    /// all cmds could be read and decoded; DemStop cmd was reached.
+    Ok { last_tick: i32 },
+    /// stream ended abruptly; `tick` is the tick of the last complete cmd.
+    Truncated { tick: i32 },
+    /// cmd at the given position can't be read or decoded.
+    CorruptCmd {
+        position: u64,
+        tick: i32,
+        reason: String,
+    },
+}
+
+impl VerifyReport {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok { .. })
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok { last_tick } => write!(f, "ok (last tick {last_tick})"),
+            Self::Truncated { tick } => write!(f, "truncated at tick {tick}"),
+            Self::CorruptCmd {
+                position,
+                tick,
+                reason,
+            } => write!(
+                f,
+                "corrupt command at offset {position} (tick {tick}): {reason}"
+            ),
+        }
+    }
+}
+
+#[inline]
+fn is_unexpected_eof(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::UnexpectedEof
+}
+
+/// reads (and decompresses) every cmd of the stream and decodes cmds that the parser depends on.
+/// entities and packet messages are not decoded. stream position is restored afterwards.
+///
+/// io errors that are not caused by unexpected eof are returned as errors; everything else is
+/// reported.
+pub fn verify<D: DemoStream>(demo_stream: &mut D) -> Result<VerifyReport, io::Error> {
+    let backup = demo_stream.stream_position()?;
+    let stream_len = demo_stream.stream_len()?;
+    demo_stream.seek(SeekFrom::Start(demo_stream.start_position()))?;
+
+    let report = verify_inner(demo_stream, stream_len);
+
+    demo_stream.seek(SeekFrom::Start(backup))?;
+    report
+}
+
+fn verify_inner<D: DemoStream>(
+    demo_stream: &mut D,
+    stream_len: u64,
+) -> Result<VerifyReport, io::Error> {
+    let mut tick: i32 = -1;
+    loop {
+        let position = demo_stream.stream_position()?;
+        let corrupt = |tick: i32, reason: String| VerifyReport::CorruptCmd {
+            position,
+            tick,
+            reason,
+        };
+
+        let cmd_header = match demo_stream.read_cmd_header() {
+            Ok(cmd_header) => cmd_header,
+            Err(ReadCmdHeaderError::IoError(err)) if is_unexpected_eof(&err) => {
+                return Ok(VerifyReport::Truncated { tick });
+            }
+            Err(ReadCmdHeaderError::IoError(err)) => return Err(err),
+            Err(_) if demo_stream.is_at_eof().unwrap_or_default() => {
+                return Ok(VerifyReport::Truncated { tick });
+            }
+            Err(err) => return Ok(corrupt(tick, err.to_string())),
+        };
+
+        if cmd_header.body_size as usize > DEMO_RECORD_BUFFER_SIZE {
+            return Ok(corrupt(
+                cmd_header.tick,
+                format!("body size {} is too large", cmd_header.body_size),
+            ));
+        }
+        if position + cmd_header.size as u64 + cmd_header.body_size as u64 > stream_len {
+            return Ok(VerifyReport::Truncated { tick });
+        }
+
+        let cmd_body = match demo_stream.read_cmd(&cmd_header) {
+            Ok(cmd_body) => cmd_body,
+            Err(ReadCmdError::IoError(err)) if is_unexpected_eof(&err) => {
+                return Ok(VerifyReport::Truncated { tick });
+            }
+            Err(ReadCmdError::IoError(err)) => return Err(err),
+            Err(err) => return Ok(corrupt(cmd_header.tick, err.to_string())),
+        };
+
+        let decode_result = match cmd_header.cmd {
+            EDemoCommands::DemPacket | EDemoCommands::DemSignonPacket => {
+                D::decode_cmd_packet(cmd_body).map(|_| ())
+            }
+            EDemoCommands::DemSendTables => D::decode_cmd_send_tables(cmd_body).map(|_| ()),
+            EDemoCommands::DemClassInfo => D::decode_cmd_class_info(cmd_body).map(|_| ()),
+            EDemoCommands::DemStringTables => D::decode_cmd_string_tables(cmd_body).map(|_| ()),
+            EDemoCommands::DemFullPacket => D::decode_cmd_full_packet(cmd_body).map(|_| ()),
+            _ => Ok(()),
+        };
+        if let Err(err) = decode_result {
+            return Ok(corrupt(cmd_header.tick, err.to_string()));
+        }
+
+        tick = tick.max(cmd_header.tick);
+
+        if cmd_header.cmd == EDemoCommands::DemStop {
+            return Ok(VerifyReport::Ok { last_tick: tick });
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use valveprotos::common::{CDemoFileInfo, EDemoCommands};
+
+use crate::demofile::{DemoFile, DEMO_HEADER_ID, DEMO_HEADER_ID_SIZE};
+use crate::demostream::{DecodeCmdError, DemoStream, ReadCmdError, ReadCmdHeaderError};
+use crate::protobackend::ProtoMessage;
+use crate::varint;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WriteCmdError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    CompressError(#[from] snap::Error),
+}
+
+/// writes demo files; counterpart of [`DemoFile`].
+///
+/// - cmd headers are written exactly as [`DemoFile`] expects to read them.
+/// - demo header's fileinfo offset is patched in [`DemoWriter::finish`]; spawngroups offset is
+/// left zeroed.
+pub struct DemoWriter<W: Write + Seek> {
+    wtr: W,
+    buf: Vec<u8>,
+    encoder: snap::raw::Encoder,
+    start_position: u64,
+    // NOTE: relying on Seek::stream_position is not great because for example BufWriter flushes
+    // its buffer when seeking.
+    position: u64,
+    last_tick: i32,
+}
+
+impl<W: Write + Seek> DemoWriter<W> {
+    /// writes demo header and returns a new [`DemoWriter`] instance.
+    ///
+    /// # performance note
+    ///
+    /// for optimal performance make sure to provide a writer that implements buffering (for
+    /// example [`std::io::BufWriter`]).
+    pub fn start_writing(mut wtr: W) -> Result<Self, io::Error> {
+        let start_position = wtr.stream_position()?;
+
+        wtr.write_all(&DEMO_HEADER_ID)?;
+        // NOTE: fileinfo_offset and spawngroups_offset are not known yet.
+        wtr.write_all(&0i32.to_le_bytes())?;
+        wtr.write_all(&0i32.to_le_bytes())?;
+
+        Ok(Self {
+            wtr,
+            buf: Vec::new(),
+            encoder: snap::raw::Encoder::new(),
+            start_position,
+            position: start_position + (DEMO_HEADER_ID_SIZE + 2 * size_of::<i32>()) as u64,
+            last_tick: -1,
+        })
+    }
+
+    /// writes cmd header followed by the body; if `compress` is true the body will be snappy
+    /// compressed. returns position of the cmd header within the stream.
+    pub fn write_cmd(
+        &mut self,
+        cmd: EDemoCommands,
+        tick: i32,
+        body: &[u8],
+        compress: bool,
+    ) -> Result<u64, WriteCmdError> {
+        let position = self.position;
+
+        let body = if compress {
+            let max_compress_len = snap::raw::max_compress_len(body.len());
+            if self.buf.len() < max_compress_len {
+                self.buf.resize(max_compress_len, 0);
+            }
+            let n = self.encoder.compress(body, &mut self.buf)?;
+            &self.buf[..n]
+        } else {
+            body
+        };
+
+        let mut cmd_raw = cmd as u32;
+        if compress {
+            cmd_raw |= EDemoCommands::DemIsCompressed as u32;
+        }
+
+        let mut n = varint::write_uvarint32(&mut self.wtr, cmd_raw)?;
+        // NOTE: see DemoFile::read_cmd_header for why casting i32 to u32 is okay.
+        n += varint::write_uvarint32(&mut self.wtr, tick as u32)?;
+        n += varint::write_uvarint32(&mut self.wtr, body.len() as u32)?;
+        self.wtr.write_all(body)?;
+
+        self.position += (n + body.len()) as u64;
+        self.last_tick = tick;
+        Ok(position)
+    }
+
+    /// encodes the message and writes it as a cmd body; see [`DemoWriter::write_cmd`].
+    pub fn write_cmd_msg<M: ProtoMessage>(
+        &mut self,
+        cmd: EDemoCommands,
+        tick: i32,
+        msg: &M,
+        compress: bool,
+    ) -> Result<u64, WriteCmdError> {
+        self.write_cmd(cmd, tick, &msg.encode_message_to_vec(), compress)
+    }
+
+    /// writes DemStop and DemFileInfo cmds, patches fileinfo offset in demo header and returns the
+    /// underlying writer.
+    pub fn finish(mut self, file_info: &CDemoFileInfo) -> Result<W, WriteCmdError> {
+        let tick = self.last_tick;
+        self.write_cmd(EDemoCommands::DemStop, tick, &[], false)?;
+        let fileinfo_offset =
+            self.write_cmd_msg(EDemoCommands::DemFileInfo, tick, file_info, false)?;
+
+        let end = self.position;
+        self.wtr.seek(SeekFrom::Start(
+            self.start_position + DEMO_HEADER_ID_SIZE as u64,
+        ))?;
+        self.wtr
+            .write_all(&((fileinfo_offset - self.start_position) as i32).to_le_bytes())?;
+        self.wtr.seek(SeekFrom::Start(end))?;
+        self.wtr.flush()?;
+
+        Ok(self.wtr)
+    }
+}
+
+// trim
+// ----
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrimError {
+    #[error("invalid tick range (from {from_tick}; to {to_tick})")]
+    InvalidTickRange { from_tick: i32, to_tick: i32 },
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    ReadCmdHeaderError(#[from] ReadCmdHeaderError),
+    #[error(transparent)]
+    ReadCmdError(#[from] ReadCmdError),
+    #[error(transparent)]
+    DecodeCmdError(#[from] DecodeCmdError),
+    #[error(transparent)]
+    WriteCmdError(#[from] WriteCmdError),
+    // NOTE: DemoFile::file_info returns anyhow error
+    #[error(transparent)]
+    FileInfoError(#[from] anyhow::Error),
+}
+
+/// writes a demo that covers only the given tick range (both ends are inclusive).
+///
+/// the output consists of:
+/// - initialization cmds (everything up to and including the first DemSyncTick);
+/// - state of the closest full packet at or before `from_tick`. it is written as standalone
+/// DemStringTables and DemPacket cmds because full packets are only handled when seeking;
+/// - everything after the full packet up to and including `to_tick`.
+///
+/// which means that the output may start a little earlier than `from_tick` (by at most full
+/// packet interval).
+pub fn trim<R: Read + Seek, W: Write + Seek>(
+    demo_file: &mut DemoFile<R>,
+    wtr: W,
+    from_tick: i32,
+    to_tick: i32,
+) -> Result<W, TrimError> {
+    if from_tick > to_tick {
+        return Err(TrimError::InvalidTickRange { from_tick, to_tick });
+    }
+
+    let mut file_info = demo_file.file_info()?.clone();
+    demo_file.seek(SeekFrom::Start(demo_file.start_position()))?;
+
+    let mut writer = DemoWriter::start_writing(wtr)?;
+
+    // initialization cmds
+    loop {
+        let cmd_header = demo_file.read_cmd_header()?;
+        let cmd_body = demo_file.read_cmd(&cmd_header)?;
+        writer.write_cmd(
+            cmd_header.cmd,
+            cmd_header.tick,
+            cmd_body,
+            cmd_header.body_compressed,
+        )?;
+        if cmd_header.cmd == EDemoCommands::DemSyncTick {
+            break;
+        }
+    }
+    let init_end = demo_file.stream_position()?;
+
+    // find the closest full packet
+    let mut full_packet_position: Option<u64> = None;
+    loop {
+        let cmd_header = match demo_file.read_cmd_header() {
+            Ok(cmd_header) => cmd_header,
+            Err(_) if demo_file.is_at_eof().unwrap_or_default() => break,
+            Err(err) => return Err(err.into()),
+        };
+        if cmd_header.tick > from_tick || cmd_header.cmd == EDemoCommands::DemStop {
+            break;
+        }
+        if cmd_header.cmd == EDemoCommands::DemFullPacket {
+            full_packet_position = Some(demo_file.stream_position()? - cmd_header.size as u64);
+        }
+        demo_file.skip_cmd(&cmd_header)?;
+    }
+
+    let mut first_tick = 0;
+    if let Some(full_packet_position) = full_packet_position {
+        demo_file.seek(SeekFrom::Start(full_packet_position))?;
+        let cmd_header = demo_file.read_cmd_header()?;
+        let cmd = DemoFile::<R>::decode_cmd_full_packet(demo_file.read_cmd(&cmd_header)?)?;
+
+        first_tick = cmd_header.tick;
+        if let Some(string_tables) = cmd.string_table {
+            writer.write_cmd_msg(
+                EDemoCommands::DemStringTables,
+                first_tick,
+                &string_tables,
+                true,
+            )?;
+        }
+        if let Some(packet) = cmd.packet {
+            writer.write_cmd_msg(EDemoCommands::DemPacket, first_tick, &packet, true)?;
+        }
+    } else {
+        // NOTE: there's no full packet before from_tick; nothing can be dropped.
+        demo_file.seek(SeekFrom::Start(init_end))?;
+    }
+
+    // the rest
+    let mut last_tick = first_tick;
+    loop {
+        let cmd_header = match demo_file.read_cmd_header() {
+            Ok(cmd_header) => cmd_header,
+            Err(_) if demo_file.is_at_eof().unwrap_or_default() => break,
+            Err(err) => return Err(err.into()),
+        };
+        if cmd_header.tick > to_tick || cmd_header.cmd == EDemoCommands::DemStop {
+            break;
+        }
+        // NOTE: DemoWriter::finish writes its own
+        if cmd_header.cmd == EDemoCommands::DemFileInfo {
+            demo_file.skip_cmd(&cmd_header)?;
+            continue;
+        }
+
+        let cmd_body = demo_file.read_cmd(&cmd_header)?;
+        writer.write_cmd(
+            cmd_header.cmd,
+            cmd_header.tick,
+            cmd_body,
+            cmd_header.body_compressed,
+        )?;
+        last_tick = cmd_header.tick;
+    }
+
+    let playback_ticks = last_tick - first_tick;
+    if file_info.playback_ticks() > 0 {
+        let ratio = playback_ticks as f32 / file_info.playback_ticks() as f32;
+        file_info.playback_time = Some(file_info.playback_time() * ratio);
+        file_info.playback_frames = Some((file_info.playback_frames() as f32 * ratio) as i32);
+    }
+    file_info.playback_ticks = Some(playback_ticks);
+
+    writer.finish(&file_info).map_err(TrimError::from)
+}
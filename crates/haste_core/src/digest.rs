@@ -0,0 +1,301 @@
+//! canonical, deterministic digests of replays: per-tick entity state hashes and event counts.
+//! meant to be compared against checked-in goldens to catch regressions in decoders.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use anyhow::Result;
+use valveprotos::common::{CMsgSource1LegacyGameEvent, EBaseGameEvents};
+
+use crate::demostream::DemoStream;
+use crate::entities::{Entity, EntityContainer};
+use crate::fieldvalue::FieldValue;
+use crate::fxhash;
+use crate::parser::{Context, Parser, Visitor};
+use crate::protobackend::ProtoMessage;
+
+const DIGEST_HEADER: &str = "# haste digest v1";
+
+#[derive(thiserror::Error, Debug)]
+pub enum DigestReadError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("invalid digest header")]
+    InvalidHeader,
+    #[error("invalid digest line {line}")]
+    InvalidLine { line: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickDigest {
+    pub tick: i32,
+    pub entities: usize,
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayDigest {
+    pub ticks: Vec<TickDigest>,
+    /// number of game events by name.
+    pub game_events: BTreeMap<String, usize>,
+    /// number of packet messages by type.
+    pub packets: BTreeMap<u32, usize>,
+}
+
+fn hash_f32s(hash: u64, values: &[f32]) -> u64 {
+    values.iter().fold(hash, |hash, v| {
+        fxhash::add_u64_to_hash(hash, v.to_bits() as u64)
+    })
+}
+
+/// variant of the value is mixed into the hash, so that for example `I64(1)` and `U64(1)` do not
+/// collide.
+pub fn hash_field_value(hash: u64, value: &FieldValue) -> u64 {
+    use fxhash::add_u64_to_hash;
+    match value {
+        FieldValue::I64(v) => add_u64_to_hash(add_u64_to_hash(hash, 0), *v as u64),
+        FieldValue::U64(v) => add_u64_to_hash(add_u64_to_hash(hash, 1), *v),
+        FieldValue::F32(v) => add_u64_to_hash(add_u64_to_hash(hash, 2), v.to_bits() as u64),
+        FieldValue::Bool(v) => add_u64_to_hash(add_u64_to_hash(hash, 3), *v as u64),
+        FieldValue::Vector2(v) => hash_f32s(add_u64_to_hash(hash, 4), v),
+        FieldValue::Vector3(v) => hash_f32s(add_u64_to_hash(hash, 5), v),
+        FieldValue::Vector4(v) => hash_f32s(add_u64_to_hash(hash, 6), v),
+        FieldValue::QAngle(v) => hash_f32s(add_u64_to_hash(hash, 7), v),
+        FieldValue::String(v) => {
+            add_u64_to_hash(add_u64_to_hash(hash, 8), fxhash::hash_bytes(v.as_bytes()))
+        }
+        FieldValue::QAnglePitchYaw(v) => hash_f32s(add_u64_to_hash(hash, 9), v),
+    }
+}
+
+/// hash of entity's serializer and all of its fields; does not depend on iteration order of
+/// fields.
+pub fn hash_entity(entity: &Entity) -> u64 {
+    let mut fields: Vec<(&u64, &FieldValue)> = entity.iter().collect();
+    fields.sort_unstable_by_key(|(key, _)| **key);
+
+    let hash = fxhash::add_u64_to_hash(
+        entity.index() as u64,
+        entity.serializer().serializer_name.hash,
+    );
+    fields.into_iter().fold(hash, |hash, (key, value)| {
+        hash_field_value(fxhash::add_u64_to_hash(hash, *key), value)
+    })
+}
+
+/// hash of all entities; does not depend on iteration order of entities.
+pub fn hash_entities(entities: &EntityContainer) -> u64 {
+    let mut entities: Vec<(&i32, &Entity)> = entities.iter().collect();
+    entities.sort_unstable_by_key(|(index, _)| **index);
+    entities.into_iter().fold(0, |hash, (_, entity)| {
+        fxhash::add_u64_to_hash(hash, hash_entity(entity))
+    })
+}
+
+/// counts game events and packet messages.
+#[derive(Default)]
+pub struct DigestVisitor {
+    game_events: BTreeMap<String, usize>,
+    packets: BTreeMap<u32, usize>,
+}
+
+impl Visitor for DigestVisitor {
+    fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        *self.packets.entry(packet_type).or_default() += 1;
+
+        if packet_type == EBaseGameEvents::GeSource1LegacyGameEvent as u32 {
+            let Some(game_event_list) = ctx.game_event_list() else {
+                return Ok(());
+            };
+            let msg = CMsgSource1LegacyGameEvent::decode_message(data)?;
+            if let Some(event) = game_event_list.decode(&msg) {
+                *self
+                    .game_events
+                    .entry(event.name().to_string())
+                    .or_default() += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ReplayDigest {
+    /// parses the whole demo tick by tick; entity state is hashed after each tick.
+    pub fn compute<D: DemoStream>(demo_stream: D) -> Result<Self> {
+        let mut parser = Parser::from_stream_with_visitor(demo_stream, DigestVisitor::default())?;
+
+        let mut ticks = Vec::new();
+        loop {
+            let more = parser.run_to_next_tick()?;
+            let ctx = parser.context();
+            let (entities, hash) = ctx.entities().map_or((0, 0), |entities| {
+                (entities.iter().count(), hash_entities(entities))
+            });
+            ticks.push(TickDigest {
+                tick: ctx.tick(),
+                entities,
+                hash,
+            });
+            if !more {
+                break;
+            }
+        }
+
+        let visitor = parser.into_visitor();
+        Ok(Self {
+            ticks,
+            game_events: visitor.game_events,
+            packets: visitor.packets,
+        })
+    }
+
+    /// returns a human readable description of the first difference, if any.
+    pub fn first_difference(&self, other: &Self) -> Option<String> {
+        for (l, r) in self.ticks.iter().zip(other.ticks.iter()) {
+            if l != r {
+                return Some(format!("tick {:?} != {:?}", l, r));
+            }
+        }
+        if self.ticks.len() != other.ticks.len() {
+            return Some(format!(
+                "number of ticks {} != {}",
+                self.ticks.len(),
+                other.ticks.len()
+            ));
+        }
+        if self.game_events != other.game_events {
+            return Some(format!(
+                "game events {:?} != {:?}",
+                self.game_events, other.game_events
+            ));
+        }
+        if self.packets != other.packets {
+            return Some(format!("packets {:?} != {:?}", self.packets, other.packets));
+        }
+        None
+    }
+
+    // text format, one entry per line:
+    // - game_event <name> <count>
+    // - packet <type> <count>
+    // - tick <tick> <number of entities> <hash>
+
+    pub fn write_to<W: Write>(&self, mut wtr: W) -> Result<(), io::Error> {
+        writeln!(wtr, "{DIGEST_HEADER}")?;
+        for (name, count) in self.game_events.iter() {
+            writeln!(wtr, "game_event {name} {count}")?;
+        }
+        for (packet_type, count) in self.packets.iter() {
+            writeln!(wtr, "packet {packet_type} {count}")?;
+        }
+        for tick in self.ticks.iter() {
+            writeln!(
+                wtr,
+                "tick {} {} {:016x}",
+                tick.tick, tick.entities, tick.hash
+            )?;
+        }
+        wtr.flush()
+    }
+
+    pub fn read_from<R: BufRead>(rdr: R) -> Result<Self, DigestReadError> {
+        let mut lines = rdr.lines();
+        let header = lines.next().transpose()?;
+        if header.as_deref().map(str::trim_end) != Some(DIGEST_HEADER) {
+            return Err(DigestReadError::InvalidHeader);
+        }
+
+        let mut digest = Self::default();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let invalid_line = || DigestReadError::InvalidLine { line: i + 2 };
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                [] => {}
+                ["game_event", name, count] => {
+                    let count = count.parse().map_err(|_| invalid_line())?;
+                    digest.game_events.insert(name.to_string(), count);
+                }
+                ["packet", packet_type, count] => {
+                    let packet_type = packet_type.parse().map_err(|_| invalid_line())?;
+                    let count = count.parse().map_err(|_| invalid_line())?;
+                    digest.packets.insert(packet_type, count);
+                }
+                ["tick", tick, entities, hash] => digest.ticks.push(TickDigest {
+                    tick: tick.parse().map_err(|_| invalid_line())?,
+                    entities: entities.parse().map_err(|_| invalid_line())?,
+                    hash: u64::from_str_radix(hash, 16).map_err(|_| invalid_line())?,
+                }),
+                _ => return Err(invalid_line()),
+            }
+        }
+        Ok(digest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter, Cursor};
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::demofile::DemoFile;
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    // NOTE: unlike real demos (see tests/golden.rs) this one is built from code, thus it is always
+    // there to be digested; its golden is checked in.
+    const SYNTHETIC_GOLDEN: &str = "tests/fixtures/synthetic.digest";
+
+    fn synthetic_demo() -> Result<DemoFile<Cursor<Vec<u8>>>> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.create(2, "CToyEntity", &[])?;
+        wtr.write_tick(1)?;
+        wtr.update(
+            1,
+            &[
+                ("m_iHealth", FieldValue::I64(90)),
+                ("m_bAlive", FieldValue::Bool(true)),
+            ],
+        )?;
+        wtr.write_tick(2)?;
+        wtr.delete(2)?;
+        wtr.write_tick(3)?;
+        Ok(wtr.finish_into_demo_file()?)
+    }
+
+    #[test]
+    fn test_synthetic_golden() -> Result<()> {
+        let digest = ReplayDigest::compute(synthetic_demo()?)?;
+
+        // NOTE: to accept changes run with UPDATE_GOLDENS=1, same as tests/golden.rs.
+        let golden_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SYNTHETIC_GOLDEN);
+        if env::var_os("UPDATE_GOLDENS").is_some() {
+            digest.write_to(BufWriter::new(File::create(&golden_path)?))?;
+        }
+
+        let golden = ReplayDigest::read_from(BufReader::new(File::open(&golden_path)?))?;
+        assert_eq!(golden.first_difference(&digest), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_read() -> Result<()> {
+        let digest = ReplayDigest::compute(synthetic_demo()?)?;
+        let mut buf = Vec::new();
+        digest.write_to(&mut buf)?;
+        assert_eq!(ReplayDigest::read_from(buf.as_slice())?, digest);
+
+        assert!(matches!(
+            ReplayDigest::read_from(b"# not a digest\n".as_slice()),
+            Err(DigestReadError::InvalidHeader)
+        ));
+        Ok(())
+    }
+}
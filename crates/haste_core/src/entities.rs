@@ -3,19 +3,45 @@ use std::hash::BuildHasherDefault;
 use std::rc::Rc;
 
 use hashbrown::hash_map::Entry;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use nohash::NoHashHasher;
 
-use crate::bitreader::{BitReader, BitReaderOverflowError};
+use crate::bitreader::{BitReader, BitReaderOverflowError, CheckedBitReader};
 use crate::entityclasses::EntityClasses;
+use crate::entityquery::EntityQuery;
 use crate::fielddecoder::FieldDecodeContext;
-use crate::fieldpath::{self, FieldPath};
+use crate::fieldpath::{self, FieldPath, ReadFieldPathsError};
 use crate::fieldvalue::{FieldValue, FieldValueConversionError};
 use crate::flattenedserializers::{
     FlattenedSerializer, FlattenedSerializerContainer, FlattenedSerializerField,
 };
 use crate::fxhash;
-use crate::instancebaseline::InstanceBaseline;
+use crate::game::EngineConstants;
+use crate::instancebaseline::{InstanceBaseline, InstanceBaselineError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseEntityError {
+    #[error(transparent)]
+    BitReaderOverflowError(#[from] BitReaderOverflowError),
+    #[error(transparent)]
+    ReadFieldPathsError(#[from] ReadFieldPathsError),
+    #[error("field path {field_path} does not exist in serializer of the entity")]
+    InvalidFieldPath { field_path: FieldPath },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum HandleCreateError {
+    #[error(transparent)]
+    BitReaderOverflowError(#[from] BitReaderOverflowError),
+    #[error(transparent)]
+    ParseEntityError(#[from] ParseEntityError),
+    #[error(transparent)]
+    InstanceBaselineError(#[from] InstanceBaselineError),
+    #[error("unknown class id {class_id}")]
+    UnknownClassId { class_id: i32 },
+    #[error("no serializer for class id {class_id}")]
+    MissingSerializer { class_id: i32 },
+}
 
 #[derive(thiserror::Error, Debug)]
 pub enum GetValueError {
@@ -25,39 +51,19 @@ pub enum GetValueError {
     FieldValueConversionError(#[from] FieldValueConversionError),
 }
 
-// public/const.h (adjusted)
-
-const MAX_EDICT_BITS: u32 = 14;
-const MAX_EDICTS: u32 = 1 << MAX_EDICT_BITS;
-
-const NUM_ENT_ENTRY_BITS: u32 = MAX_EDICT_BITS + 1;
-const NUM_SERIAL_NUM_BITS: u32 = 32 - NUM_ENT_ENTRY_BITS;
-
-const NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS: u32 = 10;
-const NUM_NETWORKED_EHANDLE_BITS: u32 = MAX_EDICT_BITS + NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS;
-const INVALID_NETWORKED_EHANDLE_VALUE: u32 = (1 << NUM_NETWORKED_EHANDLE_BITS) - 1;
+// NOTE: ehandle layout differs between games; see [`EngineConstants`]. free functions below use
+// dota 2 / deadlock layout.
 
+#[inline]
 pub fn is_ehandle_valid(handle: u32) -> bool {
-    handle != INVALID_NETWORKED_EHANDLE_VALUE
+    EngineConstants::DOTA2.is_ehandle_valid(handle)
 }
 
-// game/client/recvproxy.cpp
-// RecvProxy_IntToEHandle
-// int iEntity = pData->m_Value.m_Int & ((1 << MAX_EDICT_BITS) - 1);
-// int iSerialNum = pData->m_Value.m_Int >> MAX_EDICT_BITS;
-
+#[inline]
 pub fn ehandle_to_index(handle: u32) -> i32 {
-    (handle & ((1 << MAX_EDICT_BITS) - 1)) as i32
+    EngineConstants::DOTA2.ehandle_to_index(handle)
 }
 
-// TODO(blukai): investigate this (from public/basehandle.h):
-// > The low NUM_SERIAL_BITS hold the index. If this value is less than MAX_EDICTS, then the entity is networkable.
-// > The high NUM_SERIAL_NUM_BITS bits are the serial number.
-
-// NOTE(blukai): can converting index and serial to handle (what CBaseHandle::Init (in
-// public/basehandle.h) does) be somehow somewhere useful?:
-// m_Index = iEntry | (iSerialNumber << NUM_SERIAL_NUM_SHIFT_BITS);
-
 // NOTE: rust want that coord_from_cell is never used, but that is because there are no default
 // features that indicate otherwise (both deadlock and dota2 features are not active by default).
 #[allow(dead_code)]
@@ -153,11 +159,34 @@ pub const fn fkey_from_path(path: &[&str]) -> u64 {
     hash
 }
 
+/// counterpart of [`fkey_from_path`] for dot separated paths (for example
+/// `m_vecPlayerData.3.m_iszPlayerName`). numeric parts are treated as array indices and are hashed
+/// the same way as entity parser hashes them. can be called from a const context (see
+/// [`fxhash::hash_dotted_path`]).
+///
+/// NOTE: elements of fixed arrays (for example `m_hAbilities.3`) are keyed by index too; they used
+/// to share a single key (`fkey_from_path(&["m_hAbilities", "m_hAbilities"])`) which held the
+/// element that was written last, that key no longer resolves.
+#[inline]
+pub const fn fkey_from_dotted_path(path: &str) -> u64 {
+    fxhash::hash_dotted_path(path)
+}
+
+/// [`fkey_from_dotted_path`] that is guaranteed to be evaluated at compile time, for example
+/// `fkey!("m_iTeamNum")` or `fkey!("m_vecPlayerData.3.m_iszPlayerName")`.
+#[macro_export]
+macro_rules! fkey {
+    ($path:expr) => {{
+        const KEY: u64 = $crate::entities::fkey_from_dotted_path($path);
+        KEY
+    }};
+}
+
 // csgo srcs:
 // - CL_ParseDeltaHeader in engine/client.cpp.
 // - DetermineUpdateType in engine/client.cpp
 //
-// NOTE: this can be decomposed into valve-style update flags and update type, if needed.
+// NOTE: see DeltaHeader::update_flags for valve-style update flags.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct DeltaHeader(u8);
@@ -197,6 +226,87 @@ impl DeltaHeader {
         br.read_bits(&mut buf, 2);
         Self(buf[0])
     }
+
+    /// checked counterpart of [`Self::from_bit_reader`].
+    #[inline]
+    pub(crate) fn from_checked_bit_reader(
+        br: &mut CheckedBitReader,
+    ) -> Result<Self, BitReaderOverflowError> {
+        Ok(Self(br.read_ubit64(2)? as u8))
+    }
+
+    /// flags that can be derived from the delta header alone. [`UpdateFlags::FORCE_RECREATE`]
+    /// is never set here, it depends on entity state; see [`EntityCreateInfo::update_flags`].
+    #[inline]
+    pub fn update_flags(&self) -> UpdateFlags {
+        match *self {
+            Self::CREATE => UpdateFlags::ENTER_PVS,
+            Self::LEAVE => UpdateFlags::LEAVE_PVS,
+            Self::DELETE => UpdateFlags::LEAVE_PVS | UpdateFlags::DELETE,
+            _ => UpdateFlags::empty(),
+        }
+    }
+}
+
+/// valve-style entity update flags (FHDR_* in engine/client.cpp plus the recreate case of
+/// DetermineUpdateType).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct UpdateFlags(u8);
+
+impl UpdateFlags {
+    /// FHDR_ENTERPVS; entity was created or came back into pvs.
+    pub const ENTER_PVS: Self = Self(1 << 0);
+    /// FHDR_LEAVEPVS.
+    pub const LEAVE_PVS: Self = Self(1 << 1);
+    /// FHDR_DELETE; always comes with [`Self::LEAVE_PVS`].
+    pub const DELETE: Self = Self(1 << 2);
+    /// create arrived for an index that holds an entity which was not deleted: either one that is
+    /// in pvs (forced retransmit), or one that left pvs and has a different serial (slot was
+    /// reused while it was out of pvs). the previous entity was replaced; it matters for lifetime
+    /// accounting, for example when an entity leaves pvs and gets recreated within a tick.
+    pub const FORCE_RECREATE: Self = Self(1 << 3);
+
+    #[inline]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    #[inline]
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOr for UpdateFlags {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl std::ops::BitOrAssign for UpdateFlags {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -206,58 +316,159 @@ struct EntityField {
     value: FieldValue,
 }
 
+type FieldMap = HashMap<u64, EntityField, BuildHasherDefault<NoHashHasher<u64>>>;
+
 // TODO: do not publicly expose Entity's fields
 #[derive(Debug, Clone)]
 pub struct Entity {
     index: i32,
-    fields: HashMap<u64, EntityField, BuildHasherDefault<NoHashHasher<u64>>>,
+    serial: u32,
+    // NOTE: fields that were written to this entity; they shadow fields of the baseline.
+    fields: FieldMap,
+    // NOTE: baseline field state of the class, decoded once and shared by all entities of the
+    // class (copy-on-write; writes go into fields, the shared state is never modified). cloning
+    // the whole baseline for every create used to be a big chunk of create cost.
+    baseline: Option<Rc<FieldMap>>,
     serializer: Rc<FlattenedSerializer>,
 }
 
+// NOTE: field.var_name.hash is a "seed" for field key.
+//
+// SAFETY: components of the path must exist in the serializer; that can only be guaranteed by
+// the fact that they come from a valid demo. see [`resolve_field`] for the checked counterpart.
+#[inline(always)]
+unsafe fn resolve_field_unchecked<'s>(
+    serializer: &'s FlattenedSerializer,
+    fp: &FieldPath,
+) -> (&'s FlattenedSerializerField, u64) {
+    // NOTE: this loop performes much better then the unrolled
+    // version of it, probably because a bunch of ifs cause a bunch
+    // of branch misses and branch missles are disasterous.
+    let mut field = serializer.get_child_unchecked(fp.get_unchecked(0));
+    let mut field_key = field.var_name.hash;
+    for i in 1..=fp.last() {
+        if field.is_dynamic_array() {
+            field = field.get_child_unchecked(0);
+            // NOTE: it's sort of weird to hash index, yup. but it simplifies things
+            // when "user" builds a key that has numbers / it makes it so that there's
+            // no need to check whether part of a key needs to be hashed or not - just
+            // hash all parts.
+            field_key = fxhash::add_u64_to_hash(
+                field_key,
+                fxhash::add_u64_to_hash(0, fp.get_unchecked(i) as u64),
+            );
+        } else if field.is_fixed_array() {
+            // NOTE: elements of fixed arrays share var name of the array; keys of
+            // them are built the same way as keys of dynamic array elements (for
+            // example `m_hAbilities.3`), otherwise all elements would end up under a
+            // single key.
+            field = field.get_child_unchecked(fp.get_unchecked(i));
+            field_key = fxhash::add_u64_to_hash(
+                field_key,
+                fxhash::add_u64_to_hash(0, fp.get_unchecked(i) as u64),
+            );
+        } else {
+            field = field.get_child_unchecked(fp.get_unchecked(i));
+            field_key = fxhash::add_u64_to_hash(field_key, field.var_name.hash);
+        };
+    }
+    (field, field_key)
+}
+
+/// checked counterpart of [`resolve_field_unchecked`]; each component of the path is validated
+/// against children of the serializer. none if the path does not exist.
+#[inline]
+fn resolve_field<'s>(
+    serializer: &'s FlattenedSerializer,
+    fp: &FieldPath,
+) -> Option<(&'s FlattenedSerializerField, u64)> {
+    let mut field = serializer.get_child(fp.get(0)?)?;
+    let mut field_key = field.var_name.hash;
+    for i in 1..=fp.last() {
+        let component = fp.get(i)?;
+        if field.is_dynamic_array() {
+            field = field.get_child(0)?;
+            field_key =
+                fxhash::add_u64_to_hash(field_key, fxhash::add_u64_to_hash(0, component as u64));
+        } else if field.is_fixed_array() {
+            field = field.get_child(component)?;
+            field_key =
+                fxhash::add_u64_to_hash(field_key, fxhash::add_u64_to_hash(0, component as u64));
+        } else {
+            field = field.get_child(component)?;
+            field_key = fxhash::add_u64_to_hash(field_key, field.var_name.hash);
+        };
+    }
+    Some((field, field_key))
+}
+
 impl Entity {
+    /// in safe mode (see `ParserOptions::safe_mode`) field paths are read with
+    /// [`fieldpath::read_field_paths_checked`] and resolved with [`resolve_field`] instead of
+    /// trusting them blindly.
     fn parse(
         &mut self,
         field_decode_ctx: &mut FieldDecodeContext,
         br: &mut BitReader,
         fps: &mut [FieldPath],
-    ) -> Result<(), BitReaderOverflowError> {
-        // eprintln!("-- {:?}", self.serializer.serializer_name);
+        safe_mode: bool,
+    ) -> Result<(), ParseEntityError> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(serializer = ?self.serializer.serializer_name, "parsing entity");
 
         unsafe {
-            let fp_count = fieldpath::read_field_paths(br, fps);
+            let field_paths_start = br.bits_consumed();
+            let fp_count = if safe_mode {
+                fieldpath::read_field_paths_checked(br, fps)?
+            } else {
+                fieldpath::read_field_paths(br, fps)
+            };
+            if let Some(field_bits) = field_decode_ctx.field_bits.as_mut() {
+                field_bits.record_field_paths(br.bits_consumed() - field_paths_start);
+            }
+
             for i in 0..fp_count {
                 let fp = fps.get_unchecked(i);
 
-                // eprint!("{:?} ", &fp.data[..=fp.last]);
-
-                // NOTE: this loop performes much better then the unrolled
-                // version of it, probably because a bunch of ifs cause a bunch
-                // of branch misses and branch missles are disasterous.
-                let mut field = self.serializer.get_child_unchecked(fp.get_unchecked(0));
-                // NOTE: field.var_name.hash is a "seed" for field_key_hash.
-                let mut field_key = field.var_name.hash;
-                for i in 1..=fp.last() {
-                    if field.is_dynamic_array() {
-                        field = field.get_child_unchecked(0);
-                        // NOTE: it's sort of weird to hash index, yup. but it simplifies things
-                        // when "user" builds a key that has numbers / it makes it so that there's
-                        // no need to check whether part of a key needs to be hashed or not - just
-                        // hash all parts.
-                        field_key = fxhash::add_u64_to_hash(
-                            field_key,
-                            fxhash::add_u64_to_hash(0, fp.get_unchecked(i) as u64),
-                        );
-                    } else {
-                        field = field.get_child_unchecked(fp.get_unchecked(i));
-                        field_key = fxhash::add_u64_to_hash(field_key, field.var_name.hash);
+                let (field, field_key) = if safe_mode {
+                    let Some(resolved) = resolve_field(&self.serializer, fp) else {
+                        // NOTE: mark the reader as checked; the error below is what matters.
+                        let _ = br.is_overflowed();
+                        return Err(ParseEntityError::InvalidFieldPath {
+                            field_path: fp.clone(),
+                        });
                     };
-                }
+                    resolved
+                } else {
+                    resolve_field_unchecked(&self.serializer, fp)
+                };
 
-                // eprint!("{:?} {:?} ", field.var_name, field.var_type);
+                #[cfg(feature = "debug-field-keys")]
+                crate::fieldkeys::check_field_key(field_key, &self.serializer, fp);
 
-                let field_value = field.metadata.decoder.decode(field_decode_ctx, br);
+                let field_start = br.bits_consumed();
+                let field_value = {
+                    alloc_scope!(FieldValues);
+                    field.metadata.decoder.decode(field_decode_ctx, br)
+                };
+                if let Some(field_bits) = field_decode_ctx.field_bits.as_mut() {
+                    field_bits.record_field(
+                        self.serializer.serializer_name.hash,
+                        field_key,
+                        #[cfg(feature = "preserve-metadata")]
+                        fp,
+                        br.bits_consumed() - field_start,
+                    );
+                }
 
-                // eprintln!(" -> {:?}", &field_value);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(
+                    field_path = ?&fp.data[..=fp.last],
+                    var_name = ?field.var_name,
+                    var_type = ?field.var_type,
+                    value = ?field_value,
+                    "decoded field"
+                );
 
                 match self.fields.entry(field_key) {
                     Entry::Occupied(mut oe) => {
@@ -280,11 +491,34 @@ impl Entity {
         Ok(())
     }
 
+    #[inline]
+    fn field(&self, key: &u64) -> Option<&EntityField> {
+        self.fields.get(key).or_else(|| {
+            self.baseline
+                .as_ref()
+                .and_then(|baseline| baseline.get(key))
+        })
+    }
+
     // public api
     // ----------
 
     pub fn iter(&self) -> impl Iterator<Item = (&u64, &FieldValue)> {
-        self.fields.iter().map(|(key, ef)| (key, &ef.value))
+        let fields = &self.fields;
+        let baseline_fields = self
+            .baseline
+            .iter()
+            .flat_map(|baseline| baseline.iter())
+            .filter(move |(key, _)| !fields.contains_key(*key));
+        self.fields
+            .iter()
+            .chain(baseline_fields)
+            .map(|(key, ef)| (key, &ef.value))
+    }
+
+    /// get the value of the field with the provided key as is, without any conversions.
+    pub fn get(&self, key: &u64) -> Option<&FieldValue> {
+        self.field(key).map(|ef| &ef.value)
     }
 
     /// get the value of the field with the provided key, and attempt to convert it.
@@ -295,8 +529,7 @@ impl Entity {
     where
         FieldValue: TryInto<T, Error = FieldValueConversionError>,
     {
-        self.fields
-            .get(key)
+        self.field(key)
             .and_then(|entity_field| entity_field.value.clone().try_into().ok())
     }
 
@@ -309,7 +542,7 @@ impl Entity {
     where
         FieldValue: TryInto<T, Error = FieldValueConversionError>,
     {
-        self.fields.get(key).map_or_else(
+        self.field(key).map_or_else(
             || Err(GetValueError::FieldNotExist),
             |entity_field| {
                 entity_field
@@ -323,7 +556,7 @@ impl Entity {
 
     #[cfg(feature = "preserve-metadata")]
     pub fn get_path(&self, key: &u64) -> Option<&FieldPath> {
-        self.fields.get(key).map(|ef| &ef.path)
+        self.field(key).map(|ef| &ef.path)
     }
 
     pub fn serializer(&self) -> &FlattenedSerializer {
@@ -344,13 +577,59 @@ impl Entity {
     pub fn index(&self) -> i32 {
         self.index
     }
+
+    /// serial number from the create; 0 for baseline entities.
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+}
+
+/// where baseline state of a created entity came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaselineSource {
+    /// cloned from the baseline entity that was decoded for an earlier create of the same class.
+    Cached,
+    /// instance baseline was decoded for this create (first create of the class, or instance
+    /// baseline of the class changed since).
+    Parsed,
+}
+
+/// see [`crate::parser::Visitor::on_entity_create`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityCreateInfo {
+    pub class_id: i32,
+    pub network_name_hash: u64,
+    pub serial: u32,
+    pub baseline_source: BaselineSource,
+    /// tick of instance baseline version that was used.
+    pub baseline_tick: i32,
+    /// [`UpdateFlags::ENTER_PVS`], plus [`UpdateFlags::FORCE_RECREATE`] if an entity was
+    /// replaced; replaced entity is reported as deleted right before the create, see
+    /// [`crate::parser::Visitor::on_entity_create`].
+    pub update_flags: UpdateFlags,
+}
+
+/// in pvs entities of one class; see [`EntityContainer::iter_by_class`].
+#[derive(Debug)]
+struct ClassIndexEntry {
+    network_name: Box<str>,
+    indices: HashSet<i32, BuildHasherDefault<NoHashHasher<i32>>>,
 }
 
 #[derive(Debug)]
 pub struct EntityContainer {
     // NOTE: hashbrown hashmap with no hash performs better then Vec.
     entities: HashMap<i32, Entity, BuildHasherDefault<NoHashHasher<i32>>>,
-    baseline_entities: HashMap<i32, Entity, BuildHasherDefault<NoHashHasher<i32>>>,
+    // NOTE: baseline entities are keyed by class id; values are tagged with tick of the instance
+    // baseline version that they were decoded from.
+    baseline_entities: HashMap<i32, (i32, Entity), BuildHasherDefault<NoHashHasher<i32>>>,
+    // NOTE: entities that left pvs (but were not deleted) with their last state; they are moved
+    // back into `entities` if they get created (/ updated) again.
+    out_of_pvs_entities: HashMap<i32, Entity, BuildHasherDefault<NoHashHasher<i32>>>,
+    // NOTE: keyed by network name hash (which is the same as serializer name hash); covers
+    // entities (not out of pvs ones, not baselines). entries are never removed, there are only so
+    // many classes.
+    class_index: HashMap<u64, ClassIndexEntry, BuildHasherDefault<NoHashHasher<u64>>>,
 
     // NOTE: it might be tempting to introduce a "wrapper" struct, something like FieldPathReader
     // and turn read_field_path function into a method, but that's just suggar with no practical
@@ -359,81 +638,308 @@ pub struct EntityContainer {
     // FieldPathsReader there would be 2 levels of indirection (at least as i imagine it right
     // now).
     field_paths: Vec<FieldPath>,
+
+    // NOTE: emptied field map of a replaced (/ deleted) entity; the next create takes it instead
+    // of growing a new one from scratch. see Self::recycle.
+    spare_fields: Option<FieldMap>,
+
+    // NOTE: see ParserOptions::entity_shrink_threshold.
+    shrink_threshold: Option<f32>,
+    // NOTE: capacity that entities map was actually created with (hashbrown rounds requested
+    // capacity up); automatic shrinking never goes below it.
+    initial_capacity: usize,
+    engine_constants: EngineConstants,
 }
 
 impl EntityContainer {
     pub(crate) fn new() -> Self {
-        Self {
-            entities: HashMap::with_capacity_and_hasher(
+        let entities: HashMap<i32, Entity, BuildHasherDefault<NoHashHasher<i32>>> =
+            HashMap::with_capacity_and_hasher(
                 // NOTE(blukai): in dota this value can be actually higher.
-                MAX_EDICTS as usize,
+                EngineConstants::default().max_edicts() as usize,
                 BuildHasherDefault::default(),
-            ),
+            );
+        Self {
+            initial_capacity: entities.capacity(),
+            entities,
             baseline_entities: HashMap::with_capacity_and_hasher(
                 1024,
                 BuildHasherDefault::default(),
             ),
+            out_of_pvs_entities: HashMap::default(),
+            class_index: HashMap::default(),
 
             // NOTE: 4096 is an arbitrary value that is large enough that that came out of printing
             // out count of fps collected per "run". (sort -nr can be handy)
             field_paths: vec![FieldPath::default(); 4096],
+
+            spare_fields: None,
+
+            shrink_threshold: None,
+            engine_constants: EngineConstants::default(),
+        }
+    }
+
+    /// use these (and not free [`ehandle_to_index`]) to resolve handles of games other than dota 2.
+    pub fn engine_constants(&self) -> &EngineConstants {
+        &self.engine_constants
+    }
+
+    pub(crate) fn set_engine_constants(&mut self, engine_constants: EngineConstants) {
+        self.engine_constants = engine_constants;
+    }
+
+    pub(crate) fn set_shrink_threshold(&mut self, shrink_threshold: Option<f32>) {
+        self.shrink_threshold = shrink_threshold;
+    }
+
+    // NOTE: initial capacity is kept as a floor; shrinking below it would only cause re-growth
+    // (/ rehashing) on the next spike.
+    #[inline]
+    fn maybe_shrink(&mut self) {
+        let Some(threshold) = self.shrink_threshold else {
+            return;
+        };
+        let capacity = self.entities.capacity();
+        if capacity > self.initial_capacity
+            && (self.entities.len() as f32) < capacity as f32 * threshold
+        {
+            self.shrink_entities();
         }
     }
 
+    #[cold]
+    fn shrink_entities(&mut self) {
+        self.entities
+            .shrink_to((self.entities.len() * 2).max(self.initial_capacity));
+    }
+
+    /// entity (in pvs, or out of pvs with a different serial) that existed at the index is
+    /// replaced and returned, pass it to [`Self::recycle`] once done with it.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn handle_create(
         &mut self,
         index: i32,
+        tick: i32,
         field_decode_ctx: &mut FieldDecodeContext,
         br: &mut BitReader,
         entity_classes: &EntityClasses,
         instance_baseline: &InstanceBaseline,
         serializers: &FlattenedSerializerContainer,
-    ) -> Result<&Entity, BitReaderOverflowError> {
-        let class_id = br.read_ubit64(entity_classes.bits) as i32;
-        let _serial = br.read_ubit64(NUM_SERIAL_NUM_BITS as usize);
-        let _unknown = br.read_uvarint32();
-
-        let class_info = unsafe { entity_classes.by_id_unckecked(class_id) };
-        let serializer =
-            unsafe { serializers.by_name_hash_unckecked(class_info.network_name_hash) };
-
-        let mut entity = match self.baseline_entities.entry(class_id) {
-            Entry::Occupied(oe) => {
-                let mut entity = oe.get().clone();
+        safe_mode: bool,
+    ) -> Result<(&Entity, EntityCreateInfo, Option<Entity>), HandleCreateError> {
+        let num_serial_num_bits = self.engine_constants.num_serial_num_bits() as usize;
+        let (class_id, serial) = if safe_mode {
+            let mut br = br.checked();
+            let class_id = br.read_ubit64(entity_classes.bits)? as i32;
+            let serial = br.read_ubit64(num_serial_num_bits)? as u32;
+            let _unknown = br.read_uvarint32()?;
+            (class_id, serial)
+        } else {
+            let class_id = br.read_ubit64(entity_classes.bits) as i32;
+            let serial = br.read_ubit64(num_serial_num_bits) as u32;
+            let _unknown = br.read_uvarint32();
+            (class_id, serial)
+        };
+
+        // NOTE: in safe mode class id (that comes from the wire) is validated instead of being
+        // trusted blindly; corrupt demos must not cause undefined behaviour.
+        let (serializer, network_name_hash, (version_tick, baseline_data)) = if safe_mode {
+            let class_info = entity_classes
+                .by_id(class_id)
+                .ok_or(HandleCreateError::UnknownClassId { class_id })?;
+            let serializer = serializers
+                .by_name_hash(class_info.network_name_hash)
+                .ok_or(HandleCreateError::MissingSerializer { class_id })?;
+            (
+                serializer,
+                class_info.network_name_hash,
+                instance_baseline.version_by_id(class_id, tick)?,
+            )
+        } else {
+            unsafe {
+                let class_info = entity_classes.by_id_unckecked(class_id);
+                let serializer = serializers.by_name_hash_unckecked(class_info.network_name_hash);
+                (
+                    serializer,
+                    class_info.network_name_hash,
+                    instance_baseline.by_id_unchecked(class_id, tick),
+                )
+            }
+        };
+
+        let mut baseline_source = BaselineSource::Cached;
+        let mut entity = match self.baseline_entities.get(&class_id) {
+            Some((cached_version_tick, entity)) if *cached_version_tick == version_tick => {
+                let mut entity = entity.clone();
                 entity.index = index;
+                entity.serial = serial;
                 entity
             }
-            Entry::Vacant(ve) => {
+            _ => {
                 let mut entity = Entity {
                     index,
+                    serial: 0,
                     fields: HashMap::with_capacity_and_hasher(
                         serializer.fields.len(),
                         BuildHasherDefault::default(),
                     ),
+                    baseline: None,
                     serializer,
                 };
-                let baseline_data = unsafe { instance_baseline.by_id_unchecked(class_id) };
 
+                // NOTE: data is dropped once it was decoded (see
+                // ParserOptions::drop_baseline_data); decoded baseline entity is gone if the
+                // container was cleared (seeks) or the version is not the cached one.
+                let baseline_data =
+                    baseline_data.ok_or(InstanceBaselineError::DroppedBaseline {
+                        class_id,
+                        tick: version_tick,
+                    })?;
+
+                // NOTE: baselines don't come with entity deltas, their bits must not be
+                // measured.
+                let field_bits = field_decode_ctx.field_bits.take();
                 let mut baseline_br = BitReader::new(baseline_data);
-                entity.parse(field_decode_ctx, &mut baseline_br, &mut self.field_paths)?;
-                baseline_br.is_overflowed()?;
-
-                ve.insert(entity).clone()
+                let result = entity.parse(
+                    field_decode_ctx,
+                    &mut baseline_br,
+                    &mut self.field_paths,
+                    safe_mode,
+                );
+                field_decode_ctx.field_bits = field_bits;
+                let overflowed = baseline_br.is_overflowed();
+                result?;
+                overflowed?;
+
+                // NOTE: decoded fields become the shared baseline; see Entity::baseline.
+                entity.baseline = Some(Rc::new(std::mem::take(&mut entity.fields)));
+                self.baseline_entities
+                    .insert(class_id, (version_tick, entity.clone()));
+                baseline_source = BaselineSource::Parsed;
+                entity.serial = serial;
+                entity
             }
         };
 
-        entity.parse(field_decode_ctx, br, &mut self.field_paths)?;
+        // NOTE: fields are empty at this point in both cases ^ (parsed baseline fields were moved
+        // into entity's baseline).
+        if let Some(fields) = self.spare_fields.take() {
+            entity.fields = fields;
+        }
 
-        self.entities.insert(index, entity);
+        entity.parse(field_decode_ctx, br, &mut self.field_paths, safe_mode)?;
+
+        let mut update_flags = UpdateFlags::ENTER_PVS;
+        let mut replaced = None;
+        if let Some(prev) = self.out_of_pvs_entities.remove(&index) {
+            // NOTE: same serial means that the entity is coming back into pvs; that is not a
+            // new lifetime.
+            if prev.serial != serial {
+                update_flags |= UpdateFlags::FORCE_RECREATE;
+                replaced = Some(prev);
+            } else {
+                self.recycle(prev);
+            }
+        }
+        if let Some(prev) = self.entities.insert(index, entity) {
+            self.class_index_remove(&prev);
+            update_flags |= UpdateFlags::FORCE_RECREATE;
+            replaced = Some(prev);
+        }
+        self.class_index
+            .entry(network_name_hash)
+            .or_insert_with(|| ClassIndexEntry {
+                network_name: entity_classes
+                    .by_id(class_id)
+                    .map(|class_info| class_info.network_name.clone())
+                    .unwrap_or_default(),
+                indices: HashSet::default(),
+            })
+            .indices
+            .insert(index);
+        let create_info = EntityCreateInfo {
+            class_id,
+            network_name_hash,
+            serial,
+            baseline_source,
+            baseline_tick: version_tick,
+            update_flags,
+        };
         // SAFETY: the entity was just inserted ^, it's safe.
-        Ok(unsafe { self.entities.get(&index).unwrap_unchecked() })
+        Ok((
+            unsafe { self.entities.get(&index).unwrap_unchecked() },
+            create_info,
+            replaced,
+        ))
+    }
+
+    /// takes field storage of an entity that is gone (deleted or replaced) for reuse by a later
+    /// create.
+    #[inline]
+    pub(crate) fn recycle(&mut self, mut entity: Entity) {
+        // NOTE: bigger map wins; the point is to avoid re-growth.
+        if self
+            .spare_fields
+            .as_ref()
+            .map_or(true, |spare| spare.capacity() < entity.fields.capacity())
+        {
+            entity.fields.clear();
+            self.spare_fields = Some(entity.fields);
+        }
+    }
+
+    /// checked counterpart of [`Self::handle_delete_unchecked`]; returns none if entity does not
+    /// exist.
+    #[inline]
+    pub(crate) fn handle_delete(&mut self, index: i32) -> Option<Entity> {
+        let entity = match self.entities.remove(&index) {
+            Some(entity) => {
+                self.class_index_remove(&entity);
+                Some(entity)
+            }
+            None => self.out_of_pvs_entities.remove(&index),
+        };
+        self.maybe_shrink();
+        entity
+    }
+
+    #[cold]
+    fn handle_reenter(&mut self, index: i32) {
+        if let Some(entity) = self.out_of_pvs_entities.remove(&index) {
+            if let Some(entry) = self
+                .class_index
+                .get_mut(&entity.serializer.serializer_name.hash)
+            {
+                entry.indices.insert(index);
+            }
+            self.entities.insert(index, entity);
+        }
+    }
+
+    /// moves the entity into out of pvs set; returns none if entity does not exist.
+    pub(crate) fn handle_leave(&mut self, index: i32) -> Option<&Entity> {
+        let entity = self.entities.remove(&index)?;
+        self.class_index_remove(&entity);
+        self.out_of_pvs_entities.insert(index, entity);
+        self.out_of_pvs_entities.get(&index)
+    }
+
+    #[inline]
+    fn class_index_remove(&mut self, entity: &Entity) {
+        if let Some(entry) = self
+            .class_index
+            .get_mut(&entity.serializer.serializer_name.hash)
+        {
+            entry.indices.remove(&entity.index);
+        }
     }
 
     // SAFETY: if it's being deleted menas that it was created, riiight? but
     // there's a risk (that only should exist if replay is corrupted).
     #[inline]
     pub(crate) unsafe fn handle_delete_unchecked(&mut self, index: i32) -> Entity {
-        let entity = self.entities.remove(&index);
+        let entity = self.handle_delete(index);
 
         debug_assert!(
             entity.is_some(),
@@ -443,6 +949,26 @@ impl EntityContainer {
         entity.unwrap_unchecked()
     }
 
+    /// checked counterpart of [`Self::handle_update_unchecked`]; returns none if entity does not
+    /// exist (nothing is read from the bit reader in that case).
+    #[inline]
+    pub(crate) fn handle_update(
+        &mut self,
+        index: i32,
+        field_decode_ctx: &mut FieldDecodeContext,
+        br: &mut BitReader,
+    ) -> Result<Option<&Entity>, ParseEntityError> {
+        if !self.out_of_pvs_entities.is_empty() && !self.entities.contains_key(&index) {
+            self.handle_reenter(index);
+        }
+
+        let Some(entity) = self.entities.get_mut(&index) else {
+            return Ok(None);
+        };
+        entity.parse(field_decode_ctx, br, &mut self.field_paths, true)?;
+        Ok(Some(entity))
+    }
+
     // SAFETY: if entity was ever created, and not deleted, it can be updated!
     // but there's a risk (that only should exist if replay is corrupted).
     #[inline]
@@ -451,7 +977,11 @@ impl EntityContainer {
         index: i32,
         field_decode_ctx: &mut FieldDecodeContext,
         br: &mut BitReader,
-    ) -> Result<&Entity, BitReaderOverflowError> {
+    ) -> Result<&Entity, ParseEntityError> {
+        if !self.out_of_pvs_entities.is_empty() && !self.entities.contains_key(&index) {
+            self.handle_reenter(index);
+        }
+
         let entity = self.entities.get_mut(&index);
 
         debug_assert!(
@@ -460,7 +990,7 @@ impl EntityContainer {
         );
 
         let entity = entity.unwrap_unchecked();
-        entity.parse(field_decode_ctx, br, &mut self.field_paths)?;
+        entity.parse(field_decode_ctx, br, &mut self.field_paths, false)?;
         Ok(entity)
     }
 
@@ -475,12 +1005,57 @@ impl EntityContainer {
         self.entities.get(index)
     }
 
+    /// entities of the class (network name hash, for example
+    /// `fxhash::hash_bytes(b"CDOTA_Unit_Hero_Axe")`) in arbitrary order; does not scan other
+    /// entities.
+    pub fn iter_by_class(&self, network_name_hash: u64) -> impl Iterator<Item = &Entity> {
+        self.class_index
+            .get(&network_name_hash)
+            .into_iter()
+            .flat_map(|entry| entry.indices.iter())
+            .filter_map(|index| self.entities.get(index))
+    }
+
+    /// network names and hashes of classes that had at least one entity created.
+    pub fn iter_classes(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.class_index
+            .iter()
+            .map(|(network_name_hash, entry)| (*network_name_hash, entry.network_name.as_ref()))
+    }
+
+    /// see [`EntityQuery`].
+    pub fn query(&self) -> EntityQuery<'_> {
+        EntityQuery::new(self)
+    }
+
+    /// entities that left pvs but were not deleted (for example heroes that went into fog of war),
+    /// with their last state.
+    pub fn iter_out_of_pvs(&self) -> impl Iterator<Item = (&i32, &Entity)> {
+        self.out_of_pvs_entities.iter()
+    }
+
+    pub fn get_out_of_pvs(&self, index: &i32) -> Option<&Entity> {
+        self.out_of_pvs_entities.get(index)
+    }
+
+    #[inline]
+    pub fn len_out_of_pvs(&self) -> usize {
+        self.out_of_pvs_entities.len()
+    }
+
+    #[inline]
+    pub fn is_out_of_pvs(&self, index: &i32) -> bool {
+        self.out_of_pvs_entities.contains_key(index)
+    }
+
     pub fn iter_baselines(&self) -> impl Iterator<Item = (&i32, &Entity)> {
-        self.baseline_entities.iter()
+        self.baseline_entities
+            .iter()
+            .map(|(class_id, (_, entity))| (class_id, entity))
     }
 
     pub fn get_baseline(&self, index: &i32) -> Option<&Entity> {
-        self.baseline_entities.get(index)
+        self.baseline_entities.get(index).map(|(_, entity)| entity)
     }
 
     // clear clears underlying storage, but this has no effect on the allocated
@@ -488,9 +1063,110 @@ impl EntityContainer {
     pub fn clear(&mut self) {
         self.entities.clear();
         self.baseline_entities.clear();
+        self.out_of_pvs_entities.clear();
+        self.class_index.clear();
+        self.spare_fields = None;
     }
 
     pub fn is_empty(&self) -> bool {
         self.entities.is_empty()
     }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.entities.capacity()
+    }
+
+    /// approximate number of bytes that field storage of all entities (including out of pvs
+    /// entities and baselines) occupies; computed from capacities of field maps. baseline state
+    /// that entities share is counted once.
+    ///
+    /// NOTE: heap allocations of values (strings) are not counted.
+    pub fn field_state_bytes(&self) -> usize {
+        // NOTE: hashbrown stores one control byte per bucket.
+        const BUCKET_SIZE: usize = std::mem::size_of::<(u64, EntityField)>() + 1;
+        let baselines = self.baseline_entities.values().map(|(_, entity)| {
+            entity.fields.capacity() + entity.baseline.as_ref().map_or(0, |b| b.capacity())
+        });
+        self.entities
+            .values()
+            .chain(self.out_of_pvs_entities.values())
+            .map(|entity| entity.fields.capacity())
+            .chain(baselines)
+            .map(|capacity| capacity * BUCKET_SIZE)
+            .sum()
+    }
+
+    /// gives unused memory back: shrinks entity storage (including out of pvs entities and
+    /// baselines) and field storage of each entity. useful for long-running consumers (for
+    /// example live broadcasts) after entity count spikes.
+    ///
+    /// NOTE: field storage is compacted only by this call;
+    /// [`crate::parser::ParserOptions::entity_shrink_threshold`] shrinks entity storage (map of in
+    /// pvs entities) automatically, but nothing else.
+    pub fn shrink_to_fit(&mut self) {
+        self.entities.shrink_to_fit();
+        self.out_of_pvs_entities.shrink_to_fit();
+        self.baseline_entities.shrink_to_fit();
+        self.spare_fields = None;
+        for entity in self
+            .entities
+            .values_mut()
+            .chain(self.out_of_pvs_entities.values_mut())
+        {
+            entity.fields.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn insert_entities(entities: &mut EntityContainer, indices: std::ops::Range<i32>) {
+        let serializer = Rc::new(FlattenedSerializer::default());
+        for index in indices {
+            entities.entities.insert(
+                index,
+                Entity {
+                    index,
+                    serial: 0,
+                    fields: FieldMap::default(),
+                    baseline: None,
+                    serializer: serializer.clone(),
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_shrink_after_deletes() {
+        let mut entities = EntityContainer::new();
+        entities.set_shrink_threshold(Some(0.25));
+        let initial_capacity = entities.entities.capacity();
+
+        // NOTE: nothing to give back below the initial capacity.
+        insert_entities(&mut entities, 0..16);
+        for index in 0..8 {
+            entities.handle_delete(index);
+        }
+        assert_eq!(entities.entities.capacity(), initial_capacity);
+
+        let count = initial_capacity as i32 * 4;
+        insert_entities(&mut entities, 0..count);
+        let peak_capacity = entities.entities.capacity();
+        assert!(peak_capacity > initial_capacity);
+
+        for index in 0..count - 8 {
+            entities.handle_delete(index);
+        }
+        assert_eq!(entities.len(), 8);
+        assert!(entities.entities.capacity() < peak_capacity);
+        assert!(entities.entities.capacity() >= initial_capacity);
+    }
 }
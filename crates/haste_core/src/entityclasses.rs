@@ -1,3 +1,7 @@
+use std::hash::BuildHasherDefault;
+
+use hashbrown::HashMap;
+use nohash::NoHashHasher;
 use valveprotos::common::CDemoClassInfo;
 
 use crate::fxhash;
@@ -5,12 +9,15 @@ use crate::fxhash;
 #[derive(Clone)]
 pub struct ClassInfo {
     pub network_name_hash: u64,
+    pub network_name: Box<str>,
 }
 
 pub struct EntityClasses {
     pub classes: usize,
     pub bits: usize,
     class_infos: Vec<ClassInfo>,
+    // NOTE: keys are already hashes, there's no need to hash them again.
+    class_ids: HashMap<u64, i32, BuildHasherDefault<NoHashHasher<u64>>>,
 }
 
 impl EntityClasses {
@@ -30,17 +37,52 @@ impl EntityClasses {
                 assert_eq!(class_id, i, "invliad class id");
                 ClassInfo {
                     network_name_hash: fxhash::hash_bytes(class.network_name().as_bytes()),
+                    network_name: class.network_name().into(),
                 }
             })
             .collect();
 
+        let class_ids = class_infos
+            .iter()
+            .enumerate()
+            .map(|(class_id, class_info)| (class_info.network_name_hash, class_id as i32))
+            .collect();
+
         Self {
             classes: class_count,
             bits,
             class_infos,
+            class_ids,
         }
     }
 
+    /// iterates over class ids and class infos, ordered by class id.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &ClassInfo)> {
+        self.class_infos
+            .iter()
+            .enumerate()
+            .map(|(class_id, class_info)| (class_id as i32, class_info))
+    }
+
+    /// looks up class id by network name (for example `CCitadelPlayerPawn`).
+    #[inline]
+    pub fn class_id_by_name(&self, network_name: &str) -> Option<i32> {
+        self.class_id_by_name_hash(fxhash::hash_bytes(network_name.as_bytes()))
+    }
+
+    #[inline]
+    pub fn class_id_by_name_hash(&self, network_name_hash: u64) -> Option<i32> {
+        self.class_ids.get(&network_name_hash).copied()
+    }
+
+    #[inline]
+    pub fn by_id(&self, class_id: i32) -> Option<&ClassInfo> {
+        usize::try_from(class_id)
+            .ok()
+            .and_then(|class_id| self.class_infos.get(class_id))
+    }
+
     #[inline(always)]
     pub unsafe fn by_id_unckecked(&self, class_id: i32) -> &ClassInfo {
         self.class_infos.get_unchecked(class_id as usize)
@@ -0,0 +1,99 @@
+//! time series of entity counts per class. useful for spotting illusion spam / summons, and for
+//! sanity-checking parser behaviour across game patches (count of some class suddenly dropping to
+//! zero usually means that something broke).
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use nohash::NoHashHasher;
+
+use crate::entities::EntityContainer;
+
+#[derive(Debug, Clone)]
+pub struct EntityCountSample {
+    pub tick: i32,
+    /// (serializer name hash, count) pairs sorted by serializer name hash; classes without
+    /// entities are omitted.
+    pub counts: Vec<(u64, usize)>,
+}
+
+impl EntityCountSample {
+    pub fn count(&self, serializer_name_hash: u64) -> usize {
+        self.counts
+            .binary_search_by_key(&serializer_name_hash, |(hash, _)| *hash)
+            .map_or(0, |i| self.counts[i].1)
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// call [`EntityCountRecorder::sample`] from [`crate::parser::Visitor::on_tick_end`]; a sample is
+/// recorded once per `interval` ticks.
+///
+/// NOTE: only entities that are in pvs are counted (see
+/// [`EntityContainer::iter_out_of_pvs`]).
+#[derive(Debug, Clone)]
+pub struct EntityCountRecorder {
+    interval: i32,
+    last_sample_tick: Option<i32>,
+    samples: Vec<EntityCountSample>,
+}
+
+impl EntityCountRecorder {
+    pub fn new(interval: i32) -> Self {
+        Self {
+            interval: interval.max(1),
+            last_sample_tick: None,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample(&mut self, tick: i32, entities: &EntityContainer) {
+        if self
+            .last_sample_tick
+            .is_some_and(|last_sample_tick| tick - last_sample_tick < self.interval)
+        {
+            return;
+        }
+        self.last_sample_tick = Some(tick);
+
+        let mut counts: HashMap<u64, usize, BuildHasherDefault<NoHashHasher<u64>>> =
+            HashMap::default();
+        for (_, entity) in entities.iter() {
+            *counts
+                .entry(entity.serializer().serializer_name.hash)
+                .or_default() += 1;
+        }
+        let mut counts: Vec<(u64, usize)> = counts.into_iter().collect();
+        counts.sort_unstable_by_key(|(hash, _)| *hash);
+
+        self.samples.push(EntityCountSample { tick, counts });
+    }
+
+    /// samples, oldest first.
+    #[inline]
+    pub fn samples(&self) -> &[EntityCountSample] {
+        &self.samples
+    }
+
+    /// (tick, count) pairs of the class with the given serializer name hash.
+    pub fn series(&self, serializer_name_hash: u64) -> impl Iterator<Item = (i32, usize)> + '_ {
+        self.samples
+            .iter()
+            .map(move |sample| (sample.tick, sample.count(serializer_name_hash)))
+    }
+
+    /// highest count of the class with the given serializer name hash along with the tick at which
+    /// it was observed.
+    pub fn peak(&self, serializer_name_hash: u64) -> Option<(i32, usize)> {
+        self.series(serializer_name_hash)
+            .max_by_key(|(_, count)| *count)
+    }
+
+    pub fn clear(&mut self) {
+        self.last_sample_tick = None;
+        self.samples.clear();
+    }
+}
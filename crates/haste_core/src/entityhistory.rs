@@ -0,0 +1,364 @@
+//! recorder of entity state over time. each subscribed entity gets a full keyframe every n ticks
+//! and deltas (only changed fields) in between; state at any recorded tick can be reconstructed
+//! after the parse without re-parsing the demo.
+//!
+//! NOTE: cost. parser does not tell which fields a delta touched, thus each recorded update
+//! compares all fields of the entity against the last recorded state (o(fields) per update, not
+//! o(changed fields)); keyframes are plain copies of all fields (not compressed). subscribe to
+//! classes that are actually needed, and pick keyframe interval with that in mind.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasherDefault;
+
+use nohash::NoHashHasher;
+
+use crate::entities::{DeltaHeader, Entity};
+use crate::fieldvalue::FieldValue;
+use crate::replaydiff::field_values_eq;
+
+pub const DEFAULT_KEYFRAME_INTERVAL: i32 = 300;
+
+type FieldMap = HashMap<u64, FieldValue, BuildHasherDefault<NoHashHasher<u64>>>;
+
+/// reconstructed state of an entity at some tick.
+#[derive(Debug, Clone)]
+pub struct EntityState {
+    pub serializer_name_hash: u64,
+    /// values keyed by field keys (see [`crate::entities::fkey_from_path`]).
+    pub fields: FieldMap,
+}
+
+impl EntityState {
+    #[inline]
+    pub fn get(&self, key: &u64) -> Option<&FieldValue> {
+        self.fields.get(key)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Record {
+    Keyframe {
+        tick: i32,
+        serializer_name_hash: u64,
+        fields: Vec<(u64, FieldValue)>,
+    },
+    Delta {
+        tick: i32,
+        fields: Vec<(u64, FieldValue)>,
+    },
+    Deleted {
+        tick: i32,
+    },
+}
+
+impl Record {
+    fn tick(&self) -> i32 {
+        match self {
+            Self::Keyframe { tick, .. } | Self::Delta { tick, .. } | Self::Deleted { tick } => {
+                *tick
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Timeline {
+    records: Vec<Record>,
+    // NOTE: positions of keyframe records within records; used to not replay the whole timeline.
+    keyframes: Vec<usize>,
+    // NOTE: last recorded state; deltas are computed against it. none if entity is deleted.
+    current: Option<EntityState>,
+    last_keyframe_tick: i32,
+}
+
+impl Timeline {
+    fn push_keyframe(&mut self, tick: i32, entity: &Entity) {
+        let serializer_name_hash = entity.serializer().serializer_name.hash;
+        let keyframe_fields: Vec<(u64, FieldValue)> =
+            entity.iter().map(|(k, v)| (*k, v.clone())).collect();
+        let fields: FieldMap = keyframe_fields.iter().cloned().collect();
+        self.keyframes.push(self.records.len());
+        self.records.push(Record::Keyframe {
+            tick,
+            serializer_name_hash,
+            fields: keyframe_fields,
+        });
+        self.current = Some(EntityState {
+            serializer_name_hash,
+            fields,
+        });
+        self.last_keyframe_tick = tick;
+    }
+
+    // NOTE: walks all fields of the entity; see module level doc.
+    fn record(&mut self, tick: i32, entity: &Entity, keyframe_interval: i32) {
+        let current = match self.current.as_mut() {
+            Some(current)
+                if current.serializer_name_hash == entity.serializer().serializer_name.hash
+                    && tick - self.last_keyframe_tick < keyframe_interval =>
+            {
+                current
+            }
+            _ => return self.push_keyframe(tick, entity),
+        };
+
+        let mut changed = Vec::new();
+        for (key, value) in entity.iter() {
+            let is_changed = current
+                .fields
+                .get(key)
+                .map_or(true, |prev| !field_values_eq(prev, value, 0.0));
+            if is_changed {
+                current.fields.insert(*key, value.clone());
+                changed.push((*key, value.clone()));
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+
+        // NOTE: multiple updates within the same tick are merged.
+        if let Some(Record::Delta { tick: t, fields }) = self.records.last_mut() {
+            if *t == tick {
+                for (key, value) in changed {
+                    match fields.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, v)) => *v = value,
+                        None => fields.push((key, value)),
+                    }
+                }
+                return;
+            }
+        }
+        self.records.push(Record::Delta {
+            tick,
+            fields: changed,
+        });
+    }
+
+    fn record_delete(&mut self, tick: i32) {
+        if self.current.take().is_some() {
+            self.records.push(Record::Deleted { tick });
+        }
+    }
+
+    fn query(&self, tick: i32) -> Option<EntityState> {
+        let n = self
+            .keyframes
+            .partition_point(|pos| self.records[*pos].tick() <= tick);
+        let start = *self.keyframes.get(n.checked_sub(1)?)?;
+
+        let mut state: Option<EntityState> = None;
+        for record in self.records[start..].iter() {
+            if record.tick() > tick {
+                break;
+            }
+            match record {
+                Record::Keyframe {
+                    serializer_name_hash,
+                    fields,
+                    ..
+                } => {
+                    state = Some(EntityState {
+                        serializer_name_hash: *serializer_name_hash,
+                        fields: fields.iter().cloned().collect(),
+                    });
+                }
+                Record::Delta { fields, .. } => {
+                    if let Some(state) = state.as_mut() {
+                        state.fields.extend(fields.iter().cloned());
+                    }
+                }
+                Record::Deleted { .. } => state = None,
+            }
+        }
+        state
+    }
+}
+
+/// feed it with entity updates from [`crate::parser::Visitor::on_entity`]:
+///
+/// ```ignore
+/// fn on_entity(&mut self, ctx: &Context, delta_header: DeltaHeader, entity: &Entity) -> Result<()> {
+///     self.history.record(ctx.tick(), delta_header, entity);
+///     Ok(())
+/// }
+/// ```
+///
+/// NOTE: nothing is recorded until classes are subscribed to (see [`EntityHistory::subscribe`]
+/// and [`EntityHistory::subscribe_all`]); recording everything is memory hungry.
+#[derive(Debug)]
+pub struct EntityHistory {
+    keyframe_interval: i32,
+    subscribed: HashSet<u64, BuildHasherDefault<NoHashHasher<u64>>>,
+    subscribed_all: bool,
+    timelines: HashMap<i32, Timeline, BuildHasherDefault<NoHashHasher<i32>>>,
+}
+
+impl Default for EntityHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_KEYFRAME_INTERVAL)
+    }
+}
+
+impl EntityHistory {
+    /// `keyframe_interval` is a number of ticks between full snapshots of entity state; smaller
+    /// interval means more memory and faster queries.
+    pub fn new(keyframe_interval: i32) -> Self {
+        Self {
+            keyframe_interval: keyframe_interval.max(1),
+            subscribed: HashSet::default(),
+            subscribed_all: false,
+            timelines: HashMap::default(),
+        }
+    }
+
+    /// record entities of the class with the given serializer name hash (see
+    /// [`crate::fxhash::hash_bytes`]).
+    pub fn subscribe(&mut self, serializer_name_hash: u64) {
+        self.subscribed.insert(serializer_name_hash);
+    }
+
+    pub fn subscribe_all(&mut self) {
+        self.subscribed_all = true;
+    }
+
+    #[inline]
+    fn is_subscribed(&self, serializer_name_hash: u64) -> bool {
+        self.subscribed_all || self.subscribed.contains(&serializer_name_hash)
+    }
+
+    pub fn record(&mut self, tick: i32, delta_header: DeltaHeader, entity: &Entity) {
+        let index = entity.index();
+        if delta_header == DeltaHeader::DELETE {
+            if let Some(timeline) = self.timelines.get_mut(&index) {
+                timeline.record_delete(tick);
+            }
+            return;
+        }
+
+        // NOTE: entity index can be reused by an entity of a different class; previous entity's
+        // timeline must be closed.
+        if !self.is_subscribed(entity.serializer().serializer_name.hash) {
+            if let Some(timeline) = self.timelines.get_mut(&index) {
+                timeline.record_delete(tick);
+            }
+            return;
+        }
+
+        let keyframe_interval = self.keyframe_interval;
+        let timeline = self.timelines.entry(index).or_default();
+        if delta_header == DeltaHeader::CREATE {
+            timeline.push_keyframe(tick, entity);
+        } else {
+            timeline.record(tick, entity, keyframe_interval);
+        }
+    }
+
+    /// state of the entity at the given index as of the given tick (including updates that
+    /// happened at that tick); none if entity did not exist or was not recorded.
+    pub fn query_at_tick(&self, index: i32, tick: i32) -> Option<EntityState> {
+        self.timelines.get(&index)?.query(tick)
+    }
+
+    /// ticks at which entity at the given index changed.
+    pub fn change_ticks(&self, index: i32) -> impl Iterator<Item = i32> + '_ {
+        self.timelines
+            .get(&index)
+            .into_iter()
+            .flat_map(|timeline| timeline.records.iter().map(Record::tick))
+    }
+
+    /// indices of entities that have recorded timelines.
+    pub fn indices(&self) -> impl Iterator<Item = i32> + '_ {
+        self.timelines.keys().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.timelines.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entities::fkey_from_path;
+    use crate::fxhash;
+    use crate::parser::{Context, Parser, Visitor};
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    struct Recorder(EntityHistory);
+
+    impl Visitor for Recorder {
+        fn on_entity(
+            &mut self,
+            ctx: &Context,
+            delta_header: DeltaHeader,
+            entity: &Entity,
+        ) -> anyhow::Result<()> {
+            self.0.record(ctx.tick(), delta_header, entity);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_query_at_tick() -> anyhow::Result<()> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(90))])?;
+        wtr.write_tick(2)?;
+        // NOTE: keyframe interval is 2, thus this one is a keyframe.
+        wtr.update(1, &[("m_bAlive", FieldValue::Bool(true))])?;
+        wtr.write_tick(3)?;
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(80))])?;
+        wtr.write_tick(4)?;
+        wtr.delete(1)?;
+        wtr.write_tick(5)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        wtr.write_tick(6)?;
+
+        let mut history = EntityHistory::new(2);
+        history.subscribe(fxhash::hash_bytes(b"CToyEntity"));
+        let mut parser =
+            Parser::from_stream_with_visitor(wtr.finish_into_demo_file()?, Recorder(history))?;
+        parser.run_to_end()?;
+        let history = parser.into_visitor().0;
+
+        let (health, alive) = (
+            fkey_from_path(&["m_iHealth"]),
+            fkey_from_path(&["m_bAlive"]),
+        );
+        let query = |tick: i32| {
+            history
+                .query_at_tick(1, tick)
+                .map(|state| format!("{:?} {:?}", state.get(&health), state.get(&alive)))
+        };
+        assert_eq!(query(0), None);
+        assert_eq!(
+            query(1).as_deref(),
+            Some("Some(I64(100)) Some(Bool(false))")
+        );
+        assert_eq!(query(2).as_deref(), Some("Some(I64(90)) Some(Bool(false))"));
+        assert_eq!(query(3).as_deref(), Some("Some(I64(90)) Some(Bool(true))"));
+        assert_eq!(query(4).as_deref(), Some("Some(I64(80)) Some(Bool(true))"));
+        assert_eq!(query(5), None);
+        assert_eq!(query(6).as_deref(), Some("Some(I64(50)) Some(Bool(false))"));
+        assert_eq!(query(100).as_deref(), query(6).as_deref());
+
+        assert_eq!(
+            history.change_ticks(1).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+        // NOTE: keyframes at creations and at tick 3.
+        let timeline = history
+            .timelines
+            .get(&1)
+            .ok_or_else(|| anyhow::anyhow!("no timeline"))?;
+        assert_eq!(timeline.keyframes.len(), 3);
+
+        Ok(())
+    }
+}
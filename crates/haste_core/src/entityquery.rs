@@ -0,0 +1,185 @@
+//! ad-hoc filtering of entities without nested loops and manual conversions:
+//!
+//! ```ignore
+//! let radiant_heroes = entities
+//!     .query()
+//!     .class("CDOTA_Unit_Hero_*")
+//!     .where_eq(fkey!("m_iTeamNum"), 2u8)
+//!     .iter();
+//! ```
+//!
+//! class filters are pushed down to the class index of [`EntityContainer`] (see
+//! [`EntityContainer::iter_by_class`]), thus only entities of matching classes are visited; field
+//! predicates are evaluated against those, in the order they were added.
+
+use crate::entities::{Entity, EntityContainer};
+use crate::fieldvalue::{FieldValue, FieldValueConversionError};
+use crate::fxhash;
+
+type Predicate<'a> = Box<dyn Fn(&Entity) -> bool + 'a>;
+
+/// `*` matches any (possibly empty) sequence of characters; there are no other special
+/// characters.
+fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let s = s.as_bytes();
+
+    let (mut pi, mut si) = (0, 0);
+    // NOTE: position of the last star in pattern and position in s that it is matched up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while si < s.len() {
+        if pi < pattern.len() && pattern[pi] == b'*' {
+            backtrack = Some((pi, si));
+            pi += 1;
+        } else if pi < pattern.len() && pattern[pi] == s[si] {
+            pi += 1;
+            si += 1;
+        } else if let Some((star_pi, star_si)) = backtrack {
+            pi = star_pi + 1;
+            si = star_si + 1;
+            backtrack = Some((star_pi, si));
+        } else {
+            return false;
+        }
+    }
+    pattern[pi..].iter().all(|c| *c == b'*')
+}
+
+/// see [`EntityContainer::query`]. covers entities that are in pvs (same as
+/// [`EntityContainer::iter`]).
+pub struct EntityQuery<'a> {
+    entities: &'a EntityContainer,
+    // NOTE: none means that there's no class filter.
+    classes: Option<Vec<u64>>,
+    predicates: Vec<Predicate<'a>>,
+}
+
+impl<'a> EntityQuery<'a> {
+    pub(crate) fn new(entities: &'a EntityContainer) -> Self {
+        Self {
+            entities,
+            classes: None,
+            predicates: Vec::new(),
+        }
+    }
+
+    fn push_class(&mut self, network_name_hash: u64) {
+        let classes = self.classes.get_or_insert_with(Vec::new);
+        if !classes.contains(&network_name_hash) {
+            classes.push(network_name_hash);
+        }
+    }
+
+    /// entities of classes whose network name matches the pattern (for example
+    /// `CDOTA_Unit_Hero_*`; `*` matches any sequence of characters); multiple class filters are
+    /// or-ed.
+    ///
+    /// NOTE: pattern is resolved against classes that have entities at the moment of the call.
+    pub fn class(mut self, pattern: &str) -> Self {
+        if !pattern.contains('*') {
+            return self.class_hash(fxhash::hash_bytes(pattern.as_bytes()));
+        }
+        // NOTE: class filter that matches nothing must yield nothing, not everything.
+        self.classes.get_or_insert_with(Vec::new);
+        let matches: Vec<u64> = self
+            .entities
+            .iter_classes()
+            .filter(|(_, network_name)| glob_match(pattern, network_name))
+            .map(|(network_name_hash, _)| network_name_hash)
+            .collect();
+        for network_name_hash in matches {
+            self.push_class(network_name_hash);
+        }
+        self
+    }
+
+    /// counterpart of [`Self::class`] for network name hashes.
+    pub fn class_hash(mut self, network_name_hash: u64) -> Self {
+        self.push_class(network_name_hash);
+        self
+    }
+
+    /// entities that have the field and its value converts to `T` and equals `value`. `T` must be
+    /// what the field decodes into (for example `u8` for `m_iTeamNum`), same as with
+    /// [`Entity::get_value`].
+    pub fn where_eq<T>(self, key: u64, value: T) -> Self
+    where
+        FieldValue: TryInto<T, Error = FieldValueConversionError>,
+        T: PartialEq + 'a,
+    {
+        self.filter(move |entity| entity.get_value::<T>(&key).is_some_and(|v| v == value))
+    }
+
+    /// entities that have the field and its value converts to `T` and does not equal `value`.
+    pub fn where_ne<T>(self, key: u64, value: T) -> Self
+    where
+        FieldValue: TryInto<T, Error = FieldValueConversionError>,
+        T: PartialEq + 'a,
+    {
+        self.filter(move |entity| entity.get_value::<T>(&key).is_some_and(|v| v != value))
+    }
+
+    /// entities that have the field and `f` returns true for its (converted) value.
+    pub fn where_value<T, F>(self, key: u64, f: F) -> Self
+    where
+        FieldValue: TryInto<T, Error = FieldValueConversionError>,
+        F: Fn(T) -> bool + 'a,
+    {
+        self.filter(move |entity| entity.get_value::<T>(&key).is_some_and(&f))
+    }
+
+    /// entities that have the field, regardless of its value.
+    pub fn has_field(self, key: u64) -> Self {
+        self.filter(move |entity| entity.get(&key).is_some())
+    }
+
+    pub fn filter<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Entity) -> bool + 'a,
+    {
+        self.predicates.push(Box::new(f));
+        self
+    }
+
+    /// in arbitrary order.
+    pub fn iter(self) -> impl Iterator<Item = &'a Entity> + 'a {
+        let entities = self.entities;
+        let candidates: Box<dyn Iterator<Item = &'a Entity> + 'a> = match self.classes {
+            Some(classes) => Box::new(
+                classes
+                    .into_iter()
+                    .flat_map(move |network_name_hash| entities.iter_by_class(network_name_hash)),
+            ),
+            None => Box::new(entities.iter().map(|(_, entity)| entity)),
+        };
+        let predicates = self.predicates;
+        candidates.filter(move |entity| predicates.iter().all(|predicate| predicate(entity)))
+    }
+
+    pub fn first(self) -> Option<&'a Entity> {
+        self.iter().next()
+    }
+
+    pub fn count(self) -> usize {
+        self.iter().count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("CDOTA_Unit_Hero_*", "CDOTA_Unit_Hero_Axe"));
+        assert!(glob_match("CDOTA_Unit_Hero_*", "CDOTA_Unit_Hero_"));
+        assert!(!glob_match("CDOTA_Unit_Hero_*", "CDOTA_Unit_Courier"));
+        assert!(glob_match("*Gamerules*", "CDOTAGamerulesProxy"));
+        assert!(glob_match("C*_*_Hero_*e", "CDOTA_Unit_Hero_Axe"));
+        assert!(!glob_match("C*_*_Hero_*x", "CDOTA_Unit_Hero_Axe"));
+        assert!(glob_match("CDOTAGamerulesProxy", "CDOTAGamerulesProxy"));
+        assert!(!glob_match("CDOTAGamerulesProxy", "CDOTAGamerulesProxy2"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "a"));
+    }
+}
@@ -0,0 +1,116 @@
+//! how many bits each field consumed on the wire, accumulated over the whole demo. tells which
+//! fields dominate bandwidth, thus which ones are worth subscribing to (or optimizing decoders
+//! of). enable with [`crate::parser::ParserOptions::measure_field_bits`], read with
+//! [`crate::parser::Parser::field_bits`].
+//!
+//! NOTE: only entity deltas are measured; instance baselines (which arrive in a string table) and
+//! entity state that is carried by full packets are not. field paths (that precede field values)
+//! are not attributed to individual fields, see [`FieldBitCounts::field_path_bits`].
+
+use std::collections::HashMap;
+
+#[cfg(feature = "preserve-metadata")]
+use crate::fieldpath::FieldPath;
+
+#[derive(Debug, Clone)]
+pub struct FieldBitStats {
+    pub serializer_name_hash: u64,
+    pub field_key: u64,
+    /// path of the first decode; see
+    /// [`crate::flattenedserializers::FlattenedSerializer::dotted_path`].
+    #[cfg(feature = "preserve-metadata")]
+    pub path: FieldPath,
+    /// number of times the field was decoded.
+    pub count: u64,
+    pub bits: u64,
+}
+
+impl FieldBitStats {
+    #[inline]
+    pub fn bits_per_decode(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.bits as f64 / self.count as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FieldBitCounts {
+    // NOTE: keyed by serializer name hash and field key; same field of different classes is
+    // counted separately.
+    fields: HashMap<(u64, u64), FieldBitStats>,
+    field_path_bits: u64,
+}
+
+impl FieldBitCounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub(crate) fn record_field(
+        &mut self,
+        serializer_name_hash: u64,
+        field_key: u64,
+        #[cfg(feature = "preserve-metadata")] path: &FieldPath,
+        bits: usize,
+    ) {
+        let stats = self
+            .fields
+            .entry((serializer_name_hash, field_key))
+            .or_insert_with(|| FieldBitStats {
+                serializer_name_hash,
+                field_key,
+                #[cfg(feature = "preserve-metadata")]
+                path: path.clone(),
+                count: 0,
+                bits: 0,
+            });
+        stats.count += 1;
+        stats.bits += bits as u64;
+    }
+
+    #[inline]
+    pub(crate) fn record_field_paths(&mut self, bits: usize) {
+        self.field_path_bits += bits as u64;
+    }
+
+    pub fn get(&self, serializer_name_hash: u64, field_key: u64) -> Option<&FieldBitStats> {
+        self.fields.get(&(serializer_name_hash, field_key))
+    }
+
+    /// in arbitrary order; see [`Self::sorted_by_bits`].
+    pub fn iter(&self) -> impl Iterator<Item = &FieldBitStats> {
+        self.fields.values()
+    }
+
+    /// heaviest fields first.
+    pub fn sorted_by_bits(&self) -> Vec<&FieldBitStats> {
+        let mut fields: Vec<&FieldBitStats> = self.fields.values().collect();
+        fields.sort_unstable_by(|a, b| {
+            b.bits
+                .cmp(&a.bits)
+                .then(a.serializer_name_hash.cmp(&b.serializer_name_hash))
+                .then(a.field_key.cmp(&b.field_key))
+        });
+        fields
+    }
+
+    /// bits of all field values, field paths are not included.
+    pub fn field_bits(&self) -> u64 {
+        self.fields.values().map(|stats| stats.bits).sum()
+    }
+
+    /// bits of field paths of all deltas.
+    #[inline]
+    pub fn field_path_bits(&self) -> u64 {
+        self.field_path_bits
+    }
+
+    pub fn clear(&mut self) {
+        self.fields.clear();
+        self.field_path_bits = 0;
+    }
+}
@@ -1,10 +1,15 @@
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
+use std::rc::Rc;
 
 use dyn_clone::DynClone;
 
 use crate::bitreader::BitReader;
+use crate::fieldbits::FieldBitCounts;
 use crate::fieldvalue::FieldValue;
-use crate::flattenedserializers::FlattenedSerializerField;
+use crate::flattenedserializers::{
+    CustomFieldDecodeFn, F32Encoding, FieldEncoding, FlattenedSerializerField, QAngleEncoding,
+    Symbol,
+};
 use crate::fxhash;
 use crate::quantizedfloat::{QuantizedFloat, QuantizedFloatError};
 
@@ -15,6 +20,8 @@ use crate::quantizedfloat::{QuantizedFloat, QuantizedFloatError};
 pub enum FieldDecoderConstructionError {
     #[error(transparent)]
     QuantizedFloatError(#[from] QuantizedFloatError),
+    #[error("unknown var encoder: {0:?}")]
+    UnknownVarEncoder(Symbol),
 }
 
 // ----
@@ -28,6 +35,8 @@ const DT_MAX_STRING_BUFFERSIZE: u32 = 1 << DT_MAX_STRING_BITS;
 pub(crate) struct FieldDecodeContext {
     pub(crate) tick_interval: f32,
     pub(crate) string_buf: [u8; DT_MAX_STRING_BUFFERSIZE as usize],
+    /// some if bits of fields need to be measured; see [`crate::fieldbits`].
+    pub(crate) field_bits: Option<FieldBitCounts>,
 }
 
 impl Default for FieldDecodeContext {
@@ -37,12 +46,33 @@ impl Default for FieldDecodeContext {
             // becomes available "later"; it is okay to initialize it to 0.0.
             tick_interval: 0.0,
             string_buf: [0u8; DT_MAX_STRING_BUFFERSIZE as usize],
+            field_bits: None,
         }
     }
 }
 
 // ----
 
+/// wraps user-provided decode function; see [`crate::flattenedserializers::FieldDecoderRegistry`].
+#[derive(Clone)]
+pub(crate) struct CustomDecoder {
+    decode: Rc<CustomFieldDecodeFn>,
+}
+
+impl CustomDecoder {
+    pub(crate) fn new(decode: Rc<CustomFieldDecodeFn>) -> Self {
+        Self { decode }
+    }
+}
+
+impl Debug for CustomDecoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomDecoder")
+    }
+}
+
+// ----
+
 // TODO: get rid of trait objects; find a better, more efficient, way to
 // "attach" decoders to fields; but note that having separate decoding functions
 // and attaching function "pointers" to fields is even worse.
@@ -51,10 +81,21 @@ impl Default for FieldDecodeContext {
 
 pub(crate) trait FieldDecode: DynClone + Debug {
     fn decode(&self, ctx: &mut FieldDecodeContext, br: &mut BitReader) -> FieldValue;
+
+    // NOTE: this is for introspection only; it is never called on hot paths.
+    fn encoding(&self) -> Option<FieldEncoding> {
+        None
+    }
 }
 
 dyn_clone::clone_trait_object!(FieldDecode);
 
+impl FieldDecode for CustomDecoder {
+    fn decode(&self, _ctx: &mut FieldDecodeContext, br: &mut BitReader) -> FieldValue {
+        (self.decode)(br)
+    }
+}
+
 /// used during multi-phase initialization. never called.
 #[derive(Debug, Clone, Default)]
 pub(crate) struct InvalidDecoder;
@@ -238,6 +279,7 @@ impl InternalFieldDecode<f32> for InternalQuantizedFloatDecoder {
 #[derive(Debug, Clone)]
 pub(crate) struct InternalF32Decoder {
     decoder: Box<dyn InternalFieldDecode<f32>>,
+    encoding: F32Encoding,
 }
 
 impl InternalF32Decoder {
@@ -249,6 +291,7 @@ impl InternalF32Decoder {
         {
             return Ok(Self {
                 decoder: Box::<InternalF32SimulationTimeDecoder>::default(),
+                encoding: F32Encoding::SimulationTime,
             });
         }
 
@@ -257,14 +300,20 @@ impl InternalF32Decoder {
                 hash if hash == fxhash::hash_bytes(b"coord") => {
                     return Ok(Self {
                         decoder: Box::<InternalF32CoordDecoder>::default(),
+                        encoding: F32Encoding::Coord,
                     });
                 }
                 hash if hash == fxhash::hash_bytes(b"normal") => {
                     return Ok(Self {
                         decoder: Box::<InternalF32NormalDecoder>::default(),
+                        encoding: F32Encoding::Normal,
                     });
                 }
-                _ => unimplemented!("{:?}", var_encoder),
+                _ => {
+                    return Err(FieldDecoderConstructionError::UnknownVarEncoder(
+                        var_encoder.clone(),
+                    ))
+                }
             }
         }
 
@@ -275,11 +324,18 @@ impl InternalF32Decoder {
         if bit_count == 0 || bit_count == 32 {
             return Ok(Self {
                 decoder: Box::<InternalF32NoScaleDecoder>::default(),
+                encoding: F32Encoding::NoScale,
             });
         }
 
         Ok(Self {
             decoder: Box::new(InternalQuantizedFloatDecoder::new(field)?),
+            encoding: F32Encoding::Quantized {
+                bit_count,
+                low_value: field.low_value.unwrap_or_default(),
+                high_value: field.high_value.unwrap_or_default(),
+                encode_flags: field.encode_flags.unwrap_or_default(),
+            },
         })
     }
 }
@@ -292,7 +348,7 @@ impl InternalFieldDecode<f32> for InternalF32Decoder {
 
 #[derive(Debug, Clone)]
 pub(crate) struct F32Decoder {
-    decoder: Box<dyn InternalFieldDecode<f32>>,
+    decoder: InternalF32Decoder,
 }
 
 impl F32Decoder {
@@ -301,7 +357,7 @@ impl F32Decoder {
         field: &FlattenedSerializerField,
     ) -> Result<Self, FieldDecoderConstructionError> {
         Ok(Self {
-            decoder: Box::new(InternalF32Decoder::new(field)?),
+            decoder: InternalF32Decoder::new(field)?,
         })
     }
 }
@@ -310,6 +366,10 @@ impl FieldDecode for F32Decoder {
     fn decode(&self, ctx: &mut FieldDecodeContext, br: &mut BitReader) -> FieldValue {
         FieldValue::F32(self.decoder.decode(ctx, br))
     }
+
+    fn encoding(&self) -> Option<FieldEncoding> {
+        Some(FieldEncoding::F32(self.decoder.encoding))
+    }
 }
 
 // ----
@@ -342,6 +402,7 @@ impl FieldDecode for InternalVector3NormalDecoder {
 #[derive(Debug, Clone)]
 pub(crate) struct Vector3Decoder {
     decoder: Box<dyn FieldDecode>,
+    encoding: FieldEncoding,
 }
 
 impl Vector3Decoder {
@@ -352,12 +413,16 @@ impl Vector3Decoder {
         if field.var_encoder_heq(fxhash::hash_bytes(b"normal")) {
             Ok(Self {
                 decoder: Box::<InternalVector3NormalDecoder>::default(),
+                encoding: FieldEncoding::VectorNormal,
             })
         } else {
+            let decoder = InternalF32Decoder::new(field)?;
+            let encoding = FieldEncoding::Vector(decoder.encoding);
             Ok(Self {
                 decoder: Box::new(InternalVector3DefaultDecoder {
-                    decoder: Box::new(InternalF32Decoder::new(field)?),
+                    decoder: Box::new(decoder),
                 }),
+                encoding,
             })
         }
     }
@@ -367,13 +432,17 @@ impl FieldDecode for Vector3Decoder {
     fn decode(&self, ctx: &mut FieldDecodeContext, br: &mut BitReader) -> FieldValue {
         self.decoder.decode(ctx, br)
     }
+
+    fn encoding(&self) -> Option<FieldEncoding> {
+        Some(self.encoding)
+    }
 }
 
 // ----
 
 #[derive(Debug, Clone)]
 pub(crate) struct Vector2Decoder {
-    decoder: Box<dyn InternalFieldDecode<f32>>,
+    decoder: InternalF32Decoder,
 }
 
 impl Vector2Decoder {
@@ -382,7 +451,7 @@ impl Vector2Decoder {
         field: &FlattenedSerializerField,
     ) -> Result<Self, FieldDecoderConstructionError> {
         Ok(Self {
-            decoder: Box::new(InternalF32Decoder::new(field)?),
+            decoder: InternalF32Decoder::new(field)?,
         })
     }
 }
@@ -392,13 +461,17 @@ impl FieldDecode for Vector2Decoder {
         let vec2 = [self.decoder.decode(ctx, br), self.decoder.decode(ctx, br)];
         FieldValue::Vector2(vec2)
     }
+
+    fn encoding(&self) -> Option<FieldEncoding> {
+        Some(FieldEncoding::Vector(self.decoder.encoding))
+    }
 }
 
 // ----
 
 #[derive(Debug, Clone)]
 pub(crate) struct Vector4Decoder {
-    decoder: Box<dyn InternalFieldDecode<f32>>,
+    decoder: InternalF32Decoder,
 }
 
 impl Vector4Decoder {
@@ -407,7 +480,7 @@ impl Vector4Decoder {
         field: &FlattenedSerializerField,
     ) -> Result<Self, FieldDecoderConstructionError> {
         Ok(Self {
-            decoder: Box::new(InternalF32Decoder::new(field)?),
+            decoder: InternalF32Decoder::new(field)?,
         })
     }
 }
@@ -422,6 +495,10 @@ impl FieldDecode for Vector4Decoder {
         ];
         FieldValue::Vector4(vec4)
     }
+
+    fn encoding(&self) -> Option<FieldEncoding> {
+        Some(FieldEncoding::Vector(self.decoder.encoding))
+    }
 }
 
 // ----
@@ -433,12 +510,11 @@ struct InternalQAnglePitchYawDecoder {
 
 impl FieldDecode for InternalQAnglePitchYawDecoder {
     fn decode(&self, _ctx: &mut FieldDecodeContext, br: &mut BitReader) -> FieldValue {
-        let vec3 = [
+        let vec2 = [
             br.read_bitangle(self.bit_count),
             br.read_bitangle(self.bit_count),
-            0.0,
         ];
-        FieldValue::QAngle(vec3)
+        FieldValue::QAnglePitchYaw(vec2)
     }
 }
 
@@ -495,23 +571,28 @@ impl FieldDecode for InternalQAngleBitCountDecoder {
 #[derive(Debug, Clone)]
 pub(crate) struct QAngleDecoder {
     decoder: Box<dyn FieldDecode>,
+    encoding: QAngleEncoding,
 }
 
 impl QAngleDecoder {
-    pub(crate) fn new(field: &FlattenedSerializerField) -> Self {
+    pub(crate) fn new(
+        field: &FlattenedSerializerField,
+    ) -> Result<Self, FieldDecoderConstructionError> {
         let bit_count = field.bit_count.unwrap_or_default() as usize;
 
         if let Some(var_encoder) = field.var_encoder.as_ref() {
             match var_encoder.hash {
                 hash if hash == fxhash::hash_bytes(b"qangle_pitch_yaw") => {
-                    return Self {
+                    return Ok(Self {
                         decoder: Box::new(InternalQAnglePitchYawDecoder { bit_count }),
-                    };
+                        encoding: QAngleEncoding::PitchYaw { bit_count },
+                    });
                 }
                 hash if hash == fxhash::hash_bytes(b"qangle_precise") => {
-                    return Self {
+                    return Ok(Self {
                         decoder: Box::<InternalQAnglePreciseDecoder>::default(),
-                    };
+                        encoding: QAngleEncoding::Precise,
+                    });
                 }
 
                 hash if hash == fxhash::hash_bytes(b"qangle") => {}
@@ -519,19 +600,25 @@ impl QAngleDecoder {
                 // name in dota 2 replay from 2018.
                 hash if hash == fxhash::hash_bytes(b"QAngle") => {}
 
-                _ => unimplemented!("{:?}", var_encoder),
+                _ => {
+                    return Err(FieldDecoderConstructionError::UnknownVarEncoder(
+                        var_encoder.clone(),
+                    ))
+                }
             }
         }
 
         if bit_count == 0 {
-            return Self {
+            return Ok(Self {
                 decoder: Box::<InternalQAngleNoBitCountDecoder>::default(),
-            };
+                encoding: QAngleEncoding::Coord,
+            });
         }
 
-        Self {
+        Ok(Self {
             decoder: Box::new(InternalQAngleBitCountDecoder { bit_count }),
-        }
+            encoding: QAngleEncoding::BitCount { bit_count },
+        })
     }
 }
 
@@ -539,4 +626,8 @@ impl FieldDecode for QAngleDecoder {
     fn decode(&self, ctx: &mut FieldDecodeContext, br: &mut BitReader) -> FieldValue {
         self.decoder.decode(ctx, br)
     }
+
+    fn encoding(&self) -> Option<FieldEncoding> {
+        Some(FieldEncoding::QAngle(self.encoding))
+    }
 }
@@ -0,0 +1,334 @@
+//! bounded per-field history. only the last k (tick, value) samples of tracked (class, field)
+//! pairs are kept, which is enough for computing derivatives (speed from position, damage per
+//! second from health) without storing full snapshots; see [`crate::entityhistory`] for that.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasherDefault;
+
+use nohash::NoHashHasher;
+
+use crate::entities::{DeltaHeader, Entity};
+use crate::fieldvalue::FieldValue;
+use crate::replaydiff::field_values_eq;
+
+#[derive(Debug, Clone)]
+pub struct FieldSample {
+    pub tick: i32,
+    pub value: FieldValue,
+}
+
+/// feed it with entity updates from [`crate::parser::Visitor::on_entity`] (see
+/// [`FieldHistory::record`]).
+///
+/// NOTE: new sample is recorded only when value differs from the previous one. values are held
+/// between changes, thus when previous sample is older than the tick before the change, the
+/// previous value is recorded once more at that tick; otherwise interpolation (and
+/// [`FieldHistory::rate_per_tick`]) would spread the change over the whole unchanged gap. held
+/// samples count towards capacity.
+#[derive(Debug, Default)]
+pub struct FieldHistory {
+    // NOTE: keyed by serializer name hash and field key; values are capacities.
+    tracked: HashMap<(u64, u64), usize>,
+    // NOTE: field keys tracked per serializer name hash; used to not hash tuple on each field.
+    tracked_by_class: HashMap<u64, Vec<u64>, BuildHasherDefault<NoHashHasher<u64>>>,
+    // NOTE: keyed by entity index and field key.
+    samples: HashMap<(i32, u64), VecDeque<FieldSample>>,
+}
+
+impl FieldHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// retain last `capacity` samples of the field with the given key (see
+    /// [`crate::entities::fkey_from_path`]) of entities of the class with the given serializer
+    /// name hash.
+    pub fn track(&mut self, serializer_name_hash: u64, field_key: u64, capacity: usize) {
+        let capacity = capacity.max(1);
+        if self
+            .tracked
+            .insert((serializer_name_hash, field_key), capacity)
+            .is_none()
+        {
+            self.tracked_by_class
+                .entry(serializer_name_hash)
+                .or_default()
+                .push(field_key);
+        }
+    }
+
+    pub fn record(&mut self, tick: i32, delta_header: DeltaHeader, entity: &Entity) {
+        let serializer_name_hash = entity.serializer().serializer_name.hash;
+        let Some(field_keys) = self.tracked_by_class.get(&serializer_name_hash) else {
+            return;
+        };
+
+        let index = entity.index();
+        for field_key in field_keys.iter() {
+            let key = (index, *field_key);
+
+            // NOTE: samples of previous entity that lived at the same index (/ of the same entity
+            // that got re-created) must not be mixed in.
+            if delta_header == DeltaHeader::CREATE || delta_header == DeltaHeader::DELETE {
+                self.samples.remove(&key);
+            }
+            if delta_header == DeltaHeader::DELETE {
+                continue;
+            }
+
+            let Some(value) = entity.get(field_key) else {
+                continue;
+            };
+            let capacity = self
+                .tracked
+                .get(&(serializer_name_hash, *field_key))
+                .copied()
+                .unwrap_or(1);
+            let samples = match self.samples.entry(key) {
+                Entry::Occupied(oe) => oe.into_mut(),
+                Entry::Vacant(ve) => ve.insert(VecDeque::with_capacity(capacity)),
+            };
+            let held = match samples.back() {
+                Some(last) if field_values_eq(&last.value, value, 0.0) => continue,
+                Some(last) if last.tick < tick - 1 => Some(FieldSample {
+                    tick: tick - 1,
+                    value: last.value.clone(),
+                }),
+                _ => None,
+            };
+            if let Some(held) = held {
+                push_sample(samples, capacity, held);
+            }
+            push_sample(
+                samples,
+                capacity,
+                FieldSample {
+                    tick,
+                    value: value.clone(),
+                },
+            );
+        }
+    }
+
+    /// samples of the field of the entity at the given index, oldest first.
+    pub fn samples(&self, index: i32, field_key: u64) -> impl Iterator<Item = &FieldSample> {
+        self.samples
+            .get(&(index, field_key))
+            .into_iter()
+            .flat_map(|samples| samples.iter())
+    }
+
+    #[inline]
+    pub fn latest(&self, index: i32, field_key: u64) -> Option<&FieldSample> {
+        self.samples.get(&(index, field_key))?.back()
+    }
+
+    /// change of a numeric (integer or float) value per tick between two most recent samples.
+    pub fn rate_per_tick(&self, index: i32, field_key: u64) -> Option<f32> {
+        let samples = self.samples.get(&(index, field_key))?;
+        let mut it = samples.iter().rev();
+        let (last, prev) = (it.next()?, it.next()?);
+        let dt = last.tick - prev.tick;
+        if dt <= 0 {
+            return None;
+        }
+        let (last_value, prev_value) = (as_f32(&last.value)?, as_f32(&prev.value)?);
+        Some((last_value - prev_value) / dt as f32)
+    }
+
+    /// interpolated value of a vector3 field at the given game time with [`DEFAULT_INTERP`]; see
+    /// [`interpolate_position`].
+    pub fn interpolate_vector3(
+        &self,
+        index: i32,
+        field_key: u64,
+        tick_interval: f32,
+        time: f32,
+    ) -> Option<[f32; 3]> {
+        let positions: Vec<(i32, [f32; 3])> = self
+            .samples(index, field_key)
+            .filter_map(|sample| match sample.value {
+                FieldValue::Vector3(v) => Some((sample.tick, v)),
+                _ => None,
+            })
+            .collect();
+        interpolate_position(&positions, tick_interval, time, DEFAULT_INTERP)
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+fn push_sample(samples: &mut VecDeque<FieldSample>, capacity: usize, sample: FieldSample) {
+    if samples.len() >= capacity {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+fn as_f32(value: &FieldValue) -> Option<f32> {
+    match value {
+        FieldValue::I64(v) => Some(*v as f32),
+        FieldValue::U64(v) => Some(*v as f32),
+        FieldValue::F32(v) => Some(*v),
+        _ => None,
+    }
+}
+
+// interpolation
+// ----
+
+/// default value of `cl_interp` convar (in seconds).
+pub const DEFAULT_INTERP: f32 = 0.1;
+
+/// position at the given game time (in seconds) the way client renders it: clients render
+/// entities `interp` seconds in the past and linearly interpolate between the two networked
+/// samples that surround that moment. before the first and after the last sample position is
+/// clamped (no extrapolation, `cl_extrapolate 0`).
+///
+/// `samples` are (tick, position) pairs sorted by tick; game time of a tick is `tick *
+/// tick_interval` (see [`crate::parser::Context::tick_interval`]). pass `0.0` as `interp` to get
+/// positions at the exact time instead.
+///
+/// NOTE: in dota 2 and deadlock positions are networked as cell + offset within the cell, see
+/// `*_coord_from_cell` functions in [`crate::entities`] for composing them.
+pub fn interpolate_position(
+    samples: &[(i32, [f32; 3])],
+    tick_interval: f32,
+    time: f32,
+    interp: f32,
+) -> Option<[f32; 3]> {
+    let render_time = time - interp;
+    let n = samples.partition_point(|(tick, _)| *tick as f32 * tick_interval <= render_time);
+    let (prev_tick, prev) = match n.checked_sub(1) {
+        Some(i) => samples.get(i)?,
+        None => return samples.first().map(|(_, position)| *position),
+    };
+    let Some((next_tick, next)) = samples.get(n) else {
+        return Some(*prev);
+    };
+
+    let prev_time = *prev_tick as f32 * tick_interval;
+    let next_time = *next_tick as f32 * tick_interval;
+    let dt = next_time - prev_time;
+    if dt <= 0.0 {
+        return Some(*next);
+    }
+    let t = ((render_time - prev_time) / dt).clamp(0.0, 1.0);
+    Some([
+        prev[0] + (next[0] - prev[0]) * t,
+        prev[1] + (next[1] - prev[1]) * t,
+        prev[2] + (next[2] - prev[2]) * t,
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entities::fkey_from_path;
+    use crate::fxhash;
+    use crate::parser::{Context, Parser, Visitor};
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    const TICK_INTERVAL: f32 = 1.0 / 30.0;
+
+    fn time(tick: i32) -> f32 {
+        tick as f32 * TICK_INTERVAL
+    }
+
+    #[test]
+    fn test_interpolate_position() {
+        let samples = [(10, [0.0, 0.0, 0.0]), (20, [10.0, -10.0, 1.0])];
+
+        // NOTE: clamped before the first and after the last sample.
+        assert_eq!(
+            interpolate_position(&samples, TICK_INTERVAL, time(5), 0.0),
+            Some([0.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            interpolate_position(&samples, TICK_INTERVAL, time(25), 0.0),
+            Some([10.0, -10.0, 1.0])
+        );
+
+        // NOTE: exact hits.
+        assert_eq!(
+            interpolate_position(&samples, TICK_INTERVAL, time(10), 0.0),
+            Some([0.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            interpolate_position(&samples, TICK_INTERVAL, time(20), 0.0),
+            Some([10.0, -10.0, 1.0])
+        );
+
+        let [x, y, z] =
+            interpolate_position(&samples, TICK_INTERVAL, time(15), 0.0).unwrap_or_default();
+        assert!((x - 5.0).abs() < 1e-3 && (y + 5.0).abs() < 1e-3 && (z - 0.5).abs() < 1e-3);
+
+        // NOTE: rendered interp seconds in the past.
+        let [x, _, _] =
+            interpolate_position(&samples, TICK_INTERVAL, time(18), time(3)).unwrap_or_default();
+        assert!((x - 5.0).abs() < 1e-3);
+
+        assert_eq!(interpolate_position(&[], TICK_INTERVAL, time(1), 0.0), None);
+    }
+
+    struct Recorder(FieldHistory);
+
+    impl Visitor for Recorder {
+        fn on_entity(
+            &mut self,
+            ctx: &Context,
+            delta_header: DeltaHeader,
+            entity: &Entity,
+        ) -> anyhow::Result<()> {
+            self.0.record(ctx.tick(), delta_header, entity);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_held_value() -> anyhow::Result<()> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        // NOTE: health is not changed by this update.
+        wtr.update(1, &[("m_bAlive", FieldValue::Bool(true))])?;
+        wtr.write_tick(2)?;
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(40))])?;
+        wtr.write_tick(8)?;
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(30))])?;
+        wtr.write_tick(9)?;
+
+        let health = fkey_from_path(&["m_iHealth"]);
+        let mut history = FieldHistory::new();
+        history.track(fxhash::hash_bytes(b"CToyEntity"), health, 8);
+        let mut parser =
+            Parser::from_stream_with_visitor(wtr.finish_into_demo_file()?, Recorder(history))?;
+        parser.run_to_end()?;
+        let history = parser.into_visitor().0;
+
+        let samples: Vec<(i32, String)> = history
+            .samples(1, health)
+            .map(|sample| (sample.tick, format!("{:?}", sample.value)))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![
+                (1, "I64(100)".to_string()),
+                // NOTE: 100 is held up until the tick before the change.
+                (7, "I64(100)".to_string()),
+                (8, "I64(40)".to_string()),
+                // NOTE: nothing to hold between adjacent ticks.
+                (9, "I64(30)".to_string()),
+            ]
+        );
+        assert_eq!(history.rate_per_tick(1, health), Some(-10.0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+//! field key collision detection (`debug-field-keys` feature). field keys are fx hashes of field
+//! names; if two distinct paths end up with the same key values of one silently overwrite values
+//! of the other. this keeps (key -> dotted path) of every field that the parser decodes and
+//! aborts the moment two paths share a key.
+//!
+//! NOTE: this is slow (a string is built for every decoded field), don't enable it outside of
+//! debugging.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::fieldpath::FieldPath;
+use crate::flattenedserializers::FlattenedSerializer;
+
+thread_local! {
+    // NOTE: keys are the same across entities, classes and demos (a key is a hash of names only),
+    // thus a single table is shared by all parsers of a thread.
+    static FIELD_KEY_PATHS: RefCell<HashMap<u64, Box<str>>> = RefCell::new(HashMap::new());
+}
+
+/// panics if a different path was seen with the same key before.
+#[allow(clippy::panic)]
+pub(crate) fn check_field_key(field_key: u64, serializer: &FlattenedSerializer, fp: &FieldPath) {
+    let path = serializer.dotted_path(fp);
+    FIELD_KEY_PATHS.with_borrow_mut(|field_key_paths| match field_key_paths.get(&field_key) {
+        Some(known_path) if **known_path != *path => panic!(
+            "field key collision: {:?} and {:?} (of {}) both hash to {field_key:#018x}",
+            known_path,
+            path,
+            serializer.serializer_name.as_str(),
+        ),
+        Some(_) => {}
+        None => {
+            field_key_paths.insert(field_key, path.into_boxed_str());
+        }
+    });
+}
+
+/// number of distinct keys that were seen on this thread so far.
+pub fn num_field_keys() -> usize {
+    FIELD_KEY_PATHS.with_borrow(|field_key_paths| field_key_paths.len())
+}
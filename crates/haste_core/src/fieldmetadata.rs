@@ -3,7 +3,8 @@ use crate::fielddecoder::{
     InvalidDecoder, QAngleDecoder, StringDecoder, U64Decoder, Vector2Decoder, Vector3Decoder,
     Vector4Decoder,
 };
-use crate::flattenedserializers::FlattenedSerializerField;
+use crate::flattenedserializers::{FieldDecoderRegistry, FlattenedSerializerField};
+use crate::fxhash;
 use crate::vartype::{self, Expr, Lit};
 
 #[derive(thiserror::Error, Debug)]
@@ -97,6 +98,7 @@ impl Default for FieldMetadata {
 fn visit_ident(
     ident: &str,
     field: &FlattenedSerializerField,
+    decoders: &FieldDecoderRegistry,
 ) -> Result<FieldMetadata, FieldMetadataError> {
     macro_rules! non_special {
         ($decoder:ident) => {
@@ -122,6 +124,12 @@ fn visit_ident(
         };
     }
 
+    if !decoders.is_empty() {
+        if let Some(decoder) = decoders.by_var_type_hash(fxhash::hash_bytes(ident.as_bytes())) {
+            return non_special!(decoder.clone());
+        }
+    }
+
     match ident {
         // primitives
         "int8" => non_special!(I64Decoder),
@@ -151,7 +159,7 @@ fn visit_ident(
         "CUtlSymbolLarge" => non_special!(StringDecoder),
         "CUtlString" => non_special!(StringDecoder),
         // public/mathlib/vector.h
-        "QAngle" => non_special!(QAngleDecoder::new(field)),
+        "QAngle" => non_special!(QAngleDecoder::new(field)?),
         // NOTE: not all quantized floats are actually quantized (if bit_count is 0 or 32 it's
         // not!) F32Decoder will determine which kind of f32 decoder to use.
         "CNetworkedQuantizedFloat" => non_special!(F32Decoder::new(field)?),
@@ -163,6 +171,15 @@ fn visit_ident(
         // public/mathlib/vector4d.h
         "Vector4D" => non_special!(Vector4Decoder::new(field)?),
 
+        // TODO(blukai): deferred: deadlock (citadel) specific var types and var encoders
+        // (hero-specific vectors, new quantized encodings) and larger array bounds (field path
+        // components are u8s, see fieldpath.rs) are not implemented. they need fixture demos that
+        // fail with "unsupported var type" to be verified against (see tests/golden.rs), and
+        // there are none; decoders must not be guessed (for example CGlobalSymbol networked as
+        // string, Quaternion as 4 floats, or "simtime" encoder as simulation time). until then
+        // such types can be handled with FieldDecoderRegistry or UnknownFieldTypes::Fallback (see
+        // flattenedserializers.rs).
+
         // exceptional specials xd
         "m_SpeechBubbles" => Ok(FieldMetadata {
             special_descriptor: Some(FieldSpecialDescriptor::DynamicSerializerArray),
@@ -187,6 +204,7 @@ fn visit_template(
     expr: Expr,
     arg: Expr,
     field: &FlattenedSerializerField,
+    decoders: &FieldDecoderRegistry,
 ) -> Result<FieldMetadata, FieldMetadataError> {
     let Expr::Ident(ident) = expr else {
         unreachable!();
@@ -203,7 +221,7 @@ fn visit_template(
             });
         }
 
-        return visit_any(arg, field).map(|field_metadata| FieldMetadata {
+        return visit_any(arg, field, decoders).map(|field_metadata| FieldMetadata {
             special_descriptor: Some(FieldSpecialDescriptor::DynamicArray {
                 decoder: field_metadata.decoder,
             }),
@@ -211,7 +229,7 @@ fn visit_template(
         });
     }
 
-    visit_ident(ident, field)
+    visit_ident(ident, field, decoders)
 }
 
 #[inline]
@@ -219,6 +237,7 @@ fn visit_array(
     expr: Expr,
     len: Expr,
     field: &FlattenedSerializerField,
+    decoders: &FieldDecoderRegistry,
 ) -> Result<FieldMetadata, FieldMetadataError> {
     if let Expr::Ident(ident) = expr {
         if ident == "char" {
@@ -243,7 +262,7 @@ fn visit_array(
         _ => unreachable!(),
     }?;
 
-    visit_any(expr, field).map(|field_metadata| FieldMetadata {
+    visit_any(expr, field, decoders).map(|field_metadata| FieldMetadata {
         special_descriptor: Some(FieldSpecialDescriptor::FixedArray { length }),
         decoder: field_metadata.decoder,
     })
@@ -261,20 +280,66 @@ fn visit_pointer() -> Result<FieldMetadata, FieldMetadataError> {
 fn visit_any(
     expr: Expr,
     field: &FlattenedSerializerField,
+    decoders: &FieldDecoderRegistry,
 ) -> Result<FieldMetadata, FieldMetadataError> {
     match expr {
-        Expr::Ident(ident) => visit_ident(ident, field),
-        Expr::Template { expr, arg } => visit_template(*expr, *arg, field),
-        Expr::Array { expr, len } => visit_array(*expr, *len, field),
+        Expr::Ident(ident) => visit_ident(ident, field, decoders),
+        Expr::Template { expr, arg } => visit_template(*expr, *arg, field, decoders),
+        Expr::Array { expr, len } => visit_array(*expr, *len, field, decoders),
         Expr::Pointer(_) => visit_pointer(),
         _ => unreachable!(),
     }
 }
 
+/// custom decoders registered for var encoder or for the whole var type take precedence over
+/// everything else.
 pub(crate) fn get_field_metadata(
     field: &FlattenedSerializerField,
     var_type: &String,
+    decoders: &FieldDecoderRegistry,
 ) -> Result<FieldMetadata, FieldMetadataError> {
+    if !decoders.is_empty() {
+        let decoder = field
+            .var_encoder
+            .as_ref()
+            .and_then(|var_encoder| decoders.by_var_encoder_hash(var_encoder.hash))
+            .or_else(|| decoders.by_var_type_hash(field.var_type.hash));
+        if let Some(decoder) = decoder {
+            return Ok(FieldMetadata {
+                special_descriptor: None,
+                decoder: Box::new(decoder.clone()),
+            });
+        }
+    }
+
     let expr = vartype::parse(var_type.as_str())?;
-    visit_any(expr, field)
+    visit_any(expr, field, decoders)
+}
+
+// NOTE: field paths can address elements of fixed arrays whose length is unknown (because length
+// ident is unknown); this needs to be large enough to not end up out of bounds.
+const FALLBACK_ARRAY_LENGTH: usize = 256;
+
+/// meant to be used for fields for which [`get_field_metadata`] failed. values are decoded as
+/// unsigned varints (or fixed 64 bit integers, depending on var encoder); elements of fixed arrays
+/// with unknown length are decoded the same way unless their type is known.
+pub(crate) fn get_fallback_field_metadata(
+    field: &FlattenedSerializerField,
+    var_type: &str,
+    decoders: &FieldDecoderRegistry,
+) -> FieldMetadata {
+    let u64_decoder = || -> Box<dyn FieldDecode> { Box::new(U64Decoder::new(field)) };
+    match vartype::parse(var_type) {
+        Ok(Expr::Array { expr, len }) if matches!(*len, Expr::Ident(_)) => FieldMetadata {
+            special_descriptor: Some(FieldSpecialDescriptor::FixedArray {
+                length: FALLBACK_ARRAY_LENGTH,
+            }),
+            decoder: visit_any(*expr, field, decoders)
+                .map_or_else(|_| u64_decoder(), |field_metadata| field_metadata.decoder),
+        },
+        _ => FieldMetadata {
+            special_descriptor: None,
+            decoder: u64_decoder(),
+        },
+    }
 }
@@ -1,10 +1,14 @@
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::num::ParseIntError;
+use std::str::FromStr;
 
 use lazy_static::lazy_static;
 
-use crate::bitreader::BitReader;
+use crate::bitreader::{BitReader, BitReaderOverflowError};
+use crate::bitwriter::BitWriter;
 
 // NOTE: credit for figuring out field path encoding goes to invokr (github.com/dotabuff/manta) and
 // spheenik (github.com/skadistats/clarity).
@@ -17,11 +21,39 @@ use crate::bitreader::BitReader;
 // [1] https://github.com/skadistats/clarity/blob/6dcdad4abe94a519b0c797576517461401adedee/src/main/java/skadistats/clarity/model/s2/S2LongFieldPathFormat.java
 // [2] https://github.com/skadistats/clarity/commit/212eaddf7dc8b716c22faaec37952236f521a804#commitcomment-86037653
 
+// NOTE: field paths are at most 7 components deep.
+pub(crate) const MAX_COMPONENTS: usize = 7;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseFieldPathError {
+    #[error("field path is empty")]
+    Empty,
+    #[error("field path has {0} components, max is 7")]
+    TooManyComponents(usize),
+    #[error("invalid field path component: {0}")]
+    InvalidComponent(#[from] ParseIntError),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadFieldPathsError {
+    #[error(transparent)]
+    BitReaderOverflowError(#[from] BitReaderOverflowError),
+    #[error("field path gets more then 7 components or pops more components then it has")]
+    InvalidFieldPath,
+    #[error("there are more then {0} field paths")]
+    TooManyFieldPaths(usize),
+}
+
+/// renders as slash separated components (for example `1/3/0`) and parses back from that.
+/// comparison, ordering and hashing only look at components.
 #[derive(Debug, Clone)]
 pub struct FieldPath {
-    pub(crate) data: [u8; 7],
+    pub(crate) data: [u8; MAX_COMPONENTS],
     pub(crate) last: usize,
     pub(crate) finished: bool,
+    // NOTE: set by ops that push more then 7 components or pop more components then there are;
+    // that only happens when data is corrupt. see [`read_field_paths_checked`].
+    pub(crate) invalid: bool,
 }
 
 impl Default for FieldPath {
@@ -31,6 +63,7 @@ impl Default for FieldPath {
             data: [255, 0, 0, 0, 0, 0, 0],
             last: 0,
             finished: false,
+            invalid: false,
         }
     }
 }
@@ -50,12 +83,20 @@ impl FieldPath {
 
     #[inline(always)]
     fn push(&mut self, v: i32) {
-        self.last += 1;
-        self.data[self.last] = (v & 0xFF) as u8;
+        if self.last + 1 < MAX_COMPONENTS {
+            self.last += 1;
+            self.data[self.last] = (v & 0xFF) as u8;
+        } else {
+            self.invalid = true;
+        }
     }
 
     #[inline(always)]
     fn pop(&mut self, n: usize) {
+        if n > self.last {
+            self.invalid = true;
+            return;
+        }
         for _ in 0..n {
             self.data[self.last] = 0;
             self.last -= 1;
@@ -71,11 +112,43 @@ impl FieldPath {
 
     // public api
 
+    /// builds a path from its components; none if there are no components or more then 7.
+    pub fn from_components(components: &[u8]) -> Option<Self> {
+        if components.is_empty() || components.len() > MAX_COMPONENTS {
+            return None;
+        }
+        let mut fp = Self::default();
+        fp.data[..components.len()].copy_from_slice(components);
+        fp.last = components.len() - 1;
+        Some(fp)
+    }
+
+    /// none if index is past the last component.
+    //
     // NOTE: using this method can hurt performance when used in critical code paths. use the
     // unsafe [`Self::get_unchecked`] instead.
     #[inline]
     pub fn get(&self, index: usize) -> Option<usize> {
-        self.data.get(index).map(|component| *component as usize)
+        self.as_slice()
+            .get(index)
+            .map(|component| *component as usize)
+    }
+
+    /// number of components.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.last + 1
+    }
+
+    /// always false, paths have at least one component; exists to please clippy.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..=self.last]
     }
 
     #[inline]
@@ -89,6 +162,68 @@ impl FieldPath {
     }
 }
 
+impl PartialEq for FieldPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for FieldPath {}
+
+impl PartialOrd for FieldPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// lexicographic (`1/3` < `1/3/0` < `2`), which is the order in which fields appear in the
+/// serializer.
+impl Ord for FieldPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl Hash for FieldPath {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state);
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, component) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            write!(f, "{component}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = ParseFieldPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseFieldPathError::Empty);
+        }
+
+        let mut components = [0u8; MAX_COMPONENTS];
+        let mut len = 0;
+        for component in s.split('/') {
+            if len == MAX_COMPONENTS {
+                return Err(ParseFieldPathError::TooManyComponents(s.split('/').count()));
+            }
+            components[len] = component.parse()?;
+            len += 1;
+        }
+
+        Self::from_components(&components[..len]).ok_or(ParseFieldPathError::Empty)
+    }
+}
+
 type FieldOp = fn(&mut FieldPath, &mut BitReader);
 
 // PlusOne
@@ -252,7 +387,8 @@ fn push_three_pack5_left_delta_n(fp: &mut FieldPath, br: &mut BitReader) {
 fn push_n(fp: &mut FieldPath, br: &mut BitReader) {
     let n = br.read_ubitvar() as usize;
     fp.inc_last(br.read_ubitvar() as i32);
-    for _ in 0..n {
+    // NOTE: n comes from the wire; pushes past max components are invalid anyway.
+    for _ in 0..n.min(MAX_COMPONENTS) {
         fp.push(br.read_ubitvarfp() as i32);
     }
 }
@@ -265,7 +401,7 @@ fn push_n_and_non_topographical(fp: &mut FieldPath, br: &mut BitReader) {
         }
     }
     let n = br.read_ubitvar() as usize;
-    for _ in 0..n {
+    for _ in 0..n.min(MAX_COMPONENTS) {
         fp.push(br.read_ubitvarfp() as i32);
     }
 }
@@ -339,7 +475,10 @@ fn non_topo_complex(fp: &mut FieldPath, br: &mut BitReader) {
 
 // NonTopoPenultimatePluseOne
 fn non_topo_penultimate_pluse_one(fp: &mut FieldPath, _br: &mut BitReader) {
-    fp.inc_at(fp.last - 1, 1);
+    match fp.last.checked_sub(1) {
+        Some(penultimate) => fp.inc_at(penultimate, 1),
+        None => fp.invalid = true,
+    }
 }
 
 // NonTopoComplexPack4Bits
@@ -599,17 +738,19 @@ impl<T: Debug> PartialEq for Node<T> {
 
 impl<T: Debug> Eq for Node<T> {}
 
-fn build_fieldop_hierarchy() -> Node<FieldOp> {
+// NOTE: values are generic such that the same tree can be built with descriptor indices (for
+// writing, see FIELDOP_CODES).
+fn build_fieldop_hierarchy<T: Debug>(values: impl Fn(usize, &FieldOpDescriptor) -> T) -> Node<T> {
     let mut bh = BinaryHeap::with_capacity(FIELDOP_DESCRIPTORS.len());
 
     // valve's huffman-tree uses a variation which takes the node number into account
     let mut num = 0;
 
-    for fod in FIELDOP_DESCRIPTORS.iter() {
+    for (i, fod) in FIELDOP_DESCRIPTORS.iter().enumerate() {
         bh.push(Node::Leaf {
             weight: fod.weight,
             num,
-            value: fod.op,
+            value: values(i, fod),
         });
         num += 1;
     }
@@ -630,7 +771,7 @@ fn build_fieldop_hierarchy() -> Node<FieldOp> {
 }
 
 lazy_static! {
-    static ref FIELDOP_HIERARCHY: Node<FieldOp> = build_fieldop_hierarchy();
+    static ref FIELDOP_HIERARCHY: Node<FieldOp> = build_fieldop_hierarchy(|_, fod| fod.op);
 }
 
 pub(crate) fn read_field_paths(br: &mut BitReader, fps: &mut [FieldPath]) -> usize {
@@ -681,3 +822,270 @@ pub(crate) fn read_field_paths(br: &mut BitReader, fps: &mut [FieldPath]) -> usi
         };
     }
 }
+
+/// checked counterpart of [`read_field_paths`]; used in safe mode (see
+/// `ParserOptions::safe_mode`). ops are picked with checked reads, reader is checked for overflow
+/// right after each op (values that ops read are never used before that), paths that are invalid
+/// (see [`FieldPath::invalid`]) or don't fit into `fps` result in errors.
+pub(crate) fn read_field_paths_checked(
+    br: &mut BitReader,
+    fps: &mut [FieldPath],
+) -> Result<usize, ReadFieldPathsError> {
+    let mut fp = FieldPath::default();
+    let mut i: usize = 0;
+
+    let mut root: &Node<FieldOp> = &FIELDOP_HIERARCHY;
+
+    loop {
+        let next = if br.checked().read_bool()? {
+            root.unwrap_right_branch()
+        } else {
+            root.unwrap_left_branch()
+        };
+
+        root = if let Node::Leaf { value: op, .. } = next {
+            (op)(&mut fp, br);
+            br.is_overflowed()?;
+            if fp.invalid {
+                return Err(ReadFieldPathsError::InvalidFieldPath);
+            }
+            if fp.finished {
+                return Ok(i);
+            }
+            let Some(slot) = fps.get_mut(i) else {
+                return Err(ReadFieldPathsError::TooManyFieldPaths(fps.len()));
+            };
+            *slot = fp.clone();
+
+            i += 1;
+
+            &FIELDOP_HIERARCHY
+        } else {
+            next
+        };
+    }
+}
+
+// writing
+// ----
+
+// NOTE: indices into FIELDOP_DESCRIPTORS. writer gets away with only a few ops, codes are longer
+// than what valve would produce, but readers don't care.
+const FIELDOP_PLUS_ONE: usize = 0;
+const FIELDOP_PUSH_N_AND_NON_TOPOGRAPHICAL: usize = 26;
+const FIELDOP_POP_N_AND_NON_TOPOGRAPHICAL: usize = 35;
+const FIELDOP_FIELD_PATH_ENCODE_FINISH: usize = 39;
+
+fn collect_fieldop_codes(node: &Node<usize>, code: &mut Vec<bool>, codes: &mut [Vec<bool>]) {
+    match node {
+        Node::Leaf { value, .. } => codes[*value] = code.clone(),
+        Node::Branch { left, right, .. } => {
+            // NOTE: see read_field_paths; false goes left, true goes right.
+            code.push(false);
+            collect_fieldop_codes(left, code, codes);
+            code.pop();
+            code.push(true);
+            collect_fieldop_codes(right, code, codes);
+            code.pop();
+        }
+    }
+}
+
+lazy_static! {
+    static ref FIELDOP_CODES: Vec<Vec<bool>> = {
+        let mut codes = vec![Vec::new(); FIELDOP_DESCRIPTORS.len()];
+        collect_fieldop_codes(
+            &build_fieldop_hierarchy(|i, _| i),
+            &mut Vec::new(),
+            &mut codes,
+        );
+        codes
+    };
+}
+
+fn write_fieldop(bw: &mut BitWriter, fieldop: usize) {
+    for bit in FIELDOP_CODES[fieldop].iter() {
+        bw.write_bool(*bit);
+    }
+}
+
+/// counterpart of [`read_field_paths`]; encodes paths (followed by the finishing op) such that
+/// reading them back produces exactly the same paths. useful for producing entity data (see
+/// `syntheticdemo` module).
+pub fn write_field_paths(bw: &mut BitWriter, fps: &[FieldPath]) {
+    let mut prev = FieldPath::default();
+    for fp in fps {
+        let delta = |i: usize| fp.data[i] as i32 - prev.data[i] as i32;
+
+        if fp.last == prev.last
+            && fp.data[..fp.last] == prev.data[..prev.last]
+            && fp.data[fp.last] == prev.data[prev.last].wrapping_add(1)
+        {
+            write_fieldop(bw, FIELDOP_PLUS_ONE);
+        } else if fp.last < prev.last {
+            write_fieldop(bw, FIELDOP_POP_N_AND_NON_TOPOGRAPHICAL);
+            bw.write_ubitvarfp((prev.last - fp.last) as u32);
+            for i in 0..=fp.last {
+                let delta = delta(i);
+                bw.write_bool(delta != 0);
+                if delta != 0 {
+                    bw.write_varint32(delta);
+                }
+            }
+        } else {
+            write_fieldop(bw, FIELDOP_PUSH_N_AND_NON_TOPOGRAPHICAL);
+            for i in 0..=prev.last {
+                let delta = delta(i);
+                bw.write_bool(delta != 0);
+                if delta != 0 {
+                    // NOTE: reader adds one.
+                    bw.write_varint32(delta - 1);
+                }
+            }
+            bw.write_ubitvar((fp.last - prev.last) as u32);
+            for i in prev.last + 1..=fp.last {
+                bw.write_ubitvarfp(fp.data[i] as u32);
+            }
+        }
+
+        prev = fp.clone();
+    }
+    write_fieldop(bw, FIELDOP_FIELD_PATH_ENCODE_FINISH);
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_display_from_str() -> Result<(), ParseFieldPathError> {
+        let fp: FieldPath = "1/3/0".parse()?;
+        assert_eq!(fp.as_slice(), &[1, 3, 0]);
+        assert_eq!(fp.to_string(), "1/3/0");
+        assert_eq!(fp.get(2), Some(0));
+        assert_eq!(fp.get(3), None);
+
+        assert!("".parse::<FieldPath>().is_err());
+        assert!("1//2".parse::<FieldPath>().is_err());
+        assert!("256".parse::<FieldPath>().is_err());
+        assert!("0/1/2/3/4/5/6/7".parse::<FieldPath>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ord() -> Result<(), ParseFieldPathError> {
+        assert!("1/3".parse::<FieldPath>()? < "1/3/0".parse()?);
+        assert!("1/3/0".parse::<FieldPath>()? < "2".parse()?);
+        assert_eq!(
+            "4/2".parse::<FieldPath>().ok(),
+            FieldPath::from_components(&[4, 2])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_read_field_paths() -> Result<(), ParseFieldPathError> {
+        let fps: Vec<FieldPath> = ["0", "1", "4", "4/0", "4/0/2", "4/1", "5", "6/3", "250"]
+            .iter()
+            .map(|fp| fp.parse())
+            .collect::<Result<_, _>>()?;
+
+        let mut bw = BitWriter::new();
+        write_field_paths(&mut bw, &fps);
+
+        let buf = bw.into_bytes();
+        let mut br = BitReader::new(&buf);
+        let mut out = vec![FieldPath::default(); fps.len() + 1];
+        let n = read_field_paths(&mut br, &mut out);
+        assert!(br.is_overflowed().is_ok());
+        assert_eq!(&out[..n], fps.as_slice());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_field_paths_checked() -> Result<(), ReadFieldPathsError> {
+        let mut out = vec![FieldPath::default(); 2];
+
+        // NOTE: 8 components.
+        let mut bw = BitWriter::new();
+        write_fieldop(&mut bw, FIELDOP_PUSH_N_AND_NON_TOPOGRAPHICAL);
+        bw.write_bool(false);
+        bw.write_ubitvar(8);
+        for _ in 0..8 {
+            bw.write_ubitvarfp(0);
+        }
+        write_fieldop(&mut bw, FIELDOP_FIELD_PATH_ENCODE_FINISH);
+        let buf = bw.into_bytes();
+        let mut br = BitReader::new(&buf);
+        assert!(matches!(
+            read_field_paths_checked(&mut br, &mut out),
+            Err(ReadFieldPathsError::InvalidFieldPath)
+        ));
+
+        // NOTE: pops 3 components out of 1.
+        let mut bw = BitWriter::new();
+        write_fieldop(&mut bw, FIELDOP_POP_N_AND_NON_TOPOGRAPHICAL);
+        bw.write_ubitvarfp(3);
+        bw.write_bool(false);
+        write_fieldop(&mut bw, FIELDOP_FIELD_PATH_ENCODE_FINISH);
+        let buf = bw.into_bytes();
+        let mut br = BitReader::new(&buf);
+        assert!(matches!(
+            read_field_paths_checked(&mut br, &mut out),
+            Err(ReadFieldPathsError::InvalidFieldPath)
+        ));
+
+        let fps: Vec<FieldPath> = ["0", "1", "2"]
+            .iter()
+            .filter_map(|fp| fp.parse().ok())
+            .collect();
+        let mut bw = BitWriter::new();
+        write_field_paths(&mut bw, &fps);
+        let buf = bw.into_bytes();
+        let mut br = BitReader::new(&buf);
+        assert!(matches!(
+            read_field_paths_checked(&mut br, &mut out),
+            Err(ReadFieldPathsError::TooManyFieldPaths(2))
+        ));
+
+        let mut br = BitReader::new(&[]);
+        assert!(matches!(
+            read_field_paths_checked(&mut br, &mut out),
+            Err(ReadFieldPathsError::BitReaderOverflowError(_))
+        ));
+
+        Ok(())
+    }
+
+    fn field_path() -> impl Strategy<Value = FieldPath> {
+        prop::collection::vec(any::<u8>(), 1..=MAX_COMPONENTS)
+            .prop_map(|components| FieldPath::from_components(&components).unwrap_or_default())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_write_read_field_paths(fps in prop::collection::vec(field_path(), 0..256)) {
+            let mut bw = BitWriter::new();
+            write_field_paths(&mut bw, &fps);
+
+            let buf = bw.into_bytes();
+            let mut br = BitReader::new(&buf);
+            let mut out = vec![FieldPath::default(); fps.len() + 1];
+            let n = read_field_paths(&mut br, &mut out);
+            br.is_overflowed()?;
+            prop_assert_eq!(&out[..n], fps.as_slice());
+
+            let mut br = BitReader::new(&buf);
+            let n = read_field_paths_checked(&mut br, &mut out)?;
+            br.is_overflowed()?;
+            prop_assert_eq!(&out[..n], fps.as_slice());
+        }
+
+        #[test]
+        fn prop_display_from_str(fp in field_path()) {
+            prop_assert_eq!(fp.to_string().parse::<FieldPath>()?, fp);
+        }
+    }
+}
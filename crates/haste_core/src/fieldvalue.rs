@@ -16,6 +16,9 @@ pub enum FieldValue {
     Vector2([f32; 2]),
     Vector4([f32; 4]),
     QAngle([f32; 3]),
+    /// qangle of which only pitch and yaw are networked (roll is always 0); see
+    /// [`crate::flattenedserializers::QAngleEncoding::PitchYaw`].
+    QAnglePitchYaw([f32; 2]),
     String(Box<str>),
 }
 
@@ -73,12 +76,22 @@ macro_rules! impl_try_into_inner {
 
 impl_try_into_inner! {
     Bool => bool,
-    Vector2 => [f32; 2],
     Vector4 => [f32; 4]
 }
 
 // and some specials...
 
+impl TryInto<[f32; 2]> for FieldValue {
+    type Error = FieldValueConversionError;
+
+    fn try_into(self) -> Result<[f32; 2], Self::Error> {
+        match self {
+            FieldValue::Vector2(value) | FieldValue::QAnglePitchYaw(value) => Ok(value),
+            _ => Err(FieldValueConversionError),
+        }
+    }
+}
+
 impl TryInto<[f32; 3]> for FieldValue {
     type Error = FieldValueConversionError;
 
@@ -124,6 +137,7 @@ impl_debug! {
     Vector2,
     Vector4,
     QAngle,
+    QAnglePitchYaw,
     String
 }
 
@@ -148,5 +162,6 @@ impl_display! {
     Vector2,
     Vector4,
     QAngle,
+    QAnglePitchYaw,
     String
 }
@@ -0,0 +1,124 @@
+//! callbacks that fire when a particular field changes, for example "tell me whenever any hero's
+//! `m_lifeState` flips". old values are kept only for watched fields.
+
+use std::collections::HashMap;
+
+use crate::entities::{DeltaHeader, Entity};
+use crate::fieldvalue::FieldValue;
+use crate::replaydiff::field_values_eq;
+
+#[derive(Debug)]
+pub struct FieldChange<'a> {
+    pub tick: i32,
+    pub index: i32,
+    pub serializer_name_hash: u64,
+    pub field_key: u64,
+    /// none if the field was seen for the first time (entity was created or the field was not
+    /// networked before).
+    pub old_value: Option<&'a FieldValue>,
+    pub new_value: &'a FieldValue,
+}
+
+type Callback = Box<dyn FnMut(&FieldChange)>;
+
+struct Watcher {
+    // NOTE: none matches any class.
+    serializer_name_hash: Option<u64>,
+    field_key: u64,
+    callback: Callback,
+}
+
+/// feed it with entity updates from [`crate::parser::Visitor::on_entity`] (see
+/// [`FieldWatchers::update`]).
+#[derive(Default)]
+pub struct FieldWatchers {
+    watchers: Vec<Watcher>,
+    // NOTE: keyed by entity index and field key.
+    values: HashMap<(i32, u64), FieldValue>,
+}
+
+impl FieldWatchers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// calls `callback` whenever the field with the given key (see
+    /// [`crate::entities::fkey_from_path`]) of an entity of the class with the given serializer
+    /// name hash changes; if `serializer_name_hash` is none entities of any class are watched
+    /// (useful for fields that a family of classes shares, like heroes).
+    pub fn watch(
+        &mut self,
+        serializer_name_hash: Option<u64>,
+        field_key: u64,
+        callback: impl FnMut(&FieldChange) + 'static,
+    ) {
+        self.watchers.push(Watcher {
+            serializer_name_hash,
+            field_key,
+            callback: Box::new(callback),
+        });
+    }
+
+    pub fn update(&mut self, tick: i32, delta_header: DeltaHeader, entity: &Entity) {
+        let index = entity.index();
+        let serializer_name_hash = entity.serializer().serializer_name.hash;
+        let is_watched = |watcher: &Watcher| {
+            watcher
+                .serializer_name_hash
+                .map_or(true, |hash| hash == serializer_name_hash)
+        };
+
+        // NOTE: values of previous entity that lived at the same index (/ of the same entity that
+        // got re-created) must not be compared against.
+        if delta_header == DeltaHeader::CREATE || delta_header == DeltaHeader::DELETE {
+            for watcher in self.watchers.iter().filter(|watcher| is_watched(watcher)) {
+                self.values.remove(&(index, watcher.field_key));
+            }
+        }
+        if delta_header == DeltaHeader::DELETE {
+            return;
+        }
+
+        // NOTE: values are updated in a separate pass because multiple watchers may be watching
+        // the same field.
+        for watcher in self
+            .watchers
+            .iter_mut()
+            .filter(|watcher| is_watched(watcher))
+        {
+            let Some(new_value) = entity.get(&watcher.field_key) else {
+                continue;
+            };
+            let old_value = self.values.get(&(index, watcher.field_key));
+            if old_value.is_some_and(|old_value| field_values_eq(old_value, new_value, 0.0)) {
+                continue;
+            }
+            (watcher.callback)(&FieldChange {
+                tick,
+                index,
+                serializer_name_hash,
+                field_key: watcher.field_key,
+                old_value,
+                new_value,
+            });
+        }
+        for watcher in self.watchers.iter().filter(|watcher| is_watched(watcher)) {
+            let Some(new_value) = entity.get(&watcher.field_key) else {
+                continue;
+            };
+            let key = (index, watcher.field_key);
+            if !self
+                .values
+                .get(&key)
+                .is_some_and(|old_value| field_values_eq(old_value, new_value, 0.0))
+            {
+                self.values.insert(key, new_value.clone());
+            }
+        }
+    }
+
+    /// forgets old values; watchers are kept.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
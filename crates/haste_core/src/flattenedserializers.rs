@@ -1,31 +1,179 @@
 use std::hash::BuildHasherDefault;
 use std::rc::Rc;
 
-use dungers::varint;
 use hashbrown::hash_map::Values;
 use hashbrown::HashMap;
 use nohash::NoHashHasher;
-use prost::{self, Message};
 use valveprotos::common::{
     CDemoSendTables, CsvcMsgFlattenedSerializer, ProtoFlattenedSerializerFieldT,
     ProtoFlattenedSerializerT,
 };
 
+use crate::bitreader::BitReader;
+use crate::fielddecoder::CustomDecoder;
 use crate::fieldmetadata::{
-    get_field_metadata, FieldMetadata, FieldMetadataError, FieldSpecialDescriptor,
+    get_fallback_field_metadata, get_field_metadata, FieldMetadata, FieldMetadataError,
+    FieldSpecialDescriptor,
 };
+#[cfg(feature = "preserve-metadata")]
+use crate::fieldpath::FieldPath;
+use crate::fieldvalue::FieldValue;
 use crate::fxhash;
+use crate::protobackend::{DecodeError, ProtoMessage};
+use crate::varint;
 
 #[derive(thiserror::Error, Debug)]
 pub enum FlattenedSerializersError {
     #[error(transparent)]
-    DecodeError(#[from] prost::DecodeError),
+    DecodeError(#[from] DecodeError),
     #[error(transparent)]
     ReadVarintError(#[from] varint::ReadVarintError),
     #[error(transparent)]
     FieldMetadataError(#[from] FieldMetadataError),
 }
 
+/// determines what happens with fields whose decoder can't be determined (for example because a
+/// game patch introduced a new var type or var encoder).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownFieldTypes {
+    /// fail parsing of flattened serializers.
+    #[default]
+    Fail,
+    /// decode values of such fields as unsigned varints (or fixed 64 bit integers, depending on
+    /// var encoder) and list them in [`FlattenedSerializerContainer::unknown_field_types`].
+    ///
+    /// NOTE: values of such fields are most likely garbage, and if fallback decoder reads wrong
+    /// number of bits all the following fields of the entity will be garbage too.
+    Fallback,
+}
+
+/// field for which [`UnknownFieldTypes::Fallback`] decoder is used.
+#[derive(Debug, Clone)]
+pub struct UnknownFieldType {
+    /// name of the first serializer in which the field was encountered (fields can be shared
+    /// between serializers).
+    pub serializer_name: Box<str>,
+    pub var_name: Box<str>,
+    pub var_type: Box<str>,
+    /// why decoder could not be determined.
+    pub reason: String,
+}
+
+/// how f32 values (and components of vectors) are encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum F32Encoding {
+    /// number of ticks; decoded value is multiplied by tick interval.
+    SimulationTime,
+    Coord,
+    Normal,
+    /// raw 32 bit float.
+    NoScale,
+    Quantized {
+        bit_count: i32,
+        low_value: f32,
+        high_value: f32,
+        encode_flags: i32,
+    },
+}
+
+/// how qangles are encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QAngleEncoding {
+    /// only pitch and yaw are networked; values are decoded into [`FieldValue::QAnglePitchYaw`].
+    PitchYaw {
+        bit_count: usize,
+    },
+    /// each component is optional and is 20 bits.
+    Precise,
+    /// each component is an optional coord.
+    Coord,
+    BitCount {
+        bit_count: usize,
+    },
+}
+
+/// see [`FlattenedSerializerField::encoding`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldEncoding {
+    F32(F32Encoding),
+    /// components of `Vector` / `Vector2D` / `Vector4D` are encoded as f32s.
+    Vector(F32Encoding),
+    /// normalized `Vector`; x and y are normals, z is derived from them.
+    VectorNormal,
+    QAngle(QAngleEncoding),
+}
+
+/// signature of user-provided field decoders. decoder must read exactly as many bits as the
+/// field's value occupies.
+pub type CustomFieldDecodeFn = dyn Fn(&mut BitReader) -> FieldValue;
+
+type CustomDecoderMap = HashMap<u64, CustomDecoder, BuildHasherDefault<NoHashHasher<u64>>>;
+
+/// user-provided decoders for var types or var encoders that are not supported (yet), or for
+/// overriding built-in ones.
+///
+/// decoders registered for var encoders take precedence over decoders registered for var types.
+/// var types are matched against the whole var type (for example `CHandle< CBaseEntity >`) and
+/// against identifiers within it (so that decoder registered for `CNewType` is also used for
+/// elements of `CNewType[4]` and `CNetworkUtlVectorBase< CNewType >`).
+#[derive(Clone, Default)]
+pub struct FieldDecoderRegistry {
+    by_var_type: CustomDecoderMap,
+    by_var_encoder: CustomDecoderMap,
+}
+
+impl FieldDecoderRegistry {
+    pub fn register_var_type<F>(&mut self, var_type: &str, decode: F)
+    where
+        F: Fn(&mut BitReader) -> FieldValue + 'static,
+    {
+        self.by_var_type.insert(
+            fxhash::hash_bytes(var_type.as_bytes()),
+            CustomDecoder::new(Rc::new(decode)),
+        );
+    }
+
+    pub fn register_var_encoder<F>(&mut self, var_encoder: &str, decode: F)
+    where
+        F: Fn(&mut BitReader) -> FieldValue + 'static,
+    {
+        self.by_var_encoder.insert(
+            fxhash::hash_bytes(var_encoder.as_bytes()),
+            CustomDecoder::new(Rc::new(decode)),
+        );
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.by_var_type.is_empty() && self.by_var_encoder.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn by_var_type_hash(&self, var_type_hash: u64) -> Option<&CustomDecoder> {
+        self.by_var_type.get(&var_type_hash)
+    }
+
+    #[inline]
+    pub(crate) fn by_var_encoder_hash(&self, var_encoder_hash: u64) -> Option<&CustomDecoder> {
+        self.by_var_encoder.get(&var_encoder_hash)
+    }
+}
+
+impl std::fmt::Debug for FieldDecoderRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldDecoderRegistry")
+            .field("var_types", &self.by_var_type.len())
+            .field("var_encoders", &self.by_var_encoder.len())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FlattenedSerializerOptions {
+    pub unknown_field_types: UnknownFieldTypes,
+    pub custom_decoders: FieldDecoderRegistry,
+}
+
 // TODO: symbol table / string cache (but do not use servo's string cache
 // because it's super slow; it relies on rust-phf that uses sip13 cryptograpgic
 // hasher, and you can't replace it with something else (without forking it
@@ -41,6 +189,14 @@ pub struct Symbol {
     pub str: Box<str>,
 }
 
+impl Symbol {
+    #[cfg(feature = "preserve-metadata")]
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        self.str.as_ref()
+    }
+}
+
 impl From<&String> for Symbol {
     #[inline(always)]
     fn from(value: &String) -> Self {
@@ -61,15 +217,13 @@ impl From<&String> for Symbol {
 // TODO: do not clone strings, but reference them instead -> introduce lifetimes
 // or build a symbol table from symbols (string cache?)
 
-/// note about missing `field_serializer_version` field (from
-/// [`valveprotos::common::ProtoFlattenedSerializerFieldT`]): i did not find any evidence of it
-/// being used nor any breakage or data corruptions. it is possible that i missed something. but
-/// unless proven otherwise i don't see a reason for incorporating it. field serializers always
-/// reference "highest" version of serializer.
+/// all fields are meant to be read-only; fields are shared between serializers (through [`Rc`]).
+///
+/// NOTE: names of symbols (`var_type`, `var_name`, etc.) are available only with
+/// `preserve-metadata` feature, otherwise only their hashes are kept.
 ///
-/// it might be reasonable to actually incorporate it if there will be a need to run this parser in
-/// an environment that processes high volumes of replays. theoretically flattened serializers can
-/// be parsed once and then reused for future parse passes.
+/// NOTE: `field_serializer_version` is not used for decoding. field serializers always reference
+/// "highest" version of serializer; it is kept for introspection only.
 #[derive(Debug, Clone, Default)]
 pub struct FlattenedSerializerField {
     pub var_type: Symbol,
@@ -80,17 +234,37 @@ pub struct FlattenedSerializerField {
     pub encode_flags: Option<i32>,
     pub field_serializer_name: Option<Symbol>,
     pub var_encoder: Option<Symbol>,
+    pub field_serializer_version: Option<i32>,
 
     pub field_serializer: Option<Rc<FlattenedSerializer>>,
+    /// serializers of types derived from the field's type; see [`PolymorphicType`].
+    pub polymorphic_types: Vec<PolymorphicType>,
     pub(crate) metadata: FieldMetadata,
 }
 
+/// some fields (pointers to a base class) can hold values of derived types, in which case their
+/// fields must be decoded with derived type's serializer instead of `field_serializer`.
+//
+// TODO(blukai): decoding of polymorphic fields. entities do not (yet) track which derived type
+// was selected for the field; children are decoded by `field_serializer`. i don't have a replay
+// that would allow to verify how the selection is networked.
+#[derive(Debug, Clone, Default)]
+pub struct PolymorphicType {
+    pub serializer_name: Symbol,
+    pub serializer_version: Option<i32>,
+    pub serializer: Option<Rc<FlattenedSerializer>>,
+}
+
 // TODO: try to split flattened serializer field initialization into 3 clearly separate stages
 // (protobuf mapping; metadata; field serializer construction).
 impl FlattenedSerializerField {
     fn new(
         msg: &CsvcMsgFlattenedSerializer,
         field: &ProtoFlattenedSerializerFieldT,
+        serializer_name: &str,
+        custom_decoders: &FieldDecoderRegistry,
+        // NOTE: none means that unknown field types are not allowed.
+        unknown_field_types: Option<&mut Vec<UnknownFieldType>>,
     ) -> Result<Self, FieldMetadataError> {
         // SAFETY: some symbols are cricual, if they don't exist - fail early
         // and loudly.
@@ -124,11 +298,41 @@ impl FlattenedSerializerField {
                 .map(resolve_sym)
                 .map(Symbol::from),
             var_encoder: field.var_encoder_sym.map(resolve_sym).map(Symbol::from),
+            field_serializer_version: field.field_serializer_version,
 
             field_serializer: None,
+            polymorphic_types: field
+                .polymorphic_types
+                .iter()
+                .filter_map(|polymorphic_type| {
+                    Some(PolymorphicType {
+                        serializer_name: polymorphic_type
+                            .polymorphic_field_serializer_name_sym
+                            .and_then(|i| msg.symbols.get(i as usize))
+                            .map(Symbol::from)?,
+                        serializer_version: polymorphic_type.polymorphic_field_serializer_version,
+                        serializer: None,
+                    })
+                })
+                .collect(),
             metadata: Default::default(),
         };
-        ret.metadata = get_field_metadata(&ret, var_type)?;
+        ret.metadata = match (
+            get_field_metadata(&ret, var_type, custom_decoders),
+            unknown_field_types,
+        ) {
+            (Ok(metadata), _) => metadata,
+            (Err(err), Some(unknown_field_types)) => {
+                unknown_field_types.push(UnknownFieldType {
+                    serializer_name: serializer_name.into(),
+                    var_name: var_name.as_str().into(),
+                    var_type: var_type.as_str().into(),
+                    reason: err.to_string(),
+                });
+                get_fallback_field_metadata(&ret, var_type, custom_decoders)
+            }
+            (Err(err), None) => return Err(err),
+        };
         Ok(ret)
     }
 
@@ -161,14 +365,68 @@ impl FlattenedSerializerField {
             .as_ref()
             .is_some_and(|sd| sd.is_dynamic_array())
     }
+
+    #[inline(always)]
+    pub(crate) fn is_fixed_array(&self) -> bool {
+        matches!(
+            self.metadata.special_descriptor,
+            Some(FieldSpecialDescriptor::FixedArray { .. })
+        )
+    }
+
+    /// length of the fixed array (for example `m_hItems: CHandle< CBaseEntity >[19]`).
+    #[inline]
+    pub fn fixed_array_length(&self) -> Option<usize> {
+        match self.metadata.special_descriptor {
+            Some(FieldSpecialDescriptor::FixedArray { length }) => Some(length),
+            _ => None,
+        }
+    }
+
+    /// wire encoding of float, vector and qangle fields (none for other fields and for fields with
+    /// custom decoders).
+    #[inline]
+    pub fn encoding(&self) -> Option<FieldEncoding> {
+        self.metadata.decoder.encoding()
+    }
+
+    #[inline]
+    pub fn is_polymorphic(&self) -> bool {
+        !self.polymorphic_types.is_empty()
+    }
+
+    /// looks up serializer of a derived type by its name hash.
+    pub fn polymorphic_serializer(
+        &self,
+        serializer_name_hash: u64,
+    ) -> Option<&Rc<FlattenedSerializer>> {
+        self.polymorphic_types
+            .iter()
+            .find(|polymorphic_type| polymorphic_type.serializer_name.hash == serializer_name_hash)
+            .and_then(|polymorphic_type| polymorphic_type.serializer.as_ref())
+    }
+
+    #[inline]
+    pub fn is_pointer(&self) -> bool {
+        matches!(
+            self.metadata.special_descriptor,
+            Some(FieldSpecialDescriptor::Pointer)
+        )
+    }
+
+    /// human readable description of the decoder that is used to decode values of this field (for
+    /// example `QuantizedFloatDecoder { .. }`). meant for debugging and documentation, the format
+    /// is not stable.
+    pub fn decoder_description(&self) -> String {
+        format!("{:?}", self.metadata.decoder)
+    }
 }
 
-/// note about missing `serializer_version` field (from
-/// [`valveprotos::common::ProtoFlattenedSerializerT`]): entities resolve their serializers by
-/// looking up their class info within the [crate::entityclasses::EntityClasses] struct (which i
-/// parse out of [`valveprotos::common::CDemoClassInfo`] proto).
-/// [`valveprotos::common::CDemoClassInfo`] carries absolutely no info about serializer version
-/// thus i don't see any need to preserve it.
+/// NOTE: entities resolve their serializers by looking up their class info within the
+/// [crate::entityclasses::EntityClasses] struct (which i parse out of
+/// [`valveprotos::common::CDemoClassInfo`] proto). [`valveprotos::common::CDemoClassInfo`] carries
+/// absolutely no info about serializer version; `serializer_version` is kept for introspection
+/// only.
 //
 // NOTE: Clone is derived because Entity in entities.rs needs to be clonable which means that all
 // members of it also should be clonable.
@@ -177,6 +435,7 @@ impl FlattenedSerializerField {
 #[derive(Debug, Clone, Default)]
 pub struct FlattenedSerializer {
     pub serializer_name: Symbol,
+    pub serializer_version: Option<i32>,
     pub fields: Vec<Rc<FlattenedSerializerField>>,
 }
 
@@ -194,6 +453,7 @@ impl FlattenedSerializer {
 
         Self {
             serializer_name: Symbol::from(serializer_name),
+            serializer_version: fs.serializer_version,
             fields: Vec::with_capacity(fs.fields_index.len()),
         }
     }
@@ -214,6 +474,37 @@ impl FlattenedSerializer {
     pub fn get_child(&self, index: usize) -> Option<&FlattenedSerializerField> {
         self.fields.get(index).map(|field| field.as_ref())
     }
+
+    /// dotted path (for example `m_vecPlayerData.3.m_iszPlayerName`) of the field; mirrors how
+    /// entity parser builds keys, thus [`crate::entities::fkey_from_dotted_path`] of the result is
+    /// the key of the field.
+    #[cfg(feature = "preserve-metadata")]
+    pub fn dotted_path(&self, fp: &FieldPath) -> String {
+        let mut path = String::new();
+        let mut field = fp.get(0).and_then(|i| self.get_child(i));
+        if let Some(field) = field {
+            path.push_str(field.var_name.as_str());
+        }
+        for i in fp.iter().skip(1).map(|i| *i as usize) {
+            path.push('.');
+            field = field.and_then(|f| {
+                if f.is_dynamic_array() {
+                    path.push_str(&i.to_string());
+                    f.get_child(0)
+                } else if f.fixed_array_length().is_some() {
+                    path.push_str(&i.to_string());
+                    f.get_child(i)
+                } else {
+                    let child = f.get_child(i);
+                    if let Some(child) = child {
+                        path.push_str(child.var_name.as_str());
+                    }
+                    child
+                }
+            });
+        }
+        path
+    }
 }
 
 type FieldMap = HashMap<i32, Rc<FlattenedSerializerField>, BuildHasherDefault<NoHashHasher<i32>>>;
@@ -221,10 +512,22 @@ type SerializerMap = HashMap<u64, Rc<FlattenedSerializer>, BuildHasherDefault<No
 
 pub struct FlattenedSerializerContainer {
     serializer_map: SerializerMap,
+    // NOTE: serializer names are kept regardless of preserve-metadata feature; there are only a
+    // few hundreds of them. ordered as they appear in the message.
+    serializer_names: Vec<(u64, Box<str>)>,
+    unknown_field_types: Vec<UnknownFieldType>,
 }
 
 impl FlattenedSerializerContainer {
+    #[inline]
     pub fn parse(cmd: CDemoSendTables) -> Result<Self, FlattenedSerializersError> {
+        Self::parse_with_options(cmd, &FlattenedSerializerOptions::default())
+    }
+
+    pub fn parse_with_options(
+        cmd: CDemoSendTables,
+        options: &FlattenedSerializerOptions,
+    ) -> Result<Self, FlattenedSerializersError> {
         let msg = {
             // TODO: make prost work with ByteString and turn data into Bytes
             //
@@ -240,7 +543,7 @@ impl FlattenedSerializerContainer {
             // -> createa a function that will be capable of reading varint from
             // &[u8] without multiple levels of indirection.
             let (_size, _count) = varint::read_uvarint64(&mut data)?;
-            CsvcMsgFlattenedSerializer::decode(data)?
+            CsvcMsgFlattenedSerializer::decode_message(data)?
         };
 
         let mut field_map: FieldMap =
@@ -249,9 +552,17 @@ impl FlattenedSerializerContainer {
             msg.serializers.len(),
             BuildHasherDefault::default(),
         );
+        let mut serializer_names: Vec<(u64, Box<str>)> = Vec::with_capacity(msg.serializers.len());
+        let mut unknown_field_types: Vec<UnknownFieldType> = Vec::new();
+        let allow_unknown_field_types = options.unknown_field_types == UnknownFieldTypes::Fallback;
 
         for serializer in msg.serializers.iter() {
             let mut flattened_serializer = FlattenedSerializer::new(&msg, serializer);
+            let serializer_name = serializer
+                .serializer_name_sym
+                .and_then(|i| msg.symbols.get(i as usize))
+                .map(|name| name.as_str())
+                .unwrap_or_default();
 
             for field_index in serializer.fields_index.iter() {
                 if let Some(field) = field_map.get(field_index) {
@@ -259,12 +570,26 @@ impl FlattenedSerializerContainer {
                     continue;
                 }
 
-                let mut field =
-                    FlattenedSerializerField::new(&msg, &msg.fields[*field_index as usize])?;
+                let mut field = FlattenedSerializerField::new(
+                    &msg,
+                    &msg.fields[*field_index as usize],
+                    serializer_name,
+                    &options.custom_decoders,
+                    allow_unknown_field_types.then_some(&mut unknown_field_types),
+                )?;
+
+                for polymorphic_type in field.polymorphic_types.iter_mut() {
+                    polymorphic_type.serializer = serializer_map
+                        .get(&polymorphic_type.serializer_name.hash)
+                        .cloned();
+                }
 
                 field.field_serializer = match field.metadata.special_descriptor {
                     Some(FieldSpecialDescriptor::FixedArray { length }) => {
                         let mut field = field.clone();
+                        // NOTE: elements are not arrays themselves; entity parser relies on this
+                        // when building field keys.
+                        field.metadata.special_descriptor = None;
                         field.field_serializer = field
                             .field_serializer_name
                             .as_ref()
@@ -315,16 +640,27 @@ impl FlattenedSerializerContainer {
                 flattened_serializer.fields.push(field);
             }
 
-            serializer_map.insert(
-                flattened_serializer.serializer_name.hash,
-                Rc::new(flattened_serializer),
-            );
+            let serializer_name_hash = flattened_serializer.serializer_name.hash;
+            if serializer_map
+                .insert(serializer_name_hash, Rc::new(flattened_serializer))
+                .is_none()
+            {
+                serializer_names.push((serializer_name_hash, serializer_name.into()));
+            }
         }
 
-        Ok(Self { serializer_map })
+        Ok(Self {
+            serializer_map,
+            serializer_names,
+            unknown_field_types,
+        })
     }
 
-    // TODO: think about exposing the whole serializer map
+    /// looks up serializer by its name (for example `CDOTA_Unit_Hero_Axe`).
+    #[inline]
+    pub fn by_name(&self, serializer_name: &str) -> Option<Rc<FlattenedSerializer>> {
+        self.by_name_hash(fxhash::hash_bytes(serializer_name.as_bytes()))
+    }
 
     #[inline(always)]
     pub fn by_name_hash(&self, serializer_name_hash: u64) -> Option<Rc<FlattenedSerializer>> {
@@ -348,4 +684,31 @@ impl FlattenedSerializerContainer {
     pub fn values(&self) -> Values<'_, u64, Rc<FlattenedSerializer>> {
         self.serializer_map.values()
     }
+
+    /// iterates over serializer names and serializers, in order in which they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Rc<FlattenedSerializer>)> {
+        self.serializer_names
+            .iter()
+            .filter_map(|(serializer_name_hash, serializer_name)| {
+                self.serializer_map
+                    .get(serializer_name_hash)
+                    .map(|serializer| (serializer_name.as_ref(), serializer))
+            })
+    }
+
+    /// fields that are decoded with fallback decoder; see [`UnknownFieldTypes::Fallback`].
+    #[inline]
+    pub fn unknown_field_types(&self) -> &[UnknownFieldType] {
+        &self.unknown_field_types
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.serializer_map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.serializer_map.is_empty()
+    }
 }
@@ -53,6 +53,13 @@
 //!
 //! a little bit more info on fx hash is available on
 //! <https://nnethercote.github.io/2021/12/08/a-brutally-effective-hash-function-in-rust.html>
+//!
+//! # stability
+//!
+//! values produced by [`add_u64_to_hash`], [`hash_bytes`] and [`hash_dotted_path`] are stable
+//! across releases (field keys and serializer name hashes are built with them and downstream code
+//! bakes those into binaries); changing any of them is a breaking change. tests at the bottom of
+//! this file pin a few values.
 
 // NOTE: u64 golden ration is stolen from
 // https://github.com/rust-lang/rustc-hash/blob/786ccda70fce97a3177d6088f21a22ac7f0f2f85/src/lib.rs#L67
@@ -87,3 +94,105 @@ pub const fn hash_bytes(bytes: &[u8]) -> u64 {
 
     hash
 }
+
+/// hashes dot separated path (for example `m_vecPlayerData.3.m_iszPlayerName`) part by part,
+/// without allocating; can be called from a const context. parts that consist only of digits are
+/// treated as array indices (hashed as `add_u64_to_hash(0, index)`), other parts are hashed with
+/// [`hash_bytes`]; part hashes are combined with [`add_u64_to_hash`].
+///
+/// `hash_dotted_path("a.b")` == `add_u64_to_hash(hash_bytes(b"a"), hash_bytes(b"b"))`.
+pub const fn hash_dotted_path(path: &str) -> u64 {
+    let bytes = path.as_bytes();
+
+    let mut hash = 0;
+    let mut is_first_part = true;
+
+    // state of the current part
+    let mut part_hash = 0;
+    let mut part_len = 0;
+    let mut index: Option<u64> = Some(0);
+
+    let mut i = 0;
+    while i <= bytes.len() {
+        if i == bytes.len() || bytes[i] == b'.' {
+            let part = match index {
+                Some(index) if part_len > 0 && !is_first_part => add_u64_to_hash(0, index),
+                _ => part_hash,
+            };
+            hash = if is_first_part {
+                part
+            } else {
+                add_u64_to_hash(hash, part)
+            };
+
+            is_first_part = false;
+            part_hash = 0;
+            part_len = 0;
+            index = Some(0);
+            i += 1;
+            continue;
+        }
+
+        let byte = bytes[i];
+        part_hash = add_u64_to_hash(part_hash, byte as u64);
+        part_len += 1;
+        index = match index {
+            Some(index) if byte.is_ascii_digit() => match index.checked_mul(10) {
+                Some(index) => index.checked_add((byte - b'0') as u64),
+                None => None,
+            },
+            _ => None,
+        };
+
+        i += 1;
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // NOTE: if any of these fail, stability guarantee is broken (see module docs).
+    #[test]
+    fn test_stability() {
+        assert_eq!(hash_bytes(b""), 0);
+        assert_eq!(hash_bytes(b"CDOTA_PlayerResource"), 0x830d881170d74f6b);
+        assert_eq!(hash_dotted_path("m_iHealth"), hash_bytes(b"m_iHealth"));
+        assert_eq!(
+            hash_dotted_path("m_vecPlayerData.3.m_iszPlayerName"),
+            0x984b1d1b59d2455e
+        );
+    }
+
+    #[test]
+    fn test_hash_dotted_path() {
+        let expected = add_u64_to_hash(
+            add_u64_to_hash(hash_bytes(b"m_vecPlayerData"), add_u64_to_hash(0, 3)),
+            hash_bytes(b"m_iszPlayerName"),
+        );
+        assert_eq!(
+            hash_dotted_path("m_vecPlayerData.3.m_iszPlayerName"),
+            expected
+        );
+
+        const KEY: u64 = hash_dotted_path("m_pGameRules.m_fGameTime");
+        assert_eq!(
+            KEY,
+            add_u64_to_hash(hash_bytes(b"m_pGameRules"), hash_bytes(b"m_fGameTime"))
+        );
+
+        // empty parts hash to 0, same as hash_bytes(b"")
+        assert_eq!(
+            hash_dotted_path("a..b"),
+            add_u64_to_hash(add_u64_to_hash(hash_bytes(b"a"), 0), hash_bytes(b"b"))
+        );
+
+        // indices that do not fit into u64 are hashed as regular parts
+        assert_eq!(
+            hash_dotted_path("a.99999999999999999999"),
+            add_u64_to_hash(hash_bytes(b"a"), hash_bytes(b"99999999999999999999"))
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! game time as shown by in-game clocks. game time is not networked directly, it's computed from
+//! net ticks and a bunch of gamerules fields; see `examples/deadlock-gametime.rs` for the manual
+//! version of this.
+
+use valveprotos::common::{CnetMsgTick, NetMessages};
+
+use crate::entities::{fkey_from_path, Entity};
+use crate::fxhash;
+use crate::protobackend::{DecodeError, ProtoMessage};
+
+pub const DOTA2_GAMERULES_ENTITY: u64 = fxhash::hash_bytes(b"CDOTAGamerulesProxy");
+pub const DEADLOCK_GAMERULES_ENTITY: u64 = fxhash::hash_bytes(b"CCitadelGameRulesProxy");
+
+/// duration of dota 2 pre-game (time between heroes being picked and the horn) in seconds.
+pub const DOTA2_DEFAULT_PRE_GAME_DURATION: f32 = 90.0;
+
+const GAME_START_TIME_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_flGameStartTime"]);
+const PRE_GAME_START_TIME_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_flPreGameStartTime"]);
+const GAME_PAUSED_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_bGamePaused"]);
+const PAUSE_START_TICK_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_nPauseStartTick"]);
+const TOTAL_PAUSED_TICKS_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_nTotalPausedTicks"]);
+
+// NOTE: 0.001 is an arbitrary number; nothing special. gamerules fields are 0 until the thing
+// happens.
+fn is_set(time: f32) -> bool {
+    time > 0.001
+}
+
+/// feed it with packets from [`crate::parser::Visitor::on_packet`] and entities from
+/// [`crate::parser::Visitor::on_entity`], and set tick interval once it's known (on
+/// `DemSyncTick` cmd, see [`crate::parser::Context::tick_interval`]).
+#[derive(Debug, Clone)]
+pub struct GameClock {
+    tick_interval: Option<f32>,
+    net_tick: u32,
+    game_start_time: f32,
+    pre_game_start_time: f32,
+    pre_game_duration: f32,
+    game_paused: bool,
+    pause_start_tick: i32,
+    total_paused_ticks: i32,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            tick_interval: None,
+            net_tick: 0,
+            game_start_time: 0.0,
+            pre_game_start_time: 0.0,
+            pre_game_duration: DOTA2_DEFAULT_PRE_GAME_DURATION,
+            game_paused: false,
+            pause_start_tick: 0,
+            total_paused_ticks: 0,
+        }
+    }
+}
+
+impl GameClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// pre-game duration is not networked; it differs between game modes (for example turbo).
+    pub fn set_pre_game_duration(&mut self, pre_game_duration: f32) {
+        self.pre_game_duration = pre_game_duration;
+    }
+
+    pub fn set_tick_interval(&mut self, tick_interval: f32) {
+        self.tick_interval = Some(tick_interval);
+    }
+
+    /// `NetTick` packets are handled, other packets are ignored.
+    pub fn update_from_packet(&mut self, packet_type: u32, data: &[u8]) -> Result<(), DecodeError> {
+        if packet_type == NetMessages::NetTick as u32 {
+            if let Some(net_tick) = CnetMsgTick::decode_message(data)?.tick {
+                self.net_tick = net_tick;
+            }
+        }
+        Ok(())
+    }
+
+    /// dota 2 and deadlock gamerules entities are handled, other entities are ignored.
+    pub fn update_from_entity(&mut self, entity: &Entity) {
+        if !entity.serializer_name_heq(DOTA2_GAMERULES_ENTITY)
+            && !entity.serializer_name_heq(DEADLOCK_GAMERULES_ENTITY)
+        {
+            return;
+        }
+
+        if let Some(game_start_time) = entity.get_value(&GAME_START_TIME_KEY) {
+            self.game_start_time = game_start_time;
+        }
+        if let Some(pre_game_start_time) = entity.get_value(&PRE_GAME_START_TIME_KEY) {
+            self.pre_game_start_time = pre_game_start_time;
+        }
+        if let Some(game_paused) = entity.get_value(&GAME_PAUSED_KEY) {
+            self.game_paused = game_paused;
+        }
+        if let Some(pause_start_tick) = entity.get_value(&PAUSE_START_TICK_KEY) {
+            self.pause_start_tick = pause_start_tick;
+        }
+        if let Some(total_paused_ticks) = entity.get_value(&TOTAL_PAUSED_TICKS_KEY) {
+            self.total_paused_ticks = total_paused_ticks;
+        }
+    }
+
+    #[inline]
+    pub fn net_tick(&self) -> u32 {
+        self.net_tick
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.game_paused
+    }
+
+    /// true once the horn sounded (creeps spawned in dota 2 / game started in deadlock).
+    #[inline]
+    pub fn has_started(&self) -> bool {
+        is_set(self.game_start_time)
+    }
+
+    /// time in seconds that passed since the server started, excluding pauses. the clock stops
+    /// while the game is paused.
+    pub fn unpaused_time(&self) -> Option<f32> {
+        let tick_interval = self.tick_interval?;
+        // NOTE: total paused ticks are updated once the pause ends; during the pause clock is
+        // frozen at the tick at which the pause started.
+        let tick = if self.game_paused {
+            self.pause_start_tick
+        } else {
+            self.net_tick as i32
+        };
+        Some((tick - self.total_paused_ticks) as f32 * tick_interval)
+    }
+
+    /// game time in seconds as of the current net tick. negative during dota 2 pre-game (the clock
+    /// counts down to the horn), none if neither pre-game nor game started yet (or tick interval is
+    /// unknown).
+    pub fn game_time(&self) -> Option<f32> {
+        let time = self.unpaused_time()?;
+        if is_set(self.game_start_time) {
+            Some(time - self.game_start_time)
+        } else if is_set(self.pre_game_start_time) {
+            Some(time - (self.pre_game_start_time + self.pre_game_duration))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entities::DeltaHeader;
+    use crate::fieldvalue::FieldValue;
+    use crate::parser::{Context, Parser, Visitor};
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    // NOTE: 0.5 keeps all the math exact.
+    const TICK_INTERVAL: f32 = 0.5;
+
+    struct GameClockVisitor {
+        clock: GameClock,
+        game_times: Vec<(i32, Option<f32>, bool)>,
+    }
+
+    impl Visitor for GameClockVisitor {
+        fn on_entity(
+            &mut self,
+            _ctx: &Context,
+            _delta_header: DeltaHeader,
+            entity: &Entity,
+        ) -> anyhow::Result<()> {
+            self.clock.update_from_entity(entity);
+            Ok(())
+        }
+
+        fn on_packet(
+            &mut self,
+            _ctx: &Context,
+            packet_type: u32,
+            data: &[u8],
+        ) -> anyhow::Result<()> {
+            Ok(self.clock.update_from_packet(packet_type, data)?)
+        }
+
+        fn on_tick_end(&mut self, ctx: &Context) -> anyhow::Result<()> {
+            if ctx.tick() > 0 {
+                self.game_times
+                    .push((ctx.tick(), self.clock.game_time(), self.clock.is_paused()));
+            }
+            Ok(())
+        }
+    }
+
+    fn net_tick(wtr: &mut SyntheticDemoWriter, tick: u32) {
+        let msg = CnetMsgTick {
+            tick: Some(tick),
+            ..Default::default()
+        };
+        wtr.packet_message(NetMessages::NetTick as u32, msg.encode_message_to_vec());
+    }
+
+    #[test]
+    fn test_game_time() -> anyhow::Result<()> {
+        let game_rules = SyntheticClass::new("CDOTAGamerules")
+            .field("m_flGameStartTime", SyntheticFieldType::Float32)
+            .field("m_flPreGameStartTime", SyntheticFieldType::Float32)
+            .field("m_bGamePaused", SyntheticFieldType::Bool)
+            .field("m_nPauseStartTick", SyntheticFieldType::Int32)
+            .field("m_nTotalPausedTicks", SyntheticFieldType::Int32);
+        let classes = vec![SyntheticClass::new("CDOTAGamerulesProxy")
+            .field("m_pGameRules", SyntheticFieldType::Pointer(game_rules))];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+
+        // nothing started yet
+        net_tick(&mut wtr, 10);
+        wtr.create(1, "CDOTAGamerulesProxy", &[])?;
+        wtr.write_tick(1)?;
+        // pre-game; counts down to the horn
+        net_tick(&mut wtr, 20);
+        wtr.update(
+            1,
+            &[("m_pGameRules.m_flPreGameStartTime", FieldValue::F32(5.0))],
+        )?;
+        wtr.write_tick(2)?;
+        // horn
+        net_tick(&mut wtr, 200);
+        wtr.update(
+            1,
+            &[("m_pGameRules.m_flGameStartTime", FieldValue::F32(95.0))],
+        )?;
+        wtr.write_tick(3)?;
+        // paused at net tick 210; clock is frozen
+        net_tick(&mut wtr, 220);
+        wtr.update(
+            1,
+            &[
+                ("m_pGameRules.m_bGamePaused", FieldValue::Bool(true)),
+                ("m_pGameRules.m_nPauseStartTick", FieldValue::I64(210)),
+            ],
+        )?;
+        wtr.write_tick(4)?;
+        // unpaused at net tick 250
+        net_tick(&mut wtr, 260);
+        wtr.update(
+            1,
+            &[
+                ("m_pGameRules.m_bGamePaused", FieldValue::Bool(false)),
+                ("m_pGameRules.m_nTotalPausedTicks", FieldValue::I64(40)),
+            ],
+        )?;
+        wtr.write_tick(5)?;
+
+        let mut clock = GameClock::new();
+        clock.set_tick_interval(TICK_INTERVAL);
+        let visitor = GameClockVisitor {
+            clock,
+            game_times: Vec::new(),
+        };
+        let mut parser = Parser::from_stream_with_visitor(wtr.finish_into_demo_file()?, visitor)?;
+        parser.run_to_end()?;
+
+        assert_eq!(
+            parser.visitor().game_times,
+            vec![
+                (1, None, false),
+                (2, Some(20.0 * TICK_INTERVAL - (5.0 + 90.0)), false),
+                (3, Some(200.0 * TICK_INTERVAL - 95.0), false),
+                (4, Some(210.0 * TICK_INTERVAL - 95.0), true),
+                (5, Some((260.0 - 40.0) * TICK_INTERVAL - 95.0), false),
+            ]
+        );
+        assert!(parser.visitor().clock.has_started());
+        assert_eq!(parser.visitor().clock.net_tick(), 260);
+
+        Ok(())
+    }
+}
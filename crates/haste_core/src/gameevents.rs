@@ -0,0 +1,407 @@
+use std::collections::HashMap as StdHashMap;
+use std::hash::BuildHasherDefault;
+
+use hashbrown::HashMap;
+use nohash::NoHashHasher;
+use valveprotos::common::{
+    c_msg_source1_legacy_game_event, CMsgSource1LegacyGameEvent, CMsgSource1LegacyGameEventList,
+};
+
+use crate::stringtables::StringTableContainer;
+use crate::userinfo::{self, PlayerInfo};
+
+// NOTE: key types are defined in game/shared/igameevents.h (or somewhere near). only "values" of
+// those matter here, names are for humans.
+//
+// TYPE_LOCAL = 0, // not networked
+// TYPE_STRING,    // zero terminated ASCII string
+// TYPE_FLOAT,     // float 32 bit
+// TYPE_LONG,      // signed int 32 bit
+// TYPE_SHORT,     // signed int 16 bit
+// TYPE_BYTE,      // unsigned int 8 bit
+// TYPE_BOOL,      // unsigned int 1 bit
+// TYPE_UINT64,    // unsigned int 64 bit
+// TYPE_WSTRING,   // zero terminated wide char string
+const KEY_TYPE_STRING: i32 = 1;
+const KEY_TYPE_FLOAT: i32 = 2;
+const KEY_TYPE_LONG: i32 = 3;
+const KEY_TYPE_SHORT: i32 = 4;
+const KEY_TYPE_BYTE: i32 = 5;
+const KEY_TYPE_BOOL: i32 = 6;
+const KEY_TYPE_UINT64: i32 = 7;
+
+#[derive(Debug, Clone)]
+pub struct GameEventKeyDescriptor {
+    pub name: Box<str>,
+    pub r#type: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct GameEventDescriptor {
+    pub event_id: i32,
+    pub name: Box<str>,
+    pub keys: Vec<GameEventKeyDescriptor>,
+}
+
+/// list of game event descriptors; arrives in signon packets as
+/// [`CMsgSource1LegacyGameEventList`].
+#[derive(Debug, Default)]
+pub struct GameEventList {
+    descriptors: HashMap<i32, GameEventDescriptor, BuildHasherDefault<NoHashHasher<i32>>>,
+}
+
+impl GameEventList {
+    pub fn parse(msg: CMsgSource1LegacyGameEventList) -> Self {
+        let descriptors = msg
+            .descriptors
+            .into_iter()
+            .map(|descriptor| {
+                let event_id = descriptor.eventid();
+                let descriptor = GameEventDescriptor {
+                    event_id,
+                    name: descriptor.name().into(),
+                    keys: descriptor
+                        .keys
+                        .iter()
+                        .map(|key| GameEventKeyDescriptor {
+                            name: key.name().into(),
+                            r#type: key.r#type(),
+                        })
+                        .collect(),
+                };
+                (event_id, descriptor)
+            })
+            .collect();
+        Self { descriptors }
+    }
+
+    #[inline]
+    pub fn get(&self, event_id: i32) -> Option<&GameEventDescriptor> {
+        self.descriptors.get(&event_id)
+    }
+
+    /// linear search; prefer [`GameEventList::get`] when event id is known.
+    pub fn find_by_name(&self, name: &str) -> Option<&GameEventDescriptor> {
+        self.descriptors
+            .values()
+            .find(|descriptor| descriptor.name.as_ref().eq(name))
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &GameEventDescriptor> {
+        self.descriptors.values()
+    }
+
+    /// pairs values of the given event with key names from its descriptor. returns `None` if
+    /// there's no descriptor for the event.
+    pub fn decode<'a>(&'a self, msg: &CMsgSource1LegacyGameEvent) -> Option<GameEvent<'a>> {
+        let descriptor = self.get(msg.eventid())?;
+        let values = descriptor
+            .keys
+            .iter()
+            .zip(msg.keys.iter())
+            .map(|(key, value)| (key.name.as_ref(), EventValue::from_key(key.r#type, value)))
+            .collect();
+        Some(GameEvent { descriptor, values })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventValue {
+    String(Box<str>),
+    F32(f32),
+    I32(i32),
+    Bool(bool),
+    U64(u64),
+}
+
+impl EventValue {
+    fn from_key(r#type: i32, key: &c_msg_source1_legacy_game_event::KeyT) -> Self {
+        match r#type {
+            KEY_TYPE_STRING => Self::String(key.val_string().into()),
+            KEY_TYPE_FLOAT => Self::F32(key.val_float()),
+            KEY_TYPE_LONG => Self::I32(key.val_long()),
+            KEY_TYPE_SHORT => Self::I32(key.val_short()),
+            KEY_TYPE_BYTE => Self::I32(key.val_byte()),
+            KEY_TYPE_BOOL => Self::Bool(key.val_bool()),
+            KEY_TYPE_UINT64 => Self::U64(key.val_uint64()),
+            // NOTE: newer types (player controller, entity handles, etc.) are networked as longs
+            // (at least as far as observed).
+            _ => Self::I32(key.val_long()),
+        }
+    }
+
+    // NOTE: getters below do not convert between variants; long, short and byte keys all are
+    // [`EventValue::I32`].
+
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(v) => Some(v.as_ref()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Self::F32(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Self::I32(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::U64(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameEvent<'a> {
+    descriptor: &'a GameEventDescriptor,
+    values: Vec<(&'a str, EventValue)>,
+}
+
+impl<'a> GameEvent<'a> {
+    #[inline]
+    pub fn name(&self) -> &'a str {
+        self.descriptor.name.as_ref()
+    }
+
+    #[inline]
+    pub fn event_id(&self) -> i32 {
+        self.descriptor.event_id
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &(&'a str, EventValue)> {
+        self.values.iter()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&EventValue> {
+        self.values
+            .iter()
+            .find_map(|(name, value)| (*name == key).then_some(value))
+    }
+
+    /// none if there's no such key or if the value is not a string; same goes for the other typed
+    /// getters (see [`EventValue::as_str`] and friends).
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(EventValue::as_str)
+    }
+
+    pub fn get_f32(&self, key: &str) -> Option<f32> {
+        self.get(key).and_then(EventValue::as_f32)
+    }
+
+    pub fn get_i32(&self, key: &str) -> Option<i32> {
+        self.get(key).and_then(EventValue::as_i32)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(EventValue::as_bool)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(EventValue::as_u64)
+    }
+
+    /// key names to values; values are cloned (see [`GameEvent::into_map`]).
+    pub fn to_map(&self) -> StdHashMap<&'a str, EventValue> {
+        self.values.iter().cloned().collect()
+    }
+
+    pub fn into_map(self) -> StdHashMap<&'a str, EventValue> {
+        self.values.into_iter().collect()
+    }
+
+    /// resolves player that the key (for example `userid`, `attacker` or `victim`) refers to
+    /// through `userinfo` string table (see [`userinfo::find_player_by_userid`]); use
+    /// [`PlayerInfo::controller_index`] to get to player's entity.
+    pub fn get_player(
+        &self,
+        key: &str,
+        string_tables: &StringTableContainer,
+    ) -> Option<PlayerInfo> {
+        let userid = self.get_i32(key)?;
+        userinfo::find_player_by_userid(&userinfo::players(string_tables), userid).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use valveprotos::common::{
+        c_demo_string_tables, c_msg_source1_legacy_game_event_list, CDemoStringTables,
+        CMsgPlayerInfo,
+    };
+
+    use super::*;
+    use crate::protobackend::ProtoMessage;
+
+    // NOTE: not a real key type; newer ones are networked as longs.
+    const KEY_TYPE_UNKNOWN: i32 = 100;
+
+    fn game_event_list() -> GameEventList {
+        let keys = [
+            ("name", KEY_TYPE_STRING),
+            ("value", KEY_TYPE_FLOAT),
+            ("userid", KEY_TYPE_LONG),
+            ("attacker", KEY_TYPE_SHORT),
+            ("team", KEY_TYPE_BYTE),
+            ("headshot", KEY_TYPE_BOOL),
+            ("steamid", KEY_TYPE_UINT64),
+            ("pawn", KEY_TYPE_UNKNOWN),
+        ];
+        GameEventList::parse(CMsgSource1LegacyGameEventList {
+            descriptors: vec![c_msg_source1_legacy_game_event_list::DescriptorT {
+                eventid: Some(7),
+                name: Some("player_hurt".to_string()),
+                keys: keys
+                    .iter()
+                    .map(
+                        |(name, r#type)| c_msg_source1_legacy_game_event_list::KeyT {
+                            r#type: Some(*r#type),
+                            name: Some(name.to_string()),
+                        },
+                    )
+                    .collect(),
+            }],
+        })
+    }
+
+    fn key(
+        r#type: i32,
+        f: impl FnOnce(&mut c_msg_source1_legacy_game_event::KeyT),
+    ) -> c_msg_source1_legacy_game_event::KeyT {
+        let mut key = c_msg_source1_legacy_game_event::KeyT {
+            r#type: Some(r#type),
+            ..Default::default()
+        };
+        f(&mut key);
+        key
+    }
+
+    fn player_hurt() -> CMsgSource1LegacyGameEvent {
+        CMsgSource1LegacyGameEvent {
+            eventid: Some(7),
+            keys: vec![
+                key(KEY_TYPE_STRING, |key| key.val_string = Some("axe".into())),
+                key(KEY_TYPE_FLOAT, |key| key.val_float = Some(1.5)),
+                key(KEY_TYPE_LONG, |key| key.val_long = Some(1001)),
+                key(KEY_TYPE_SHORT, |key| key.val_short = Some(-2)),
+                key(KEY_TYPE_BYTE, |key| key.val_byte = Some(3)),
+                key(KEY_TYPE_BOOL, |key| key.val_bool = Some(true)),
+                key(KEY_TYPE_UINT64, |key| key.val_uint64 = Some(u64::MAX)),
+                key(KEY_TYPE_UNKNOWN, |key| key.val_long = Some(42)),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_typed_getters() -> anyhow::Result<()> {
+        let list = game_event_list();
+        let msg = player_hurt();
+        let event = list
+            .decode(&msg)
+            .ok_or_else(|| anyhow::anyhow!("no descriptor"))?;
+        assert_eq!(event.name(), "player_hurt");
+        assert_eq!(event.event_id(), 7);
+
+        assert_eq!(event.get_str("name"), Some("axe"));
+        assert_eq!(event.get_f32("value"), Some(1.5));
+        assert_eq!(event.get_i32("userid"), Some(1001));
+        assert_eq!(event.get_i32("attacker"), Some(-2));
+        assert_eq!(event.get_i32("team"), Some(3));
+        assert_eq!(event.get_bool("headshot"), Some(true));
+        assert_eq!(event.get_u64("steamid"), Some(u64::MAX));
+        // NOTE: unknown key types fall back to longs.
+        assert_eq!(event.get("pawn"), Some(&EventValue::I32(42)));
+
+        // NOTE: getters do not convert between variants.
+        assert_eq!(event.get_f32("name"), None);
+        assert_eq!(event.get_u64("userid"), None);
+        assert_eq!(event.get_i32("missing"), None);
+
+        let map = event.to_map();
+        assert_eq!(map.len(), 8);
+        assert_eq!(map.get("name"), Some(&EventValue::String("axe".into())));
+        assert_eq!(event.clone().into_map(), map);
+
+        // NOTE: events without descriptor can't be decoded.
+        let msg = CMsgSource1LegacyGameEvent {
+            eventid: Some(8),
+            ..Default::default()
+        };
+        assert!(list.decode(&msg).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_player() -> anyhow::Result<()> {
+        let mut string_tables = StringTableContainer::default();
+        string_tables.create_string_table_mut(userinfo::USERINFO_TABLE_NAME, false, 0, 0, 0, true);
+        let players = [(0, 1000), (2, 1001)];
+        string_tables.do_full_update(CDemoStringTables {
+            tables: vec![c_demo_string_tables::TableT {
+                table_name: Some(userinfo::USERINFO_TABLE_NAME.to_string()),
+                // NOTE: slot 1 is empty.
+                items: (0..3)
+                    .map(|slot| c_demo_string_tables::ItemsT {
+                        str: None,
+                        data: players
+                            .iter()
+                            .find(|(player_slot, _)| *player_slot == slot)
+                            .map(|(_, userid)| {
+                                CMsgPlayerInfo {
+                                    name: Some(format!("player {slot}")),
+                                    userid: Some(*userid),
+                                    ..Default::default()
+                                }
+                                .encode_message_to_vec()
+                            }),
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+        });
+
+        let list = game_event_list();
+        let msg = player_hurt();
+        let event = list
+            .decode(&msg)
+            .ok_or_else(|| anyhow::anyhow!("no descriptor"))?;
+        let player = event
+            .get_player("userid", &string_tables)
+            .ok_or_else(|| anyhow::anyhow!("player was not resolved"))?;
+        assert_eq!(player.slot, 2);
+        assert_eq!(player.name, "player 2");
+        assert_eq!(player.controller_index(), 3);
+
+        // NOTE: there's no player with user id -2; steamid is not a long.
+        assert!(event.get_player("attacker", &string_tables).is_none());
+        assert!(event.get_player("steamid", &string_tables).is_none());
+
+        Ok(())
+    }
+}
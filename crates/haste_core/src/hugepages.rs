@@ -0,0 +1,135 @@
+//! large zeroed buffers that are (optionally) backed by 2mb huge pages on linux; fewer tlb misses
+//! on write-heavy paths that touch multi-megabyte buffers all over. see
+//! [`crate::parser::ParserOptions::hugepages`].
+//!
+//! NOTE: transparent huge pages are requested with `madvise(MADV_HUGEPAGE)` on a 2mb-aligned
+//! allocation; if thp is disabled (or the platform is not linux) the kernel just keeps regular
+//! pages, there's no error to handle.
+
+use std::alloc::{self, Layout};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+#[cfg(target_os = "linux")]
+fn advise_hugepages(ptr: *mut u8, len: usize) {
+    // NOTE: std links libc on linux anyway, there's no need for a dependency for one function.
+    extern "C" {
+        fn madvise(
+            addr: *mut std::ffi::c_void,
+            len: usize,
+            advice: std::ffi::c_int,
+        ) -> std::ffi::c_int;
+    }
+    // linux/mman.h (same on all architectures)
+    const MADV_HUGEPAGE: std::ffi::c_int = 14;
+
+    // NOTE: result is ignored on purpose; regular pages are a fine fallback.
+    unsafe {
+        madvise(ptr.cast(), len, MADV_HUGEPAGE);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_hugepages(_ptr: *mut u8, _len: usize) {}
+
+/// fixed-size zeroed byte buffer; derefs to a slice.
+pub struct HugePageBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    // NOTE: none for empty buffers; nothing is allocated for them.
+    layout: Option<Layout>,
+}
+
+// SAFETY: the buffer owns its memory exclusively, same as Vec<u8>.
+unsafe impl Send for HugePageBuffer {}
+unsafe impl Sync for HugePageBuffer {}
+
+impl HugePageBuffer {
+    /// with `hugepages` false this is a plain zeroed allocation (no different from
+    /// `vec![0; len]`).
+    pub fn zeroed(len: usize, hugepages: bool) -> Self {
+        if len == 0 {
+            return Self {
+                ptr: NonNull::dangling(),
+                len,
+                layout: None,
+            };
+        }
+
+        // NOTE: huge page backed allocations are rounded up to a whole number of huge pages;
+        // partial huge pages are not a thing.
+        let (size, align) = if hugepages {
+            (len.next_multiple_of(HUGE_PAGE_SIZE), HUGE_PAGE_SIZE)
+        } else {
+            (len, 1)
+        };
+        let Ok(layout) = Layout::from_size_align(size, align) else {
+            alloc::handle_alloc_error(Layout::new::<u8>());
+        };
+        // SAFETY: layout is not zero-sized.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+        if hugepages {
+            advise_hugepages(ptr.as_ptr(), size);
+        }
+
+        Self {
+            ptr,
+            len,
+            layout: Some(layout),
+        }
+    }
+}
+
+impl Drop for HugePageBuffer {
+    fn drop(&mut self) {
+        if let Some(layout) = self.layout {
+            // SAFETY: allocated in zeroed with this exact layout.
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), layout) };
+        }
+    }
+}
+
+impl Deref for HugePageBuffer {
+    type Target = [u8];
+
+    #[inline(always)]
+    fn deref(&self) -> &[u8] {
+        // SAFETY: ptr points to len initialized (zeroed) bytes, or is dangling with len 0.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for HugePageBuffer {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: see deref.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_zeroed() {
+        for hugepages in [false, true] {
+            let mut buf = HugePageBuffer::zeroed(3 * 1024 * 1024 + 7, hugepages);
+            assert_eq!(buf.len(), 3 * 1024 * 1024 + 7);
+            assert!(buf.iter().all(|b| *b == 0));
+            buf[0] = 1;
+            let last = buf.len() - 1;
+            buf[last] = 2;
+            assert_eq!((buf[0], buf[last]), (1, 2));
+            if hugepages {
+                assert_eq!(buf.as_ptr() as usize % HUGE_PAGE_SIZE, 0);
+            }
+        }
+        assert!(HugePageBuffer::zeroed(0, true).is_empty());
+    }
+}
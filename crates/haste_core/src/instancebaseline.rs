@@ -1,14 +1,65 @@
-use std::cell::UnsafeCell;
-use std::num::ParseIntError;
-use std::rc::Rc;
+//! raw instance baselines (initial field state of entities, per class) from `instancebaseline`
+//! string table; see [`crate::parser::Context::instance_baseline`]. parser decodes them into
+//! baseline entities (see [`crate::entities::EntityContainer::get_baseline`]), raw data is what
+//! own tooling can be fed with.
 
 use crate::stringtables::StringTable;
 
 pub(crate) const INSTANCE_BASELINE_TABLE_NAME: &str = "instancebaseline";
 
+// NOTE: baselines rarely change mid-game; this bounds memory in case some demo updates them
+// constantly. when the limit is reached the oldest version is dropped.
+pub const MAX_VERSIONS_PER_CLASS: usize = 16;
+
+#[derive(thiserror::Error, Debug)]
+pub enum InstanceBaselineError {
+    #[error("instancebaseline entry #{entry_index} has no string")]
+    MissingString { entry_index: i32 },
+    #[error("invalid class id {string:?} in instancebaseline entry #{entry_index}")]
+    InvalidClassId { entry_index: i32, string: String },
+    #[error("class id {class_id} is out of range (classes {classes})")]
+    ClassIdOutOfRange { class_id: i32, classes: usize },
+    #[error("no baseline for class id {class_id}")]
+    MissingBaseline { class_id: i32 },
+    #[error("baseline data of class id {class_id} (tick {tick}) was dropped")]
+    DroppedBaseline { class_id: i32, tick: i32 },
+}
+
+/// parses class id out of the key (string) of instancebaseline entry. normally it is just the
+/// number, but some builds (seen in deadlock) pad keys with nul bytes / whitespace or append a
+/// suffix after a separator (for example `123:1`); those are normalized. none for keys that don't
+/// start with a number or where the number is followed by anything other than a separator.
+fn parse_class_id(key: &[u8]) -> Option<i32> {
+    let key = key.trim_ascii_start();
+    let end = key
+        .iter()
+        .rposition(|b| *b != 0 && !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    let key = &key[..end];
+    let digits = key.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 || key.get(digits).is_some_and(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    // NOTE: ascii digits are valid utf8.
+    std::str::from_utf8(&key[..digits])
+        .ok()?
+        .parse::<i32>()
+        .ok()
+}
+
+struct BaselineVersion {
+    /// tick at which this version came into effect.
+    tick: i32,
+    // NOTE: none once dropped; see ParserOptions::drop_baseline_data.
+    data: Option<Box<[u8]>>,
+}
+
+// NOTE: baselines are versioned by tick such that entities that are created after seeking
+// backwards (or while handling full packets) get the baseline that was in effect at that tick, not
+// the latest one.
 #[derive(Default)]
-pub(crate) struct InstanceBaseline {
-    data: Vec<Option<Rc<UnsafeCell<Vec<u8>>>>>,
+pub struct InstanceBaseline {
+    data: Vec<Vec<BaselineVersion>>,
 }
 
 impl InstanceBaseline {
@@ -16,32 +67,151 @@ impl InstanceBaseline {
         &mut self,
         string_table: &StringTable,
         classes: usize,
-    ) -> Result<(), ParseIntError> {
+        tick: i32,
+    ) -> Result<(), InstanceBaselineError> {
         if self.data.len() < classes {
-            self.data.resize(classes, None);
+            self.data.resize_with(classes, Vec::new);
         }
 
-        for (_entity_index, item) in string_table.items() {
-            // SAFETY: in normal circumbstances this is safe; it is expected for
-            // instancebaseline's string to be convertable to number, if it
-            // cannot be converted to number - fail loudly!
-            let string =
-                unsafe { std::str::from_utf8_unchecked(item.string.as_ref().unwrap_unchecked()) };
-            let class_id = string.parse::<i32>()?;
-            self.data[class_id as usize] = item.user_data.clone();
+        for (entry_index, item) in string_table.items() {
+            // NOTE: it is expected for instancebaseline's string to be convertable to number (see
+            // parse_class_id), if it cannot be converted to number - fail loudly!
+            let string = item
+                .string
+                .as_ref()
+                .ok_or(InstanceBaselineError::MissingString {
+                    entry_index: *entry_index,
+                })?;
+            let class_id =
+                parse_class_id(string).ok_or_else(|| InstanceBaselineError::InvalidClassId {
+                    entry_index: *entry_index,
+                    string: String::from_utf8_lossy(string).to_string(),
+                })?;
+            let Some(user_data) = item.user_data() else {
+                continue;
+            };
+
+            let Some(versions) = usize::try_from(class_id)
+                .ok()
+                .and_then(|class_id| self.data.get_mut(class_id))
+            else {
+                return Err(InstanceBaselineError::ClassIdOutOfRange {
+                    class_id,
+                    classes: self.data.len(),
+                });
+            };
+            // NOTE: position of the first version that came into effect after the tick.
+            let position = versions.partition_point(|version| version.tick <= tick);
+            if let Some(in_effect) = position.checked_sub(1).map(|i| &mut versions[i]) {
+                if in_effect.data.as_deref() == Some(user_data) {
+                    continue;
+                }
+                if in_effect.tick == tick {
+                    in_effect.data = Some(user_data.into());
+                    continue;
+                }
+            }
+            versions.insert(
+                position,
+                BaselineVersion {
+                    tick,
+                    data: Some(user_data.into()),
+                },
+            );
+            if versions.len() > MAX_VERSIONS_PER_CLASS {
+                versions.remove(0);
+            }
         }
         Ok(())
     }
 
+    /// returns tick at which returned baseline came into effect and baseline data that was in
+    /// effect at the given tick (pass [`i32::MAX`] for the latest one); see
+    /// [`Self::by_id_unchecked`].
+    pub fn by_id(&self, class_id: i32, tick: i32) -> Result<(i32, &[u8]), InstanceBaselineError> {
+        let (version_tick, data) = self.version_by_id(class_id, tick)?;
+        let data = data.ok_or(InstanceBaselineError::DroppedBaseline {
+            class_id,
+            tick: version_tick,
+        })?;
+        Ok((version_tick, data))
+    }
+
+    /// same as [`Self::by_id`], but data of dropped versions is none instead of an error; the tick
+    /// is all that is needed as long as the decoded baseline entity is still around.
+    pub(crate) fn version_by_id(
+        &self,
+        class_id: i32,
+        tick: i32,
+    ) -> Result<(i32, Option<&[u8]>), InstanceBaselineError> {
+        let versions = usize::try_from(class_id)
+            .ok()
+            .and_then(|class_id| self.data.get(class_id))
+            .ok_or(InstanceBaselineError::ClassIdOutOfRange {
+                class_id,
+                classes: self.data.len(),
+            })?;
+        let position = versions
+            .partition_point(|version| version.tick <= tick)
+            .max(1);
+        let version = versions
+            .get(position - 1)
+            .ok_or(InstanceBaselineError::MissingBaseline { class_id })?;
+        Ok((version.tick, version.data.as_deref()))
+    }
+
+    /// returns tick at which returned baseline came into effect (can be used to tell versions
+    /// apart) and baseline data. if there's no version that is old enough, the oldest one is
+    /// returned. data of dropped versions is none.
     #[inline]
-    pub(crate) unsafe fn by_id_unchecked(&self, class_id: i32) -> &[u8] {
-        unsafe {
-            &*self
-                .data
-                .get_unchecked(class_id as usize)
-                .as_ref()
-                .unwrap_unchecked()
-                .get()
+    pub(crate) unsafe fn by_id_unchecked(&self, class_id: i32, tick: i32) -> (i32, Option<&[u8]>) {
+        let versions = unsafe { self.data.get_unchecked(class_id as usize) };
+        let position = versions
+            .partition_point(|version| version.tick <= tick)
+            .max(1);
+        let version = unsafe { versions.get_unchecked(position - 1) };
+        (version.tick, version.data.as_deref())
+    }
+
+    /// latest baseline data of each class that has one: class id, tick at which it came into
+    /// effect and data. dropped versions are not included.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &[u8])> {
+        self.data
+            .iter()
+            .enumerate()
+            .filter_map(|(class_id, versions)| {
+                let version = versions.last()?;
+                let data = version.data.as_deref()?;
+                Some((class_id as i32, version.tick, data))
+            })
+    }
+
+    /// all retained versions of baseline data of the class, oldest first: tick at which each
+    /// came into effect and data. see also [`MAX_VERSIONS_PER_CLASS`].
+    pub fn versions(&self, class_id: i32) -> impl Iterator<Item = (i32, &[u8])> {
+        usize::try_from(class_id)
+            .ok()
+            .and_then(|class_id| self.data.get(class_id))
+            .into_iter()
+            .flatten()
+            .filter_map(|version| Some((version.tick, version.data.as_deref()?)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.iter().all(Vec::is_empty)
+    }
+
+    /// frees data of the version that came into effect at the given tick; see
+    /// [`crate::parser::ParserOptions::drop_baseline_data`].
+    pub(crate) fn drop_data(&mut self, class_id: i32, tick: i32) {
+        let Some(versions) = usize::try_from(class_id)
+            .ok()
+            .and_then(|class_id| self.data.get_mut(class_id))
+        else {
+            return;
+        };
+        if let Some(version) = versions.iter_mut().find(|version| version.tick == tick) {
+            version.data = None;
         }
     }
 
@@ -50,3 +220,60 @@ impl InstanceBaseline {
         self.data.clear();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_class_id() {
+        assert_eq!(parse_class_id(b"0"), Some(0));
+        assert_eq!(parse_class_id(b"1234"), Some(1234));
+        assert_eq!(parse_class_id(b"12345678"), Some(12345678));
+        assert_eq!(parse_class_id(b"42\0\0"), Some(42));
+        assert_eq!(parse_class_id(b" 42 \0"), Some(42));
+        assert_eq!(parse_class_id(b"42:1"), Some(42));
+        assert_eq!(parse_class_id(b"42_extra"), Some(42));
+
+        assert_eq!(parse_class_id(b""), None);
+        assert_eq!(parse_class_id(b"\0\0"), None);
+        assert_eq!(parse_class_id(b"abc"), None);
+        assert_eq!(parse_class_id(b"42abc"), None);
+        assert_eq!(parse_class_id(b"-1"), None);
+        assert_eq!(parse_class_id(b"99999999999"), None);
+        assert_eq!(parse_class_id(&[0xff, b'1']), None);
+    }
+
+    #[test]
+    fn test_dropped_data() -> Result<(), InstanceBaselineError> {
+        let mut instance_baseline = InstanceBaseline {
+            data: vec![vec![
+                BaselineVersion {
+                    tick: -1,
+                    data: Some(Box::from(&b"old"[..])),
+                },
+                BaselineVersion {
+                    tick: 5,
+                    data: Some(Box::from(&b"new"[..])),
+                },
+            ]],
+        };
+        instance_baseline.drop_data(0, 5);
+
+        assert_eq!(instance_baseline.by_id(0, 4)?, (-1, &b"old"[..]));
+        assert!(matches!(
+            instance_baseline.by_id(0, 5),
+            Err(InstanceBaselineError::DroppedBaseline {
+                class_id: 0,
+                tick: 5
+            })
+        ));
+        assert_eq!(instance_baseline.version_by_id(0, i32::MAX)?, (5, None));
+        assert_eq!(
+            unsafe { instance_baseline.by_id_unchecked(0, 6) },
+            (5, None)
+        );
+
+        Ok(())
+    }
+}
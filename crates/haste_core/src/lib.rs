@@ -2,22 +2,92 @@
 #![deny(clippy::expect_used)]
 #![deny(clippy::panic)]
 
+// NOTE: attributes allocations of the rest of the enclosing block to a subsystem (see
+// allocstats); expands to nothing without alloc-stats feature. must stay above mod declarations.
+macro_rules! alloc_scope {
+    ($subsystem:ident) => {
+        #[cfg(feature = "alloc-stats")]
+        let _alloc_scope = $crate::allocstats::scope($crate::allocstats::Subsystem::$subsystem);
+    };
+}
+
 // TODO: figure pub scopes for all the things
+#[cfg(feature = "dota2")]
+pub mod abilities;
+#[cfg(feature = "alloc-stats")]
+pub mod allocstats;
 pub mod bitreader;
+pub mod bitwriter;
+pub mod camera;
+pub mod changelog;
+#[cfg(feature = "cs2")]
+pub mod cs2;
+#[cfg(feature = "deadlock")]
+pub mod deadlock;
+pub mod demobuffer;
 pub mod demofile;
+pub mod demoindex;
+pub mod demosplit;
 pub mod demostream;
+pub mod demoverify;
+pub mod demowriter;
+pub mod digest;
 pub mod entities;
 pub mod entityclasses;
+pub mod entitycounts;
+pub mod entityhistory;
+pub mod entityquery;
+pub mod fieldbits;
 pub(crate) mod fielddecoder;
+pub mod fieldhistory;
+#[cfg(feature = "debug-field-keys")]
+pub mod fieldkeys;
 pub(crate) mod fieldmetadata;
 pub mod fieldpath;
 pub mod fieldvalue;
+pub mod fieldwatch;
 pub mod flattenedserializers;
 pub mod fxhash;
-pub(crate) mod instancebaseline;
+pub mod game;
+pub mod gameclock;
+pub mod gameevents;
+pub mod hugepages;
+pub mod instancebaseline;
+pub mod limits;
+pub mod maps;
+#[cfg(feature = "dota2")]
+pub mod matchinfo;
+#[cfg(feature = "dota2")]
+pub mod modifiers;
+#[cfg(feature = "dota2")]
+pub mod names;
+pub mod nohash;
+pub mod packetmessages;
 pub mod parser;
+pub mod parsermetrics;
+#[cfg(feature = "dota2")]
+pub mod playerstats;
+pub mod prefetchreader;
+#[cfg(feature = "dota2")]
+pub mod projectiles;
+pub mod protobackend;
+pub mod protoscan;
 pub(crate) mod quantizedfloat;
+pub mod replaydiff;
+#[cfg(feature = "http")]
+pub mod replaydownload;
+#[cfg(feature = "preserve-metadata")]
+pub mod schema;
+pub mod serializerregistry;
+pub mod sink;
+pub mod stringtablelog;
 pub mod stringtables;
+pub mod stringtablesubscriptions;
+#[cfg(any(test, feature = "test-support"))]
+pub mod syntheticdemo;
+pub mod userinfo;
+pub mod varint;
+pub mod wellknowntables;
 
 // own crate re-exports
 pub(crate) use haste_vartype as vartype;
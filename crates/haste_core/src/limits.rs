@@ -0,0 +1,120 @@
+//! hard limits on resources that parsing of a single demo may consume; protects services that
+//! parse untrusted demos (which may be maliciously crafted or just pathological) from being taken
+//! down by one of them. see [`crate::parser::ParserOptions::resource_limits`].
+//!
+//! parser fails with [`ResourceLimitError`] once a limit is exceeded; parser errors are
+//! [`anyhow::Error`]s, use `err.downcast_ref::<ResourceLimitError>()` to tell them apart.
+
+use std::time::{Duration, Instant};
+
+/// all limits are off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// number of entities (including the ones that are out of pvs); checked after each packet
+    /// entities message.
+    pub max_entities: Option<usize>,
+    /// approximate number of bytes that field state of entities (including the ones that are out
+    /// of pvs and baselines) occupies, see [`crate::entities::EntityContainer::field_state_bytes`];
+    /// checked at the end of each tick.
+    pub max_field_state_bytes: Option<usize>,
+    /// time spent inside of parser's run methods (time between the calls is not counted); checked
+    /// before each cmd.
+    pub max_wall_time: Option<Duration>,
+    /// size of a single cmd body or a single packet message. compressed cmd bodies are checked
+    /// twice: as stored in the demo, and once decompressed. string table data that the parser
+    /// decompresses itself is checked against length that snappy header claims before it is
+    /// decompressed.
+    ///
+    /// NOTE: cmd bodies are decompressed by the demo stream, thus their decompressed size is only
+    /// checked after the fact; [`crate::demofile::DemoFile`] decompresses into a buffer of
+    /// fixed size ([`crate::demofile::DEMO_RECORD_BUFFER_SIZE`]) and fails on anything larger.
+    pub max_message_size: Option<usize>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ResourceLimitError {
+    #[error("too many entities ({count}, limit is {limit})")]
+    TooManyEntities { count: usize, limit: usize },
+    #[error("field state is too large (~{bytes} bytes, limit is {limit})")]
+    FieldStateTooLarge { bytes: usize, limit: usize },
+    #[error("parsing took too long ({elapsed:?}, limit is {limit:?})")]
+    WallTimeExceeded { elapsed: Duration, limit: Duration },
+    #[error("message of {size} bytes is too large (limit is {limit})")]
+    MessageTooLarge { size: usize, limit: usize },
+}
+
+impl ResourceLimits {
+    #[inline]
+    pub(crate) fn check_entities(&self, count: usize) -> Result<(), ResourceLimitError> {
+        match self.max_entities {
+            Some(limit) if count > limit => {
+                Err(ResourceLimitError::TooManyEntities { count, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn check_field_state_bytes(&self, bytes: usize) -> Result<(), ResourceLimitError> {
+        match self.max_field_state_bytes {
+            Some(limit) if bytes > limit => {
+                Err(ResourceLimitError::FieldStateTooLarge { bytes, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn check_message_size(&self, size: usize) -> Result<(), ResourceLimitError> {
+        match self.max_message_size {
+            Some(limit) if size > limit => Err(ResourceLimitError::MessageTooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// accumulates time spent inside of parser's run methods; see
+/// [`ResourceLimits::max_wall_time`].
+#[derive(Debug, Default)]
+pub(crate) struct Watchdog {
+    limit: Option<Duration>,
+    elapsed: Duration,
+    started_at: Option<Instant>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(limit: Option<Duration>) -> Self {
+        Self {
+            limit,
+            elapsed: Duration::ZERO,
+            started_at: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn start(&mut self) {
+        if self.limit.is_some() && self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+    }
+
+    #[inline]
+    pub(crate) fn stop(&mut self) {
+        if let Some(started_at) = self.started_at.take() {
+            self.elapsed += started_at.elapsed();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn check(&self) -> Result<(), ResourceLimitError> {
+        let (Some(limit), Some(started_at)) = (self.limit, self.started_at) else {
+            return Ok(());
+        };
+        let elapsed = self.elapsed + started_at.elapsed();
+        if elapsed > limit {
+            Err(ResourceLimitError::WallTimeExceeded { elapsed, limit })
+        } else {
+            Ok(())
+        }
+    }
+}
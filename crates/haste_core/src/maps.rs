@@ -0,0 +1,130 @@
+//! per-map world bounds and minimap transforms. world coordinates of entities can be composed
+//! from cells and offsets (see `*_coord_from_cell` functions in [`crate::entities`]); this module
+//! maps them onto the minimap.
+
+use std::collections::HashMap;
+
+use crate::entities::{fkey_from_path, Entity};
+
+const MINIMAP_MINS_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_vMinimapMins"]);
+const MINIMAP_MAXS_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_vMinimapMaxs"]);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapInfo {
+    pub name: Box<str>,
+    /// distinguishes versions of the same map (for example dota 2 map before and after 7.33 which
+    /// made it bigger); none for maps that have a single known version.
+    pub version: Option<Box<str>>,
+    /// world coords (x, y) of the bottom left corner of the minimap.
+    pub world_min: [f32; 2],
+    /// world coords (x, y) of the top right corner of the minimap.
+    pub world_max: [f32; 2],
+}
+
+impl MapInfo {
+    pub fn new(name: &str, world_min: [f32; 2], world_max: [f32; 2]) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            world_min,
+            world_max,
+        }
+    }
+
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// deadlock networks minimap bounds in gamerules (`CCitadelGameRulesProxy`); none if the
+    /// entity does not have them.
+    pub fn from_game_rules(name: &str, entity: &Entity) -> Option<Self> {
+        let mins: [f32; 3] = entity.get_value(&MINIMAP_MINS_KEY)?;
+        let maxs: [f32; 3] = entity.get_value(&MINIMAP_MAXS_KEY)?;
+        Some(Self::new(name, [mins[0], mins[1]], [maxs[0], maxs[1]]))
+    }
+
+    /// normalized minimap coords of the given world position; (0, 0) is the top left corner, (1,
+    /// 1) is the bottom right corner (same as in images). positions outside of the map bounds
+    /// produce values outside of 0..1 range.
+    pub fn to_minimap_coords(&self, position: [f32; 3]) -> [f32; 2] {
+        let width = self.world_max[0] - self.world_min[0];
+        let height = self.world_max[1] - self.world_min[1];
+        [
+            (position[0] - self.world_min[0]) / width,
+            // NOTE: world y grows upwards, image y grows downwards.
+            1.0 - (position[1] - self.world_min[1]) / height,
+        ]
+    }
+
+    /// inverse of [`Self::to_minimap_coords`]; z is 0.
+    pub fn minimap_to_world_coords(&self, coords: [f32; 2]) -> [f32; 3] {
+        let width = self.world_max[0] - self.world_min[0];
+        let height = self.world_max[1] - self.world_min[1];
+        [
+            self.world_min[0] + coords[0] * width,
+            self.world_min[1] + (1.0 - coords[1]) * height,
+            0.0,
+        ]
+    }
+}
+
+/// known maps. maps are keyed by name (as in `CSVCMsg_ServerInfo.map_name`); when there are
+/// multiple versions of the same map the one that was registered last is the default.
+#[derive(Debug, Clone)]
+pub struct MapRegistry {
+    maps: HashMap<Box<str>, Vec<MapInfo>>,
+}
+
+impl Default for MapRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        // NOTE: dota 2 bounds are not networked; these are taken from minimap images of the
+        // corresponding versions and are approximate (good enough for heatmaps).
+        registry.register(
+            MapInfo::new("dota", [-8288.0, -8288.0], [8288.0, 8288.0]).with_version("pre-7.33"),
+        );
+        registry.register(
+            MapInfo::new("dota", [-10240.0, -10240.0], [10240.0, 10240.0]).with_version("7.33"),
+        );
+        // NOTE: deadlock values are what `CCitadelGameRulesProxy` networks (see
+        // MapInfo::from_game_rules).
+        registry.register(MapInfo::new(
+            "street_test",
+            [-8960.0, -8960.0],
+            [8960.0, 8960.0],
+        ));
+        registry
+    }
+}
+
+impl MapRegistry {
+    pub fn empty() -> Self {
+        Self {
+            maps: HashMap::new(),
+        }
+    }
+
+    /// replaces map with the same name and version if there's one.
+    pub fn register(&mut self, map_info: MapInfo) {
+        let versions = self.maps.entry(map_info.name.clone()).or_default();
+        versions.retain(|v| v.version != map_info.version);
+        versions.push(map_info);
+    }
+
+    /// latest registered version of the map.
+    pub fn get(&self, name: &str) -> Option<&MapInfo> {
+        self.maps.get(name)?.last()
+    }
+
+    pub fn get_version(&self, name: &str, version: &str) -> Option<&MapInfo> {
+        self.maps
+            .get(name)?
+            .iter()
+            .find(|map_info| map_info.version.as_deref() == Some(version))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MapInfo> {
+        self.maps.values().flatten()
+    }
+}
@@ -0,0 +1,161 @@
+//! dota 2 match metadata (match id, lobby type, game mode, league id). it is available in two
+//! places: gamerules entity (networked from the very beginning) and `CDemoFileInfo` (written at
+//! the end of the demo, missing in demos that were not finished / are being streamed).
+
+use valveprotos::common::CDemoFileInfo;
+
+use crate::entities::{fkey_from_path, Entity, EntityContainer};
+use crate::gameclock::DOTA2_GAMERULES_ENTITY;
+
+const MATCH_ID_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_unMatchID64"]);
+const LOBBY_TYPE_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_lobbyType"]);
+const GAME_MODE_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_iGameMode"]);
+const LEAGUE_ID_KEY: u64 = fkey_from_path(&["m_pGameRules", "m_lobbyLeagueID"]);
+
+/// zero values are treated as missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchInfo {
+    pub match_id: Option<u64>,
+    /// `DOTA_lobby_type` value (for example 7 is ranked matchmaking). not present in
+    /// `CDemoFileInfo`.
+    pub lobby_type: Option<i32>,
+    /// `DOTA_GameMode` value (for example 22 is all draft).
+    pub game_mode: Option<i32>,
+    pub league_id: Option<u32>,
+}
+
+fn non_zero<T: Default + PartialEq>(v: T) -> Option<T> {
+    (v != T::default()).then_some(v)
+}
+
+impl MatchInfo {
+    pub fn from_file_info(file_info: &CDemoFileInfo) -> Self {
+        let Some(dota) = file_info
+            .game_info
+            .as_ref()
+            .and_then(|game_info| game_info.dota.as_ref())
+        else {
+            return Self::default();
+        };
+        Self {
+            match_id: non_zero(dota.match_id()),
+            lobby_type: None,
+            game_mode: non_zero(dota.game_mode()),
+            league_id: non_zero(dota.leagueid()),
+        }
+    }
+
+    pub fn from_game_rules(entity: &Entity) -> Self {
+        Self {
+            match_id: entity.get_value(&MATCH_ID_KEY).and_then(non_zero),
+            // NOTE: lobby type 0 is public matchmaking, it's as valid as any other value.
+            lobby_type: entity.get_value(&LOBBY_TYPE_KEY),
+            game_mode: entity.get_value(&GAME_MODE_KEY).and_then(non_zero),
+            league_id: entity.get_value(&LEAGUE_ID_KEY).and_then(non_zero),
+        }
+    }
+
+    /// fills missing values with values from `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            match_id: self.match_id.or(other.match_id),
+            lobby_type: self.lobby_type.or(other.lobby_type),
+            game_mode: self.game_mode.or(other.game_mode),
+            league_id: self.league_id.or(other.league_id),
+        }
+    }
+
+    /// true if match id is known.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.match_id.is_some()
+    }
+}
+
+/// collects match info from gamerules entity, falling back to file info (see
+/// [`crate::demofile::DemoFile::file_info`]) for values that are missing. both sources are
+/// optional.
+pub fn match_info(
+    entities: Option<&EntityContainer>,
+    file_info: Option<&CDemoFileInfo>,
+) -> MatchInfo {
+    let from_entities = entities
+        .and_then(|entities| {
+            entities
+                .iter()
+                .find(|(_, entity)| entity.serializer_name_heq(DOTA2_GAMERULES_ENTITY))
+        })
+        .map(|(_, entity)| MatchInfo::from_game_rules(entity))
+        .unwrap_or_default();
+    let from_file_info = file_info.map(MatchInfo::from_file_info).unwrap_or_default();
+    from_entities.or(from_file_info)
+}
+
+#[cfg(test)]
+mod test {
+    use valveprotos::common::{c_game_info, CGameInfo};
+
+    use super::*;
+    use crate::fieldvalue::FieldValue;
+    use crate::parser::Parser;
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    #[test]
+    fn test_match_info() -> anyhow::Result<()> {
+        let game_rules = SyntheticClass::new("CDOTAGamerules")
+            .field("m_unMatchID64", SyntheticFieldType::UInt64)
+            .field("m_lobbyType", SyntheticFieldType::Int32)
+            .field("m_iGameMode", SyntheticFieldType::Int32)
+            .field("m_lobbyLeagueID", SyntheticFieldType::UInt64);
+        let classes = vec![SyntheticClass::new("CDOTAGamerulesProxy")
+            .field("m_pGameRules", SyntheticFieldType::Pointer(game_rules))];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        // NOTE: league id is left zero, thus is missing.
+        wtr.create(
+            1,
+            "CDOTAGamerulesProxy",
+            &[
+                ("m_pGameRules.m_unMatchID64", FieldValue::U64(123)),
+                ("m_pGameRules.m_iGameMode", FieldValue::I64(22)),
+            ],
+        )?;
+        wtr.write_tick(1)?;
+
+        let mut parser = Parser::from_stream(wtr.finish_into_demo_file()?)?;
+        parser.run_to_end()?;
+
+        let file_info = CDemoFileInfo {
+            game_info: Some(CGameInfo {
+                dota: Some(c_game_info::CDotaGameInfo {
+                    match_id: Some(456),
+                    game_mode: Some(1),
+                    leagueid: Some(42),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            match_info(parser.context().entities(), Some(&file_info)),
+            MatchInfo {
+                match_id: Some(123),
+                lobby_type: Some(0),
+                game_mode: Some(22),
+                league_id: Some(42),
+            }
+        );
+        assert_eq!(
+            match_info(None, Some(&file_info)),
+            MatchInfo {
+                match_id: Some(456),
+                lobby_type: None,
+                game_mode: Some(1),
+                league_id: Some(42),
+            }
+        );
+
+        Ok(())
+    }
+}
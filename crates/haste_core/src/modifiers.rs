@@ -0,0 +1,400 @@
+//! dota 2 modifiers (buffs / debuffs). modifiers are not networked as entities, they live in
+//! `ActiveModifiers` string table; user data of each entry is `CDOTAModifierBuffTableEntry`.
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use nohash::NoHashHasher;
+use valveprotos::dota2::{CdotaModifierBuffTableEntry, DotaModifierEntryType};
+
+use crate::entities::{ehandle_to_index, is_ehandle_valid};
+use crate::protobackend::{DecodeError, ProtoMessage};
+use crate::stringtables::{StringTable, StringTableContainer};
+
+pub const ACTIVE_MODIFIERS_TABLE_NAME: &str = "ActiveModifiers";
+/// `modifier_class` of [`Modifier`] is an index of an entry in this table.
+pub const MODIFIER_NAMES_TABLE_NAME: &str = "ModifierNames";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier {
+    /// handle of the entity that the modifier is attached to.
+    pub parent: u32,
+    /// index of the modifier within parent's modifier list.
+    pub index: i32,
+    pub serial_num: i32,
+    pub modifier_class: i32,
+    /// handle of the entity that applied the modifier.
+    pub caster: Option<u32>,
+    /// handle of the ability entity that applied the modifier.
+    pub ability: Option<u32>,
+    pub ability_level: i32,
+    pub stack_count: i32,
+    pub creation_time: f32,
+    /// -1 for modifiers that do not expire.
+    pub duration: f32,
+}
+
+impl Modifier {
+    #[inline]
+    pub fn parent_index(&self) -> i32 {
+        ehandle_to_index(self.parent)
+    }
+
+    #[inline]
+    pub fn caster_index(&self) -> Option<i32> {
+        self.caster.map(ehandle_to_index)
+    }
+
+    #[inline]
+    pub fn ability_index(&self) -> Option<i32> {
+        self.ability.map(ehandle_to_index)
+    }
+
+    /// true if the modifier has a duration and it ran out as of the given game time (in the same
+    /// clock as [`Modifier::creation_time`]).
+    #[inline]
+    pub fn is_expired(&self, game_time: f32) -> bool {
+        self.duration >= 0.0 && self.creation_time + self.duration <= game_time
+    }
+
+    /// looks up modifier's name (for example `modifier_item_bottle`) in `ModifierNames` table.
+    pub fn name<'st>(&self, string_tables: &'st StringTableContainer) -> Option<&'st str> {
+        string_tables
+            .find_table(MODIFIER_NAMES_TABLE_NAME)?
+            .get_item(&self.modifier_class)?
+            .key()
+    }
+}
+
+fn valid_ehandle(handle: u32) -> Option<u32> {
+    is_ehandle_valid(handle).then_some(handle)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModifierEntry {
+    Active(Modifier),
+    /// modifier with the given index was removed from the parent.
+    Removed {
+        parent: u32,
+        index: i32,
+    },
+}
+
+/// decodes user data of `ActiveModifiers` entry.
+pub fn decode_modifier_entry(user_data: &[u8]) -> Result<ModifierEntry, DecodeError> {
+    let msg = CdotaModifierBuffTableEntry::decode_message(user_data)?;
+    if msg.entry_type() == DotaModifierEntryType::Removed {
+        return Ok(ModifierEntry::Removed {
+            parent: msg.parent(),
+            index: msg.index(),
+        });
+    }
+    Ok(ModifierEntry::Active(Modifier {
+        parent: msg.parent(),
+        index: msg.index(),
+        serial_num: msg.serial_num(),
+        modifier_class: msg.modifier_class(),
+        caster: valid_ehandle(msg.caster()),
+        ability: valid_ehandle(msg.ability()),
+        ability_level: msg.ability_level(),
+        stack_count: msg.stack_count(),
+        creation_time: msg.creation_time(),
+        duration: msg.duration(),
+    }))
+}
+
+type ModifierKey = (i32, i32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveModifier {
+    pub modifier: Modifier,
+    /// tick at which the modifier was seen for the first time; stays the same when the modifier
+    /// gets refreshed (for example when stack count changes).
+    pub start_tick: i32,
+}
+
+/// keeps track of modifiers that are currently active. feed it with `ActiveModifiers` table
+/// updates from [`crate::parser::Visitor::on_string_table_update`], and call
+/// [`ModifierTracker::expire`] with current game time (for example on tick end) to drop modifiers
+/// that ran out.
+#[derive(Debug, Default)]
+pub struct ModifierTracker {
+    // NOTE: keyed by parent entity index and modifier index.
+    active: HashMap<ModifierKey, ActiveModifier>,
+    by_parent: HashMap<i32, Vec<i32>, BuildHasherDefault<NoHashHasher<i32>>>,
+}
+
+impl ModifierTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// applies changed entries of `ActiveModifiers` table; other tables are ignored.
+    pub fn update(&mut self, tick: i32, string_table: &StringTable) -> Result<(), DecodeError> {
+        if string_table.name() != ACTIVE_MODIFIERS_TABLE_NAME {
+            return Ok(());
+        }
+
+        for entry_index in string_table.changed_entries() {
+            let Some(user_data) = string_table
+                .get_item(entry_index)
+                .and_then(|item| item.user_data())
+            else {
+                continue;
+            };
+            match decode_modifier_entry(user_data)? {
+                ModifierEntry::Active(modifier) => self.insert(tick, modifier),
+                ModifierEntry::Removed { parent, index } => {
+                    self.remove(ehandle_to_index(parent), index)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, tick: i32, modifier: Modifier) {
+        let key = (modifier.parent_index(), modifier.index);
+        match self.active.get_mut(&key) {
+            // NOTE: serial number changes when a different modifier takes the slot.
+            Some(active) if active.modifier.serial_num == modifier.serial_num => {
+                active.modifier = modifier;
+            }
+            Some(active) => {
+                *active = ActiveModifier {
+                    modifier,
+                    start_tick: tick,
+                };
+            }
+            None => {
+                self.active.insert(
+                    key,
+                    ActiveModifier {
+                        modifier,
+                        start_tick: tick,
+                    },
+                );
+                self.by_parent.entry(key.0).or_default().push(key.1);
+            }
+        }
+    }
+
+    fn remove(&mut self, parent_index: i32, index: i32) {
+        if self.active.remove(&(parent_index, index)).is_some() {
+            if let Some(indices) = self.by_parent.get_mut(&parent_index) {
+                indices.retain(|i| *i != index);
+            }
+        }
+    }
+
+    /// removes modifiers whose duration ran out as of the given game time (see
+    /// [`Modifier::is_expired`]). removals are normally networked, but entries of modifiers that
+    /// expire while their parent is out of pvs (/ near the end of the demo) may be never removed.
+    pub fn expire(&mut self, game_time: f32) {
+        let expired: Vec<ModifierKey> = self
+            .active
+            .iter()
+            .filter(|(_, active)| active.modifier.is_expired(game_time))
+            .map(|(key, _)| *key)
+            .collect();
+        for (parent_index, index) in expired {
+            self.remove(parent_index, index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.active.clear();
+        self.by_parent.clear();
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &ActiveModifier> {
+        self.active.values()
+    }
+
+    /// active modifiers of the entity with the given index; same as [`Self::by_parent`].
+    #[inline]
+    pub fn entity_modifiers(&self, index: i32) -> impl Iterator<Item = &ActiveModifier> {
+        self.by_parent(index)
+    }
+
+    /// active modifiers of the entity with the given index.
+    pub fn by_parent(&self, parent_index: i32) -> impl Iterator<Item = &ActiveModifier> {
+        self.by_parent
+            .get(&parent_index)
+            .into_iter()
+            .flatten()
+            .filter_map(move |index| self.active.get(&(parent_index, *index)))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use valveprotos::common::{c_demo_string_tables, CDemoStringTables};
+
+    use super::*;
+    use crate::game::EngineConstants;
+
+    const PARENT: i32 = 5;
+
+    fn entry(index: i32, serial_num: i32, stack_count: i32) -> CdotaModifierBuffTableEntry {
+        CdotaModifierBuffTableEntry {
+            parent: Some(PARENT as u32),
+            index: Some(index),
+            serial_num: Some(serial_num),
+            modifier_class: Some(7),
+            stack_count: Some(stack_count),
+            creation_time: Some(10.0),
+            duration: Some(5.0),
+            caster: Some(EngineConstants::DOTA2.invalid_ehandle()),
+            ability: Some(42),
+            ..Default::default()
+        }
+    }
+
+    fn removed(index: i32) -> CdotaModifierBuffTableEntry {
+        let mut msg = CdotaModifierBuffTableEntry {
+            parent: Some(PARENT as u32),
+            index: Some(index),
+            ..Default::default()
+        };
+        msg.set_entry_type(DotaModifierEntryType::Removed);
+        msg
+    }
+
+    /// full update of `ActiveModifiers` table; entries are placed in the given order.
+    fn active_modifiers(entries: &[CdotaModifierBuffTableEntry]) -> CDemoStringTables {
+        CDemoStringTables {
+            tables: vec![c_demo_string_tables::TableT {
+                table_name: Some(ACTIVE_MODIFIERS_TABLE_NAME.to_string()),
+                items: entries
+                    .iter()
+                    .map(|entry| c_demo_string_tables::ItemsT {
+                        str: None,
+                        data: Some(entry.encode_message_to_vec()),
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_decode_modifier_entry() -> anyhow::Result<()> {
+        let ModifierEntry::Active(modifier) =
+            decode_modifier_entry(&entry(2, 10, 1).encode_message_to_vec())?
+        else {
+            return Err(anyhow::anyhow!("expected active modifier"));
+        };
+        assert_eq!(modifier.parent_index(), PARENT);
+        assert_eq!((modifier.index, modifier.serial_num), (2, 10));
+        assert_eq!(modifier.modifier_class, 7);
+        // NOTE: invalid handles are turned into none.
+        assert_eq!(modifier.caster_index(), None);
+        assert_eq!(modifier.ability_index(), Some(42));
+
+        assert_eq!(
+            decode_modifier_entry(&removed(2).encode_message_to_vec())?,
+            ModifierEntry::Removed {
+                parent: PARENT as u32,
+                index: 2
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_modifier_tracker() -> anyhow::Result<()> {
+        let mut string_tables = StringTableContainer::default();
+        string_tables.create_string_table_mut(ACTIVE_MODIFIERS_TABLE_NAME, false, 0, 0, 0, true);
+        let mut tracker = ModifierTracker::new();
+        let mut update = |tick: i32, entries: &[CdotaModifierBuffTableEntry]| {
+            string_tables.do_full_update(active_modifiers(entries));
+            let string_table = string_tables
+                .find_table(ACTIVE_MODIFIERS_TABLE_NAME)
+                .ok_or_else(|| anyhow::anyhow!("no active modifiers table"))?;
+            tracker.update(tick, string_table)?;
+            anyhow::Ok(
+                tracker
+                    .by_parent(PARENT)
+                    .map(|active| {
+                        (
+                            active.modifier.index,
+                            active.modifier.stack_count,
+                            active.start_tick,
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        // NOTE: insert.
+        assert_eq!(update(10, &[entry(2, 10, 1)])?, [(2, 1, 10)]);
+        // NOTE: refresh of the same modifier keeps its start tick.
+        assert_eq!(update(20, &[entry(2, 10, 2)])?, [(2, 2, 10)]);
+        // NOTE: different modifier took the slot.
+        assert_eq!(update(30, &[entry(2, 11, 1)])?, [(2, 1, 30)]);
+        // NOTE: removal.
+        assert!(update(40, &[removed(2)])?.is_empty());
+
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.entity_modifiers(PARENT).count(), 0);
+
+        Ok(())
+    }
+
+    fn modifier(index: i32, creation_time: f32, duration: f32) -> Modifier {
+        Modifier {
+            parent: PARENT as u32,
+            index,
+            serial_num: 1,
+            modifier_class: 7,
+            caster: None,
+            ability: None,
+            ability_level: 1,
+            stack_count: 0,
+            creation_time,
+            duration,
+        }
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut tracker = ModifierTracker::new();
+        tracker.insert(1, modifier(0, 10.0, 5.0));
+        tracker.insert(1, modifier(1, 10.0, 20.0));
+        // NOTE: does not expire.
+        tracker.insert(1, modifier(2, 10.0, -1.0));
+
+        tracker.expire(14.9);
+        assert_eq!(tracker.len(), 3);
+
+        // NOTE: expires exactly at creation time + duration.
+        tracker.expire(15.0);
+        let mut indices: Vec<i32> = tracker
+            .entity_modifiers(PARENT)
+            .map(|active| active.modifier.index)
+            .collect();
+        indices.sort_unstable();
+        assert_eq!(indices, [1, 2]);
+
+        tracker.expire(1000.0);
+        assert_eq!(
+            tracker
+                .by_parent(PARENT)
+                .map(|active| active.modifier.index)
+                .collect::<Vec<_>>(),
+            [2]
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! human-readable names of dota 2 heroes, abilities and items, so that exports don't need to be
+//! joined against external dumps.
+//!
+//! heroes are built in, they change rarely. ability and item ids shift with patches and there are
+//! thousands of them; they are not shipped - load them from user-provided game data (see
+//! [`NameRegistry::read_from`]), or resolve internal names of ability and item entities directly
+//! from `EntityNames` string table (see [`entity_name`]).
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::entities::{fkey_from_path, Entity};
+use crate::stringtables::StringTableContainer;
+
+pub const ENTITY_NAMES_TABLE_NAME: &str = "EntityNames";
+
+const ENTITY_NAME_INDEX_KEY: u64 = fkey_from_path(&["m_pEntity", "m_nameStringableIndex"]);
+
+const HERO_NAME_PREFIX: &str = "npc_dota_hero_";
+const HERO_CLASS_NAME_PREFIX: &str = "CDOTA_Unit_Hero_";
+
+const NAMES_HEADER: &str = "# haste names v1";
+const SEPARATOR: char = '\t';
+
+/// internal name of the entity (for example `npc_dota_hero_nevermore`, `item_blink` or
+/// `nevermore_shadowraze1`); an index into `EntityNames` string table is networked in
+/// `m_pEntity.m_nameStringableIndex`.
+pub fn entity_name<'a>(
+    entity: &Entity,
+    string_tables: &'a StringTableContainer,
+) -> Option<&'a str> {
+    let index: i32 = entity.get_value(&ENTITY_NAME_INDEX_KEY)?;
+    string_tables
+        .find_table(ENTITY_NAMES_TABLE_NAME)?
+        .get_item(&index)?
+        .key()
+}
+
+// heroes
+// ----
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeroInfo {
+    pub id: u32,
+    /// internal name, for example `npc_dota_hero_nevermore`.
+    pub name: Box<str>,
+    /// for example `Shadow Fiend`.
+    pub display_name: Box<str>,
+}
+
+impl HeroInfo {
+    pub fn new(id: u32, name: &str, display_name: &str) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            display_name: display_name.into(),
+        }
+    }
+}
+
+// NOTE: (id, internal name without `npc_dota_hero_` prefix, display name); ids are what
+// `m_nSelectedHeroID` of player resource holds.
+const HEROES: &[(u32, &str, &str)] = &[
+    (1, "antimage", "Anti-Mage"),
+    (2, "axe", "Axe"),
+    (3, "bane", "Bane"),
+    (4, "bloodseeker", "Bloodseeker"),
+    (5, "crystal_maiden", "Crystal Maiden"),
+    (6, "drow_ranger", "Drow Ranger"),
+    (7, "earthshaker", "Earthshaker"),
+    (8, "juggernaut", "Juggernaut"),
+    (9, "mirana", "Mirana"),
+    (10, "morphling", "Morphling"),
+    (11, "nevermore", "Shadow Fiend"),
+    (12, "phantom_lancer", "Phantom Lancer"),
+    (13, "puck", "Puck"),
+    (14, "pudge", "Pudge"),
+    (15, "razor", "Razor"),
+    (16, "sand_king", "Sand King"),
+    (17, "storm_spirit", "Storm Spirit"),
+    (18, "sven", "Sven"),
+    (19, "tiny", "Tiny"),
+    (20, "vengefulspirit", "Vengeful Spirit"),
+    (21, "windrunner", "Windranger"),
+    (22, "zuus", "Zeus"),
+    (23, "kunkka", "Kunkka"),
+    (25, "lina", "Lina"),
+    (26, "lion", "Lion"),
+    (27, "shadow_shaman", "Shadow Shaman"),
+    (28, "slardar", "Slardar"),
+    (29, "tidehunter", "Tidehunter"),
+    (30, "witch_doctor", "Witch Doctor"),
+    (31, "lich", "Lich"),
+    (32, "riki", "Riki"),
+    (33, "enigma", "Enigma"),
+    (34, "tinker", "Tinker"),
+    (35, "sniper", "Sniper"),
+    (36, "necrolyte", "Necrophos"),
+    (37, "warlock", "Warlock"),
+    (38, "beastmaster", "Beastmaster"),
+    (39, "queenofpain", "Queen of Pain"),
+    (40, "venomancer", "Venomancer"),
+    (41, "faceless_void", "Faceless Void"),
+    (42, "skeleton_king", "Wraith King"),
+    (43, "death_prophet", "Death Prophet"),
+    (44, "phantom_assassin", "Phantom Assassin"),
+    (45, "pugna", "Pugna"),
+    (46, "templar_assassin", "Templar Assassin"),
+    (47, "viper", "Viper"),
+    (48, "luna", "Luna"),
+    (49, "dragon_knight", "Dragon Knight"),
+    (50, "dazzle", "Dazzle"),
+    (51, "rattletrap", "Clockwerk"),
+    (52, "leshrac", "Leshrac"),
+    (53, "furion", "Nature's Prophet"),
+    (54, "life_stealer", "Lifestealer"),
+    (55, "dark_seer", "Dark Seer"),
+    (56, "clinkz", "Clinkz"),
+    (57, "omniknight", "Omniknight"),
+    (58, "enchantress", "Enchantress"),
+    (59, "huskar", "Huskar"),
+    (60, "night_stalker", "Night Stalker"),
+    (61, "broodmother", "Broodmother"),
+    (62, "bounty_hunter", "Bounty Hunter"),
+    (63, "weaver", "Weaver"),
+    (64, "jakiro", "Jakiro"),
+    (65, "batrider", "Batrider"),
+    (66, "chen", "Chen"),
+    (67, "spectre", "Spectre"),
+    (68, "ancient_apparition", "Ancient Apparition"),
+    (69, "doom_bringer", "Doom"),
+    (70, "ursa", "Ursa"),
+    (71, "spirit_breaker", "Spirit Breaker"),
+    (72, "gyrocopter", "Gyrocopter"),
+    (73, "alchemist", "Alchemist"),
+    (74, "invoker", "Invoker"),
+    (75, "silencer", "Silencer"),
+    (76, "obsidian_destroyer", "Outworld Destroyer"),
+    (77, "lycan", "Lycan"),
+    (78, "brewmaster", "Brewmaster"),
+    (79, "shadow_demon", "Shadow Demon"),
+    (80, "lone_druid", "Lone Druid"),
+    (81, "chaos_knight", "Chaos Knight"),
+    (82, "meepo", "Meepo"),
+    (83, "treant", "Treant Protector"),
+    (84, "ogre_magi", "Ogre Magi"),
+    (85, "undying", "Undying"),
+    (86, "rubick", "Rubick"),
+    (87, "disruptor", "Disruptor"),
+    (88, "nyx_assassin", "Nyx Assassin"),
+    (89, "naga_siren", "Naga Siren"),
+    (90, "keeper_of_the_light", "Keeper of the Light"),
+    (91, "wisp", "Io"),
+    (92, "visage", "Visage"),
+    (93, "slark", "Slark"),
+    (94, "medusa", "Medusa"),
+    (95, "troll_warlord", "Troll Warlord"),
+    (96, "centaur", "Centaur Warrunner"),
+    (97, "magnataur", "Magnus"),
+    (98, "shredder", "Timbersaw"),
+    (99, "bristleback", "Bristleback"),
+    (100, "tusk", "Tusk"),
+    (101, "skywrath_mage", "Skywrath Mage"),
+    (102, "abaddon", "Abaddon"),
+    (103, "elder_titan", "Elder Titan"),
+    (104, "legion_commander", "Legion Commander"),
+    (105, "techies", "Techies"),
+    (106, "ember_spirit", "Ember Spirit"),
+    (107, "earth_spirit", "Earth Spirit"),
+    (108, "abyssal_underlord", "Underlord"),
+    (109, "terrorblade", "Terrorblade"),
+    (110, "phoenix", "Phoenix"),
+    (111, "oracle", "Oracle"),
+    (112, "winter_wyvern", "Winter Wyvern"),
+    (113, "arc_warden", "Arc Warden"),
+    (114, "monkey_king", "Monkey King"),
+    (119, "dark_willow", "Dark Willow"),
+    (120, "pangolier", "Pangolier"),
+    (121, "grimstroke", "Grimstroke"),
+    (123, "hoodwink", "Hoodwink"),
+    (126, "void_spirit", "Void Spirit"),
+    (128, "snapfire", "Snapfire"),
+    (129, "mars", "Mars"),
+    (131, "ringmaster", "Ringmaster"),
+    (135, "dawnbreaker", "Dawnbreaker"),
+    (136, "marci", "Marci"),
+    (137, "primal_beast", "Primal Beast"),
+    (138, "muerta", "Muerta"),
+    (145, "kez", "Kez"),
+];
+
+/// hero class names are camel cased internal names (`CDOTA_Unit_Hero_DoomBringer` for
+/// `npc_dota_hero_doom_bringer`, but there's also `CDOTA_Unit_Hero_Life_Stealer`); they are
+/// compared case-insensitively, ignoring underscores.
+fn eq_normalized(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| *c != '_')
+            .map(|c| c.to_ascii_lowercase())
+            .collect::<Vec<char>>()
+    };
+    normalize(a) == normalize(b)
+}
+
+// registry
+// ----
+
+#[derive(thiserror::Error, Debug)]
+pub enum NamesReadError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("invalid names header")]
+    InvalidHeader,
+    #[error("invalid names line {line}")]
+    InvalidLine { line: usize },
+}
+
+/// lookup tables of names; default one knows heroes only.
+///
+/// user-provided game data is a tab separated text file (same as what [`Self::write_to`]
+/// produces):
+///
+/// ```text
+/// # haste names v1
+/// hero	11	npc_dota_hero_nevermore	Shadow Fiend
+/// ability	5059	nevermore_shadowraze1
+/// item	1	item_blink
+/// display	item_blink	Blink Dagger
+/// ```
+///
+/// lines that start with `#` (besides the header) are comments.
+#[derive(Debug, Clone)]
+pub struct NameRegistry {
+    heroes: Vec<HeroInfo>,
+    abilities: HashMap<u32, Box<str>>,
+    items: HashMap<u32, Box<str>>,
+    // NOTE: keyed by internal name; hero display names are in heroes.
+    display_names: HashMap<Box<str>, Box<str>>,
+}
+
+impl Default for NameRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for (id, name, display_name) in HEROES {
+            registry.register_hero(HeroInfo::new(
+                *id,
+                &format!("{HERO_NAME_PREFIX}{name}"),
+                display_name,
+            ));
+        }
+        registry
+    }
+}
+
+impl NameRegistry {
+    pub fn empty() -> Self {
+        Self {
+            heroes: Vec::new(),
+            abilities: HashMap::new(),
+            items: HashMap::new(),
+            display_names: HashMap::new(),
+        }
+    }
+
+    /// replaces hero with the same id if there's one.
+    pub fn register_hero(&mut self, hero_info: HeroInfo) {
+        self.heroes.retain(|h| h.id != hero_info.id);
+        self.heroes.push(hero_info);
+    }
+
+    pub fn register_ability(&mut self, id: u32, name: &str) {
+        self.abilities.insert(id, name.into());
+    }
+
+    pub fn register_item(&mut self, id: u32, name: &str) {
+        self.items.insert(id, name.into());
+    }
+
+    /// display name of an ability or an item (or of anything else that has an internal name).
+    pub fn register_display_name(&mut self, name: &str, display_name: &str) {
+        self.display_names.insert(name.into(), display_name.into());
+    }
+
+    pub fn hero_by_id(&self, id: u32) -> Option<&HeroInfo> {
+        self.heroes.iter().find(|h| h.id == id)
+    }
+
+    /// by internal name, for example `npc_dota_hero_nevermore`.
+    pub fn hero_by_name(&self, name: &str) -> Option<&HeroInfo> {
+        self.heroes.iter().find(|h| &*h.name == name)
+    }
+
+    /// by entity class (serializer) name, for example `CDOTA_Unit_Hero_Nevermore`.
+    pub fn hero_by_class_name(&self, class_name: &str) -> Option<&HeroInfo> {
+        let class_name = class_name.strip_prefix(HERO_CLASS_NAME_PREFIX)?;
+        self.heroes.iter().find(|h| {
+            h.name
+                .strip_prefix(HERO_NAME_PREFIX)
+                .is_some_and(|name| eq_normalized(name, class_name))
+        })
+    }
+
+    /// internal name of the ability, for example `nevermore_shadowraze1`.
+    pub fn ability_name(&self, id: u32) -> Option<&str> {
+        self.abilities.get(&id).map(|name| name.as_ref())
+    }
+
+    /// internal name of the item, for example `item_blink`.
+    pub fn item_name(&self, id: u32) -> Option<&str> {
+        self.items.get(&id).map(|name| name.as_ref())
+    }
+
+    /// display name of a hero, an ability or an item given its internal name.
+    pub fn display_name(&self, name: &str) -> Option<&str> {
+        self.hero_by_name(name)
+            .map(|h| h.display_name.as_ref())
+            .or_else(|| self.display_names.get(name).map(|name| name.as_ref()))
+    }
+
+    /// adds (or replaces) names from the file; see [`NameRegistry`] for the format.
+    pub fn read_from<R: BufRead>(&mut self, rdr: R) -> Result<(), NamesReadError> {
+        let mut lines = rdr.lines();
+        let header = lines.next().transpose()?;
+        if header.as_deref().map(str::trim_end) != Some(NAMES_HEADER) {
+            return Err(NamesReadError::InvalidHeader);
+        }
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = || NamesReadError::InvalidLine { line: i + 2 };
+            let parts: Vec<&str> = line.split(SEPARATOR).collect();
+            match parts.as_slice() {
+                ["hero", id, name, display_name] => {
+                    let id = id.parse().map_err(|_| invalid_line())?;
+                    self.register_hero(HeroInfo::new(id, name, display_name));
+                }
+                ["ability", id, name] => {
+                    let id = id.parse().map_err(|_| invalid_line())?;
+                    self.register_ability(id, name);
+                }
+                ["item", id, name] => {
+                    let id = id.parse().map_err(|_| invalid_line())?;
+                    self.register_item(id, name);
+                }
+                ["display", name, display_name] => {
+                    self.register_display_name(name, display_name);
+                }
+                _ => return Err(invalid_line()),
+            }
+        }
+        Ok(())
+    }
+
+    /// entries are sorted, thus output is stable.
+    pub fn write_to<W: Write>(&self, mut wtr: W) -> Result<(), io::Error> {
+        writeln!(wtr, "{NAMES_HEADER}")?;
+
+        let mut heroes: Vec<&HeroInfo> = self.heroes.iter().collect();
+        heroes.sort_unstable_by_key(|h| h.id);
+        for h in heroes {
+            writeln!(wtr, "hero\t{}\t{}\t{}", h.id, h.name, h.display_name)?;
+        }
+
+        let mut abilities: Vec<(&u32, &Box<str>)> = self.abilities.iter().collect();
+        abilities.sort_unstable_by_key(|(id, _)| **id);
+        for (id, name) in abilities {
+            writeln!(wtr, "ability\t{id}\t{name}")?;
+        }
+
+        let mut items: Vec<(&u32, &Box<str>)> = self.items.iter().collect();
+        items.sort_unstable_by_key(|(id, _)| **id);
+        for (id, name) in items {
+            writeln!(wtr, "item\t{id}\t{name}")?;
+        }
+
+        let mut display_names: Vec<(&Box<str>, &Box<str>)> = self.display_names.iter().collect();
+        display_names.sort_unstable();
+        for (name, display_name) in display_names {
+            writeln!(wtr, "display\t{name}\t{display_name}")?;
+        }
+
+        wtr.flush()
+    }
+}
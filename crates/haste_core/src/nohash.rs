@@ -0,0 +1,60 @@
+//! lookup tables keyed by integers that do not need to be hashed again (entity indices, field
+//! keys, serializer name hashes - those are either small and dense or already are fx hashes).
+//! this is what haste's own tables use; exposed so that downstream crates building parallel
+//! tables get the same performance characteristics.
+//!
+//! NOTE: no-hash hasher only makes sense for integer keys; [`IntKey`] is the guard. constructors
+//! below require it, thus `new_int_map::<String, _>()` does not compile.
+
+use std::hash::{BuildHasherDefault, Hash};
+
+pub use ::nohash::NoHashHasher;
+
+pub type BuildNoHashHasher<K> = BuildHasherDefault<NoHashHasher<K>>;
+
+pub type IntMap<K, V> = hashbrown::HashMap<K, V, BuildNoHashHasher<K>>;
+pub type IntSet<K> = hashbrown::HashSet<K, BuildNoHashHasher<K>>;
+
+/// keyed by entity index.
+pub type EntityIndexMap<V> = IntMap<i32, V>;
+/// keyed by field key (see [`crate::entities::fkey_from_path`]) or serializer name hash (see
+/// [`crate::fxhash::hash_bytes`]).
+pub type FieldKeyMap<V> = IntMap<u64, V>;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// integer types that can be used as keys of [`IntMap`] / [`IntSet`]. sealed.
+pub trait IntKey: private::Sealed + Copy + Eq + Hash {}
+
+macro_rules! impl_int_key {
+    ($($ty:ty),*) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl IntKey for $ty {}
+        )*
+    };
+}
+
+impl_int_key!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+#[inline]
+pub fn new_int_map<K: IntKey, V>() -> IntMap<K, V> {
+    IntMap::default()
+}
+
+#[inline]
+pub fn int_map_with_capacity<K: IntKey, V>(capacity: usize) -> IntMap<K, V> {
+    IntMap::with_capacity_and_hasher(capacity, BuildNoHashHasher::default())
+}
+
+#[inline]
+pub fn new_int_set<K: IntKey>() -> IntSet<K> {
+    IntSet::default()
+}
+
+#[inline]
+pub fn int_set_with_capacity<K: IntKey>(capacity: usize) -> IntSet<K> {
+    IntSet::with_capacity_and_hasher(capacity, BuildNoHashHasher::default())
+}
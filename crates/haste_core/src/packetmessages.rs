@@ -0,0 +1,73 @@
+//! embedded messages of `CDemoPacket` (and of `CDemoFullPacket`'s packet). each message is
+//! framed as ubitvar type id (`SvcMessages`, `EBaseUserMessages`, etc.) followed by uvarint32
+//! size and the payload. allows to inspect messages or re-order their handling without haste
+//! interpreting them.
+//!
+//! NOTE: messages are not byte-aligned within the packet, thus payloads are copied into an
+//! internal buffer and [`PacketMessages::next_message`] lends them.
+
+use crate::bitreader::{BitReader, BitReaderOverflowError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum PacketMessagesError {
+    #[error(transparent)]
+    OverflowError(#[from] BitReaderOverflowError),
+    #[error("message of {size} bytes exceeds remaining {remaining} bytes of packet")]
+    TruncatedMessage { size: usize, remaining: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PacketMessage<'a> {
+    pub packet_type: u32,
+    pub data: &'a [u8],
+}
+
+/// ```ignore
+/// let mut messages = PacketMessages::new(cmd.data());
+/// while let Some(msg) = messages.next_message()? {
+///     println!("{} ({} bytes)", msg.packet_type, msg.data.len());
+/// }
+/// ```
+pub struct PacketMessages<'a> {
+    br: BitReader<'a>,
+    buf: Vec<u8>,
+}
+
+impl<'a> PacketMessages<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            br: BitReader::new(data),
+            buf: Vec::new(),
+        }
+    }
+
+    /// returns none when there are no more messages.
+    pub fn next_message(&mut self) -> Result<Option<PacketMessage<'_>>, PacketMessagesError> {
+        // NOTE: same condition as in parser; what's left is padding.
+        if self.br.num_bits_left() <= 8 {
+            return Ok(None);
+        }
+
+        let mut br = self.br.checked();
+        let packet_type = br.read_ubitvar()?;
+        let size = br.read_uvarint32()? as usize;
+        let remaining = br.num_bits_left() / 8;
+        if size > remaining {
+            return Err(PacketMessagesError::TruncatedMessage { size, remaining });
+        }
+
+        self.buf.resize(size, 0);
+        br.read_bytes(&mut self.buf)?;
+        Ok(Some(PacketMessage {
+            packet_type,
+            data: &self.buf,
+        }))
+    }
+}
+
+impl Drop for PacketMessages<'_> {
+    fn drop(&mut self) {
+        // NOTE: all reads are checked, see BitReader's Drop impl.
+        let _ = self.br.is_overflowed();
+    }
+}
@@ -1,21 +1,38 @@
 use std::io::{self, SeekFrom};
 
-use anyhow::Result;
-use prost::Message;
+use anyhow::{bail, Result};
 use valveprotos::common::{
-    CDemoFullPacket, CDemoPacket, CDemoStringTables, CsvcMsgCreateStringTable,
-    CsvcMsgPacketEntities, CsvcMsgServerInfo, CsvcMsgUpdateStringTable, EDemoCommands, SvcMessages,
+    CDemoAnimationData, CDemoAnimationHeader, CDemoFileHeader, CDemoFullPacket, CDemoPacket,
+    CDemoStringTables, CMsgSource1LegacyGameEventList, CsvcMsgCreateStringTable,
+    CsvcMsgPacketEntities, CsvcMsgServerInfo, CsvcMsgUpdateStringTable, EBaseGameEvents,
+    EDemoCommands, SvcMessages,
 };
 
 use crate::bitreader::BitReader;
 use crate::demofile::{DemoHeaderError, DEMO_RECORD_BUFFER_SIZE};
 use crate::demostream::{CmdHeader, DemoStream};
-use crate::entities::{DeltaHeader, Entity, EntityContainer};
+use crate::entities::{BaselineSource, DeltaHeader, Entity, EntityContainer, EntityCreateInfo};
 use crate::entityclasses::EntityClasses;
+use crate::fieldbits::FieldBitCounts;
 use crate::fielddecoder::FieldDecodeContext;
-use crate::flattenedserializers::FlattenedSerializerContainer;
+use crate::flattenedserializers::{
+    FieldDecoderRegistry, FlattenedSerializerContainer, FlattenedSerializerOptions,
+    UnknownFieldTypes,
+};
+use crate::game::Game;
+use crate::gameevents::GameEventList;
+use crate::hugepages::HugePageBuffer;
 use crate::instancebaseline::{InstanceBaseline, INSTANCE_BASELINE_TABLE_NAME};
-use crate::stringtables::StringTableContainer;
+use crate::limits::{ResourceLimits, Watchdog};
+use crate::packetmessages::PacketMessages;
+use crate::parsermetrics::{self, RunTimer};
+use crate::protobackend::ProtoMessage;
+use crate::protoscan;
+use crate::replaydiff::{diff_entities, DiffOptions, DiffReport};
+use crate::stringtablelog::{Retention, StringTableLog};
+use crate::stringtables::{StringTable, StringTableContainer, StringTablesSnapshot};
+use crate::stringtablesubscriptions::StringTableSubscriptions;
+use crate::userinfo::{self, PlayerInfo};
 
 // as can be observed when dumping commands. also as specified in clarity
 // (src/main/java/skadistats/clarity/model/engine/AbstractDotaEngineType.java)
@@ -28,6 +45,23 @@ const DEFAULT_FULL_PACKET_INTERVAL: i32 = 1800;
 // dota2's tick interval is 1 / 30; deadlock's 1 / 60 - they are constant.
 const DEFAULT_TICK_INTERVAL: f32 = 1.0 / 30.0;
 
+// NOTE: counts allocations of protobuf decoding; see crate::allocstats.
+#[inline(always)]
+fn decoding<T>(f: impl FnOnce() -> T) -> T {
+    alloc_scope!(Protobuf);
+    f()
+}
+
+// NOTE: packet messages that the parser itself needs; they are decoded regardless of
+// ParserOptions::packet_types.
+const PARSER_PACKET_TYPES: [u32; 5] = [
+    SvcMessages::SvcCreateStringTable as u32,
+    SvcMessages::SvcUpdateStringTable as u32,
+    SvcMessages::SvcPacketEntities as u32,
+    SvcMessages::SvcServerInfo as u32,
+    EBaseGameEvents::GeSource1LegacyGameEventList as u32,
+];
+
 // NOTE: primary purpose of Context is to to be able to expose state to the
 // public; attempts to put parser into arguments of Visitor's method did not
 // result in anything satisfyable.
@@ -41,7 +75,10 @@ pub struct Context {
     instance_baseline: InstanceBaseline,
     serializers: Option<FlattenedSerializerContainer>,
     entity_classes: Option<EntityClasses>,
+    game_event_list: Option<GameEventList>,
     entities: EntityContainer,
+    string_table_log: Option<StringTableLog>,
+    game: Game,
     tick_interval: f32,
     full_packet_interval: i32,
     tick: i32,
@@ -70,6 +107,11 @@ impl Context {
         self.entity_classes.as_ref()
     }
 
+    #[inline]
+    pub fn game_event_list(&self) -> Option<&GameEventList> {
+        self.game_event_list.as_ref()
+    }
+
     #[inline]
     pub fn entities(&self) -> Option<&EntityContainer> {
         if self.entities.is_empty() {
@@ -79,6 +121,34 @@ impl Context {
         }
     }
 
+    /// raw instance baselines; see [`crate::instancebaseline`] and
+    /// [`ParserOptions::drop_baseline_data`].
+    #[inline]
+    pub fn instance_baseline(&self) -> Option<&InstanceBaseline> {
+        if self.instance_baseline.is_empty() {
+            None
+        } else {
+            Some(&self.instance_baseline)
+        }
+    }
+
+    /// players decoded from `userinfo` string table; see [`crate::userinfo`].
+    pub fn players(&self) -> Vec<PlayerInfo> {
+        userinfo::players(&self.string_tables)
+    }
+
+    /// available only if enabled with [`ParserOptions::string_table_log`].
+    #[inline]
+    pub fn string_table_log(&self) -> Option<&StringTableLog> {
+        self.string_table_log.as_ref()
+    }
+
+    /// sniffed from demo file header; see [`Game::from_file_header`].
+    #[inline]
+    pub fn game(&self) -> Game {
+        self.game
+    }
+
     #[inline]
     pub fn tick_interval(&self) -> f32 {
         self.tick_interval
@@ -91,6 +161,12 @@ impl Context {
 }
 
 pub trait Visitor {
+    /// `delta_header` is one of [`DeltaHeader`] constants. entities that left pvs
+    /// ([`DeltaHeader::LEAVE`]) are not deleted, they are moved into a separate set; see
+    /// [`EntityContainer::iter_out_of_pvs`]. see [`DeltaHeader::update_flags`] for valve-style
+    /// flags; recreation of an existing entity is reported through
+    /// [`EntityCreateInfo::update_flags`] of [`Self::on_entity_create`].
+    //
     // TODO: include updated fields (list of field paths?)
     #[allow(unused_variables)]
     fn on_entity(
@@ -102,6 +178,23 @@ pub trait Visitor {
         Ok(())
     }
 
+    /// called right before [`Self::on_entity`] for created entities.
+    ///
+    /// if create replaces an existing entity ([`crate::entities::UpdateFlags::FORCE_RECREATE`]; forced
+    /// retransmit, or entity slot was reused while previous occupant was out of pvs) the previous
+    /// entity is reported through [`Self::on_entity`] with [`DeltaHeader::DELETE`] first, so that
+    /// each entity lifetime ends with a delete. note that at that point the container already
+    /// holds the new entity.
+    #[allow(unused_variables)]
+    fn on_entity_create(
+        &mut self,
+        ctx: &Context,
+        create_info: &EntityCreateInfo,
+        entity: &Entity,
+    ) -> Result<()> {
+        Ok(())
+    }
+
     #[allow(unused_variables)]
     fn on_cmd(&mut self, ctx: &Context, cmd_header: &CmdHeader, data: &[u8]) -> Result<()> {
         Ok(())
@@ -112,10 +205,39 @@ pub trait Visitor {
         Ok(())
     }
 
+    /// called once all cmds of the tick were handled (before the first cmd of the next tick, or
+    /// at the end of the stream, see [`DemoStream::is_finished`]); [`Context::tick`] is the tick that ended. the last callback of a
+    /// tick, nothing of that tick follows it.
     #[allow(unused_variables)]
     fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
         Ok(())
     }
+
+    /// called once per [`ParserOptions::snapshot_interval`], right before [`Self::on_tick_end`].
+    /// entities are sorted by index.
+    #[allow(unused_variables)]
+    fn on_snapshot(&mut self, ctx: &Context, entities: &[&Entity]) -> Result<()> {
+        Ok(())
+    }
+
+    /// newer deadlock demos carry animation data of entities; haste does not interpret it.
+    #[allow(unused_variables)]
+    fn on_animation_header(&mut self, ctx: &Context, cmd: &CDemoAnimationHeader) -> Result<()> {
+        Ok(())
+    }
+
+    /// see [`Self::on_animation_header`].
+    #[allow(unused_variables)]
+    fn on_animation_data(&mut self, ctx: &Context, cmd: &CDemoAnimationData) -> Result<()> {
+        Ok(())
+    }
+
+    /// called when entries of the string table were added or changed; see
+    /// [`StringTable::changed_entries`].
+    #[allow(unused_variables)]
+    fn on_string_table_update(&mut self, ctx: &Context, string_table: &StringTable) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// ControlFlow indicates the desired behavior of the run loop.
@@ -133,34 +255,207 @@ enum ControlFlow {
     Break,
 }
 
+/// see [`ParserOptions::snapshot_interval`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotInterval {
+    Ticks(i32),
+    /// converted to ticks with tick interval.
+    ///
+    /// NOTE: pauses are not excluded; see [`crate::gameclock`] if that matters.
+    Seconds(f32),
+}
+
+/// decrypts encrypted payload with the key; see [`PacketDecryption`].
+pub type DecryptFn = fn(key: &[u8], encrypted: &[u8]) -> Result<Vec<u8>>;
+
+/// see [`ParserOptions::packet_decryption`].
+///
+/// NOTE: source 2 protos that valveprotos ship do not define an encrypted data message (source 1
+/// had `svc_EncryptedData` encrypted with ice cipher and per-match keys); which message carries
+/// encrypted data and how it is encrypted is thus up to the caller.
+#[derive(Debug, Clone)]
+pub struct PacketDecryption {
+    /// type of packet messages that carry encrypted payload.
+    pub packet_type: u32,
+    /// number of the (length delimited) field of the message that holds encrypted payload.
+    pub payload_field: u64,
+    pub key: Box<[u8]>,
+    /// decrypted payload must be packet data (packet messages prefixed with their type and size,
+    /// same as [`CDemoPacket::data`]).
+    pub decrypt: DecryptFn,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    /// enables string table change log with the given retention; see
+    /// [`Context::string_table_log`].
+    pub string_table_log: Option<Retention>,
+    /// validates data that is otherwise trusted blindly on hot paths; corrupt demos result in
+    /// errors instead of panics or undefined behaviour. validated are:
+    /// - type and size prefixes of packet messages, and bodies of them (checked reads, see
+    /// [`crate::bitreader::CheckedBitReader`]), bodies must fit into the packet buffer;
+    /// - presence of entity classes and serializers when packet entities arrive;
+    /// - entity headers (index deltas, delta headers, class ids and serials; checked reads),
+    /// class ids of created entities and serializers of their classes;
+    /// - existence of deleted and updated entities;
+    /// - existence of updated string tables;
+    /// - field paths of entities (reader is checked for overflow after each field path op), each
+    /// component must exist in the serializer.
+    ///
+    /// NOTE: field values are decoded with unchecked reads in safe mode too; they are never used
+    /// as indices or sizes, overflows of them are detected once the entity data was read.
+    pub safe_mode: bool,
+    /// see [`UnknownFieldTypes`]; fields that are decoded with fallback decoder are listed in
+    /// [`FlattenedSerializerContainer::unknown_field_types`].
+    pub unknown_field_types: UnknownFieldTypes,
+    /// user-provided decoders for var types and var encoders; see [`FieldDecoderRegistry`].
+    pub custom_field_decoders: FieldDecoderRegistry,
+    /// when set, entity storage (map of in pvs entities) is shrunk after deletes once occupancy
+    /// (len / capacity) drops below the given fraction (for example `0.25`), but never below its
+    /// initial capacity. field storage of entities is not compacted automatically, call
+    /// [`EntityContainer::shrink_to_fit`] for that.
+    pub entity_shrink_threshold: Option<f32>,
+    /// checks bit consumption of entity deltas; the fastest way to localize decoder bugs when an
+    /// update shifts an encoding. errors point to the delta that was decoded last. checked are:
+    /// - after each delta: entity data must not be overflowed;
+    /// - before each delta: index of the entity must be within range, updated and deleted
+    /// entities must exist (garbage here means that the previous delta was decoded with a wrong
+    /// number of bits);
+    /// - after all deltas: entity data must be consumed exactly (except for padding up to the
+    /// byte boundary).
+    ///
+    /// NOTE: sizes of deltas are not on the wire, a wrongly decoded delta can slip through if
+    /// what follows it happens to look valid.
+    pub validate_bit_consumption: bool,
+    /// at each full packet (`CDemoFullPacket`, once per full packet interval) entity state is
+    /// reconstructed from the snapshot that it carries and compared against the state that was
+    /// maintained incrementally from deltas; the first diverging field results in an error. the
+    /// ultimate correctness oracle for decoder changes; slow.
+    ///
+    /// NOTE: has no effect on [`Parser::run_to_tick`], it restores state from full packets.
+    pub validate_full_packets: bool,
+    /// when set, [`Visitor::on_snapshot`] is called with full state of entities once per
+    /// interval; saves user-side tick bookkeeping in statistical pipelines.
+    pub snapshot_interval: Option<SnapshotInterval>,
+    /// serializer name hashes of entities that are included into snapshots; empty means all.
+    pub snapshot_classes: Vec<u64>,
+    /// records how many bits each field consumes; see [`Parser::field_bits`]. a little slower.
+    pub measure_field_bits: bool,
+    /// hard limits that abort parsing with [`crate::limits::ResourceLimitError`] once exceeded;
+    /// see [`crate::limits`].
+    pub resource_limits: ResourceLimits,
+    /// packet message types that reach [`Visitor::on_packet`]; none means all. messages that are
+    /// neither listed nor needed by the parser itself are skipped over without being copied out
+    /// of the packet, which cuts a good chunk of work for selective parses.
+    pub packet_types: Option<Vec<u32>>,
+    /// cmd types that reach [`Visitor::on_cmd`] (and [`Visitor::on_animation_header`] /
+    /// [`Visitor::on_animation_data`]); none means all. cmds that are neither listed nor needed by
+    /// the parser itself are skipped before they are read, and thus never decompressed.
+    pub cmd_types: Option<Vec<EDemoCommands>>,
+    /// backs large buffers (for example the packet buffer) with 2mb huge pages on linux; see
+    /// [`crate::hugepages`]. silently falls back to regular pages when unavailable.
+    pub hugepages: bool,
+    /// frees raw instance baseline data of a class (see [`Context::instance_baseline`]) once it
+    /// was decoded into a baseline entity. by default raw data is kept.
+    ///
+    /// NOTE: entities of the class can be created only for as long as the decoded baseline
+    /// entity stays around; a baseline that was replaced or cleared and is needed again (seeks
+    /// backwards, [`Parser::run_to_tick`] which decodes full packets from scratch) results in
+    /// [`crate::instancebaseline::InstanceBaselineError::DroppedBaseline`], unless its data came
+    /// back with string tables that were re-read on the way (signon ones always are, string
+    /// tables of full packets are when they carry them).
+    pub drop_baseline_data: bool,
+    /// user data decoders of string tables; see [`crate::stringtablesubscriptions`].
+    pub string_table_subscriptions: StringTableSubscriptions,
+    /// decrypts payload of encrypted packet messages; messages that it carries are handled as if
+    /// they were in the packet itself (they reach [`Visitor::on_packet`] after the encrypted
+    /// message). encrypted messages that lack the payload field, or nest inside of decrypted data,
+    /// result in errors.
+    pub packet_decryption: Option<PacketDecryption>,
+}
+
 // TODO: maybe rename to DemoPlayer (or DemoRunner?)
 pub struct Parser<D: DemoStream, V: Visitor> {
     demo_stream: D,
-    buf: Vec<u8>,
+    buf: HugePageBuffer,
     visitor: V,
     ctx: Context,
+    safe_mode: bool,
+    validate_bit_consumption: bool,
+    validate_full_packets: bool,
+    snapshot_interval: Option<SnapshotInterval>,
+    snapshot_classes: Vec<u64>,
+    last_snapshot_tick: Option<i32>,
+    // NOTE: tick of cmds that were handled, but the end of which was not signalled yet.
+    pending_tick_end: Option<i32>,
+    serializer_options: FlattenedSerializerOptions,
+    resource_limits: ResourceLimits,
+    watchdog: Watchdog,
+    packet_types: Option<Vec<u32>>,
+    cmd_types: Option<Vec<EDemoCommands>>,
+    drop_baseline_data: bool,
+    string_table_subscriptions: StringTableSubscriptions,
+    packet_decryption: Option<PacketDecryption>,
+    // NOTE: reused between packets; see the comment in handle_cmd.
+    packet_data: Vec<u8>,
     // NOTE(blukai): is this the place for this? can it be moved "closer" to entities somewhere?
     field_decode_ctx: FieldDecodeContext,
 }
 
 impl<D: DemoStream, V: Visitor> Parser<D, V> {
     pub fn from_stream_with_visitor(demo_stream: D, visitor: V) -> Result<Self, DemoHeaderError> {
+        Self::from_stream_with_visitor_and_options(demo_stream, visitor, ParserOptions::default())
+    }
+
+    pub fn from_stream_with_visitor_and_options(
+        demo_stream: D,
+        visitor: V,
+        options: ParserOptions,
+    ) -> Result<Self, DemoHeaderError> {
+        let mut entities = EntityContainer::new();
+        entities.set_shrink_threshold(options.entity_shrink_threshold);
+
         Ok(Self {
             demo_stream,
-            buf: vec![0; DEMO_RECORD_BUFFER_SIZE],
+            buf: HugePageBuffer::zeroed(DEMO_RECORD_BUFFER_SIZE, options.hugepages),
             visitor,
             ctx: Context {
-                entities: EntityContainer::new(),
+                entities,
+                string_table_log: options.string_table_log.map(StringTableLog::new),
                 string_tables: StringTableContainer::default(),
                 instance_baseline: InstanceBaseline::default(),
                 serializers: None,
                 entity_classes: None,
+                game_event_list: None,
+                game: Game::Unknown,
                 tick_interval: 0.0,
                 full_packet_interval: 0,
                 tick: -1,
                 prev_tick: -1,
             },
-            field_decode_ctx: FieldDecodeContext::default(),
+            safe_mode: options.safe_mode,
+            validate_bit_consumption: options.validate_bit_consumption,
+            validate_full_packets: options.validate_full_packets,
+            snapshot_interval: options.snapshot_interval,
+            snapshot_classes: options.snapshot_classes,
+            last_snapshot_tick: None,
+            pending_tick_end: None,
+            serializer_options: FlattenedSerializerOptions {
+                unknown_field_types: options.unknown_field_types,
+                custom_decoders: options.custom_field_decoders,
+            },
+            resource_limits: options.resource_limits,
+            watchdog: Watchdog::new(options.resource_limits.max_wall_time),
+            packet_types: options.packet_types,
+            cmd_types: options.cmd_types,
+            drop_baseline_data: options.drop_baseline_data,
+            string_table_subscriptions: options.string_table_subscriptions,
+            packet_decryption: options.packet_decryption,
+            packet_data: Vec::new(),
+            field_decode_ctx: FieldDecodeContext {
+                field_bits: options.measure_field_bits.then(FieldBitCounts::default),
+                ..Default::default()
+            },
         })
     }
 
@@ -173,35 +468,77 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
     // recorded).
     //
     // must be publicly exposed for this to be actually useful.
-    fn run<F>(&mut self, mut handler: F) -> Result<()>
+    fn run<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: FnMut(&mut Self, &CmdHeader) -> Result<ControlFlow>,
+    {
+        let timer = RunTimer::start(self.ctx.tick);
+        self.watchdog.start();
+        let result = self.run_inner(handler);
+        self.watchdog.stop();
+        match result {
+            Ok(reached_end) => {
+                timer.finish(self.ctx.tick, reached_end);
+                Ok(())
+            }
+            Err(err) => {
+                parsermetrics::record_error(&err);
+                Err(err)
+            }
+        }
+    }
+
+    /// returns true if the end of the stream was reached.
+    fn run_inner<F>(&mut self, mut handler: F) -> Result<bool>
     where
         F: FnMut(&mut Self, &CmdHeader) -> Result<ControlFlow>,
     {
         loop {
             match self.demo_stream.read_cmd_header() {
                 Ok(cmd_header) => {
+                    self.watchdog.check()?;
+                    self.resource_limits
+                        .check_message_size(cmd_header.body_size as usize)?;
                     self.ctx.prev_tick = self.ctx.tick;
                     self.ctx.tick = cmd_header.tick;
                     match handler(self, &cmd_header)? {
                         ControlFlow::HandleCmd => {
-                            self.handle_cmd(&cmd_header)?;
-                            if self.ctx.prev_tick != self.ctx.tick {
-                                self.visitor.on_tick_end(&self.ctx)?;
+                            self.end_tick_before(cmd_header.tick)?;
+                            // NOTE: unwanted cmds are skipped before their body is read (and
+                            // decompressed), but the tick still advances.
+                            if self.wants_cmd(cmd_header.cmd) {
+                                self.handle_cmd(&cmd_header)?;
+                            } else {
+                                self.demo_stream.skip_cmd(&cmd_header)?;
                             }
+                            self.pending_tick_end = Some(cmd_header.tick);
                         }
                         ControlFlow::SkipCmd => self.demo_stream.skip_cmd(&cmd_header)?,
                         ControlFlow::IgnoreCmd => {}
                         ControlFlow::Break => {
                             self.demo_stream.unread_cmd_header(&cmd_header)?;
                             self.ctx.tick = self.ctx.prev_tick;
-                            return Ok(());
+                            // NOTE: the cmd belongs to a different tick, thus the pending one is
+                            // complete.
+                            self.end_tick_before(cmd_header.tick)?;
+                            return Ok(false);
                         }
                     }
                 }
                 Err(err) => {
                     if self.demo_stream.is_at_eof().unwrap_or_default() {
-                        return Ok(());
+                        // NOTE: streams that are fed incrementally (see DemoBuffer) run out of
+                        // data in the middle of a tick; the rest of it may still arrive, thus
+                        // the tick is not over until the stream is.
+                        if !self.demo_stream.is_finished().unwrap_or_default() {
+                            return Ok(false);
+                        }
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(tick = self.ctx.tick, "reached end of stream");
+                        return self.end_pending_tick().map(|_| true);
                     }
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(tick = self.ctx.tick, error = %err, "could not read cmd header");
                     return Err(err.into());
                 }
             }
@@ -212,19 +549,156 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
         self.run(|_notnotself, _cmd_header| Ok(ControlFlow::HandleCmd))
     }
 
+    /// handles all cmds of the next tick (and initialization cmds, if they were not handled yet).
+    /// returns false if the end of the stream was reached.
+    pub fn run_to_next_tick(&mut self) -> Result<bool> {
+        let start_tick = self.ctx.tick;
+        let mut next_tick: Option<i32> = None;
+        self.run(|_notnotself, cmd_header| {
+            if cmd_header.tick <= start_tick {
+                return Ok(ControlFlow::HandleCmd);
+            }
+            match next_tick {
+                Some(next_tick) if next_tick != cmd_header.tick => Ok(ControlFlow::Break),
+                Some(_) => Ok(ControlFlow::HandleCmd),
+                None => {
+                    next_tick = Some(cmd_header.tick);
+                    Ok(ControlFlow::HandleCmd)
+                }
+            }
+        })?;
+        Ok(!self.demo_stream.is_at_eof()?)
+    }
+
+    /// handles cmds tick by tick until `predicate` returns true (for example once first blood
+    /// was seen, or game state reached post game); predicate is checked at the end of each tick.
+    /// returns false if the end of the stream was reached before predicate was satisfied.
+    ///
+    /// parser can be resumed afterwards (for example with [`Self::run_to_end`]).
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Result<bool>
+    where
+        F: FnMut(&Context) -> bool,
+    {
+        loop {
+            let has_more = self.run_to_next_tick()?;
+            if predicate(&self.ctx) {
+                return Ok(true);
+            }
+            if !has_more {
+                return Ok(false);
+            }
+        }
+    }
+
     fn reset(&mut self) -> Result<(), io::Error> {
         self.demo_stream
             .seek(SeekFrom::Start(self.demo_stream.start_position()))?;
 
         self.ctx.entities.clear();
         self.ctx.string_tables.clear();
-        self.ctx.instance_baseline.clear();
+        // NOTE: instance baseline is not cleared, it keeps versions keyed by tick; see
+        // InstanceBaseline.
+
+        // NOTE: string tables are re-populated from scratch after reset; keeping the log would
+        // result in duplicate changes.
+        if let Some(string_table_log) = self.ctx.string_table_log.as_mut() {
+            string_table_log.clear();
+        }
+        // NOTE: same as above; bits would be counted twice.
+        if let Some(field_bits) = self.field_decode_ctx.field_bits.as_mut() {
+            field_bits.clear();
+        }
         self.ctx.tick = -1;
         self.ctx.prev_tick = -1;
+        self.last_snapshot_tick = None;
+        self.pending_tick_end = None;
+
+        Ok(())
+    }
+
+    /// ends the pending tick if `tick` (of the cmd that is about to be handled) is a different
+    /// one.
+    fn end_tick_before(&mut self, tick: i32) -> Result<()> {
+        if self
+            .pending_tick_end
+            .is_some_and(|pending_tick| pending_tick != tick)
+        {
+            self.end_pending_tick()?;
+        }
+        Ok(())
+    }
+
+    /// NOTE: ctx.tick is temporarily set to the tick that ended; cmd header of the next tick may
+    /// have advanced it already.
+    fn end_pending_tick(&mut self) -> Result<()> {
+        let Some(tick) = self.pending_tick_end.take() else {
+            return Ok(());
+        };
+        let current_tick = self.ctx.tick;
+        self.ctx.tick = tick;
+        let result = self.handle_tick_end();
+        self.ctx.tick = current_tick;
+        result
+    }
+
+    fn handle_tick_end(&mut self) -> Result<()> {
+        self.handle_snapshot()?;
+        self.visitor.on_tick_end(&self.ctx)?;
+
+        // NOTE: computing field state size walks all entities; don't if there's no limit.
+        if self.resource_limits.max_field_state_bytes.is_some() {
+            self.resource_limits
+                .check_field_state_bytes(self.ctx.entities.field_state_bytes())?;
+        }
 
         Ok(())
     }
 
+    fn handle_snapshot(&mut self) -> Result<()> {
+        let Some(snapshot_interval) = self.snapshot_interval else {
+            return Ok(());
+        };
+        let tick = self.ctx.tick;
+        // NOTE: initialization cmds are at tick -1, there's nothing to snapshot yet.
+        if tick < 0 {
+            return Ok(());
+        }
+        let interval = match snapshot_interval {
+            SnapshotInterval::Ticks(ticks) => ticks,
+            SnapshotInterval::Seconds(seconds) => {
+                let tick_interval = if self.ctx.tick_interval > 0.0 {
+                    self.ctx.tick_interval
+                } else {
+                    DEFAULT_TICK_INTERVAL
+                };
+                (seconds / tick_interval).round() as i32
+            }
+        }
+        .max(1);
+        if self
+            .last_snapshot_tick
+            .is_some_and(|last_snapshot_tick| tick - last_snapshot_tick < interval)
+        {
+            return Ok(());
+        }
+        self.last_snapshot_tick = Some(tick);
+
+        let mut entities: Vec<&Entity> = self
+            .ctx
+            .entities
+            .iter()
+            .map(|(_, entity)| entity)
+            .filter(|entity| {
+                self.snapshot_classes.is_empty()
+                    || self
+                        .snapshot_classes
+                        .contains(&entity.serializer().serializer_name.hash)
+            })
+            .collect();
+        entities.sort_unstable_by_key(|entity| entity.index());
+        self.visitor.on_snapshot(&self.ctx, &entities)
+    }
+
     pub fn run_to_tick(&mut self, target_tick: i32) -> Result<()> {
         // TODO: do not allow tick to be less then -1
 
@@ -262,12 +736,13 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
             let has_full_packet_ahead =
                 distance_to_target_tick > notnotself.ctx.full_packet_interval + 100;
             if is_full_packet {
+                notnotself.end_tick_before(cmd_header.tick)?;
                 let cmd_body = notnotself.demo_stream.read_cmd(cmd_header)?;
                 notnotself
                     .visitor
                     .on_cmd(&notnotself.ctx, cmd_header, cmd_body)?;
 
-                let mut cmd = D::decode_cmd_full_packet(cmd_body)?;
+                let mut cmd = decoding(|| D::decode_cmd_full_packet(cmd_body))?;
                 if has_full_packet_ahead {
                     // NOTE: clarity seem to ignore "intermediary" full packet's
                     // packet
@@ -277,8 +752,7 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
                     cmd.packet = None;
                 }
                 notnotself.handle_cmd_full_packet(cmd)?;
-                // NOTE: there's absolutely no reason to check if tick changed because it changed.
-                notnotself.visitor.on_tick_end(&notnotself.ctx)?;
+                notnotself.pending_tick_end = Some(cmd_header.tick);
 
                 did_handle_last_full_packet = !has_full_packet_ahead;
 
@@ -293,21 +767,90 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
         })
     }
 
+    // NOTE: cmds that parser needs regardless of the visitor; unwanted ones are skipped before
+    // their body is read.
+    #[inline]
+    fn wants_cmd(&self, cmd: EDemoCommands) -> bool {
+        self.visitor_wants_cmd(cmd)
+            || match cmd {
+                EDemoCommands::DemFileHeader
+                | EDemoCommands::DemPacket
+                | EDemoCommands::DemSignonPacket
+                | EDemoCommands::DemStringTables => true,
+                EDemoCommands::DemFullPacket => self.validate_full_packets,
+                EDemoCommands::DemSendTables => self.ctx.serializers.is_none(),
+                EDemoCommands::DemClassInfo => self.ctx.entity_classes.is_none(),
+                _ => false,
+            }
+    }
+
+    #[inline]
+    fn visitor_wants_cmd(&self, cmd: EDemoCommands) -> bool {
+        self.cmd_types
+            .as_ref()
+            .map_or(true, |cmd_types| cmd_types.contains(&cmd))
+    }
+
     // important initialization messages:
     // 1. DemSignonPacket (SvcCreateStringTable)
     // 2. DemSendTables (flattened serializers; never update)
     // 3. DemClassInfo (never update)
     fn handle_cmd(&mut self, cmd_header: &CmdHeader) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "cmd",
+            cmd = ?cmd_header.cmd,
+            tick = cmd_header.tick,
+            size = cmd_header.body_size,
+        )
+        .entered();
+
         // TODO: consider introducing CmdInstance thing that would allow to decode body once and
         // not read it, but skip, if unconsumed. note that to work temporary ownership of
         // demo_stream will need to be taken.
+        let visitor_wants_cmd = self.visitor_wants_cmd(cmd_header.cmd);
         let cmd_body = self.demo_stream.read_cmd(cmd_header)?;
-        self.visitor.on_cmd(&self.ctx, cmd_header, cmd_body)?;
+        if cmd_header.body_compressed {
+            self.resource_limits.check_message_size(cmd_body.len())?;
+        }
+        if visitor_wants_cmd {
+            self.visitor.on_cmd(&self.ctx, cmd_header, cmd_body)?;
+        }
 
         match cmd_header.cmd {
+            EDemoCommands::DemFileHeader => {
+                let cmd = decoding(|| CDemoFileHeader::decode_message(cmd_body))?;
+                self.ctx.game = Game::from_file_header(&cmd);
+                self.ctx
+                    .entities
+                    .set_engine_constants(self.ctx.game.engine_constants());
+                // NOTE: actual tick interval arrives with SvcServerInfo; until then use the one
+                // that the game is known to run at.
+                if self.ctx.tick_interval == 0.0 {
+                    if let Some(tick_interval) = self.ctx.game.tick_interval() {
+                        self.set_tick_interval(tick_interval);
+                    }
+                }
+            }
+
+            // NOTE: in regular flow full packets are redundant (state is maintained from deltas),
+            // unless they're needed for validation.
+            EDemoCommands::DemFullPacket if self.validate_full_packets => {
+                let cmd = decoding(|| D::decode_cmd_full_packet(cmd_body))?;
+                self.validate_full_packet(cmd)?;
+            }
+
+            // NOTE: instead of decoding CDemoPacket (which allocates a vec for its data), data
+            // is taken out of the wire bytes (see DemoStream::packet_data) and copied into a
+            // reused buffer; it can't be borrowed directly because cmd_body borrows demo_stream.
             EDemoCommands::DemPacket | EDemoCommands::DemSignonPacket => {
-                let cmd = D::decode_cmd_packet(cmd_body)?;
-                self.handle_cmd_packet(cmd)?;
+                let data = D::packet_data(cmd_body)?;
+                let mut packet_data = std::mem::take(&mut self.packet_data);
+                packet_data.clear();
+                packet_data.extend_from_slice(data);
+                let result = self.handle_packet_data(&packet_data, false);
+                self.packet_data = packet_data;
+                result?;
             }
 
             EDemoCommands::DemSendTables => {
@@ -317,8 +860,22 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
                     return Ok(());
                 }
 
-                let cmd = D::decode_cmd_send_tables(cmd_body)?;
-                self.ctx.serializers = Some(FlattenedSerializerContainer::parse(cmd)?);
+                let cmd = decoding(|| D::decode_cmd_send_tables(cmd_body))?;
+                let serializers = FlattenedSerializerContainer::parse_with_options(
+                    cmd,
+                    &self.serializer_options,
+                )?;
+                #[cfg(feature = "tracing")]
+                for unknown_field_type in serializers.unknown_field_types() {
+                    tracing::warn!(
+                        serializer_name = %unknown_field_type.serializer_name,
+                        var_name = %unknown_field_type.var_name,
+                        var_type = %unknown_field_type.var_type,
+                        reason = %unknown_field_type.reason,
+                        "falling back to u64 decoder",
+                    );
+                }
+                self.ctx.serializers = Some(serializers);
             }
 
             EDemoCommands::DemClassInfo => {
@@ -328,7 +885,7 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
                     return Ok(());
                 }
 
-                let cmd = D::decode_cmd_class_info(cmd_body)?;
+                let cmd = decoding(|| D::decode_cmd_class_info(cmd_body))?;
                 self.ctx.entity_classes = Some(EntityClasses::parse(cmd));
 
                 // NOTE: DemClassInfo message becomes available after
@@ -344,12 +901,31 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
                     // SAFETY: entity_classes value was assigned above ^.
                     let entity_classes =
                         unsafe { self.ctx.entity_classes.as_ref().unwrap_unchecked() };
-                    self.ctx
-                        .instance_baseline
-                        .update(string_table, entity_classes.classes)?;
+                    self.ctx.instance_baseline.update(
+                        string_table,
+                        entity_classes.classes,
+                        self.ctx.tick,
+                    )?;
                 }
             }
 
+            // NOTE: regular demos don't seem to contain standalone string tables cmds (only as
+            // part of full packets), but trimmed demos (see demowriter) do.
+            EDemoCommands::DemStringTables => {
+                let cmd = decoding(|| D::decode_cmd_string_tables(cmd_body))?;
+                self.handle_cmd_string_tables(cmd)?;
+            }
+
+            EDemoCommands::DemAnimationHeader if visitor_wants_cmd => {
+                let cmd = decoding(|| D::decode_cmd_animation_header(cmd_body))?;
+                self.visitor.on_animation_header(&self.ctx, &cmd)?;
+            }
+
+            EDemoCommands::DemAnimationData if visitor_wants_cmd => {
+                let cmd = decoding(|| D::decode_cmd_animation_data(cmd_body))?;
+                self.visitor.on_animation_data(&self.ctx, &cmd)?;
+            }
+
             _ => {
                 // ignore
             }
@@ -359,46 +935,134 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
     }
 
     fn handle_cmd_packet(&mut self, cmd: CDemoPacket) -> Result<()> {
-        let data = cmd.data.unwrap_or_default();
-        let mut br = BitReader::new(&data);
+        self.handle_packet_data(&cmd.data.unwrap_or_default(), false)
+    }
 
+    #[inline]
+    fn visitor_wants_packet(&self, command: u32) -> bool {
+        self.packet_types
+            .as_ref()
+            .map_or(true, |packet_types| packet_types.contains(&command))
+    }
+
+    /// `decrypted` is true for data that came out of an encrypted packet message.
+    fn handle_packet_data(&mut self, data: &[u8], decrypted: bool) -> Result<()> {
+        let mut br = BitReader::new(data);
+        // NOTE: reader must be checked even if handling of a message fails; errors of messages
+        // are more meaningful than overflows though.
+        let result = self.handle_packet_messages(&mut br, decrypted);
+        let overflowed = br.is_overflowed();
+        result?;
+        overflowed?;
+        Ok(())
+    }
+
+    fn handle_packet_messages(&mut self, br: &mut BitReader, decrypted: bool) -> Result<()> {
         while br.num_bits_left() > 8 {
-            let command = br.read_ubitvar();
-            let size = br.read_uvarint32() as usize;
+            let (command, size) = if self.safe_mode {
+                let mut br = br.checked();
+                (br.read_ubitvar()?, br.read_uvarint32()? as usize)
+            } else {
+                (br.read_ubitvar(), br.read_uvarint32() as usize)
+            };
+            self.resource_limits.check_message_size(size)?;
+            // NOTE: size comes from the wire; skips and reads below are unchecked outside of safe
+            // mode.
+            if size.saturating_mul(8) > br.num_bits_left() {
+                bail!(
+                    "message of {size} bytes does not fit into remaining {} bits of packet",
+                    br.num_bits_left()
+                );
+            }
+
+            // NOTE: checked before the message is copied into the buffer which borrows self.
+            let visitor_wants_packet = self.visitor_wants_packet(command);
+            let is_encrypted = self
+                .packet_decryption
+                .as_ref()
+                .is_some_and(|packet_decryption| packet_decryption.packet_type == command);
+            if !visitor_wants_packet && !is_encrypted && !PARSER_PACKET_TYPES.contains(&command) {
+                if self.safe_mode {
+                    br.checked().skip_bits(size * 8)?;
+                } else {
+                    br.skip_bits(size * 8);
+                }
+                continue;
+            }
 
-            let buf = &mut self.buf[..size];
-            br.read_bytes(buf);
+            let buf = if self.safe_mode {
+                let Some(buf) = self.buf.get_mut(..size) else {
+                    bail!("message of {size} bytes does not fit into packet buffer");
+                };
+                br.checked().read_bytes(buf)?;
+                buf
+            } else {
+                let buf = &mut self.buf[..size];
+                br.read_bytes(buf);
+                buf
+            };
             let buf: &_ = buf;
 
-            self.visitor.on_packet(&self.ctx, command, buf)?;
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("packet", command, size).entered();
+
+            if visitor_wants_packet {
+                self.visitor.on_packet(&self.ctx, command, buf)?;
+            }
+
+            if let Some(packet_decryption) =
+                self.packet_decryption.as_ref().filter(|_| is_encrypted)
+            {
+                // NOTE: encrypted messages are not expected to nest; crafted data that nests them
+                // must not be able to recurse without bound.
+                if decrypted {
+                    bail!("encrypted packet message (type {command}) inside of decrypted data");
+                }
+                let Some(payload) =
+                    protoscan::find_len_field(buf, packet_decryption.payload_field)?
+                else {
+                    bail!(
+                        "encrypted packet message (type {command}) has no payload field {}",
+                        packet_decryption.payload_field
+                    );
+                };
+                let data = (packet_decryption.decrypt)(&packet_decryption.key, payload)?;
+                self.handle_packet_data(&data, true)?;
+                continue;
+            }
 
             match command {
                 c if c == SvcMessages::SvcCreateStringTable as u32 => {
-                    let msg = CsvcMsgCreateStringTable::decode(buf)?;
+                    let msg = decoding(|| CsvcMsgCreateStringTable::decode_message(buf))?;
                     self.handle_svc_create_string_table(msg)?;
                 }
 
                 c if c == SvcMessages::SvcUpdateStringTable as u32 => {
-                    let msg = CsvcMsgUpdateStringTable::decode(buf)?;
+                    let msg = decoding(|| CsvcMsgUpdateStringTable::decode_message(buf))?;
                     self.handle_svc_update_string_table(msg)?;
                 }
 
                 c if c == SvcMessages::SvcPacketEntities as u32 => {
-                    let msg = CsvcMsgPacketEntities::decode(buf)?;
+                    let msg = decoding(|| CsvcMsgPacketEntities::decode_message(buf))?;
                     self.handle_svc_packet_entities(msg)?;
+                    self.resource_limits.check_entities(
+                        self.ctx.entities.len() + self.ctx.entities.len_out_of_pvs(),
+                    )?;
                 }
 
                 c if c == SvcMessages::SvcServerInfo as u32 => {
-                    let msg = CsvcMsgServerInfo::decode(buf)?;
+                    let msg = decoding(|| CsvcMsgServerInfo::decode_message(buf))?;
                     if let Some(tick_interval) = msg.tick_interval {
-                        self.ctx.tick_interval = tick_interval;
-
-                        let ratio = DEFAULT_TICK_INTERVAL / tick_interval;
-                        self.ctx.full_packet_interval = DEFAULT_FULL_PACKET_INTERVAL * ratio as i32;
+                        self.set_tick_interval(tick_interval);
+                    }
+                }
 
-                        // NOTE(blukai): field decoder context needs tick interval to be able to
-                        // decode simulation time floats.
-                        self.field_decode_ctx.tick_interval = tick_interval;
+                c if c == EBaseGameEvents::GeSource1LegacyGameEventList as u32 => {
+                    // NOTE: same as with serializers and entity classes; there's no need to
+                    // re-parse the list when seeking.
+                    if self.ctx.game_event_list.is_none() {
+                        let msg = decoding(|| CMsgSource1LegacyGameEventList::decode_message(buf))?;
+                        self.ctx.game_event_list = Some(GameEventList::parse(msg));
                     }
                 }
 
@@ -408,11 +1072,24 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
             }
         }
 
-        br.is_overflowed()?;
         Ok(())
     }
 
+    fn set_tick_interval(&mut self, tick_interval: f32) {
+        self.ctx.tick_interval = tick_interval;
+
+        let ratio = DEFAULT_TICK_INTERVAL / tick_interval;
+        self.ctx.full_packet_interval = DEFAULT_FULL_PACKET_INTERVAL * ratio as i32;
+
+        // NOTE(blukai): field decoder context needs tick interval to be able to decode simulation
+        // time floats.
+        self.field_decode_ctx.tick_interval = tick_interval;
+    }
+
     fn handle_svc_create_string_table(&mut self, msg: CsvcMsgCreateStringTable) -> Result<()> {
+        alloc_scope!(StringTables);
+
+        let table_id = self.ctx.string_tables.tables().count();
         let string_table = self.ctx.string_tables.create_string_table_mut(
             msg.name(),
             msg.user_data_fixed_size(),
@@ -425,6 +1102,7 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
         let string_data = if msg.data_compressed() {
             let sd = msg.string_data();
             let decompress_len = snap::raw::decompress_len(sd)?;
+            self.resource_limits.check_message_size(decompress_len)?;
             snap::raw::Decoder::new().decompress(sd, &mut self.buf)?;
             &self.buf[..decompress_len]
         } else {
@@ -437,28 +1115,44 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
 
         if string_table.name().eq(INSTANCE_BASELINE_TABLE_NAME) {
             if let Some(entity_classes) = self.ctx.entity_classes.as_ref() {
-                self.ctx
-                    .instance_baseline
-                    .update(string_table, entity_classes.classes)?;
+                self.ctx.instance_baseline.update(
+                    string_table,
+                    entity_classes.classes,
+                    self.ctx.tick,
+                )?;
             }
         }
 
-        Ok(())
+        self.notify_string_table_update(table_id)
     }
 
     fn handle_svc_update_string_table(&mut self, msg: CsvcMsgUpdateStringTable) -> Result<()> {
+        alloc_scope!(StringTables);
+
         debug_assert!(msg.table_id.is_some(), "invalid table id");
         let table_id = msg.table_id() as usize;
 
-        debug_assert!(
-            self.ctx.string_tables.has_table(table_id),
-            "tryting to update non-existent table"
-        );
-        let string_table = unsafe {
-            self.ctx
-                .string_tables
-                .get_table_mut(table_id)
-                .unwrap_unchecked()
+        #[cfg(feature = "tracing")]
+        if !self.ctx.string_tables.has_table(table_id) {
+            tracing::error!(table_id, "trying to update non-existent string table");
+        }
+
+        let string_table = if self.safe_mode {
+            let Some(string_table) = self.ctx.string_tables.get_table_mut(table_id) else {
+                bail!("tried to update non-existent string table #{table_id}");
+            };
+            string_table
+        } else {
+            debug_assert!(
+                self.ctx.string_tables.has_table(table_id),
+                "tryting to update non-existent table"
+            );
+            unsafe {
+                self.ctx
+                    .string_tables
+                    .get_table_mut(table_id)
+                    .unwrap_unchecked()
+            }
         };
 
         let mut br = BitReader::new(msg.string_data());
@@ -467,47 +1161,110 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
 
         if string_table.name().eq(INSTANCE_BASELINE_TABLE_NAME) {
             if let Some(entity_classes) = self.ctx.entity_classes.as_ref() {
-                self.ctx
-                    .instance_baseline
-                    .update(string_table, entity_classes.classes)?;
+                self.ctx.instance_baseline.update(
+                    string_table,
+                    entity_classes.classes,
+                    self.ctx.tick,
+                )?;
             }
         }
 
+        self.notify_string_table_update(table_id)
+    }
+
+    fn notify_string_table_update(&mut self, table_id: usize) -> Result<()> {
+        if let Some(string_table) = self.ctx.string_tables.get_table(table_id) {
+            if !string_table.changed_entries().is_empty() {
+                if let Some(string_table_log) = self.ctx.string_table_log.as_mut() {
+                    string_table_log.record(self.ctx.tick, table_id, string_table);
+                }
+                self.string_table_subscriptions
+                    .notify(self.ctx.tick, string_table)?;
+                self.visitor
+                    .on_string_table_update(&self.ctx, string_table)?;
+            }
+        }
         Ok(())
     }
 
     // NOTE: handle_msg_packet_entities is partially based on
     // ReadPacketEntities in engine/client.cpp
     fn handle_svc_packet_entities(&mut self, msg: CsvcMsgPacketEntities) -> Result<()> {
+        alloc_scope!(Entities);
+
+        if self.safe_mode && (self.ctx.entity_classes.is_none() || self.ctx.serializers.is_none()) {
+            bail!("packet entities arrived before entity classes and serializers");
+        }
+
         // SAFETY: safety here can only be guaranteed by the fact that entity
         // classes and flattened serializers become available before packet
-        // entities.
+        // entities (this is checked above in safe mode).
         let entity_classes = unsafe { self.ctx.entity_classes.as_ref().unwrap_unchecked() };
         let serializers = unsafe { self.ctx.serializers.as_ref().unwrap_unchecked() };
-        let instance_baseline = &self.ctx.instance_baseline;
 
         let entity_data = msg.entity_data();
         let mut br = BitReader::new(entity_data);
 
+        // NOTE: used for error reporting in validate_bit_consumption mode.
+        let mut last_delta: Option<(i32, DeltaHeader, usize)> = None;
+
         let mut entity_index: i32 = -1;
         for _ in (0..msg.updated_entries()).rev() {
+            let bits_consumed = br.bits_consumed();
             // TODO(blukai): maybe try to make naming consistent with valve; see
             // https://github.com/taylorfinnell/csgo-demoinfo/blob/74960c07c387b080a0965c4fc33d69ccf9bfe6c8/demoinfogo/demofiledump.cpp#L1153C18-L1153C29
             // and CL_ParseDeltaHeader in engine/client.cpp
-            entity_index += br.read_ubitvar() as i32 + 1;
-
-            let delta_header = DeltaHeader::from_bit_reader(&mut br);
+            let delta_header = if self.safe_mode {
+                let mut checked_br = br.checked();
+                let delta = checked_br.read_ubitvar()?;
+                let delta_header = DeltaHeader::from_checked_bit_reader(&mut checked_br)?;
+                // NOTE: delta comes from the wire; garbage must not overflow the index.
+                let Some(next_index) = i32::try_from(delta)
+                    .ok()
+                    .and_then(|delta| entity_index.checked_add(delta)?.checked_add(1))
+                else {
+                    // NOTE: mark the reader as checked; the error below is what matters.
+                    let _ = br.is_overflowed();
+                    bail!("entity index overflows after entity #{entity_index}");
+                };
+                entity_index = next_index;
+                delta_header
+            } else {
+                entity_index += br.read_ubitvar() as i32 + 1;
+                DeltaHeader::from_bit_reader(&mut br)
+            };
+            // NOTE: sizes of deltas are not on the wire; a delta that was decoded with a wrong
+            // number of bits shows up as garbage in the delta that follows it.
+            if self.validate_bit_consumption && !self.is_valid_delta(entity_index, delta_header) {
+                // NOTE: mark the reader as checked; the error below is what matters.
+                let _ = br.is_overflowed();
+                return Err(self.bit_consumption_error(
+                    format!("invalid delta ({delta_header:?}) of entity #{entity_index}"),
+                    last_delta,
+                ));
+            }
             match delta_header {
                 DeltaHeader::CREATE => {
-                    let entity = unsafe {
-                        let entity = self.ctx.entities.handle_create(
+                    let (entity, create_info, replaced) = unsafe {
+                        let result = self.ctx.entities.handle_create(
                             entity_index,
+                            self.ctx.tick,
                             &mut self.field_decode_ctx,
                             &mut br,
                             entity_classes,
-                            instance_baseline,
+                            &self.ctx.instance_baseline,
                             serializers,
-                        )?;
+                            self.safe_mode,
+                        );
+                        let (entity, create_info, replaced) = match result {
+                            Ok(created) => created,
+                            Err(err) => {
+                                // NOTE: mark the reader as checked; the error below is what
+                                // matters.
+                                let _ = br.is_overflowed();
+                                return Err(err.into());
+                            }
+                        };
                         // SAFETY: borrow checker is not happy because handle_create requires
                         // mutable access to entities; rust's borrowing rules specify that you
                         // cannot have both mutable and immutable refs to the same data at the same
@@ -517,13 +1274,53 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
                         // not make any sense, that is redundant because .get is called inside of
                         // .handle_create. i can't think of any issues that may arrise because of
                         // my raw pointer approach.
-                        &*(entity as *const Entity)
+                        (&*(entity as *const Entity), create_info, replaced)
                     };
+                    if let Some(replaced) = replaced.as_ref() {
+                        self.visitor
+                            .on_entity(&self.ctx, DeltaHeader::DELETE, replaced)?;
+                    }
+                    if self.drop_baseline_data
+                        && create_info.baseline_source == BaselineSource::Parsed
+                    {
+                        self.ctx
+                            .instance_baseline
+                            .drop_data(create_info.class_id, create_info.baseline_tick);
+                    }
+                    self.visitor
+                        .on_entity_create(&self.ctx, &create_info, entity)?;
                     self.visitor.on_entity(&self.ctx, delta_header, entity)?;
+                    // NOTE: recycle needs mutable access to entities; it must not happen for as
+                    // long as `entity` (which points into them) is in use.
+                    if let Some(replaced) = replaced {
+                        self.ctx.entities.recycle(replaced);
+                    }
                 }
                 DeltaHeader::DELETE => {
-                    let entity = unsafe { self.ctx.entities.handle_delete_unchecked(entity_index) };
+                    let entity = if self.safe_mode {
+                        let Some(entity) = self.ctx.entities.handle_delete(entity_index) else {
+                            let _ = br.is_overflowed();
+                            bail!("tried to delete non-existent entity #{entity_index}");
+                        };
+                        entity
+                    } else {
+                        unsafe { self.ctx.entities.handle_delete_unchecked(entity_index) }
+                    };
                     self.visitor.on_entity(&self.ctx, delta_header, &entity)?;
+                    self.ctx.entities.recycle(entity);
+                }
+                DeltaHeader::UPDATE if self.safe_mode => {
+                    let entity = self
+                        .ctx
+                        .entities
+                        .handle_update(entity_index, &mut self.field_decode_ctx, &mut br)?
+                        // SAFETY: see comment above (below .handle_create call); same stuff.
+                        .map(|entity| unsafe { &*(entity as *const Entity) });
+                    let Some(entity) = entity else {
+                        let _ = br.is_overflowed();
+                        bail!("tried to update non-existent entity #{entity_index}");
+                    };
+                    self.visitor.on_entity(&self.ctx, delta_header, entity)?;
                 }
                 DeltaHeader::UPDATE => {
                     let entity = unsafe {
@@ -537,27 +1334,216 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
                     };
                     self.visitor.on_entity(&self.ctx, delta_header, entity)?;
                 }
+                DeltaHeader::LEAVE => {
+                    // SAFETY: see comment above (below .handle_create call); same stuff.
+                    let entity = self
+                        .ctx
+                        .entities
+                        .handle_leave(entity_index)
+                        .map(|entity| unsafe { &*(entity as *const Entity) });
+                    if let Some(entity) = entity {
+                        self.visitor.on_entity(&self.ctx, delta_header, entity)?;
+                    }
+                }
                 _ => {}
             }
+
+            if self.validate_bit_consumption {
+                let delta = (
+                    entity_index,
+                    delta_header,
+                    br.bits_consumed() - bits_consumed,
+                );
+                if br.is_overflowed().is_err() {
+                    return Err(self
+                        .bit_consumption_error("entity data overflowed".to_string(), Some(delta)));
+                }
+                last_delta = Some(delta);
+            }
         }
 
         br.is_overflowed()?;
+
+        if self.validate_bit_consumption && br.bits_remaining() >= 8 {
+            return Err(self.bit_consumption_error(
+                format!(
+                    "entity data was not fully consumed ({} bits left)",
+                    br.bits_remaining()
+                ),
+                last_delta,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// see [`ParserOptions::validate_bit_consumption`]. entity must be within range and must exist,
+    /// unless it's being created or leaves pvs.
+    fn is_valid_delta(&self, entity_index: i32, delta_header: DeltaHeader) -> bool {
+        let max_edicts = self.ctx.entities.engine_constants().max_edicts() as i32;
+        if !(0..max_edicts).contains(&entity_index) {
+            return false;
+        }
+        match delta_header {
+            DeltaHeader::UPDATE | DeltaHeader::DELETE => {
+                self.ctx.entities.get(&entity_index).is_some()
+                    || self.ctx.entities.get_out_of_pvs(&entity_index).is_some()
+            }
+            _ => true,
+        }
+    }
+
+    /// points to the delta that was decoded last (which is the likely culprit).
+    #[cold]
+    fn bit_consumption_error(
+        &self,
+        problem: String,
+        last_delta: Option<(i32, DeltaHeader, usize)>,
+    ) -> anyhow::Error {
+        match last_delta {
+            Some((entity_index, delta_header, num_bits)) => {
+                let class = self
+                    .ctx
+                    .entities
+                    .get(&entity_index)
+                    .or_else(|| self.ctx.entities.get_out_of_pvs(&entity_index))
+                    .map(|entity| entity.serializer().serializer_name.clone());
+                anyhow::anyhow!(
+                    "{problem} at tick {} after entity #{entity_index} ({class:?}, \
+                    {delta_header:?}, {num_bits} bits)",
+                    self.ctx.tick,
+                )
+            }
+            None => anyhow::anyhow!("{problem} at tick {}", self.ctx.tick),
+        }
+    }
+
+    /// see [`ParserOptions::validate_full_packets`].
+    fn validate_full_packet(&mut self, cmd: CDemoFullPacket) -> Result<()> {
+        let Some(packet) = cmd.packet else {
+            return Ok(());
+        };
+        let data = packet.data.unwrap_or_default();
+
+        let opts = DiffOptions {
+            f32_epsilon: 0.0,
+            max_divergences: 1,
+        };
+        let mut messages = PacketMessages::new(&data);
+        while let Some(msg) = messages.next_message()? {
+            if msg.packet_type != SvcMessages::SvcPacketEntities as u32 {
+                continue;
+            }
+
+            let msg = decoding(|| CsvcMsgPacketEntities::decode_message(msg.data))?;
+            let full_packet_entities = self.reconstruct_entities(&msg)?;
+
+            let mut report = DiffReport::default();
+            diff_entities(
+                self.ctx.tick,
+                &self.ctx.entities,
+                &full_packet_entities,
+                &opts,
+                &mut report,
+            );
+            if let Some(divergence) = report.divergences.first() {
+                bail!("delta state (left) diverges from full packet (right): {divergence}");
+            }
+        }
+
         Ok(())
     }
 
+    /// decodes packet entities into a fresh entity container; visitor is not notified.
+    fn reconstruct_entities(&mut self, msg: &CsvcMsgPacketEntities) -> Result<EntityContainer> {
+        let (Some(entity_classes), Some(serializers)) = (
+            self.ctx.entity_classes.as_ref(),
+            self.ctx.serializers.as_ref(),
+        ) else {
+            bail!("packet entities arrived before entity classes and serializers");
+        };
+
+        let mut entities = EntityContainer::new();
+        entities.set_engine_constants(*self.ctx.entities.engine_constants());
+        // NOTE: separate context, bits of full packet entities must not be measured.
+        let mut field_decode_ctx = FieldDecodeContext {
+            tick_interval: self.field_decode_ctx.tick_interval,
+            ..Default::default()
+        };
+
+        let mut br = BitReader::new(msg.entity_data());
+        let mut entity_index: i32 = -1;
+        for _ in 0..msg.updated_entries() {
+            entity_index += br.read_ubitvar() as i32 + 1;
+
+            match DeltaHeader::from_bit_reader(&mut br) {
+                DeltaHeader::CREATE => {
+                    if let Err(err) = entities.handle_create(
+                        entity_index,
+                        self.ctx.tick,
+                        &mut field_decode_ctx,
+                        &mut br,
+                        entity_classes,
+                        &self.ctx.instance_baseline,
+                        serializers,
+                        true,
+                    ) {
+                        // NOTE: mark the reader as checked; the error below is what matters.
+                        let _ = br.is_overflowed();
+                        return Err(err.into());
+                    }
+                }
+                DeltaHeader::DELETE => {
+                    entities.handle_delete(entity_index);
+                }
+                DeltaHeader::UPDATE => {
+                    let entity =
+                        entities.handle_update(entity_index, &mut field_decode_ctx, &mut br)?;
+                    if entity.is_none() {
+                        // NOTE: mark the reader as checked; the error below is what matters.
+                        let _ = br.is_overflowed();
+                        bail!("full packet updates entity #{entity_index} that it did not create");
+                    }
+                }
+                DeltaHeader::LEAVE => {
+                    entities.handle_leave(entity_index);
+                }
+                _ => {}
+            }
+        }
+
+        br.is_overflowed()?;
+        Ok(entities)
+    }
+
     fn handle_cmd_string_tables(&mut self, cmd: CDemoStringTables) -> Result<()> {
+        alloc_scope!(StringTables);
+
         self.ctx.string_tables.do_full_update(cmd);
 
-        // SAFETY: entity_classes value is expected to be already assigned
-        let entity_classes = unsafe { self.ctx.entity_classes.as_ref().unwrap_unchecked() };
-        if let Some(string_table) = self
-            .ctx
-            .string_tables
-            .find_table(INSTANCE_BASELINE_TABLE_NAME)
-        {
+        if let (Some(string_table), Some(entity_classes)) = (
             self.ctx
-                .instance_baseline
-                .update(string_table, entity_classes.classes)?;
+                .string_tables
+                .find_table(INSTANCE_BASELINE_TABLE_NAME),
+            self.ctx.entity_classes.as_ref(),
+        ) {
+            self.ctx.instance_baseline.update(
+                string_table,
+                entity_classes.classes,
+                self.ctx.tick,
+            )?;
+        }
+
+        for (table_id, string_table) in self.ctx.string_tables.tables().enumerate() {
+            if !string_table.changed_entries().is_empty() {
+                if let Some(string_table_log) = self.ctx.string_table_log.as_mut() {
+                    string_table_log.record(self.ctx.tick, table_id, string_table);
+                }
+                self.string_table_subscriptions
+                    .notify(self.ctx.tick, string_table)?;
+                self.visitor
+                    .on_string_table_update(&self.ctx, string_table)?;
+            }
         }
 
         Ok(())
@@ -592,6 +1578,62 @@ impl<D: DemoStream, V: Visitor> Parser<D, V> {
     pub fn context(&self) -> &Context {
         &self.ctx
     }
+
+    /// none unless [`ParserOptions::measure_field_bits`] is enabled. counts are meaningful after
+    /// a run from the start (for example [`Self::run_to_end`]); seeking resets them.
+    #[inline]
+    pub fn field_bits(&self) -> Option<&FieldBitCounts> {
+        self.field_decode_ctx.field_bits.as_ref()
+    }
+
+    #[inline]
+    pub fn visitor(&self) -> &V {
+        &self.visitor
+    }
+
+    #[inline]
+    pub fn visitor_mut(&mut self) -> &mut V {
+        &mut self.visitor
+    }
+
+    /// restores string tables from the snapshot (see [`StringTableContainer::snapshot`]) and
+    /// rebuilds instance baselines from them. meant to be used for seeking, together with
+    /// restoring entity state: entity baselines (and anything that resolves names via string
+    /// tables) will be wrong if tables do not match the target tick.
+    pub fn restore_string_tables(&mut self, snapshot: &StringTablesSnapshot) -> Result<()> {
+        self.ctx.string_tables.restore(snapshot);
+
+        if let (Some(string_table), Some(entity_classes)) = (
+            self.ctx
+                .string_tables
+                .find_table(INSTANCE_BASELINE_TABLE_NAME),
+            self.ctx.entity_classes.as_ref(),
+        ) {
+            self.ctx.instance_baseline.update(
+                string_table,
+                entity_classes.classes,
+                self.ctx.tick,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// makes parser use given serializers instead of waiting for DemSendTables (which will be
+    /// ignored); see [`crate::serializerregistry`]. meant for demos / streams that do not contain
+    /// send tables (joined mid-way). serializers must come from the same game build.
+    pub fn set_serializers(&mut self, serializers: FlattenedSerializerContainer) {
+        self.ctx.serializers = Some(serializers);
+    }
+
+    /// same as [`Context::string_tables`].
+    #[inline]
+    pub fn string_tables(&self) -> Option<&StringTableContainer> {
+        self.ctx.string_tables()
+    }
+
+    pub fn into_visitor(self) -> V {
+        self.visitor
+    }
 }
 
 pub struct NopVisitor;
@@ -603,3 +1645,773 @@ impl<D: DemoStream> Parser<D, NopVisitor> {
         Self::from_stream_with_visitor(demo_stream, NopVisitor)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use valveprotos::common::CsvcMsgServerInfo;
+
+    use super::*;
+    use crate::bitwriter::BitWriter;
+    use crate::demofile::DemoFile;
+    use crate::entities::{fkey_from_path, HandleCreateError, ParseEntityError};
+    use crate::fieldpath::{self, FieldPath};
+    use crate::fieldvalue::FieldValue;
+    use crate::instancebaseline::InstanceBaselineError;
+    use crate::limits::ResourceLimitError;
+    use crate::syntheticdemo::{
+        SyntheticClass, SyntheticDemoError, SyntheticDemoWriter, SyntheticFieldType,
+    };
+
+    // NOTE: not a real message type.
+    const ENCRYPTED_PACKET_TYPE: u32 = 1000;
+
+    fn xor(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data
+            .iter()
+            .zip(key.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect())
+    }
+
+    fn encrypted_message(key: &[u8], packet_type: u32, data: &[u8]) -> Result<Vec<u8>> {
+        let mut bw = BitWriter::new();
+        bw.write_ubitvar(packet_type);
+        bw.write_uvarint32(data.len() as u32);
+        bw.write_bytes(data);
+        let encrypted = xor(key, bw.as_bytes())?;
+        // NOTE: field 2, length delimited; length fits into a single byte varint.
+        let mut msg = vec![(2 << 3) | 2, encrypted.len() as u8];
+        msg.extend_from_slice(&encrypted);
+        Ok(msg)
+    }
+
+    fn encrypted_demo(msg: Vec<u8>) -> Result<DemoFile<std::io::Cursor<Vec<u8>>>> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.packet_message(ENCRYPTED_PACKET_TYPE, msg);
+        wtr.write_tick(1)?;
+        Ok(wtr.finish_into_demo_file()?)
+    }
+
+    fn decryption_options(key: &[u8]) -> ParserOptions {
+        ParserOptions {
+            packet_decryption: Some(PacketDecryption {
+                packet_type: ENCRYPTED_PACKET_TYPE,
+                payload_field: 2,
+                key: Box::from(key),
+                decrypt: xor,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_packet_decryption() -> Result<()> {
+        let key = b"key";
+
+        let mut server_info = Vec::new();
+        CsvcMsgServerInfo {
+            tick_interval: Some(1.0 / 64.0),
+            ..Default::default()
+        }
+        .encode_message(&mut server_info);
+        let msg = encrypted_message(key, SvcMessages::SvcServerInfo as u32, &server_info)?;
+
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            encrypted_demo(msg)?,
+            NopVisitor,
+            decryption_options(key),
+        )?;
+        parser.run_to_end()?;
+        assert_eq!(parser.context().tick_interval(), 1.0 / 64.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_packet_decryption_without_payload() -> Result<()> {
+        let key = b"key";
+
+        // NOTE: field 1 instead of 2.
+        let msg = vec![(1 << 3) | 2, 3, 1, 2, 3];
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            encrypted_demo(msg)?,
+            NopVisitor,
+            decryption_options(key),
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("missing payload was not caught"))?;
+        assert!(err.to_string().contains("has no payload field 2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_packet_decryption() -> Result<()> {
+        let key = b"key";
+
+        let inner = encrypted_message(key, SvcMessages::SvcServerInfo as u32, &[])?;
+        let msg = encrypted_message(key, ENCRYPTED_PACKET_TYPE, &inner)?;
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            encrypted_demo(msg)?,
+            NopVisitor,
+            decryption_options(key),
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("nested encrypted message was handled"))?;
+        assert!(err.to_string().contains("inside of decrypted data"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_packet_message() -> Result<()> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let wtr = SyntheticDemoWriter::start_writing(classes)?;
+        let mut parser = Parser::from_stream(wtr.finish_into_demo_file()?)?;
+
+        let mut bw = BitWriter::new();
+        bw.write_ubitvar(ENCRYPTED_PACKET_TYPE);
+        bw.write_uvarint32(1024);
+        bw.write_bytes(b"truncated");
+        assert!(parser.handle_packet_data(bw.as_bytes(), false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_bit_consumption() -> Result<()> {
+        let options = ParserOptions {
+            validate_bit_consumption: true,
+            ..Default::default()
+        };
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            toy_entity_demo(false)?,
+            NopVisitor,
+            options.clone(),
+        )?;
+        parser.run_to_end()?;
+
+        // NOTE: update of entity #4 that does not exist; that is what a misaligned delta looks
+        // like.
+        let mut bw = BitWriter::new();
+        bw.write_ubitvar(4);
+        bw.write_ubit64(0b00, 2);
+        let mut packet_entities = Vec::new();
+        CsvcMsgPacketEntities {
+            max_entries: Some(1),
+            updated_entries: Some(1),
+            entity_data: Some(bw.into_bytes()),
+            ..Default::default()
+        }
+        .encode_message(&mut packet_entities);
+
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.packet_message(SvcMessages::SvcPacketEntities as u32, packet_entities);
+        wtr.write_tick(1)?;
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            wtr.finish_into_demo_file()?,
+            NopVisitor,
+            options,
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("invalid delta was not caught"))?;
+        assert!(err.to_string().contains("invalid delta"));
+
+        Ok(())
+    }
+
+    fn safe_mode_options() -> ParserOptions {
+        ParserOptions {
+            safe_mode: true,
+            ..Default::default()
+        }
+    }
+
+    /// toy entity #1 is created at tick 1; packet message is written as is at tick 2.
+    fn corrupt_demo(
+        packet_type: u32,
+        data: Vec<u8>,
+    ) -> Result<DemoFile<std::io::Cursor<Vec<u8>>>, SyntheticDemoError> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[])?;
+        wtr.write_tick(1)?;
+        wtr.packet_message(packet_type, data);
+        wtr.write_tick(2)?;
+        wtr.finish_into_demo_file()
+    }
+
+    fn packet_entities(updated_entries: i32, entity_data: Vec<u8>) -> Vec<u8> {
+        let mut packet_entities = Vec::new();
+        CsvcMsgPacketEntities {
+            max_entries: Some(updated_entries),
+            updated_entries: Some(updated_entries),
+            entity_data: Some(entity_data),
+            ..Default::default()
+        }
+        .encode_message(&mut packet_entities);
+        packet_entities
+    }
+
+    #[test]
+    fn test_update_non_existent_string_table() -> Result<()> {
+        let mut update_string_table = Vec::new();
+        CsvcMsgUpdateStringTable {
+            table_id: Some(5),
+            num_changed_entries: Some(1),
+            string_data: Some(vec![0xff]),
+        }
+        .encode_message(&mut update_string_table);
+        let demo_file = corrupt_demo(
+            SvcMessages::SvcUpdateStringTable as u32,
+            update_string_table,
+        )?;
+
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            demo_file,
+            NopVisitor,
+            safe_mode_options(),
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("update of non-existent string table was handled"))?;
+        assert!(err.to_string().contains("non-existent string table #5"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_field_path() -> Result<()> {
+        // NOTE: update of entity #1; the class has a single field, there's no field #5.
+        let mut bw = BitWriter::new();
+        bw.write_ubitvar(1);
+        bw.write_ubit64(0b00, 2);
+        let field_path = FieldPath::from_components(&[5])
+            .ok_or_else(|| anyhow::anyhow!("invalid field path"))?;
+        fieldpath::write_field_paths(&mut bw, &[field_path]);
+        let demo_file = corrupt_demo(
+            SvcMessages::SvcPacketEntities as u32,
+            packet_entities(1, bw.into_bytes()),
+        )?;
+
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            demo_file,
+            NopVisitor,
+            safe_mode_options(),
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("invalid field path was resolved"))?;
+        assert!(matches!(
+            err.downcast_ref::<ParseEntityError>(),
+            Some(ParseEntityError::InvalidFieldPath { field_path }) if field_path.as_slice() == [5]
+        ));
+
+        Ok(())
+    }
+
+    /// two toy entities are created at tick 1 and updated at tick 2.
+    fn limits_demo() -> Result<DemoFile<std::io::Cursor<Vec<u8>>>, SyntheticDemoError> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.create(2, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(90))])?;
+        wtr.write_tick(2)?;
+        wtr.finish_into_demo_file()
+    }
+
+    fn run_with_limits<V: Visitor>(
+        visitor: V,
+        resource_limits: ResourceLimits,
+    ) -> Result<ResourceLimitError> {
+        let options = ParserOptions {
+            resource_limits,
+            ..Default::default()
+        };
+        let mut parser =
+            Parser::from_stream_with_visitor_and_options(limits_demo()?, visitor, options)?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("limit was not exceeded"))?;
+        err.downcast::<ResourceLimitError>()
+    }
+
+    struct SlowVisitor;
+
+    impl Visitor for SlowVisitor {
+        fn on_cmd(&mut self, _ctx: &Context, _cmd_header: &CmdHeader, _data: &[u8]) -> Result<()> {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_resource_limits() -> Result<()> {
+        // NOTE: limits that are not exceeded must not fail anything.
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            limits_demo()?,
+            NopVisitor,
+            ParserOptions {
+                resource_limits: ResourceLimits {
+                    max_entities: Some(2),
+                    max_message_size: Some(DEMO_RECORD_BUFFER_SIZE),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )?;
+        parser.run_to_end()?;
+
+        let err = run_with_limits(
+            NopVisitor,
+            ResourceLimits {
+                max_entities: Some(1),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            err,
+            ResourceLimitError::TooManyEntities { count: 2, limit: 1 }
+        );
+
+        let err = run_with_limits(
+            NopVisitor,
+            ResourceLimits {
+                max_field_state_bytes: Some(1),
+                ..Default::default()
+            },
+        )?;
+        assert!(matches!(
+            err,
+            ResourceLimitError::FieldStateTooLarge { limit: 1, .. }
+        ));
+
+        let err = run_with_limits(
+            NopVisitor,
+            ResourceLimits {
+                max_message_size: Some(4),
+                ..Default::default()
+            },
+        )?;
+        assert!(matches!(
+            err,
+            ResourceLimitError::MessageTooLarge { limit: 4, .. }
+        ));
+
+        // NOTE: the limit is checked before each cmd; the visitor sleeps on each one.
+        let limit = std::time::Duration::from_millis(1);
+        let err = run_with_limits(
+            SlowVisitor,
+            ResourceLimits {
+                max_wall_time: Some(limit),
+                ..Default::default()
+            },
+        )?;
+        assert!(matches!(
+            err,
+            ResourceLimitError::WallTimeExceeded { limit: l, .. } if l == limit
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_existent_entity() -> Result<()> {
+        // NOTE: entity #4 does not exist; only #1 does.
+        for (delta_header, action) in [(0b11, "delete"), (0b00, "update")] {
+            let mut bw = BitWriter::new();
+            bw.write_ubitvar(4);
+            bw.write_ubit64(delta_header, 2);
+            let demo_file = corrupt_demo(
+                SvcMessages::SvcPacketEntities as u32,
+                packet_entities(1, bw.into_bytes()),
+            )?;
+
+            let mut parser = Parser::from_stream_with_visitor_and_options(
+                demo_file,
+                NopVisitor,
+                safe_mode_options(),
+            )?;
+            let err = parser
+                .run_to_end()
+                .err()
+                .ok_or_else(|| anyhow::anyhow!("{action} of non-existent entity was handled"))?;
+            assert!(err
+                .to_string()
+                .contains(&format!("tried to {action} non-existent entity #4")));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_class_id() -> Result<()> {
+        // NOTE: 3 classes take 2 bits; class id 3 does not exist.
+        let classes = vec![
+            SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32),
+            SyntheticClass::new("CToyItem").field("m_iCharges", SyntheticFieldType::Int32),
+            SyntheticClass::new("CToyCourier").field("m_iHealth", SyntheticFieldType::Int32),
+        ];
+        let mut bw = BitWriter::new();
+        bw.write_ubitvar(1);
+        bw.write_ubit64(0b10, 2);
+        bw.write_ubit64(3, 2);
+        bw.write_ubit64(
+            0,
+            Game::Unknown.engine_constants().num_serial_num_bits() as usize,
+        );
+        bw.write_uvarint32(0);
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.packet_message(
+            SvcMessages::SvcPacketEntities as u32,
+            packet_entities(1, bw.into_bytes()),
+        );
+        wtr.write_tick(1)?;
+
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            wtr.finish_into_demo_file()?,
+            NopVisitor,
+            safe_mode_options(),
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("unknown class id was resolved"))?;
+        assert!(matches!(
+            err.downcast_ref::<HandleCreateError>(),
+            Some(HandleCreateError::UnknownClassId { class_id: 3 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_serializer() -> Result<()> {
+        // NOTE: serializers of a demo that does not have CToyItem class.
+        let mut parser = Parser::from_stream(toy_entity_demo(false)?)?;
+        parser.run_to_end()?;
+        let serializers = parser
+            .ctx
+            .serializers
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("no serializers"))?;
+
+        let classes = vec![
+            SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32),
+            SyntheticClass::new("CToyItem").field("m_iCharges", SyntheticFieldType::Int32),
+        ];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyItem", &[])?;
+        wtr.write_tick(1)?;
+
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            wtr.finish_into_demo_file()?,
+            NopVisitor,
+            safe_mode_options(),
+        )?;
+        parser.set_serializers(serializers);
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("class without serializer was created"))?;
+        assert!(matches!(
+            err.downcast_ref::<HandleCreateError>(),
+            Some(HandleCreateError::MissingSerializer { class_id: 1 })
+        ));
+
+        Ok(())
+    }
+
+    fn dump_string_tables(snapshot: &StringTablesSnapshot) -> Result<String> {
+        let mut dump = Vec::new();
+        snapshot.dump(&mut dump)?;
+        Ok(String::from_utf8(dump)?)
+    }
+
+    #[test]
+    fn test_restore_string_tables() -> Result<()> {
+        let classes = vec![
+            SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32),
+            SyntheticClass::new("CToyItem").field("m_iCharges", SyntheticFieldType::Int32),
+        ];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.update_baseline("CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        wtr.write_tick(1)?;
+        wtr.update_baseline("CToyEntity", &[("m_iHealth", FieldValue::I64(75))])?;
+        wtr.update_baseline("CToyItem", &[("m_iCharges", FieldValue::I64(3))])?;
+        wtr.write_tick(2)?;
+
+        let mut parser = Parser::from_stream(wtr.finish_into_demo_file()?)?;
+        parser.run_until(|ctx| ctx.tick() >= 1)?;
+        let snapshot = parser
+            .string_tables()
+            .ok_or_else(|| anyhow::anyhow!("no string tables"))?
+            .snapshot();
+        let table = snapshot
+            .find_table(INSTANCE_BASELINE_TABLE_NAME)
+            .ok_or_else(|| anyhow::anyhow!("no instance baseline table"))?;
+        let entity_baseline = table
+            .entries()
+            .find_map(|(index, _, user_data)| (index == 0).then_some(user_data))
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("no baseline of CToyEntity"))?
+            .to_vec();
+
+        parser.run_to_end()?;
+        let mutated = parser
+            .string_tables()
+            .ok_or_else(|| anyhow::anyhow!("no string tables"))?
+            .snapshot();
+        assert_ne!(
+            dump_string_tables(&mutated)?,
+            dump_string_tables(&snapshot)?
+        );
+
+        parser.restore_string_tables(&snapshot)?;
+        let restored = parser
+            .string_tables()
+            .ok_or_else(|| anyhow::anyhow!("no string tables"))?
+            .snapshot();
+        // NOTE: dumps list ids, names, flags, keys and user data of all entries.
+        assert_eq!(
+            dump_string_tables(&restored)?,
+            dump_string_tables(&snapshot)?
+        );
+        // NOTE: instance baselines are rebuilt from restored tables.
+        let instance_baseline = parser
+            .context()
+            .instance_baseline()
+            .ok_or_else(|| anyhow::anyhow!("no instance baseline"))?;
+        let (_, data) = instance_baseline.by_id(0, i32::MAX)?;
+        assert_eq!(data, entity_baseline.as_slice());
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct EntityEventRecorder {
+        // NOTE: delta header (or none for on_entity_create) and health of the entity.
+        events: Vec<(Option<DeltaHeader>, i32)>,
+    }
+
+    impl Visitor for EntityEventRecorder {
+        fn on_entity(
+            &mut self,
+            _ctx: &Context,
+            delta_header: DeltaHeader,
+            entity: &Entity,
+        ) -> Result<()> {
+            let health = entity
+                .get_value(&fkey_from_path(&["m_iHealth"]))
+                .ok_or_else(|| anyhow::anyhow!("no health"))?;
+            self.events.push((Some(delta_header), health));
+            Ok(())
+        }
+
+        fn on_entity_create(
+            &mut self,
+            _ctx: &Context,
+            _create_info: &EntityCreateInfo,
+            entity: &Entity,
+        ) -> Result<()> {
+            let health = entity
+                .get_value(&fkey_from_path(&["m_iHealth"]))
+                .ok_or_else(|| anyhow::anyhow!("no health"))?;
+            self.events.push((None, health));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_recreate_callback_order() -> Result<()> {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        wtr.write_tick(2)?;
+
+        let mut parser = Parser::from_stream_with_visitor(
+            wtr.finish_into_demo_file()?,
+            EntityEventRecorder::default(),
+        )?;
+        parser.run_to_end()?;
+
+        assert_eq!(
+            parser.into_visitor().events,
+            vec![
+                (None, 100),
+                (Some(DeltaHeader::CREATE), 100),
+                // NOTE: the replaced entity is deleted before the new one is created.
+                (Some(DeltaHeader::DELETE), 100),
+                (None, 50),
+                (Some(DeltaHeader::CREATE), 50),
+            ]
+        );
+
+        Ok(())
+    }
+
+    fn toy_entity_demo(
+        update_baseline: bool,
+    ) -> Result<DemoFile<std::io::Cursor<Vec<u8>>>, SyntheticDemoError> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        if update_baseline {
+            wtr.update_baseline("CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        }
+        wtr.create(1, "CToyEntity", &[])?;
+        wtr.write_tick(1)?;
+        wtr.create(1, "CToyEntity", &[])?;
+        wtr.write_full_packet(2)?;
+        wtr.update(1, &[("m_bAlive", FieldValue::Bool(true))])?;
+        wtr.write_tick(3)?;
+        wtr.finish_into_demo_file()
+    }
+
+    fn toy_entity_fields<D: DemoStream, V: Visitor>(parser: &Parser<D, V>) -> Option<(i32, bool)> {
+        let entity = parser.context().entities()?.get(&1)?;
+        Some((
+            entity.get_value(&fkey_from_path(&["m_iHealth"]))?,
+            entity.get_value(&fkey_from_path(&["m_bAlive"]))?,
+        ))
+    }
+
+    #[test]
+    fn test_safe_mode() -> Result<()> {
+        // NOTE: checked reads must decode valid data exactly the same way as unchecked ones.
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            toy_entity_demo(true)?,
+            NopVisitor,
+            safe_mode_options(),
+        )?;
+        parser.run_to_end()?;
+        assert_eq!(toy_entity_fields(&parser), Some((50, true)));
+        parser.run_to_tick(3)?;
+        assert_eq!(toy_entity_fields(&parser), Some((50, true)));
+
+        Ok(())
+    }
+
+    fn full_packet_options() -> ParserOptions {
+        ParserOptions {
+            validate_full_packets: true,
+            ..Default::default()
+        }
+    }
+
+    /// toy entity #1 is created at tick 1; full packet at tick 2 carries different health.
+    fn diverging_full_packet_demo() -> Result<DemoFile<std::io::Cursor<Vec<u8>>>, SyntheticDemoError>
+    {
+        let classes =
+            vec![SyntheticClass::new("CToyEntity").field("m_iHealth", SyntheticFieldType::Int32)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(50))])?;
+        wtr.write_full_packet(2)?;
+        wtr.finish_into_demo_file()
+    }
+
+    #[test]
+    fn test_validate_full_packets() -> Result<()> {
+        // NOTE: full packet at tick 2 carries the same state that deltas produced.
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            toy_entity_demo(false)?,
+            NopVisitor,
+            full_packet_options(),
+        )?;
+        parser.run_to_end()?;
+        assert_eq!(toy_entity_fields(&parser), Some((0, true)));
+
+        // NOTE: full packets are not applied in regular flow, thus nothing diverges unless they
+        // are validated.
+        let mut parser = Parser::from_stream(diverging_full_packet_demo()?)?;
+        parser.run_to_end()?;
+
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            diverging_full_packet_demo()?,
+            NopVisitor,
+            full_packet_options(),
+        )?;
+        let err = parser
+            .run_to_end()
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("diverging full packet was not caught"))?;
+        assert!(err.to_string().contains("diverges from full packet"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_to_tick_with_dropped_baseline_data() -> Result<()> {
+        let options = ParserOptions {
+            drop_baseline_data: true,
+            ..Default::default()
+        };
+
+        // NOTE: signon baseline is re-read when seeking, thus data that was dropped comes back.
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            toy_entity_demo(false)?,
+            NopVisitor,
+            options.clone(),
+        )?;
+        parser.run_to_end()?;
+        let expected = Some((0, true));
+        assert_eq!(toy_entity_fields(&parser), expected);
+        parser.run_to_tick(3)?;
+        assert_eq!(toy_entity_fields(&parser), expected);
+
+        // NOTE: baseline that was updated at tick 1 is not re-read (the full packet does not carry
+        // string tables).
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            toy_entity_demo(true)?,
+            NopVisitor,
+            options,
+        )?;
+        parser.run_to_end()?;
+        assert_eq!(toy_entity_fields(&parser), Some((50, true)));
+        let err = parser
+            .run_to_tick(3)
+            .err()
+            .ok_or_else(|| anyhow::anyhow!("dropped baseline was decoded"))?;
+        assert!(matches!(
+            err.downcast_ref::<HandleCreateError>(),
+            Some(HandleCreateError::InstanceBaselineError(
+                InstanceBaselineError::DroppedBaseline {
+                    class_id: 0,
+                    tick: 1
+                }
+            ))
+        ));
+
+        // NOTE: data is kept by default.
+        let mut parser = Parser::from_stream(toy_entity_demo(true)?)?;
+        parser.run_to_end()?;
+        parser.run_to_tick(3)?;
+        assert_eq!(toy_entity_fields(&parser), Some((50, true)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! metrics that are emitted through the [`metrics`](https://docs.rs/metrics) facade when `metrics`
+//! feature is enabled. install any recorder (for example `metrics-exporter-prometheus`) to collect
+//! them; without `metrics` feature all of this compiles into nothing.
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+use crate::demostream::{DecodeCmdError, ReadCmdError, ReadCmdHeaderError};
+#[cfg(feature = "metrics")]
+use crate::limits::ResourceLimitError;
+#[cfg(feature = "metrics")]
+use crate::protobackend::DecodeError;
+#[cfg(feature = "metrics")]
+use crate::protoscan::ProtoScanError;
+
+/// counter; number of demos that were parsed to the end.
+pub const DEMOS_PARSED: &str = "haste_demos_parsed_total";
+/// counter; number of ticks that parser advanced over (seeks count ticks that they skip).
+pub const TICKS: &str = "haste_ticks_total";
+/// histogram; ticks per second of each parser run (any of [`crate::parser::Parser`]'s `run_*`
+/// methods) that advanced.
+pub const TICKS_PER_SECOND: &str = "haste_ticks_per_second";
+/// counter with `kind` label; number of errors that stopped parsing.
+pub const ERRORS: &str = "haste_errors_total";
+/// gauge; peak resident set size of the process (linux only); updated once a demo was parsed to
+/// the end.
+pub const MEMORY_HIGH_WATER_BYTES: &str = "haste_memory_high_water_bytes";
+
+/// registers descriptions of all the metrics with the installed recorder.
+pub fn describe() {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::describe_counter!(DEMOS_PARSED, "number of demos that were parsed to the end");
+        metrics::describe_counter!(TICKS, "number of ticks that were handled");
+        metrics::describe_histogram!(TICKS_PER_SECOND, "parsing speed in ticks per second");
+        metrics::describe_counter!(ERRORS, "number of errors that stopped parsing");
+        metrics::describe_gauge!(
+            MEMORY_HIGH_WATER_BYTES,
+            metrics::Unit::Bytes,
+            "peak resident set size of the process"
+        );
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn error_kind(err: &anyhow::Error) -> &'static str {
+    if err.is::<std::io::Error>() {
+        "io"
+    } else if err.is::<ReadCmdHeaderError>() || err.is::<ReadCmdError>() {
+        "read_cmd"
+    } else if err.is::<DecodeCmdError>() || err.is::<DecodeError>() || err.is::<ProtoScanError>() {
+        "decode"
+    } else if err.is::<ResourceLimitError>() {
+        "resource_limit"
+    } else {
+        "other"
+    }
+}
+
+#[allow(unused_variables)]
+#[inline]
+pub(crate) fn record_error(err: &anyhow::Error) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(ERRORS, "kind" => error_kind(err)).increment(1);
+}
+
+/// measures a single parser run; every entry point (`run_to_end`, `run_to_tick`, `run_until`,
+/// `run_to_next_tick`) goes through it.
+pub(crate) struct RunTimer {
+    #[cfg(feature = "metrics")]
+    start: Instant,
+    #[cfg(feature = "metrics")]
+    start_tick: i32,
+}
+
+impl RunTimer {
+    #[allow(unused_variables)]
+    #[inline]
+    pub(crate) fn start(tick: i32) -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            start: Instant::now(),
+            #[cfg(feature = "metrics")]
+            start_tick: tick,
+        }
+    }
+
+    #[allow(unused_variables)]
+    #[inline]
+    pub(crate) fn finish(self, tick: i32, reached_end: bool) {
+        #[cfg(feature = "metrics")]
+        {
+            let ticks = (tick - self.start_tick).max(0) as u64;
+            if ticks > 0 {
+                metrics::counter!(TICKS).increment(ticks);
+                let secs = self.start.elapsed().as_secs_f64();
+                if secs > 0.0 {
+                    metrics::histogram!(TICKS_PER_SECOND).record(ticks as f64 / secs);
+                }
+            }
+            // NOTE: runs that find the stream at its end already (nothing was handled) are not
+            // counted as another parsed demo.
+            if reached_end && tick != self.start_tick {
+                metrics::counter!(DEMOS_PARSED).increment(1);
+                if let Some(bytes) = memory_high_water_bytes() {
+                    metrics::gauge!(MEMORY_HIGH_WATER_BYTES).set(bytes as f64);
+                }
+            }
+        }
+    }
+}
+
+/// reads `VmHWM` from `/proc/self/status`.
+#[cfg(all(feature = "metrics", target_os = "linux"))]
+fn memory_high_water_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(all(feature = "metrics", not(target_os = "linux")))]
+fn memory_high_water_bytes() -> Option<u64> {
+    None
+}
@@ -0,0 +1,146 @@
+//! dota 2 per-player stats (gold, net worth, last hits, kills / deaths / assists) sampled over
+//! time into columns. kda lives in `CDOTA_PlayerResource`; gold, net worth and last hits live in
+//! `CDOTA_DataRadiant` / `CDOTA_DataDire` (indexed by player slot within the team).
+
+use crate::entities::{fkey_from_dotted_path, Entity, EntityContainer};
+use crate::fxhash;
+
+pub const PLAYER_RESOURCE_ENTITY: u64 = fxhash::hash_bytes(b"CDOTA_PlayerResource");
+pub const DATA_RADIANT_ENTITY: u64 = fxhash::hash_bytes(b"CDOTA_DataRadiant");
+pub const DATA_DIRE_ENTITY: u64 = fxhash::hash_bytes(b"CDOTA_DataDire");
+
+pub const MAX_PLAYERS: usize = 10;
+const PLAYERS_PER_TEAM: usize = 5;
+
+struct PlayerKeys {
+    kills: u64,
+    deaths: u64,
+    assists: u64,
+    reliable_gold: u64,
+    unreliable_gold: u64,
+    net_worth: u64,
+    last_hits: u64,
+}
+
+impl PlayerKeys {
+    fn new(player_id: usize) -> Self {
+        let team_data =
+            |name: &str| fkey_from_dotted_path(&format!("m_vecPlayerTeamData.{player_id}.{name}"));
+        let data_team = |name: &str| {
+            fkey_from_dotted_path(&format!(
+                "m_vecDataTeam.{}.{name}",
+                player_id % PLAYERS_PER_TEAM
+            ))
+        };
+        Self {
+            kills: team_data("m_iKills"),
+            deaths: team_data("m_iDeaths"),
+            assists: team_data("m_iAssists"),
+            reliable_gold: data_team("m_iReliableGold"),
+            unreliable_gold: data_team("m_iUnreliableGold"),
+            net_worth: data_team("m_iNetWorth"),
+            last_hits: data_team("m_iLastHitCount"),
+        }
+    }
+}
+
+/// one row per (sample, player); missing values are 0.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerStatsColumns {
+    pub tick: Vec<i32>,
+    pub player_id: Vec<i32>,
+    pub gold: Vec<i32>,
+    pub net_worth: Vec<i32>,
+    pub last_hits: Vec<i32>,
+    pub kills: Vec<i32>,
+    pub deaths: Vec<i32>,
+    pub assists: Vec<i32>,
+}
+
+impl PlayerStatsColumns {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tick.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tick.is_empty()
+    }
+}
+
+/// call [`PlayerStatsRecorder::sample`] from [`crate::parser::Visitor::on_tick_end`]; a sample is
+/// recorded once per `interval` ticks (once all the needed entities exist).
+///
+/// NOTE: player ids 0..5 are radiant, 5..10 are dire.
+pub struct PlayerStatsRecorder {
+    interval: i32,
+    last_sample_tick: Option<i32>,
+    keys: Vec<PlayerKeys>,
+    columns: PlayerStatsColumns,
+}
+
+impl PlayerStatsRecorder {
+    pub fn new(interval: i32) -> Self {
+        Self {
+            interval: interval.max(1),
+            last_sample_tick: None,
+            keys: (0..MAX_PLAYERS).map(PlayerKeys::new).collect(),
+            columns: PlayerStatsColumns::default(),
+        }
+    }
+
+    pub fn sample(&mut self, tick: i32, entities: &EntityContainer) {
+        if self
+            .last_sample_tick
+            .is_some_and(|last_sample_tick| tick - last_sample_tick < self.interval)
+        {
+            return;
+        }
+
+        let find = |serializer_name_hash: u64| -> Option<&Entity> {
+            entities
+                .iter()
+                .map(|(_, entity)| entity)
+                .find(|entity| entity.serializer_name_heq(serializer_name_hash))
+        };
+        let (Some(player_resource), Some(data_radiant), Some(data_dire)) = (
+            find(PLAYER_RESOURCE_ENTITY),
+            find(DATA_RADIANT_ENTITY),
+            find(DATA_DIRE_ENTITY),
+        ) else {
+            return;
+        };
+        self.last_sample_tick = Some(tick);
+
+        for (player_id, keys) in self.keys.iter().enumerate() {
+            let data_team = if player_id < PLAYERS_PER_TEAM {
+                data_radiant
+            } else {
+                data_dire
+            };
+            let get = |entity: &Entity, key: u64| -> i32 { entity.get_value(&key).unwrap_or(0) };
+
+            let columns = &mut self.columns;
+            columns.tick.push(tick);
+            columns.player_id.push(player_id as i32);
+            columns
+                .gold
+                .push(get(data_team, keys.reliable_gold) + get(data_team, keys.unreliable_gold));
+            columns.net_worth.push(get(data_team, keys.net_worth));
+            columns.last_hits.push(get(data_team, keys.last_hits));
+            columns.kills.push(get(player_resource, keys.kills));
+            columns.deaths.push(get(player_resource, keys.deaths));
+            columns.assists.push(get(player_resource, keys.assists));
+        }
+    }
+
+    #[inline]
+    pub fn columns(&self) -> &PlayerStatsColumns {
+        &self.columns
+    }
+
+    pub fn into_columns(self) -> PlayerStatsColumns {
+        self.columns
+    }
+}
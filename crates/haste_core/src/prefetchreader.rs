@@ -0,0 +1,292 @@
+//! reader that reads ahead on a helper thread; while the parser decodes bytes of one chunk the
+//! next chunk is being read. hides latency of slow inputs (network file systems, disks that are
+//! busy with other things, readers that decompress on the fly) behind decoding.
+//!
+//! ```ignore
+//! let file = File::open(filepath)?;
+//! let demo_file = DemoFile::start_reading(PrefetchReader::new(file))?;
+//! ```
+//!
+//! NOTE: it is pointless for inputs that are already in memory, and for fast local disks gains
+//! are small; os page cache does read ahead too.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc;
+use std::thread;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+// NOTE: one chunk is being consumed, the other one is being filled.
+const NUM_CHUNKS: usize = 2;
+
+enum Request {
+    /// fill the buffer with the next bytes; buffers are recycled.
+    Fill(Vec<u8>),
+    Seek(SeekFrom),
+}
+
+enum Response {
+    /// `generation` is a number of seeks that happened before the chunk was read; chunks of
+    /// previous generations are stale. empty chunk means end of stream.
+    Chunk {
+        generation: u64,
+        buf: Vec<u8>,
+    },
+    Error {
+        generation: u64,
+        err: io::Error,
+    },
+    Seeked(Result<u64, io::Error>),
+}
+
+fn fill<R: Read>(rdr: &mut R, buf: &mut Vec<u8>, chunk_size: usize) -> Result<(), io::Error> {
+    buf.resize(chunk_size, 0);
+    let mut n = 0;
+    while n < chunk_size {
+        match rdr.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => {
+                buf.truncate(n);
+                return Err(err);
+            }
+        }
+    }
+    buf.truncate(n);
+    Ok(())
+}
+
+fn run_helper<R: Read + Seek>(
+    mut rdr: R,
+    chunk_size: usize,
+    requests: mpsc::Receiver<Request>,
+    responses: mpsc::Sender<Response>,
+) {
+    let mut generation = 0;
+    // NOTE: recv fails when the reader is dropped; that's the signal to stop.
+    while let Ok(request) = requests.recv() {
+        let response = match request {
+            Request::Fill(mut buf) => match fill(&mut rdr, &mut buf, chunk_size) {
+                Ok(()) => Response::Chunk { generation, buf },
+                Err(err) => Response::Error { generation, err },
+            },
+            Request::Seek(pos) => {
+                generation += 1;
+                Response::Seeked(rdr.seek(pos))
+            }
+        };
+        if responses.send(response).is_err() {
+            return;
+        }
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "prefetch thread is gone")
+}
+
+/// wraps a reader, moves it onto a helper thread. double-buffered: one chunk is read while the
+/// other one is consumed.
+///
+/// seeks that land within the current chunk (for example
+/// [`crate::demostream::DemoStream::unread_cmd_header`]) are served from memory; other seeks
+/// discard chunks that were read ahead.
+pub struct PrefetchReader {
+    requests: mpsc::Sender<Request>,
+    responses: mpsc::Receiver<Response>,
+    generation: u64,
+    chunk: Vec<u8>,
+    // NOTE: position within chunk.
+    pos: usize,
+    // NOTE: stream position of the first byte of chunk.
+    chunk_start: u64,
+    // NOTE: number of chunks that were requested but not received yet.
+    in_flight: usize,
+}
+
+impl PrefetchReader {
+    pub fn new<R: Read + Seek + Send + 'static>(rdr: R) -> Self {
+        Self::with_chunk_size(rdr, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size<R: Read + Seek + Send + 'static>(rdr: R, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let (requests_tx, requests_rx) = mpsc::channel();
+        let (responses_tx, responses_rx) = mpsc::channel();
+        // NOTE: the thread is detached; it exits once it notices that the reader was dropped
+        // (after the read that is in progress completes). if it fails to spawn, channels are
+        // disconnected and reads fail.
+        let _ = thread::Builder::new()
+            .name("haste-prefetch".to_string())
+            .spawn(move || run_helper(rdr, chunk_size, requests_rx, responses_tx));
+
+        let mut prefetch_reader = Self {
+            requests: requests_tx,
+            responses: responses_rx,
+            generation: 0,
+            chunk: Vec::new(),
+            pos: 0,
+            chunk_start: 0,
+            in_flight: 0,
+        };
+        for _ in 0..NUM_CHUNKS {
+            prefetch_reader.request_fill(Vec::with_capacity(chunk_size));
+        }
+        prefetch_reader
+    }
+
+    #[inline]
+    fn request_fill(&mut self, buf: Vec<u8>) {
+        // NOTE: if the thread is gone, the error will surface on the next receive.
+        if self.requests.send(Request::Fill(buf)).is_ok() {
+            self.in_flight += 1;
+        }
+    }
+
+    /// replaces the current chunk with the next one; returns false at the end of the stream.
+    fn next_chunk(&mut self) -> Result<bool, io::Error> {
+        loop {
+            if self.in_flight == 0 {
+                return Err(disconnected());
+            }
+            let response = self.responses.recv().map_err(|_| disconnected())?;
+            match response {
+                Response::Chunk { generation, buf } if generation == self.generation => {
+                    self.in_flight -= 1;
+                    let prev = std::mem::replace(&mut self.chunk, buf);
+                    self.chunk_start += prev.len() as u64;
+                    self.pos = 0;
+                    self.request_fill(prev);
+                    return Ok(!self.chunk.is_empty());
+                }
+                Response::Error { generation, err } if generation == self.generation => {
+                    self.in_flight -= 1;
+                    // NOTE: the buffer is lost with the error; keep the number of chunks.
+                    self.request_fill(Vec::new());
+                    return Err(err);
+                }
+                Response::Chunk { buf, .. } => {
+                    self.in_flight -= 1;
+                    self.request_fill(buf);
+                }
+                Response::Error { .. } => {
+                    self.in_flight -= 1;
+                    self.request_fill(Vec::new());
+                }
+                Response::Seeked(_) => {}
+            }
+        }
+    }
+
+    fn seek_helper(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
+        self.requests
+            .send(Request::Seek(pos))
+            .map_err(|_| disconnected())?;
+        self.generation += 1;
+
+        let chunk = std::mem::take(&mut self.chunk);
+        self.request_fill(chunk);
+
+        loop {
+            match self.responses.recv().map_err(|_| disconnected())? {
+                Response::Seeked(result) => {
+                    let position = result?;
+                    self.chunk_start = position;
+                    self.pos = 0;
+                    return Ok(position);
+                }
+                // NOTE: chunks that were read before the seek are stale.
+                Response::Chunk { generation, buf } if generation < self.generation => {
+                    self.in_flight -= 1;
+                    self.request_fill(buf);
+                }
+                Response::Error { generation, .. } if generation < self.generation => {
+                    self.in_flight -= 1;
+                    self.request_fill(Vec::new());
+                }
+                // NOTE: fills are queued after the seek, they can't be answered before it.
+                Response::Chunk { .. } | Response::Error { .. } => {}
+            }
+        }
+    }
+}
+
+impl Read for PrefetchReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos >= self.chunk.len() && !self.next_chunk()? {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Seek for PrefetchReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.chunk_start + self.pos as u64;
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => position.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        };
+        match target {
+            Some(target)
+                if target >= self.chunk_start
+                    && target <= self.chunk_start + self.chunk.len() as u64 =>
+            {
+                self.pos = (target - self.chunk_start) as usize;
+                Ok(target)
+            }
+            Some(target) => self.seek_helper(SeekFrom::Start(target)),
+            None => self.seek_helper(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_read_and_seek() -> Result<(), io::Error> {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut rdr = PrefetchReader::with_chunk_size(Cursor::new(data.clone()), 64);
+
+        let mut buf = vec![0u8; 1000];
+        rdr.read_exact(&mut buf)?;
+        assert_eq!(buf, data[..1000]);
+
+        // within the current chunk
+        assert_eq!(rdr.seek(SeekFrom::Current(-3))?, 997);
+        let mut small = [0u8; 3];
+        rdr.read_exact(&mut small)?;
+        assert_eq!(small, data[997..1000]);
+
+        // far away
+        assert_eq!(rdr.seek(SeekFrom::Start(5000))?, 5000);
+        rdr.read_exact(&mut buf)?;
+        assert_eq!(buf, data[5000..6000]);
+        assert_eq!(rdr.stream_position()?, 6000);
+
+        assert_eq!(rdr.seek(SeekFrom::End(-10))?, 9990);
+        let mut rest = Vec::new();
+        rdr.read_to_end(&mut rest)?;
+        assert_eq!(rest, data[9990..]);
+        assert_eq!(rdr.read(&mut small)?, 0);
+
+        assert_eq!(rdr.seek(SeekFrom::Start(0))?, 0);
+        let mut all = Vec::new();
+        rdr.read_to_end(&mut all)?;
+        assert_eq!(all, data);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+//! dota 2 projectiles. projectiles are not networked as entities. tracking projectiles (attacks,
+//! most targeted spells) arrive as temp entity user messages (`CDOTAUserMsg_TE_Projectile`,
+//! `CDOTAUserMsg_TE_ProjectileLoc`, `CDOTAUserMsg_TE_DodgeProjectile`,
+//! `CDOTAUserMsg_TE_DestroyProjectile`); linear projectiles (for example mirana's arrow) arrive as
+//! `CDOTAUserMsg_CreateLinearProjectile` and `CDOTAUserMsg_DestroyLinearProjectile`.
+//!
+//! NOTE: linear projectiles can not be dodged and do not have a target; destroy message does not
+//! tell whether the projectile hit something or reached its max distance.
+//!
+//! TODO(blukai): attach damage to impacts. combat log entries do not carry entity indices (only
+//! indices into `CombatLogNames` table), thus matching them against projectiles needs unit names.
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use nohash::NoHashHasher;
+use valveprotos::dota2::{
+    CdotaUserMsgCreateLinearProjectile, CdotaUserMsgDestroyLinearProjectile,
+    CdotaUserMsgTeDestroyProjectile, CdotaUserMsgTeDodgeProjectile, CdotaUserMsgTeProjectile,
+    CdotaUserMsgTeProjectileLoc, EDotaUserMessages,
+};
+
+use crate::entities::{ehandle_to_index, is_ehandle_valid};
+use crate::protobackend::{DecodeError, ProtoMessage};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projectile {
+    /// id of the projectile; unique among active projectiles.
+    pub handle: i32,
+    pub launch_tick: i32,
+    /// index of the entity that launched the projectile; none for projectiles launched from a
+    /// location.
+    pub source: Option<i32>,
+    pub source_loc: Option<[f32; 3]>,
+    /// index of the entity that the projectile is tracking.
+    pub target: Option<i32>,
+    pub target_loc: Option<[f32; 3]>,
+    pub move_speed: i32,
+    pub dodgeable: bool,
+    /// true for attacks, false for spells.
+    pub is_attack: bool,
+    pub expire_time: f32,
+    pub dodged: bool,
+    /// some for linear projectiles.
+    pub linear: Option<LinearMotion>,
+}
+
+/// movement of a linear projectile on the xy plane, starting at `source_loc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearMotion {
+    /// units per second.
+    pub velocity: [f32; 2],
+    /// units per second squared.
+    pub acceleration: [f32; 2],
+    pub max_speed: f32,
+    /// distance that the projectile travels before it gets destroyed.
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProjectileEventKind {
+    Spawn,
+    /// target dodged the projectile (blinked, became invulnerable, etc.).
+    Dodge,
+    /// projectile reached its target.
+    Impact,
+    /// projectile that was dodged got destroyed.
+    Vanish,
+    /// linear projectile got destroyed.
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectileEvent {
+    pub tick: i32,
+    pub kind: ProjectileEventKind,
+    pub projectile: Projectile,
+}
+
+fn valid_index(handle: i32) -> Option<i32> {
+    is_ehandle_valid(handle as u32).then(|| ehandle_to_index(handle as u32))
+}
+
+type ProjectileMap = HashMap<i32, Projectile, BuildHasherDefault<NoHashHasher<i32>>>;
+
+/// keeps track of projectiles that are currently in flight. feed it with packets from
+/// [`crate::parser::Visitor::on_packet`]; other packets are ignored.
+#[derive(Debug, Default)]
+pub struct ProjectileTracker {
+    active: ProjectileMap,
+    // NOTE: handles of linear projectiles are not related to handles of tracking projectiles, they
+    // may collide.
+    active_linear: ProjectileMap,
+    events: Vec<ProjectileEvent>,
+}
+
+impl ProjectileTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, tick: i32, packet_type: u32, data: &[u8]) -> Result<(), DecodeError> {
+        match packet_type {
+            t if t == EDotaUserMessages::DotaUmTeProjectile as u32 => {
+                let msg = CdotaUserMsgTeProjectile::decode_message(data)?;
+                self.spawn(
+                    tick,
+                    Projectile {
+                        handle: msg.handle(),
+                        launch_tick: tick,
+                        source: valid_index(msg.source()),
+                        source_loc: None,
+                        target: valid_index(msg.target()),
+                        target_loc: msg.target_loc.as_ref().map(|v| [v.x(), v.y(), v.z()]),
+                        move_speed: msg.move_speed(),
+                        dodgeable: msg.dodgeable(),
+                        is_attack: msg.is_attack(),
+                        expire_time: msg.expire_time(),
+                        dodged: false,
+                        linear: None,
+                    },
+                );
+            }
+            t if t == EDotaUserMessages::DotaUmTeProjectileLoc as u32 => {
+                let msg = CdotaUserMsgTeProjectileLoc::decode_message(data)?;
+                self.spawn(
+                    tick,
+                    Projectile {
+                        handle: msg.handle(),
+                        launch_tick: tick,
+                        source: None,
+                        source_loc: msg.source_loc.as_ref().map(|v| [v.x(), v.y(), v.z()]),
+                        target: valid_index(msg.target()),
+                        target_loc: msg.target_loc.as_ref().map(|v| [v.x(), v.y(), v.z()]),
+                        move_speed: msg.move_speed(),
+                        dodgeable: msg.dodgeable(),
+                        is_attack: msg.is_attack(),
+                        expire_time: msg.expire_time(),
+                        dodged: false,
+                        linear: None,
+                    },
+                );
+            }
+            t if t == EDotaUserMessages::DotaUmTeDodgeProjectile as u32 => {
+                let msg = CdotaUserMsgTeDodgeProjectile::decode_message(data)?;
+                // NOTE: source of the dodge message is the unit that dodges.
+                let Some(index) = valid_index(msg.source()) else {
+                    return Ok(());
+                };
+                let attacks_only = msg.attacks_only();
+                for projectile in self.active.values_mut() {
+                    if projectile.target == Some(index)
+                        && projectile.dodgeable
+                        && !projectile.dodged
+                        && (!attacks_only || projectile.is_attack)
+                    {
+                        projectile.dodged = true;
+                        self.events.push(ProjectileEvent {
+                            tick,
+                            kind: ProjectileEventKind::Dodge,
+                            projectile: projectile.clone(),
+                        });
+                    }
+                }
+            }
+            t if t == EDotaUserMessages::DotaUmTeDestroyProjectile as u32 => {
+                let msg = CdotaUserMsgTeDestroyProjectile::decode_message(data)?;
+                if let Some(projectile) = self.active.remove(&msg.handle()) {
+                    let kind = if projectile.dodged {
+                        ProjectileEventKind::Vanish
+                    } else {
+                        ProjectileEventKind::Impact
+                    };
+                    self.events.push(ProjectileEvent {
+                        tick,
+                        kind,
+                        projectile,
+                    });
+                }
+            }
+            t if t == EDotaUserMessages::DotaUmCreateLinearProjectile as u32 => {
+                let msg = CdotaUserMsgCreateLinearProjectile::decode_message(data)?;
+                let velocity = msg.velocity.as_ref().map_or([0.0; 2], |v| [v.x(), v.y()]);
+                let projectile = Projectile {
+                    handle: msg.handle(),
+                    launch_tick: tick,
+                    // NOTE: unlike in temp entities this is an entity index, not a handle.
+                    source: (msg.entindex() >= 0).then(|| msg.entindex()),
+                    source_loc: msg.origin.as_ref().map(|v| [v.x(), v.y(), v.z()]),
+                    target: None,
+                    target_loc: None,
+                    move_speed: velocity[0].hypot(velocity[1]) as i32,
+                    dodgeable: false,
+                    is_attack: false,
+                    expire_time: 0.0,
+                    dodged: false,
+                    linear: Some(LinearMotion {
+                        velocity,
+                        acceleration: msg
+                            .acceleration
+                            .as_ref()
+                            .map_or([0.0; 2], |v| [v.x(), v.y()]),
+                        max_speed: msg.max_speed(),
+                        distance: msg.distance(),
+                    }),
+                };
+                self.events.push(ProjectileEvent {
+                    tick,
+                    kind: ProjectileEventKind::Spawn,
+                    projectile: projectile.clone(),
+                });
+                self.active_linear.insert(projectile.handle, projectile);
+            }
+            t if t == EDotaUserMessages::DotaUmDestroyLinearProjectile as u32 => {
+                let msg = CdotaUserMsgDestroyLinearProjectile::decode_message(data)?;
+                if let Some(projectile) = self.active_linear.remove(&msg.handle()) {
+                    self.events.push(ProjectileEvent {
+                        tick,
+                        kind: ProjectileEventKind::End,
+                        projectile,
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn spawn(&mut self, tick: i32, projectile: Projectile) {
+        self.events.push(ProjectileEvent {
+            tick,
+            kind: ProjectileEventKind::Spawn,
+            projectile: projectile.clone(),
+        });
+        self.active.insert(projectile.handle, projectile);
+    }
+
+    /// events that happened since the last call, in order.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = ProjectileEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// tracking projectiles in flight.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Projectile> {
+        self.active.values()
+    }
+
+    /// linear projectiles in flight.
+    #[inline]
+    pub fn iter_linear(&self) -> impl Iterator<Item = &Projectile> {
+        self.active_linear.values()
+    }
+
+    #[inline]
+    pub fn get(&self, handle: i32) -> Option<&Projectile> {
+        self.active.get(&handle)
+    }
+
+    /// projectiles in flight that are tracking the entity with the given index.
+    pub fn by_target(&self, index: i32) -> impl Iterator<Item = &Projectile> {
+        self.active
+            .values()
+            .filter(move |projectile| projectile.target == Some(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.active.clear();
+        self.active_linear.clear();
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use valveprotos::common::{CMsgVector, CMsgVector2D};
+
+    use super::*;
+
+    #[test]
+    fn test_linear_projectile() -> Result<(), DecodeError> {
+        let create = CdotaUserMsgCreateLinearProjectile {
+            origin: Some(CMsgVector {
+                x: Some(1.0),
+                y: Some(2.0),
+                z: Some(3.0),
+                ..Default::default()
+            }),
+            velocity: Some(CMsgVector2D {
+                x: Some(300.0),
+                y: Some(400.0),
+            }),
+            entindex: Some(5),
+            handle: Some(7),
+            distance: Some(1000.0),
+            ..Default::default()
+        };
+        let destroy = CdotaUserMsgDestroyLinearProjectile { handle: Some(7) };
+
+        let mut tracker = ProjectileTracker::new();
+        tracker.update(
+            10,
+            EDotaUserMessages::DotaUmCreateLinearProjectile as u32,
+            &create.encode_message_to_vec(),
+        )?;
+        assert_eq!(tracker.iter_linear().count(), 1);
+        assert_eq!(tracker.iter().count(), 0);
+        tracker.update(
+            20,
+            EDotaUserMessages::DotaUmDestroyLinearProjectile as u32,
+            &destroy.encode_message_to_vec(),
+        )?;
+        assert_eq!(tracker.iter_linear().count(), 0);
+
+        let events: Vec<_> = tracker.drain_events().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, ProjectileEventKind::Spawn);
+        assert_eq!(events[1].kind, ProjectileEventKind::End);
+        assert_eq!(events[1].tick, 20);
+        let projectile = &events[1].projectile;
+        assert_eq!(projectile.source, Some(5));
+        assert_eq!(projectile.source_loc, Some([1.0, 2.0, 3.0]));
+        assert_eq!(projectile.move_speed, 500);
+        assert_eq!(
+            projectile.linear.as_ref().map(|linear| linear.distance),
+            Some(1000.0)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+//! thin layer between the workspace and generated protobuf code; everything that decodes (or
+//! encodes) messages, in haste_core and in crates / tools that depend on it, goes through
+//! [`ProtoMessage`] instead of calling into prost directly, which keeps prost usage in a single
+//! place.
+//!
+//! NOTE: a selectable backend (prost vs rust-protobuf) is deferred, not implemented. `valveprotos`
+//! generates prost code only; rust-protobuf would need its own codegen of the same protos with the
+//! same accessors (the parser reads fields of decoded messages directly), which does not exist.
+//! once it does, it gets an impl of [`ProtoMessage`] behind a feature flag; until then prost is
+//! the only backend and it is not optional.
+
+use std::collections::HashMap;
+
+use crate::protoscan::{self, ProtoScanError, RawField, WIRE_TYPE_LEN};
+
+/// error of the protobuf backend.
+pub type DecodeError = prost::DecodeError;
+
+pub trait ProtoMessage: Sized {
+    fn decode_message(data: &[u8]) -> Result<Self, DecodeError>;
+
+    fn encode_message(&self, buf: &mut Vec<u8>);
+
+    #[inline]
+    fn encode_message_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_message(&mut buf);
+        buf
+    }
+}
+
+impl<T: prost::Message + Default> ProtoMessage for T {
+    #[inline(always)]
+    fn decode_message(data: &[u8]) -> Result<Self, DecodeError> {
+        T::decode(data)
+    }
+
+    #[inline(always)]
+    fn encode_message(&self, buf: &mut Vec<u8>) {
+        buf.reserve(self.encoded_len());
+        // NOTE: encoding into a vec can't fail, it grows.
+        let _ = self.encode(buf);
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DecodeUnknownFieldsError {
+    #[error(transparent)]
+    DecodeError(#[from] DecodeError),
+    #[error(transparent)]
+    ProtoScanError(#[from] ProtoScanError),
+}
+
+/// field that the message type does not model; see [`decode_with_unknown_fields`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField<'a> {
+    /// numbers of (known) embedded message fields that lead to the field, outermost first; empty
+    /// for top-level fields.
+    pub path: Vec<u64>,
+    pub field: RawField<'a>,
+}
+
+/// decodes the message and additionally returns its fields that `T` does not model (generated
+/// code drops them silently), including the ones inside of embedded messages; lets you inspect
+/// data that new game patches add to messages before haste (/ valveprotos) catches up. for
+/// example raw packets of [`crate::parser::Visitor::on_packet`] can be fed into this.
+///
+/// the message is re-encoded (once, nested messages are not decoded separately); a field is
+/// considered unknown if its number does not appear at the same place in the re-encoded message.
+/// n-th occurrence of a length-delimited field is compared against n-th occurrence of it in the
+/// re-encoded message, if they differ the field is an embedded message that lost something and
+/// it is scanned recursively. this costs an extra encode and a scan, don't use it on hot paths.
+///
+/// NOTE: demo protos are proto2 where presence of known fields is tracked, thus known fields
+/// always survive re-encoding; this would not hold for proto3 fields that are set to their
+/// default values. occurrences of a non-repeated embedded message (which get merged into one)
+/// other than the first one are not scanned.
+pub fn decode_with_unknown_fields<T: ProtoMessage>(
+    data: &[u8],
+) -> Result<(T, Vec<UnknownField<'_>>), DecodeUnknownFieldsError> {
+    let msg = T::decode_message(data)?;
+
+    let mut encoded = Vec::new();
+    msg.encode_message(&mut encoded);
+    let mut unknown_fields = Vec::new();
+    collect_unknown_fields(data, &encoded, &mut Vec::new(), &mut unknown_fields)?;
+
+    Ok((msg, unknown_fields))
+}
+
+fn collect_unknown_fields<'a>(
+    data: &'a [u8],
+    encoded: &[u8],
+    path: &mut Vec<u64>,
+    unknown_fields: &mut Vec<UnknownField<'a>>,
+) -> Result<(), ProtoScanError> {
+    let mut known_fields: HashMap<u64, Vec<RawField<'_>>> = HashMap::new();
+    for field in protoscan::fields(encoded) {
+        let field = field?;
+        known_fields
+            .entry(field.field_number)
+            .or_default()
+            .push(field);
+    }
+
+    let mut occurrences: HashMap<u64, usize> = HashMap::new();
+    for field in protoscan::fields(data) {
+        let field = field?;
+        let Some(known) = known_fields.get(&field.field_number) else {
+            unknown_fields.push(UnknownField {
+                path: path.clone(),
+                field,
+            });
+            continue;
+        };
+
+        let occurrence = occurrences.entry(field.field_number).or_default();
+        let known = known.get(*occurrence);
+        *occurrence += 1;
+        let Some(known) = known.filter(|known| {
+            field.wire_type == WIRE_TYPE_LEN
+                && known.wire_type == WIRE_TYPE_LEN
+                && known.value != field.value
+        }) else {
+            continue;
+        };
+
+        // NOTE: bytes and strings survive re-encoding as is; value that differs but can't be
+        // scanned is not an embedded message (for example a packed repeated field), skip it.
+        let mut nested = Vec::new();
+        path.push(field.field_number);
+        let result = collect_unknown_fields(field.value, known.value, path, &mut nested);
+        path.pop();
+        if result.is_ok() {
+            unknown_fields.append(&mut nested);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use valveprotos::common::{CDemoFullPacket, CDemoPacket};
+
+    use super::*;
+
+    #[test]
+    fn test_decode_with_unknown_fields() -> Result<(), DecodeUnknownFieldsError> {
+        // field 3 (data) bytes "abc", field 100 varint 42
+        let data = [0x1a, 3, b'a', b'b', b'c', 0xa0, 0x06, 42];
+        let (msg, unknown_fields) = decode_with_unknown_fields::<CDemoPacket>(&data)?;
+        assert_eq!(msg.data(), b"abc");
+        assert_eq!(
+            unknown_fields,
+            [UnknownField {
+                path: vec![],
+                field: RawField {
+                    field_number: 100,
+                    wire_type: 0,
+                    value: &[42],
+                },
+            }]
+        );
+
+        let (_, unknown_fields) = decode_with_unknown_fields::<CDemoPacket>(&data[..5])?;
+        assert!(unknown_fields.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_with_nested_unknown_fields() -> Result<(), DecodeUnknownFieldsError> {
+        // field 2 (packet) of CDemoFullPacket: field 3 (data) bytes "abc", field 100 varint 42;
+        // field 101 varint 7
+        let data = [
+            0x12, 8, 0x1a, 3, b'a', b'b', b'c', 0xa0, 0x06, 42, 0xa8, 0x06, 7,
+        ];
+        let (msg, unknown_fields) = decode_with_unknown_fields::<CDemoFullPacket>(&data)?;
+        assert_eq!(
+            msg.packet.as_ref().map(|packet| packet.data()),
+            Some(&b"abc"[..])
+        );
+        assert_eq!(
+            unknown_fields,
+            [
+                UnknownField {
+                    path: vec![2],
+                    field: RawField {
+                        field_number: 100,
+                        wire_type: 0,
+                        value: &[42],
+                    },
+                },
+                UnknownField {
+                    path: vec![],
+                    field: RawField {
+                        field_number: 101,
+                        wire_type: 0,
+                        value: &[7],
+                    },
+                },
+            ]
+        );
+
+        // NOTE: nested message that has nothing unknown is not reported.
+        let (_, unknown_fields) =
+            decode_with_unknown_fields::<CDemoFullPacket>(&[0x12, 5, 0x1a, 3, b'a', b'b', b'c'])?;
+        assert!(unknown_fields.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+//! scanning of raw protobuf wire data without decoding messages; lets the parser borrow a single
+//! field out of a message instead of materializing (allocating and copying) all of it.
+
+use crate::varint::{self, ReadVarintError};
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_FIXED64: u64 = 1;
+pub(crate) const WIRE_TYPE_LEN: u64 = 2;
+const WIRE_TYPE_FIXED32: u64 = 5;
+
+/// `CDemoPacket.data`.
+pub const CMD_PACKET_DATA_FIELD: u64 = 3;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProtoScanError {
+    #[error(transparent)]
+    ReadVarintError(#[from] ReadVarintError),
+    #[error("unsupported wire type {wire_type} (field {field_number})")]
+    UnsupportedWireType { field_number: u64, wire_type: u64 },
+    #[error("field {field_number} is truncated")]
+    Truncated { field_number: u64 },
+}
+
+fn skip(data: &mut &[u8], n: usize, field_number: u64) -> Result<(), ProtoScanError> {
+    if n > data.len() {
+        return Err(ProtoScanError::Truncated { field_number });
+    }
+    *data = &data[n..];
+    Ok(())
+}
+
+/// finds length-delimited (bytes, string or embedded message) field with the given number; if
+/// the field occurs multiple times the last occurrence wins (same as with prost for bytes
+/// fields). none if there's no such field.
+pub fn find_len_field(
+    mut data: &[u8],
+    target_field_number: u64,
+) -> Result<Option<&[u8]>, ProtoScanError> {
+    let mut found = None;
+    while !data.is_empty() {
+        let (key, _) = varint::read_uvarint64(&mut data)?;
+        let field_number = key >> 3;
+        match key & 7 {
+            WIRE_TYPE_VARINT => {
+                varint::read_uvarint64(&mut data)?;
+            }
+            WIRE_TYPE_FIXED64 => skip(&mut data, 8, field_number)?,
+            WIRE_TYPE_FIXED32 => skip(&mut data, 4, field_number)?,
+            WIRE_TYPE_LEN => {
+                let (len, _) = varint::read_uvarint64(&mut data)?;
+                let len = len as usize;
+                if len > data.len() {
+                    return Err(ProtoScanError::Truncated { field_number });
+                }
+                if field_number == target_field_number {
+                    found = Some(&data[..len]);
+                }
+                data = &data[len..];
+            }
+            // NOTE: groups are deprecated; demo protos don't use them.
+            wire_type => {
+                return Err(ProtoScanError::UnsupportedWireType {
+                    field_number,
+                    wire_type,
+                })
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// field as it is on the wire; see [`fields`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawField<'a> {
+    pub field_number: u64,
+    pub wire_type: u64,
+    /// encoded value: bytes of varint, 4 / 8 bytes of fixed32 / fixed64, or contents of
+    /// length-delimited field (without length prefix).
+    pub value: &'a [u8],
+}
+
+/// see [`fields`].
+pub struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn next_field(&mut self) -> Result<RawField<'a>, ProtoScanError> {
+        let (key, _) = varint::read_uvarint64(&mut self.data)?;
+        let field_number = key >> 3;
+        let wire_type = key & 7;
+        let value_len = match wire_type {
+            WIRE_TYPE_VARINT => {
+                let mut rest = self.data;
+                varint::read_uvarint64(&mut rest)?.1
+            }
+            WIRE_TYPE_FIXED64 => 8,
+            WIRE_TYPE_FIXED32 => 4,
+            WIRE_TYPE_LEN => {
+                let (len, _) = varint::read_uvarint64(&mut self.data)?;
+                len as usize
+            }
+            // NOTE: groups are deprecated; demo protos don't use them.
+            wire_type => {
+                return Err(ProtoScanError::UnsupportedWireType {
+                    field_number,
+                    wire_type,
+                })
+            }
+        };
+        if value_len > self.data.len() {
+            return Err(ProtoScanError::Truncated { field_number });
+        }
+        let (value, rest) = self.data.split_at(value_len);
+        self.data = rest;
+        Ok(RawField {
+            field_number,
+            wire_type,
+            value,
+        })
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<RawField<'a>, ProtoScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let result = self.next_field();
+        if result.is_err() {
+            // NOTE: there's no way to tell where the next field starts.
+            self.data = &[];
+        }
+        Some(result)
+    }
+}
+
+/// iterates over top-level fields of the message in order of appearance; iteration stops after
+/// the first error.
+pub fn fields(data: &[u8]) -> Fields<'_> {
+    Fields { data }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_len_field() -> Result<(), ProtoScanError> {
+        // field 1 varint 150, field 2 fixed32, field 3 bytes "abc", field 4 fixed64
+        let data = [
+            0x08, 0x96, 0x01, 0x15, 1, 2, 3, 4, 0x1a, 3, b'a', b'b', b'c', 0x21, 0, 0, 0, 0, 0, 0,
+            0, 0,
+        ];
+        assert_eq!(find_len_field(&data, 3)?, Some(&b"abc"[..]));
+        assert_eq!(find_len_field(&data, 5)?, None);
+        assert_eq!(find_len_field(&[], 3)?, None);
+
+        assert!(matches!(
+            find_len_field(&data[..12], 3),
+            Err(ProtoScanError::Truncated { field_number: 3 })
+        ));
+        assert!(matches!(
+            find_len_field(&[0x0b], 3),
+            Err(ProtoScanError::UnsupportedWireType {
+                field_number: 1,
+                wire_type: 3
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fields() -> Result<(), ProtoScanError> {
+        let data = [
+            0x08, 0x96, 0x01, 0x15, 1, 2, 3, 4, 0x1a, 3, b'a', b'b', b'c',
+        ];
+        let fields = fields(&data).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            fields,
+            [
+                RawField {
+                    field_number: 1,
+                    wire_type: WIRE_TYPE_VARINT,
+                    value: &[0x96, 0x01],
+                },
+                RawField {
+                    field_number: 2,
+                    wire_type: WIRE_TYPE_FIXED32,
+                    value: &[1, 2, 3, 4],
+                },
+                RawField {
+                    field_number: 3,
+                    wire_type: WIRE_TYPE_LEN,
+                    value: b"abc",
+                },
+            ]
+        );
+
+        let mut truncated = super::fields(&data[..12]);
+        assert!(matches!(truncated.next(), Some(Ok(_))));
+        assert!(matches!(truncated.next(), Some(Ok(_))));
+        assert!(matches!(
+            truncated.next(),
+            Some(Err(ProtoScanError::Truncated { field_number: 3 }))
+        ));
+        assert!(truncated.next().is_none());
+
+        Ok(())
+    }
+}
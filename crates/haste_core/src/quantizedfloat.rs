@@ -238,6 +238,43 @@ impl QuantizedFloat {
         self.low_value + range * (i as f32 * self.decode_mul)
     }
 
+    /// encodes the value; bits are returned in the order in which [`Self::decode`] reads them
+    /// (first bit is the least significant one), along with the number of bits.
+    ///
+    /// `decode(encode(value))` is equal to `quantize(value)` (of value clamped to the range),
+    /// unless the value is encoded exactly with the help of encode flags (low, high or zero).
+    pub(crate) fn encode(&self, value: f32) -> (u64, usize) {
+        let mut bits = 0u64;
+        let mut num_bits = 0usize;
+
+        if (self.encode_flags & QFE_ROUNDDOWN) != 0 {
+            if value <= self.low_value {
+                return (bits | (1 << num_bits), num_bits + 1);
+            }
+            num_bits += 1;
+        }
+
+        if (self.encode_flags & QFE_ROUNDUP) != 0 {
+            if value >= self.high_value {
+                return (bits | (1 << num_bits), num_bits + 1);
+            }
+            num_bits += 1;
+        }
+
+        if (self.encode_flags & QFE_ENCODE_ZERO_EXACTLY) != 0 {
+            if value == 0.0 {
+                return (bits | (1 << num_bits), num_bits + 1);
+            }
+            num_bits += 1;
+        }
+
+        let value = value.clamp(self.low_value, self.high_value);
+        let max = (1u64 << self.bit_count) - 1;
+        let i = (((value - self.low_value) * self.high_low_mul) as u64).min(max);
+        bits |= i << num_bits;
+        (bits, num_bits + self.bit_count as usize)
+    }
+
     pub(crate) fn decode(&self, br: &mut BitReader) -> f32 {
         if (self.encode_flags & QFE_ROUNDDOWN) != 0 && br.read_bool() {
             return self.low_value;
@@ -256,3 +293,95 @@ impl QuantizedFloat {
         self.low_value + range * (value as f32 * self.decode_mul)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn round_trip(qf: &QuantizedFloat, value: f32) -> f32 {
+        let (bits, num_bits) = qf.encode(value);
+        assert!(num_bits <= 64);
+        let buf = bits.to_le_bytes();
+        let mut br = BitReader::new(&buf);
+        let decoded = qf.decode(&mut br);
+        assert!(br.is_overflowed().is_ok());
+        assert_eq!(br.num_bits_left(), 64 - num_bits);
+        decoded
+    }
+
+    fn expected(qf: &QuantizedFloat, value: f32) -> f32 {
+        if (qf.encode_flags & QFE_ROUNDDOWN) != 0 && value <= qf.low_value {
+            qf.low_value
+        } else if (qf.encode_flags & QFE_ROUNDUP) != 0 && value >= qf.high_value {
+            qf.high_value
+        } else if (qf.encode_flags & QFE_ENCODE_ZERO_EXACTLY) != 0 && value == 0.0 {
+            0.0
+        } else {
+            // NOTE: quantize returns low / high values as is if the value is out of range.
+            qf.quantize(value.clamp(qf.low_value, qf.high_value))
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() -> Result<(), QuantizedFloatError> {
+        // (bit_count, encode_flags, low_value, high_value)
+        let params = [
+            (10, 0, 0.0, 1024.0),
+            (8, QFE_ROUNDDOWN, 0.0, 256.0),
+            (8, QFE_ROUNDUP, -1.0, 1.0),
+            (12, QFE_ENCODE_ZERO_EXACTLY, -100.0, 100.0),
+            (7, QFE_ENCODE_INTEGERS_EXACTLY, 0.0, 100.0),
+            (20, QFE_ROUNDDOWN | QFE_ENCODE_ZERO_EXACTLY, -4096.0, 4096.0),
+        ];
+
+        for (bit_count, encode_flags, low_value, high_value) in params {
+            let qf = QuantizedFloat::new(bit_count, encode_flags, low_value, high_value)?;
+
+            let steps = 1000;
+            for step in 0..=steps {
+                let value = low_value + (high_value - low_value) * (step as f32 / steps as f32);
+                assert_eq!(
+                    round_trip(&qf, value),
+                    expected(&qf, value),
+                    "value {value} with params {bit_count} {encode_flags} {low_value} {high_value}"
+                );
+            }
+
+            // out of range values are clamped
+            assert_eq!(
+                round_trip(&qf, low_value - 1.0),
+                expected(&qf, low_value - 1.0)
+            );
+            assert_eq!(
+                round_trip(&qf, high_value + 1.0),
+                expected(&qf, high_value + 1.0)
+            );
+        }
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_encode_decode_round_trip(
+            bit_count in 1..32i32,
+            encode_flags in 0..16i32,
+            low_value in -4096.0f32..4096.0,
+            range in 0.001f32..8192.0,
+            // NOTE: values slightly out of range too.
+            t in -0.1f32..1.1,
+        ) {
+            let high_value = low_value + range;
+            // NOTE: round down and round up flags are mutually exclusive; some ranges can't be
+            // represented with given number of bits.
+            let Ok(qf) = QuantizedFloat::new(bit_count, encode_flags, low_value, high_value) else {
+                return Ok(());
+            };
+
+            let value = low_value + range * t;
+            prop_assert_eq!(round_trip(&qf, value), expected(&qf, value));
+        }
+    }
+}
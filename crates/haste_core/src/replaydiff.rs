@@ -0,0 +1,359 @@
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::demostream::DemoStream;
+use crate::entities::EntityContainer;
+use crate::fieldvalue::FieldValue;
+use crate::parser::{Parser, Visitor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Left => f.write_str("left"),
+            Self::Right => f.write_str("right"),
+        }
+    }
+}
+
+/// a single difference in entity state between two parses. `side` tells which of the two parses
+/// is missing the thing.
+#[derive(Debug, Clone)]
+pub enum Divergence {
+    /// tick exists only in one of the parses.
+    MissingTick {
+        tick: i32,
+        side: Side,
+    },
+    MissingEntity {
+        tick: i32,
+        index: i32,
+        side: Side,
+    },
+    /// entity at the same index has a different serializer (values are name hashes).
+    ClassMismatch {
+        tick: i32,
+        index: i32,
+        left: u64,
+        right: u64,
+    },
+    MissingField {
+        tick: i32,
+        index: i32,
+        key: u64,
+        side: Side,
+    },
+    FieldMismatch {
+        tick: i32,
+        index: i32,
+        key: u64,
+        left: FieldValue,
+        right: FieldValue,
+    },
+}
+
+impl Divergence {
+    pub fn tick(&self) -> i32 {
+        match self {
+            Self::MissingTick { tick, .. }
+            | Self::MissingEntity { tick, .. }
+            | Self::ClassMismatch { tick, .. }
+            | Self::MissingField { tick, .. }
+            | Self::FieldMismatch { tick, .. } => *tick,
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTick { tick, side } => write!(f, "[{tick}] tick is missing on {side}"),
+            Self::MissingEntity { tick, index, side } => {
+                write!(f, "[{tick}] entity #{index} is missing on {side}")
+            }
+            Self::ClassMismatch {
+                tick,
+                index,
+                left,
+                right,
+            } => write!(
+                f,
+                "[{tick}] entity #{index} class mismatch: {left:#018x} != {right:#018x}"
+            ),
+            Self::MissingField {
+                tick,
+                index,
+                key,
+                side,
+            } => write!(
+                f,
+                "[{tick}] entity #{index} field {key:#018x} is missing on {side}"
+            ),
+            Self::FieldMismatch {
+                tick,
+                index,
+                key,
+                left,
+                right,
+            } => write!(
+                f,
+                "[{tick}] entity #{index} field {key:#018x}: {left:?} != {right:?}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// maximum absolute difference at which floats (and components of vectors) are considered
+    /// equal.
+    pub f32_epsilon: f32,
+    /// stop after collecting this many divergences.
+    pub max_divergences: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            f32_epsilon: 0.0,
+            max_divergences: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffReport {
+    /// number of ticks that were compared.
+    pub ticks: usize,
+    pub divergences: Vec<Divergence>,
+    /// true if comparison was stopped because [`DiffOptions::max_divergences`] was reached.
+    pub truncated: bool,
+}
+
+impl DiffReport {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    #[inline]
+    fn is_full(&self, opts: &DiffOptions) -> bool {
+        self.divergences.len() >= opts.max_divergences
+    }
+
+    fn push(&mut self, opts: &DiffOptions, divergence: Divergence) {
+        if self.is_full(opts) {
+            self.truncated = true;
+        } else {
+            self.divergences.push(divergence);
+        }
+    }
+}
+
+fn f32_eq(lhs: f32, rhs: f32, epsilon: f32) -> bool {
+    // NOTE: nans are equal to each other here, what matters is that both sides decoded the same
+    // thing.
+    (lhs.is_nan() && rhs.is_nan()) || lhs == rhs || (lhs - rhs).abs() <= epsilon
+}
+
+fn f32s_eq(lhs: &[f32], rhs: &[f32], epsilon: f32) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(l, r)| f32_eq(*l, *r, epsilon))
+}
+
+/// compares field values; floats are compared with the given epsilon.
+pub fn field_values_eq(lhs: &FieldValue, rhs: &FieldValue, epsilon: f32) -> bool {
+    match (lhs, rhs) {
+        (FieldValue::I64(l), FieldValue::I64(r)) => l == r,
+        (FieldValue::U64(l), FieldValue::U64(r)) => l == r,
+        (FieldValue::F32(l), FieldValue::F32(r)) => f32_eq(*l, *r, epsilon),
+        (FieldValue::Bool(l), FieldValue::Bool(r)) => l == r,
+        (FieldValue::Vector2(l), FieldValue::Vector2(r)) => f32s_eq(l, r, epsilon),
+        (FieldValue::Vector3(l), FieldValue::Vector3(r)) => f32s_eq(l, r, epsilon),
+        (FieldValue::Vector4(l), FieldValue::Vector4(r)) => f32s_eq(l, r, epsilon),
+        (FieldValue::QAngle(l), FieldValue::QAngle(r)) => f32s_eq(l, r, epsilon),
+        (FieldValue::QAnglePitchYaw(l), FieldValue::QAnglePitchYaw(r)) => f32s_eq(l, r, epsilon),
+        (FieldValue::String(l), FieldValue::String(r)) => l == r,
+        _ => false,
+    }
+}
+
+/// compares state of all entities at the given tick and appends divergences to the report.
+pub fn diff_entities(
+    tick: i32,
+    left: &EntityContainer,
+    right: &EntityContainer,
+    opts: &DiffOptions,
+    report: &mut DiffReport,
+) {
+    for (index, left_entity) in left.iter() {
+        let Some(right_entity) = right.get(index) else {
+            report.push(
+                opts,
+                Divergence::MissingEntity {
+                    tick,
+                    index: *index,
+                    side: Side::Right,
+                },
+            );
+            continue;
+        };
+
+        let left_class = left_entity.serializer().serializer_name.hash;
+        let right_class = right_entity.serializer().serializer_name.hash;
+        if left_class != right_class {
+            report.push(
+                opts,
+                Divergence::ClassMismatch {
+                    tick,
+                    index: *index,
+                    left: left_class,
+                    right: right_class,
+                },
+            );
+            continue;
+        }
+
+        for (key, left_value) in left_entity.iter() {
+            match right_entity.get(key) {
+                Some(right_value) => {
+                    if !field_values_eq(left_value, right_value, opts.f32_epsilon) {
+                        report.push(
+                            opts,
+                            Divergence::FieldMismatch {
+                                tick,
+                                index: *index,
+                                key: *key,
+                                left: left_value.clone(),
+                                right: right_value.clone(),
+                            },
+                        );
+                    }
+                }
+                None => report.push(
+                    opts,
+                    Divergence::MissingField {
+                        tick,
+                        index: *index,
+                        key: *key,
+                        side: Side::Right,
+                    },
+                ),
+            }
+        }
+        for (key, _) in right_entity.iter() {
+            if left_entity.get(key).is_none() {
+                report.push(
+                    opts,
+                    Divergence::MissingField {
+                        tick,
+                        index: *index,
+                        key: *key,
+                        side: Side::Left,
+                    },
+                );
+            }
+        }
+    }
+
+    for (index, _) in right.iter() {
+        if left.get(index).is_none() {
+            report.push(
+                opts,
+                Divergence::MissingEntity {
+                    tick,
+                    index: *index,
+                    side: Side::Left,
+                },
+            );
+        }
+    }
+}
+
+fn advance<D: DemoStream, V: Visitor>(
+    parser: &mut Parser<D, V>,
+    more: &mut bool,
+) -> Result<Option<i32>> {
+    if !*more {
+        return Ok(None);
+    }
+    *more = parser.run_to_next_tick()?;
+    Ok(Some(parser.context().tick()))
+}
+
+/// runs both parsers in lockstep, tick by tick, and compares entity state after each tick.
+///
+/// parsers may read different demos (for example a demo and its trimmed copy) or the same demo
+/// with different visitors / configurations. when one side has a tick that the other does not,
+/// the side that is behind is advanced until ticks line up again.
+pub fn diff_parsers<LD, LV, RD, RV>(
+    left: &mut Parser<LD, LV>,
+    right: &mut Parser<RD, RV>,
+    opts: &DiffOptions,
+) -> Result<DiffReport>
+where
+    LD: DemoStream,
+    LV: Visitor,
+    RD: DemoStream,
+    RV: Visitor,
+{
+    let mut report = DiffReport::default();
+    // NOTE: Context::entities returns None when there are no entities.
+    let empty = EntityContainer::new();
+
+    let (mut left_more, mut right_more) = (true, true);
+    let mut left_tick = advance(left, &mut left_more)?;
+    let mut right_tick = advance(right, &mut right_more)?;
+
+    while !report.truncated {
+        match (left_tick, right_tick) {
+            (None, None) => break,
+            (Some(l), Some(r)) if l == r => {
+                let left_entities = left.context().entities().unwrap_or(&empty);
+                let right_entities = right.context().entities().unwrap_or(&empty);
+                diff_entities(l, left_entities, right_entities, opts, &mut report);
+                report.ticks += 1;
+
+                left_tick = advance(left, &mut left_more)?;
+                right_tick = advance(right, &mut right_more)?;
+            }
+            (Some(l), Some(r)) if l < r => {
+                report.push(
+                    opts,
+                    Divergence::MissingTick {
+                        tick: l,
+                        side: Side::Right,
+                    },
+                );
+                left_tick = advance(left, &mut left_more)?;
+            }
+            (Some(l), None) => {
+                report.push(
+                    opts,
+                    Divergence::MissingTick {
+                        tick: l,
+                        side: Side::Right,
+                    },
+                );
+                left_tick = advance(left, &mut left_more)?;
+            }
+            (_, Some(r)) => {
+                report.push(
+                    opts,
+                    Divergence::MissingTick {
+                        tick: r,
+                        side: Side::Left,
+                    },
+                );
+                right_tick = advance(right, &mut right_more)?;
+            }
+        }
+    }
+
+    Ok(report)
+}
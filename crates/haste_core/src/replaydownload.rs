@@ -0,0 +1,93 @@
+//! downloads replays from valve's replay cdn. replay url is composed of cluster, match id and
+//! replay salt (dota 2's `CMsgDOTAMatch` has all three; they're returned by the game coordinator /
+//! third-party apis); replays are bzip2 compressed on the cdn and are decompressed while being
+//! downloaded.
+//!
+//! ```ignore
+//! let replay = Replay { cluster: 236, match_id: 7_800_000_000, replay_salt: 1_234_567_890 };
+//! let demo_file = replay.open(DOTA2_APP_ID)?;
+//! let mut parser = Parser::from_stream_with_visitor(demo_file, visitor)?;
+//! parser.run_to_end()?;
+//! ```
+//!
+//! NOTE: parser needs to seek (see [`crate::parser::Parser::run_to_tick`]), bzip2 streams can't,
+//! thus [`Replay::open`] keeps the whole decompressed replay in memory. use
+//! [`Replay::download_to`] to write it to a file instead.
+
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+
+use bzip2::read::BzDecoder;
+
+use crate::demofile::{DemoFile, DemoHeaderError};
+
+pub const DOTA2_APP_ID: u32 = 570;
+pub const DEADLOCK_APP_ID: u32 = 1422450;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayDownloadError {
+    #[error(transparent)]
+    HttpError(#[from] reqwest::Error),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    DemoHeaderError(#[from] DemoHeaderError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Replay {
+    pub cluster: u32,
+    pub match_id: u64,
+    pub replay_salt: u32,
+}
+
+impl Replay {
+    /// `http://replay<cluster>.valve.net/<app id>/<match id>_<replay salt>.dem.bz2`
+    pub fn url(&self, app_id: u32) -> String {
+        format!(
+            "http://replay{}.valve.net/{}/{}_{}.dem.bz2",
+            self.cluster, app_id, self.match_id, self.replay_salt
+        )
+    }
+
+    /// returns reader that decompresses the replay as it's being downloaded.
+    pub fn stream(&self, app_id: u32) -> Result<impl Read, ReplayDownloadError> {
+        // NOTE: replays that have not been uploaded yet (or that have expired) result in 404.
+        let response = reqwest::blocking::get(self.url(app_id))?.error_for_status()?;
+        Ok(BzDecoder::new(response))
+    }
+
+    /// downloads and decompresses the replay into memory; ready to be parsed.
+    pub fn open(&self, app_id: u32) -> Result<DemoFile<Cursor<Vec<u8>>>, ReplayDownloadError> {
+        let mut buf = Vec::new();
+        self.stream(app_id)?.read_to_end(&mut buf)?;
+        Ok(DemoFile::start_reading(Cursor::new(buf))?)
+    }
+
+    /// downloads and decompresses the replay into the writer; returns number of bytes written.
+    pub fn download<W: Write>(&self, app_id: u32, mut wtr: W) -> Result<u64, ReplayDownloadError> {
+        Ok(io::copy(&mut self.stream(app_id)?, &mut wtr)?)
+    }
+
+    /// downloads and decompresses the replay into a file at the given path.
+    ///
+    /// NOTE: the file is written under a temporary name and renamed once done, so that a failed
+    /// download does not leave a truncated demo behind.
+    pub fn download_to(
+        &self,
+        app_id: u32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), ReplayDownloadError> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let file = fs::File::create(&tmp_path)?;
+        let mut wtr = io::BufWriter::new(file);
+        self.download(app_id, &mut wtr)?;
+        wtr.flush()?;
+        drop(wtr);
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+//! dumps of flattened serializers (classes and their fields) and diffs between them. patches
+//! add, remove and retype fields; diffing schemas of demos recorded on different game builds
+//! shows what exactly changed.
+//!
+//! NOTE: field names are only available with `preserve-metadata` feature, thus this module is
+//! gated behind it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use anyhow::Result;
+
+use crate::demostream::DemoStream;
+use crate::flattenedserializers::{
+    FlattenedSerializer, FlattenedSerializerContainer, FlattenedSerializerField,
+};
+use crate::parser::Parser;
+
+const SCHEMA_HEADER: &str = "# haste schema v1";
+// NOTE: var types contain spaces (for example `CHandle< CBaseEntity >`), thus tabs.
+const SEPARATOR: char = '\t';
+const NONE: &str = "-";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SchemaReadError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("invalid schema header")]
+    InvalidHeader,
+    #[error("invalid schema line {line}")]
+    InvalidLine { line: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub var_type: String,
+    pub var_encoder: Option<String>,
+    pub field_serializer_name: Option<String>,
+    pub bit_count: Option<i32>,
+    pub low_value: Option<f32>,
+    pub high_value: Option<f32>,
+    pub encode_flags: Option<i32>,
+}
+
+impl FieldSchema {
+    fn from_field(field: &FlattenedSerializerField) -> Self {
+        Self {
+            name: field.var_name.as_str().to_string(),
+            var_type: field.var_type.as_str().to_string(),
+            var_encoder: field.var_encoder.as_ref().map(|s| s.as_str().to_string()),
+            field_serializer_name: field
+                .field_serializer_name
+                .as_ref()
+                .map(|s| s.as_str().to_string()),
+            bit_count: field.bit_count,
+            low_value: field.low_value,
+            high_value: field.high_value,
+            encode_flags: field.encode_flags,
+        }
+    }
+
+    /// returns true if anything but name and type differs.
+    fn encoding_differs(&self, other: &Self) -> bool {
+        self.var_encoder != other.var_encoder
+            || self.field_serializer_name != other.field_serializer_name
+            || self.bit_count != other.bit_count
+            || self.low_value.map(f32::to_bits) != other.low_value.map(f32::to_bits)
+            || self.high_value.map(f32::to_bits) != other.high_value.map(f32::to_bits)
+            || self.encode_flags != other.encode_flags
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerializerSchema {
+    pub version: Option<i32>,
+    /// in order in which they are networked.
+    pub fields: Vec<FieldSchema>,
+}
+
+impl SerializerSchema {
+    fn from_serializer(serializer: &FlattenedSerializer) -> Self {
+        Self {
+            version: serializer.serializer_version,
+            fields: serializer
+                .fields
+                .iter()
+                .map(|field| FieldSchema::from_field(field))
+                .collect(),
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    /// serializers by name.
+    pub serializers: BTreeMap<String, SerializerSchema>,
+}
+
+// returns None if value can't be parsed.
+fn parse_opt<T: std::str::FromStr>(v: &str) -> Option<Option<T>> {
+    if v == NONE {
+        Some(None)
+    } else {
+        v.parse().ok().map(Some)
+    }
+}
+
+fn parse_field(parts: &[&str]) -> Option<FieldSchema> {
+    let [name, var_type, encoder, serializer, bits, low, high, flags] = parts else {
+        return None;
+    };
+    Some(FieldSchema {
+        name: name.to_string(),
+        var_type: var_type.to_string(),
+        var_encoder: parse_opt(encoder)?,
+        field_serializer_name: parse_opt(serializer)?,
+        bit_count: parse_opt(bits)?,
+        low_value: parse_opt(low)?,
+        high_value: parse_opt(high)?,
+        encode_flags: parse_opt(flags)?,
+    })
+}
+
+impl Schema {
+    pub fn from_serializers(serializers: &FlattenedSerializerContainer) -> Self {
+        Self {
+            serializers: serializers
+                .iter()
+                .map(|(name, serializer)| {
+                    (
+                        name.to_string(),
+                        SerializerSchema::from_serializer(serializer),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// parses the demo until flattened serializers are received (they are sent before the first
+    /// tick); returns empty schema if the demo does not contain any.
+    pub fn from_demo<D: DemoStream>(demo_stream: D) -> Result<Self> {
+        let mut parser = Parser::from_stream(demo_stream)?;
+        loop {
+            if let Some(serializers) = parser.context().serializers() {
+                return Ok(Self::from_serializers(serializers));
+            }
+            if !parser.run_to_next_tick()? {
+                return Ok(Self::default());
+            }
+        }
+    }
+
+    // text format, one entry per line, values are separated by tabs; `-` stands for a missing
+    // value:
+    // - serializer <name> <version>
+    // - field <name> <var type> <var encoder> <field serializer name> <bit count> <low value>
+    //   <high value> <encode flags>
+    //
+    // fields belong to the preceding serializer.
+
+    pub fn write_to<W: Write>(&self, mut wtr: W) -> Result<(), io::Error> {
+        fn opt<T: ToString>(v: &Option<T>) -> String {
+            v.as_ref().map_or_else(|| NONE.to_string(), T::to_string)
+        }
+
+        writeln!(wtr, "{SCHEMA_HEADER}")?;
+        for (name, serializer) in self.serializers.iter() {
+            writeln!(wtr, "serializer\t{}\t{}", name, opt(&serializer.version))?;
+            for field in serializer.fields.iter() {
+                writeln!(
+                    wtr,
+                    "field\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    field.name,
+                    field.var_type,
+                    opt(&field.var_encoder),
+                    opt(&field.field_serializer_name),
+                    opt(&field.bit_count),
+                    opt(&field.low_value),
+                    opt(&field.high_value),
+                    opt(&field.encode_flags),
+                )?;
+            }
+        }
+        wtr.flush()
+    }
+
+    pub fn read_from<R: BufRead>(rdr: R) -> Result<Self, SchemaReadError> {
+        let mut lines = rdr.lines();
+        let header = lines.next().transpose()?;
+        if header.as_deref().map(str::trim_end) != Some(SCHEMA_HEADER) {
+            return Err(SchemaReadError::InvalidHeader);
+        }
+
+        let mut schema = Self::default();
+        let mut current: Option<(String, SerializerSchema)> = None;
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let invalid_line = || SchemaReadError::InvalidLine { line: i + 2 };
+            let parts: Vec<&str> = line.trim_end_matches('\r').split(SEPARATOR).collect();
+            match parts.as_slice() {
+                [""] => {}
+                ["serializer", name, version] => {
+                    let serializer = SerializerSchema {
+                        version: parse_opt(version).ok_or_else(invalid_line)?,
+                        fields: Vec::new(),
+                    };
+                    if let Some((name, serializer)) =
+                        current.replace((name.to_string(), serializer))
+                    {
+                        schema.serializers.insert(name, serializer);
+                    }
+                }
+                ["field", rest @ ..] => {
+                    let (_, serializer) = current.as_mut().ok_or_else(invalid_line)?;
+                    let field = parse_field(rest).ok_or_else(invalid_line)?;
+                    serializer.fields.push(field);
+                }
+                _ => return Err(invalid_line()),
+            }
+        }
+        if let Some((name, serializer)) = current {
+            schema.serializers.insert(name, serializer);
+        }
+        Ok(schema)
+    }
+}
+
+// diff
+// ----
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    AddedSerializer {
+        serializer: String,
+    },
+    RemovedSerializer {
+        serializer: String,
+    },
+    AddedField {
+        serializer: String,
+        field: FieldSchema,
+    },
+    RemovedField {
+        serializer: String,
+        field: FieldSchema,
+    },
+    /// var type of the field changed (for example `int32` -> `uint64`); values most likely will
+    /// decode into a different [`crate::fieldvalue::FieldValue`] variant.
+    RetypedField {
+        serializer: String,
+        old: FieldSchema,
+        new: FieldSchema,
+    },
+    /// var type stayed the same, but encoder, bit count, range or flags changed.
+    ReencodedField {
+        serializer: String,
+        old: FieldSchema,
+        new: FieldSchema,
+    },
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddedSerializer { serializer } => write!(f, "+ {serializer}"),
+            Self::RemovedSerializer { serializer } => write!(f, "- {serializer}"),
+            Self::AddedField { serializer, field } => {
+                write!(f, "+ {serializer}.{}: {}", field.name, field.var_type)
+            }
+            Self::RemovedField { serializer, field } => {
+                write!(f, "- {serializer}.{}: {}", field.name, field.var_type)
+            }
+            Self::RetypedField {
+                serializer,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {serializer}.{}: {} -> {}",
+                old.name, old.var_type, new.var_type
+            ),
+            Self::ReencodedField {
+                serializer,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {serializer}.{}: {}; {:?} -> {:?}",
+                old.name, old.var_type, old, new
+            ),
+        }
+    }
+}
+
+/// structured diff from `old` to `new`; changes are ordered by serializer name, then by field
+/// order.
+pub fn diff_schemas(old: &Schema, new: &Schema) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+
+    for (name, old_serializer) in old.serializers.iter() {
+        let Some(new_serializer) = new.serializers.get(name) else {
+            changes.push(SchemaChange::RemovedSerializer {
+                serializer: name.clone(),
+            });
+            continue;
+        };
+
+        for old_field in old_serializer.fields.iter() {
+            let change = match new_serializer.field(&old_field.name) {
+                None => SchemaChange::RemovedField {
+                    serializer: name.clone(),
+                    field: old_field.clone(),
+                },
+                Some(new_field) if new_field.var_type != old_field.var_type => {
+                    SchemaChange::RetypedField {
+                        serializer: name.clone(),
+                        old: old_field.clone(),
+                        new: new_field.clone(),
+                    }
+                }
+                Some(new_field) if new_field.encoding_differs(old_field) => {
+                    SchemaChange::ReencodedField {
+                        serializer: name.clone(),
+                        old: old_field.clone(),
+                        new: new_field.clone(),
+                    }
+                }
+                Some(_) => continue,
+            };
+            changes.push(change);
+        }
+
+        for new_field in new_serializer.fields.iter() {
+            if old_serializer.field(&new_field.name).is_none() {
+                changes.push(SchemaChange::AddedField {
+                    serializer: name.clone(),
+                    field: new_field.clone(),
+                });
+            }
+        }
+    }
+
+    for name in new.serializers.keys() {
+        if !old.serializers.contains_key(name) {
+            changes.push(SchemaChange::AddedSerializer {
+                serializer: name.clone(),
+            });
+        }
+    }
+
+    changes
+}
@@ -0,0 +1,164 @@
+//! flattened serializers saved per game build. serializers (class structure) are sent once at the
+//! beginning of a demo; demos / broadcasts that are joined mid-way, or individual messages don't
+//! carry them. saving them for a build allows to reload them later (see
+//! [`crate::parser::Parser::set_serializers`]), and to answer schema questions (see `schema`
+//! module, requires `preserve-metadata` feature) without any demo on hand.
+//!
+//! NOTE: what is saved is the raw `CDemoSendTables` message; [`FlattenedSerializerContainer`] is
+//! re-parsed from it on load, thus custom decoders / options apply as if it came from a demo.
+
+use std::fs;
+use std::io::{self, SeekFrom};
+use std::path::PathBuf;
+
+use valveprotos::common::{CDemoFileHeader, CDemoSendTables, EDemoCommands};
+
+use crate::demostream::{DecodeCmdError, DemoStream, ReadCmdError, ReadCmdHeaderError};
+use crate::flattenedserializers::{
+    FlattenedSerializerContainer, FlattenedSerializerOptions, FlattenedSerializersError,
+};
+use crate::protobackend::{DecodeError, ProtoMessage};
+
+const FILE_EXTENSION: &str = "sendtables";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SerializerRegistryError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    DecodeProtobufError(#[from] DecodeError),
+    #[error(transparent)]
+    FlattenedSerializersError(#[from] FlattenedSerializersError),
+    #[error(transparent)]
+    ReadCmdHeaderError(#[from] ReadCmdHeaderError),
+    #[error(transparent)]
+    ReadCmdError(#[from] ReadCmdError),
+    #[error(transparent)]
+    DecodeCmdError(#[from] DecodeCmdError),
+}
+
+/// send tables of a demo along with build number from its file header.
+#[derive(Debug, Clone)]
+pub struct CapturedSendTables {
+    /// none if file header does not have it.
+    pub build: Option<u32>,
+    pub send_tables: CDemoSendTables,
+}
+
+/// reads the demo from its current position until send tables are found (they're sent before the
+/// first tick), then seeks back. returns none if the demo does not contain any.
+pub fn capture_send_tables<D: DemoStream>(
+    demo_stream: &mut D,
+) -> Result<Option<CapturedSendTables>, SerializerRegistryError> {
+    let start_position = demo_stream.stream_position()?;
+
+    let mut build = None;
+    let mut send_tables = None;
+    while !demo_stream.is_at_eof()? {
+        let cmd_header = demo_stream.read_cmd_header()?;
+        match cmd_header.cmd {
+            EDemoCommands::DemFileHeader => {
+                let file_header =
+                    CDemoFileHeader::decode_message(demo_stream.read_cmd(&cmd_header)?)?;
+                build = file_header.build_num.map(|build| build as u32);
+            }
+            EDemoCommands::DemSendTables => {
+                send_tables = Some(D::decode_cmd_send_tables(
+                    demo_stream.read_cmd(&cmd_header)?,
+                )?);
+                break;
+            }
+            // NOTE: send tables are sent before the first sync tick.
+            EDemoCommands::DemSyncTick => break,
+            _ => demo_stream.skip_cmd(&cmd_header)?,
+        }
+    }
+
+    demo_stream.seek(SeekFrom::Start(start_position))?;
+    Ok(send_tables.map(|send_tables| CapturedSendTables { build, send_tables }))
+}
+
+/// directory of `<build>.sendtables` files.
+#[derive(Debug, Clone)]
+pub struct SerializerRegistry {
+    dir: PathBuf,
+}
+
+impl SerializerRegistry {
+    /// directory is created on first save.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, build: u32) -> PathBuf {
+        self.dir.join(format!("{build}.{FILE_EXTENSION}"))
+    }
+
+    pub fn contains(&self, build: u32) -> bool {
+        self.path(build).is_file()
+    }
+
+    /// overwrites whatever was saved for the build before.
+    pub fn save(&self, build: u32, send_tables: &CDemoSendTables) -> Result<(), io::Error> {
+        fs::create_dir_all(&self.dir)?;
+        // NOTE: write to tmp file and rename to not leave half written file behind if something
+        // goes wrong.
+        let path = self.path(build);
+        let tmp_path = path.with_extension(format!("{FILE_EXTENSION}.tmp"));
+        fs::write(&tmp_path, send_tables.encode_message_to_vec())?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// none if nothing was saved for the build.
+    pub fn load_send_tables(
+        &self,
+        build: u32,
+    ) -> Result<Option<CDemoSendTables>, SerializerRegistryError> {
+        let data = match fs::read(self.path(build)) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(CDemoSendTables::decode_message(data.as_slice())?))
+    }
+
+    /// none if nothing was saved for the build.
+    pub fn load(
+        &self,
+        build: u32,
+        options: &FlattenedSerializerOptions,
+    ) -> Result<Option<FlattenedSerializerContainer>, SerializerRegistryError> {
+        self.load_send_tables(build)?
+            .map(|send_tables| {
+                FlattenedSerializerContainer::parse_with_options(send_tables, options)
+                    .map_err(SerializerRegistryError::from)
+            })
+            .transpose()
+    }
+
+    /// saved builds, in ascending order.
+    pub fn builds(&self) -> Result<Vec<u32>, io::Error> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut builds = Vec::new();
+        for entry in read_dir {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(FILE_EXTENSION) {
+                continue;
+            }
+            if let Some(build) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            {
+                builds.push(build);
+            }
+        }
+        builds.sort_unstable();
+        Ok(builds)
+    }
+}
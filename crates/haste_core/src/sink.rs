@@ -0,0 +1,422 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc;
+
+use anyhow::{anyhow, Result};
+use valveprotos::common::{CMsgSource1LegacyGameEvent, EBaseGameEvents};
+
+use crate::entities::{DeltaHeader, Entity};
+use crate::fieldvalue::FieldValue;
+use crate::gameevents::EventValue;
+use crate::parser::{Context, Visitor};
+use crate::protobackend::ProtoMessage;
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// entity state after the change was applied; see [`SinkVisitor::with_entities`].
+    //
+    // NOTE: entity itself is not included because it is not Send.
+    Entity {
+        delta_header: DeltaHeader,
+        index: i32,
+        serializer_name_hash: u64,
+        fields: Vec<(u64, FieldValue)>,
+    },
+    GameEvent {
+        name: Box<str>,
+        data: Vec<(Box<str>, EventValue)>,
+    },
+    /// raw packet message; see [`SinkVisitor::with_packet_types`].
+    Packet { packet_type: u32, data: Box<[u8]> },
+    /// full state of entities; see [`crate::parser::ParserOptions::snapshot_interval`].
+    Snapshot { entities: Vec<EntitySnapshot> },
+}
+
+#[derive(Debug, Clone)]
+pub struct EntitySnapshot {
+    pub index: i32,
+    pub serializer_name_hash: u64,
+    pub fields: Vec<(u64, FieldValue)>,
+}
+
+/// events that happened during a single tick, in order of appearance.
+#[derive(Debug, Clone, Default)]
+pub struct TickEvents {
+    pub tick: i32,
+    pub events: Vec<Event>,
+}
+
+/// receiver of parsed events; implement this to forward events to external systems (message
+/// queues, databases, etc.) without touching the parser loop.
+///
+/// all events of a tick arrive in a single [`Self::on_tick_events`] call, followed by
+/// [`Self::on_tick_end`]; nothing of that tick arrives after it, which allows transactional
+/// per-tick processing (for example commit on tick end).
+pub trait Sink {
+    fn on_tick_events(&mut self, tick_events: &TickEvents) -> Result<()>;
+
+    /// called for every tick (including ticks without events) once all of its events were
+    /// delivered.
+    #[allow(unused_variables)]
+    fn on_tick_end(&mut self, tick: i32) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn on_tick_events(&mut self, tick_events: &TickEvents) -> Result<()> {
+        (**self).on_tick_events(tick_events)
+    }
+
+    fn on_tick_end(&mut self, tick: i32) -> Result<()> {
+        (**self).on_tick_end(tick)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+/// [`Visitor`] that groups events by tick and hands them over to the [`Sink`] at the end of each
+/// tick (see [`Visitor::on_tick_end`]). ticks with no events are not reported to
+/// [`Sink::on_tick_events`], but [`Sink::on_tick_end`] is called for them too.
+///
+/// by default only game events are collected.
+///
+/// NOTE: make sure to call [`SinkVisitor::finish`] when parser is done, it flushes the sink.
+pub struct SinkVisitor<S: Sink> {
+    sink: S,
+    entities: bool,
+    packet_types: Vec<u32>,
+    current: TickEvents,
+}
+
+impl<S: Sink> SinkVisitor<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            entities: false,
+            packet_types: Vec::new(),
+            current: TickEvents::default(),
+        }
+    }
+
+    /// enables entity events. note that entity fields are cloned for each event, this is
+    /// expensive.
+    pub fn with_entities(mut self, entities: bool) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    /// enables raw packet events for the given packet types (for example
+    /// `EDotaUserMessages::DotaUmChatMessage as u32`).
+    pub fn with_packet_types(mut self, packet_types: impl IntoIterator<Item = u32>) -> Self {
+        self.packet_types = packet_types.into_iter().collect();
+        self
+    }
+
+    #[inline]
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    #[inline]
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    fn flush_current(&mut self) -> Result<()> {
+        if !self.current.events.is_empty() {
+            self.sink.on_tick_events(&self.current)?;
+            self.current.events.clear();
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, tick: i32, event: Event) -> Result<()> {
+        // NOTE: events are normally flushed on tick end; this only matters for events that are
+        // pushed outside of regular parser flow.
+        if self.current.tick != tick {
+            self.flush_current()?;
+            self.current.tick = tick;
+        }
+        self.current.events.push(event);
+        Ok(())
+    }
+
+    /// hands over events of the last tick, flushes and returns the sink.
+    pub fn finish(mut self) -> Result<S> {
+        self.flush_current()?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+impl<S: Sink> Visitor for SinkVisitor<S> {
+    fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+        self.flush_current()?;
+        self.sink.on_tick_end(ctx.tick())
+    }
+
+    fn on_entity(
+        &mut self,
+        ctx: &Context,
+        delta_header: DeltaHeader,
+        entity: &Entity,
+    ) -> Result<()> {
+        if self.entities {
+            self.push(
+                ctx.tick(),
+                Event::Entity {
+                    delta_header,
+                    index: entity.index(),
+                    serializer_name_hash: entity.serializer().serializer_name.hash,
+                    fields: entity
+                        .iter()
+                        .map(|(key, value)| (*key, value.clone()))
+                        .collect(),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn on_snapshot(&mut self, ctx: &Context, entities: &[&Entity]) -> Result<()> {
+        let entities = entities
+            .iter()
+            .map(|entity| EntitySnapshot {
+                index: entity.index(),
+                serializer_name_hash: entity.serializer().serializer_name.hash,
+                fields: entity
+                    .iter()
+                    .map(|(key, value)| (*key, value.clone()))
+                    .collect(),
+            })
+            .collect();
+        self.push(ctx.tick(), Event::Snapshot { entities })
+    }
+
+    fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        if packet_type == EBaseGameEvents::GeSource1LegacyGameEvent as u32 {
+            let Some(game_event_list) = ctx.game_event_list() else {
+                return Ok(());
+            };
+            let msg = CMsgSource1LegacyGameEvent::decode_message(data)?;
+            if let Some(event) = game_event_list.decode(&msg) {
+                let event = Event::GameEvent {
+                    name: event.name().into(),
+                    data: event
+                        .iter()
+                        .map(|(key, value)| ((*key).into(), value.clone()))
+                        .collect(),
+                };
+                self.push(ctx.tick(), event)?;
+            }
+        }
+
+        if self.packet_types.contains(&packet_type) {
+            self.push(
+                ctx.tick(),
+                Event::Packet {
+                    packet_type,
+                    data: data.into(),
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// writer sink
+// ----
+
+/// writes events as lines of text; one event per line, prefixed with tick.
+pub struct WriterSink<W: Write> {
+    wtr: W,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(wtr: W) -> Self {
+        Self { wtr }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
+
+impl WriterSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        File::create(path).map(|file| Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> Sink for WriterSink<W> {
+    fn on_tick_events(&mut self, tick_events: &TickEvents) -> Result<()> {
+        let tick = tick_events.tick;
+        for event in tick_events.events.iter() {
+            match event {
+                Event::Entity {
+                    delta_header,
+                    index,
+                    fields,
+                    ..
+                } => {
+                    let kind = match *delta_header {
+                        DeltaHeader::CREATE => "create",
+                        DeltaHeader::DELETE => "delete",
+                        DeltaHeader::LEAVE => "leave",
+                        _ => "update",
+                    };
+                    writeln!(
+                        self.wtr,
+                        "[{tick}] entity {kind} #{index} ({} fields)",
+                        fields.len()
+                    )?;
+                }
+                Event::GameEvent { name, data } => {
+                    write!(self.wtr, "[{tick}] {name}")?;
+                    for (key, value) in data.iter() {
+                        write!(self.wtr, " {key}={value:?}")?;
+                    }
+                    writeln!(self.wtr)?;
+                }
+                Event::Packet { packet_type, data } => {
+                    writeln!(
+                        self.wtr,
+                        "[{tick}] packet {packet_type} ({} bytes)",
+                        data.len()
+                    )?;
+                }
+                Event::Snapshot { entities } => {
+                    writeln!(self.wtr, "[{tick}] snapshot ({} entities)", entities.len())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.wtr.flush().map_err(anyhow::Error::from)
+    }
+}
+
+// channel sink
+// ----
+
+/// sends tick events into a channel; allows to consume events on other threads.
+pub struct ChannelSink {
+    tx: mpsc::Sender<TickEvents>,
+}
+
+impl ChannelSink {
+    pub fn new(tx: mpsc::Sender<TickEvents>) -> Self {
+        Self { tx }
+    }
+}
+
+impl Sink for ChannelSink {
+    fn on_tick_events(&mut self, tick_events: &TickEvents) -> Result<()> {
+        self.tx
+            .send(tick_events.clone())
+            .map_err(|_| anyhow!("receiver disconnected"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::demofile::DemoFile;
+    use crate::parser::Parser;
+    use crate::syntheticdemo::{SyntheticClass, SyntheticDemoWriter, SyntheticFieldType};
+
+    // NOTE: not a real message type.
+    const TOY_PACKET_TYPE: u32 = 1000;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        ticks: Vec<(i32, usize)>,
+        tick_ends: Vec<i32>,
+        flushed: bool,
+    }
+
+    impl Sink for RecordingSink {
+        fn on_tick_events(&mut self, tick_events: &TickEvents) -> Result<()> {
+            self.ticks
+                .push((tick_events.tick, tick_events.events.len()));
+            Ok(())
+        }
+
+        fn on_tick_end(&mut self, tick: i32) -> Result<()> {
+            self.tick_ends.push(tick);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    fn toy_demo() -> Result<DemoFile<Cursor<Vec<u8>>>> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.write_tick(1)?;
+        wtr.packet_message(TOY_PACKET_TYPE, b"toy".to_vec());
+        wtr.update(1, &[("m_iHealth", FieldValue::I64(90))])?;
+        wtr.write_tick(2)?;
+        // NOTE: tick without events.
+        wtr.write_tick(3)?;
+        wtr.delete(1)?;
+        wtr.write_tick(4)?;
+        Ok(wtr.finish_into_demo_file()?)
+    }
+
+    #[test]
+    fn test_writer_sink() -> Result<()> {
+        let visitor = SinkVisitor::new(WriterSink::new(Vec::new()))
+            .with_entities(true)
+            .with_packet_types([TOY_PACKET_TYPE]);
+        let mut parser = Parser::from_stream_with_visitor(toy_demo()?, visitor)?;
+        parser.run_to_end()?;
+
+        let output = parser.into_visitor().finish()?.into_inner();
+        assert_eq!(
+            String::from_utf8(output)?,
+            "[1] entity create #1 (2 fields)\n\
+             [2] packet 1000 (3 bytes)\n\
+             [2] entity update #1 (2 fields)\n\
+             [4] entity delete #1 (2 fields)\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_are_grouped_by_tick() -> Result<()> {
+        let visitor = SinkVisitor::new(RecordingSink::default())
+            .with_entities(true)
+            .with_packet_types([TOY_PACKET_TYPE]);
+        let mut parser = Parser::from_stream_with_visitor(toy_demo()?, visitor)?;
+        parser.run_to_end()?;
+
+        let sink = parser.into_visitor().finish()?;
+        assert_eq!(sink.ticks, vec![(1, 1), (2, 2), (4, 1)]);
+        // NOTE: tick 3 had no events, it still ends.
+        assert!(sink.tick_ends.ends_with(&[1, 2, 3, 4]));
+        assert!(sink.flushed);
+
+        Ok(())
+    }
+}
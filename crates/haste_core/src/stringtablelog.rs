@@ -0,0 +1,132 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+use crate::stringtables::{StringTable, StringTableContainer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringTableChangeKind {
+    Added,
+    Updated,
+}
+
+#[derive(Debug, Clone)]
+pub struct StringTableChange {
+    pub tick: i32,
+    /// id (/ index) of the table within [`StringTableContainer`].
+    pub table_id: usize,
+    /// index of the entry within the table.
+    pub index: i32,
+    pub key: Option<Box<str>>,
+    pub kind: StringTableChangeKind,
+}
+
+/// determines which changes are kept by [`StringTableLog`].
+///
+/// NOTE: first appearances of entries (see [`StringTableLog::first_seen`]) are always kept; they
+/// are bounded by the size of tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    /// keep everything.
+    All,
+    /// keep at most n most recent changes.
+    Changes(usize),
+    /// keep changes that happened within n ticks of the most recent change.
+    Ticks(i32),
+}
+
+/// log of string table entry additions and updates.
+#[derive(Debug, Clone)]
+pub struct StringTableLog {
+    retention: Retention,
+    changes: VecDeque<StringTableChange>,
+    first_seen: HashMap<(usize, i32), i32>,
+}
+
+impl StringTableLog {
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            retention,
+            changes: VecDeque::new(),
+            first_seen: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, tick: i32, table_id: usize, string_table: &StringTable) {
+        for index in string_table.changed_entries() {
+            let kind = match self.first_seen.entry((table_id, *index)) {
+                Entry::Occupied(_) => StringTableChangeKind::Updated,
+                Entry::Vacant(ve) => {
+                    ve.insert(tick);
+                    StringTableChangeKind::Added
+                }
+            };
+            self.changes.push_back(StringTableChange {
+                tick,
+                table_id,
+                index: *index,
+                key: string_table
+                    .get_item(index)
+                    .and_then(|item| item.key())
+                    .map(Into::into),
+                kind,
+            });
+        }
+        self.trim(tick);
+    }
+
+    fn trim(&mut self, tick: i32) {
+        match self.retention {
+            Retention::All => {}
+            Retention::Changes(n) => {
+                while self.changes.len() > n {
+                    self.changes.pop_front();
+                }
+            }
+            Retention::Ticks(n) => {
+                while self
+                    .changes
+                    .front()
+                    .is_some_and(|change| tick - change.tick > n)
+                {
+                    self.changes.pop_front();
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// retained changes, oldest first.
+    #[inline]
+    pub fn changes(&self) -> impl Iterator<Item = &StringTableChange> {
+        self.changes.iter()
+    }
+
+    /// tick at which entry at the given index of the given table appeared for the first time.
+    #[inline]
+    pub fn first_seen(&self, table_id: usize, index: i32) -> Option<i32> {
+        self.first_seen.get(&(table_id, index)).copied()
+    }
+
+    /// tick at which entry with the given key appeared in the given table for the first time (for
+    /// example when did an item get networked into `EconItems` table).
+    pub fn first_seen_by_key(
+        &self,
+        string_tables: &StringTableContainer,
+        table_name: &str,
+        key: &str,
+    ) -> Option<i32> {
+        let table_id = string_tables.find_table_id(table_name)?;
+        let string_table = string_tables.get_table(table_id)?;
+        let (index, _) = string_table.find_item(key)?;
+        self.first_seen(table_id, *index)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.changes.clear();
+        self.first_seen.clear();
+    }
+}
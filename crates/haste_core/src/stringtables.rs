@@ -1,5 +1,6 @@
 use std::cell::UnsafeCell;
 use std::hash::BuildHasherDefault;
+use std::io::{self, Write};
 use std::mem::MaybeUninit;
 use std::rc::Rc;
 
@@ -43,6 +44,97 @@ pub struct StringTableItem {
     pub user_data: Option<Rc<UnsafeCell<Vec<u8>>>>,
 }
 
+impl StringTableItem {
+    /// key of the entry; None if entry has no key or if key is not valid utf-8.
+    #[inline]
+    pub fn key(&self) -> Option<&str> {
+        self.string
+            .as_ref()
+            .and_then(|string| std::str::from_utf8(string).ok())
+    }
+
+    #[inline]
+    pub fn user_data(&self) -> Option<&[u8]> {
+        // SAFETY: user data is only mutated by the parser, which can't happen while the item is
+        // borrowed.
+        self.user_data
+            .as_ref()
+            .map(|user_data| unsafe { (*user_data.get()).as_slice() })
+    }
+}
+
+/// owned copy of string table's state; see [`StringTable::snapshot`]. entries are ordered by
+/// index.
+#[derive(Debug, Clone)]
+pub struct StringTableSnapshot {
+    name: Box<str>,
+    user_data_fixed_size: bool,
+    user_data_size: i32,
+    user_data_size_bits: i32,
+    flags: i32,
+    using_varint_bitcounts: bool,
+    items: Vec<(i32, Option<Vec<u8>>, Option<Vec<u8>>)>,
+}
+
+impl StringTableSnapshot {
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    #[inline]
+    pub fn user_data_fixed_size(&self) -> bool {
+        self.user_data_fixed_size
+    }
+
+    #[inline]
+    pub fn user_data_size(&self) -> i32 {
+        self.user_data_size
+    }
+
+    #[inline]
+    pub fn user_data_size_bits(&self) -> i32 {
+        self.user_data_size_bits
+    }
+
+    #[inline]
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    #[inline]
+    pub fn using_varint_bitcounts(&self) -> bool {
+        self.using_varint_bitcounts
+    }
+
+    /// index, key and (decompressed) user data of each entry, ordered by index.
+    pub fn entries(&self) -> impl Iterator<Item = (i32, Option<&[u8]>, Option<&[u8]>)> {
+        self.items
+            .iter()
+            .map(|(index, string, user_data)| (*index, string.as_deref(), user_data.as_deref()))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// approximate number of bytes occupied by keys and user data.
+    pub fn size_of_data(&self) -> usize {
+        self.items
+            .iter()
+            .map(|(_, string, user_data)| {
+                string.as_ref().map_or(0, Vec::len) + user_data.as_ref().map_or(0, Vec::len)
+            })
+            .sum()
+    }
+}
+
 #[derive(Debug)]
 pub struct StringTable {
     name: Box<str>,
@@ -53,6 +145,7 @@ pub struct StringTable {
     using_varint_bitcounts: bool,
 
     items: HashMap<i32, StringTableItem, BuildHasherDefault<NoHashHasher<i32>>>,
+    changed_entries: Vec<i32>,
 
     history: Vec<StringHistoryEntry>,
     string_buf: Vec<u8>,
@@ -84,6 +177,7 @@ impl StringTable {
             flags,
             using_varint_bitcounts,
             items: HashMap::with_capacity_and_hasher(1024, BuildHasherDefault::default()),
+            changed_entries: Vec::new(),
 
             history: unsafe { make_vec(HISTORY_SIZE) },
             string_buf: unsafe { make_vec(1024) },
@@ -102,6 +196,7 @@ impl StringTable {
         num_entries: i32,
     ) -> Result<(), snap::Error> {
         let mut entry_index: i32 = -1;
+        self.changed_entries.clear();
 
         // TODO: feature flag or something for a static allocation of history,
         // string_buf and user_data_buf in single threaded environment (similar
@@ -220,6 +315,7 @@ impl StringTable {
                 None
             };
 
+            self.changed_entries.push(entry_index);
             self.items
                 .entry(entry_index)
                 .and_modify(|entry| {
@@ -258,7 +354,15 @@ impl StringTable {
             "removing entries is not supported"
         );
 
+        self.changed_entries.clear();
         for (i, incoming) in table.items.iter().enumerate() {
+            let changed = self.items.get(&(i as i32)).map_or(true, |existing| {
+                existing.user_data() != incoming.data.as_deref()
+            });
+            if changed {
+                self.changed_entries.push(i as i32);
+            }
+
             self.items
                 .entry(i as i32)
                 .and_modify(|existing| {
@@ -277,6 +381,55 @@ impl StringTable {
         }
     }
 
+    /// captures complete state of the table (user data is copied; it is stored decompressed).
+    pub fn snapshot(&self) -> StringTableSnapshot {
+        let mut items: Vec<_> = self
+            .items
+            .iter()
+            .map(|(index, item)| {
+                (
+                    *index,
+                    item.string.clone(),
+                    item.user_data().map(<[u8]>::to_vec),
+                )
+            })
+            .collect();
+        // NOTE: stable order makes snapshots comparable (and dumps diffable).
+        items.sort_unstable_by_key(|(index, _, _)| *index);
+        StringTableSnapshot {
+            name: self.name.clone(),
+            user_data_fixed_size: self.user_data_fixed_size,
+            user_data_size: self.user_data_size,
+            user_data_size_bits: self.user_data_size_bits,
+            flags: self.flags,
+            using_varint_bitcounts: self.using_varint_bitcounts,
+            items,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: &StringTableSnapshot) -> Self {
+        let mut string_table = Self::new(
+            &snapshot.name,
+            snapshot.user_data_fixed_size,
+            snapshot.user_data_size,
+            snapshot.user_data_size_bits,
+            snapshot.flags,
+            snapshot.using_varint_bitcounts,
+        );
+        for (index, string, user_data) in snapshot.items.iter() {
+            string_table.items.insert(
+                *index,
+                StringTableItem {
+                    string: string.clone(),
+                    user_data: user_data
+                        .as_ref()
+                        .map(|user_data| Rc::new(UnsafeCell::new(user_data.clone()))),
+                },
+            );
+        }
+        string_table
+    }
+
     // NOTE: might need those for fast seeks
     // // HLTV change history & rollback
     // void EnableRollback();
@@ -296,6 +449,98 @@ impl StringTable {
     pub fn get_item(&self, entry_index: &i32) -> Option<&StringTableItem> {
         self.items.get(entry_index)
     }
+
+    /// linear search; returns the first entry with the given key.
+    pub fn find_item(&self, key: &str) -> Option<(&i32, &StringTableItem)> {
+        self.items
+            .iter()
+            .find(|(_, item)| item.string.as_deref() == Some(key.as_bytes()))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// indices of entries that were added or changed by the most recent update of this table.
+    #[inline]
+    pub fn changed_entries(&self) -> &[i32] {
+        &self.changed_entries
+    }
+}
+
+/// owned copy of state of all string tables; see [`StringTableContainer::snapshot`].
+#[derive(Debug, Clone, Default)]
+pub struct StringTablesSnapshot {
+    tables: Vec<StringTableSnapshot>,
+}
+
+impl StringTablesSnapshot {
+    #[inline]
+    pub fn tables(&self) -> impl Iterator<Item = &StringTableSnapshot> {
+        self.tables.iter()
+    }
+
+    /// same order as in [`StringTableContainer`] (table id is the position).
+    #[inline]
+    pub fn get_table(&self, id: usize) -> Option<&StringTableSnapshot> {
+        self.tables.get(id)
+    }
+
+    pub fn find_table(&self, name: &str) -> Option<&StringTableSnapshot> {
+        self.tables
+            .iter()
+            .find(|table| table.name.as_ref().eq(name))
+    }
+
+    pub fn size_of_data(&self) -> usize {
+        self.tables
+            .iter()
+            .map(StringTableSnapshot::size_of_data)
+            .sum()
+    }
+
+    /// writes all tables as text, one line per table followed by one line per entry: index, key
+    /// (escaped) and user data (hex). output is stable, two dumps of the same state are equal
+    /// byte for byte; useful for debugging and for diffing state of two runs.
+    pub fn dump<W: Write>(&self, mut wtr: W) -> io::Result<()> {
+        for (table_id, table) in self.tables.iter().enumerate() {
+            writeln!(
+                wtr,
+                "table {table_id} {:?} entries={} flags={} user_data_fixed_size={} user_data_size={} user_data_size_bits={} using_varint_bitcounts={}",
+                table.name,
+                table.items.len(),
+                table.flags,
+                table.user_data_fixed_size,
+                table.user_data_size,
+                table.user_data_size_bits,
+                table.using_varint_bitcounts,
+            )?;
+            for (index, string, user_data) in table.entries() {
+                write!(wtr, "  #{index}")?;
+                match string {
+                    Some(string) => write!(wtr, " key={:?}", String::from_utf8_lossy(string))?,
+                    None => write!(wtr, " key=none")?,
+                }
+                match user_data {
+                    Some(user_data) => {
+                        write!(wtr, " user_data=")?;
+                        for byte in user_data {
+                            write!(wtr, "{byte:02x}")?;
+                        }
+                    }
+                    None => write!(wtr, " user_data=none")?,
+                }
+                writeln!(wtr)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 // NOTE: this is modelled after CNetworkStringTableContainer
@@ -335,6 +580,11 @@ impl StringTableContainer {
     }
 
     pub fn do_full_update(&mut self, cmd: CDemoStringTables) {
+        // NOTE: changes are reset so that it is possible to tell which tables were touched by
+        // this update.
+        for table in self.tables.iter_mut() {
+            table.changed_entries.clear();
+        }
         for incoming in &cmd.tables {
             if let Some(existing) = self.find_table_mut(incoming.table_name()) {
                 existing.do_full_update(incoming);
@@ -350,6 +600,18 @@ impl StringTableContainer {
             .find(|table| table.name.as_ref().eq(name))
     }
 
+    pub fn find_table_id(&self, name: &str) -> Option<usize> {
+        self.tables
+            .iter()
+            .position(|table| table.name.as_ref().eq(name))
+    }
+
+    /// same as [`Self::find_table`].
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&StringTable> {
+        self.find_table(name)
+    }
+
     pub fn find_table_mut(&mut self, name: &str) -> Option<&mut StringTable> {
         self.tables
             .iter_mut()
@@ -394,6 +656,19 @@ impl StringTableContainer {
         self.tables.is_empty()
     }
 
+    pub fn snapshot(&self) -> StringTablesSnapshot {
+        StringTablesSnapshot {
+            tables: self.tables.iter().map(StringTable::snapshot).collect(),
+        }
+    }
+
+    /// replaces all tables with tables from the snapshot. table ids are preserved.
+    pub fn restore(&mut self, snapshot: &StringTablesSnapshot) {
+        self.tables.clear();
+        self.tables
+            .extend(snapshot.tables.iter().map(StringTable::from_snapshot));
+    }
+
     // TODO: add support for change list (m_pChangeList) and rollbacks
     // NOTE: might need those for fast seeks
     // void EnableRollback( bool bState );
@@ -405,3 +680,51 @@ impl StringTableContainer {
         self.tables.iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_dump() -> io::Result<()> {
+        let mut container = StringTableContainer::default();
+        let table = container.create_string_table_mut("test", false, 0, 0, 0, true);
+        for (index, key, user_data) in [
+            (5, Some("five"), Some(vec![0xab, 0x01])),
+            (1, None, None),
+            (3, Some("three"), None),
+        ] {
+            table.items.insert(
+                index,
+                StringTableItem {
+                    string: key.map(|key| key.as_bytes().to_vec()),
+                    user_data: user_data.map(|user_data| Rc::new(UnsafeCell::new(user_data))),
+                },
+            );
+        }
+
+        let snapshot = container.snapshot();
+        let Some(table) = snapshot.find_table("test") else {
+            return Err(io::Error::other("table is missing"));
+        };
+        assert_eq!(
+            table
+                .entries()
+                .map(|(index, _, _)| index)
+                .collect::<Vec<_>>(),
+            [1, 3, 5]
+        );
+
+        let mut dump = Vec::new();
+        snapshot.dump(&mut dump)?;
+        assert_eq!(
+            String::from_utf8_lossy(&dump),
+            "table 0 \"test\" entries=3 flags=0 user_data_fixed_size=false user_data_size=0 user_data_size_bits=0 using_varint_bitcounts=true\n  \
+             #1 key=none user_data=none\n  \
+             #3 key=\"three\" user_data=none\n  \
+             #5 key=\"five\" user_data=ab01\n"
+        );
+
+        Ok(())
+    }
+}
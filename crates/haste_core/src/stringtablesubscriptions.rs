@@ -0,0 +1,125 @@
+//! per-table user data decoders; see [`crate::parser::ParserOptions::string_table_subscriptions`].
+//! useful for custom tables (for example ones of custom games) that haste knows nothing about:
+//!
+//! ```ignore
+//! let mut subscriptions = StringTableSubscriptions::default();
+//! subscriptions.subscribe_decoded(
+//!     "CustomTable",
+//!     |user_data| CMyCustomEntry::decode(user_data),
+//!     |update, entry| {
+//!         println!("[{}] #{} {entry:?}", update.tick, update.entry_index);
+//!         Ok(())
+//!     },
+//! );
+//! let options = ParserOptions {
+//!     string_table_subscriptions: subscriptions,
+//!     ..Default::default()
+//! };
+//! ```
+
+use std::cell::RefCell;
+use std::hash::BuildHasherDefault;
+use std::rc::Rc;
+
+use anyhow::Result;
+use hashbrown::HashMap;
+use nohash::NoHashHasher;
+
+use crate::fxhash;
+use crate::stringtables::StringTable;
+
+/// entry of the string table that was added or changed.
+#[derive(Debug, Clone, Copy)]
+pub struct StringTableEntryUpdate<'a> {
+    pub tick: i32,
+    pub table_name: &'a str,
+    /// index of the entry within the table.
+    pub entry_index: i32,
+    pub key: Option<&'a [u8]>,
+    /// NOTE: user data that came snappy-compressed is handed over decompressed.
+    pub user_data: Option<&'a [u8]>,
+}
+
+type Subscriber = Rc<RefCell<dyn FnMut(&StringTableEntryUpdate) -> Result<()>>>;
+
+/// subscribers are called in order of registration, on every create and update of entries of the
+/// table (including full updates that come with `CDemoStringTables`), right before
+/// [`crate::parser::Visitor::on_string_table_update`]. errors abort parsing.
+#[derive(Clone, Default)]
+pub struct StringTableSubscriptions {
+    // NOTE: keyed by hash of table name.
+    by_table_name: HashMap<u64, Vec<Subscriber>, BuildHasherDefault<NoHashHasher<u64>>>,
+}
+
+impl StringTableSubscriptions {
+    pub fn subscribe<F>(&mut self, table_name: &str, on_update: F)
+    where
+        F: FnMut(&StringTableEntryUpdate) -> Result<()> + 'static,
+    {
+        self.by_table_name
+            .entry(fxhash::hash_bytes(table_name.as_bytes()))
+            .or_default()
+            .push(Rc::new(RefCell::new(on_update)));
+    }
+
+    /// typed variant of [`Self::subscribe`]; `decode` is called with user data of each updated
+    /// entry that has it, entries without user data are skipped.
+    pub fn subscribe_decoded<T, E, D, F>(&mut self, table_name: &str, decode: D, mut on_value: F)
+    where
+        E: Into<anyhow::Error>,
+        D: Fn(&[u8]) -> Result<T, E> + 'static,
+        F: FnMut(&StringTableEntryUpdate, T) -> Result<()> + 'static,
+    {
+        self.subscribe(table_name, move |update| {
+            let Some(user_data) = update.user_data else {
+                return Ok(());
+            };
+            let value = decode(user_data).map_err(Into::into)?;
+            on_value(update, value)
+        });
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.by_table_name.is_empty()
+    }
+
+    /// calls subscribers of the table with its changed entries (see
+    /// [`StringTable::changed_entries`]).
+    pub(crate) fn notify(&self, tick: i32, string_table: &StringTable) -> Result<()> {
+        if self.by_table_name.is_empty() {
+            return Ok(());
+        }
+        let Some(subscribers) = self
+            .by_table_name
+            .get(&fxhash::hash_bytes(string_table.name().as_bytes()))
+        else {
+            return Ok(());
+        };
+
+        for entry_index in string_table.changed_entries() {
+            let Some(item) = string_table.get_item(entry_index) else {
+                continue;
+            };
+            let update = StringTableEntryUpdate {
+                tick,
+                table_name: string_table.name(),
+                entry_index: *entry_index,
+                key: item.string.as_deref(),
+                user_data: item.user_data(),
+            };
+            for subscriber in subscribers.iter() {
+                (subscriber.borrow_mut())(&update)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for StringTableSubscriptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StringTableSubscriptions")
+            .field("tables", &self.by_table_name.len())
+            .finish()
+    }
+}
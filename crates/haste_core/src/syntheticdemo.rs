@@ -0,0 +1,824 @@
+//! minimal, valid demos built in memory; for tests that need a demo, but don't want to depend on
+//! real (hundreds of megabytes large) replays. demos are written with [`DemoWriter`] and consist
+//! of:
+//! - file header;
+//! - signon packet with `instancebaseline` string table (baselines hold default values of all
+//! fields);
+//! - send tables with toy classes (serializers of primitive fields, fixed arrays and pointers to
+//! nested classes, see [`SyntheticClass`]);
+//! - class info and sync tick;
+//! - a packet with packet entities per tick (see [`SyntheticDemoWriter::write_tick`]); or a full
+//! packet (see [`SyntheticDemoWriter::write_full_packet`]).
+//!
+//! ```ignore
+//! let classes = vec![SyntheticClass::new("CToyEntity")
+//!     .field("m_iHealth", SyntheticFieldType::Int32)
+//!     .field("m_bAlive", SyntheticFieldType::Bool)];
+//! let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+//! wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+//! wtr.write_tick(1)?;
+//! wtr.update(1, &[("m_iHealth", FieldValue::I64(90))])?;
+//! wtr.write_tick(2)?;
+//! let demo_file = wtr.finish_into_demo_file()?;
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{self, Cursor};
+
+use valveprotos::common::{
+    c_demo_class_info, CDemoClassInfo, CDemoFileHeader, CDemoFileInfo, CDemoFullPacket,
+    CDemoPacket, CDemoSendTables, CDemoSyncTick, CsvcMsgCreateStringTable,
+    CsvcMsgFlattenedSerializer, CsvcMsgPacketEntities, CsvcMsgUpdateStringTable, EDemoCommands,
+    ProtoFlattenedSerializerFieldT, ProtoFlattenedSerializerT, SvcMessages,
+};
+
+use crate::bitwriter::BitWriter;
+use crate::demofile::{DemoFile, DemoHeaderError};
+use crate::demowriter::{DemoWriter, WriteCmdError};
+use crate::entities::DeltaHeader;
+use crate::fieldpath::{self, FieldPath};
+use crate::fieldvalue::FieldValue;
+use crate::game::Game;
+use crate::instancebaseline::INSTANCE_BASELINE_TABLE_NAME;
+use crate::protobackend::ProtoMessage;
+use crate::varint;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SyntheticDemoError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    WriteCmdError(#[from] WriteCmdError),
+    #[error(transparent)]
+    DemoHeaderError(#[from] DemoHeaderError),
+    #[error("unknown class {0}")]
+    UnknownClass(String),
+    #[error("class {class} has no field {field}")]
+    UnknownField { class: String, field: String },
+    #[error("value {value:?} does not match type {field_type:?} of field {field}")]
+    ValueTypeMismatch {
+        field: String,
+        field_type: SyntheticFieldType,
+        value: FieldValue,
+    },
+    #[error("entity #{0} does not exist")]
+    UnknownEntity(i32),
+    #[error("class {0} has more than 255 fields")]
+    TooManyFields(String),
+    #[error("class {0} is nested too deep")]
+    TooDeep(String),
+    #[error("tick {tick} is not after the previous tick {prev_tick}")]
+    NonIncreasingTick { tick: i32, prev_tick: i32 },
+}
+
+/// types that are decoded the same way in all games; value of each must be the [`FieldValue`]
+/// variant that the decoder produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntheticFieldType {
+    /// [`FieldValue::I64`].
+    Int32,
+    /// [`FieldValue::U64`] (for example entity handles).
+    UInt64,
+    /// [`FieldValue::Bool`].
+    Bool,
+    /// [`FieldValue::F32`], not quantized.
+    Float32,
+    /// [`FieldValue::String`].
+    String,
+    /// fixed array (`int32[length]`) of [`FieldValue::I64`] elements; elements are addressed by
+    /// dotted names (for example `m_iValues.2`).
+    Int32Array(u8),
+    /// fixed array (`uint64[length]`) of [`FieldValue::U64`] elements.
+    UInt64Array(u8),
+    /// pointer to the nested class (`CDOTAGamerules*`); pointer itself is [`FieldValue::Bool`],
+    /// fields of the nested class are addressed by dotted names (for example
+    /// `m_pGameRules.m_flGameStartTime`).
+    Pointer(SyntheticClass),
+}
+
+/// components of the field path; order of keys is the order in which field paths are encoded.
+type FieldKey = Vec<usize>;
+
+impl SyntheticFieldType {
+    fn var_type(&self) -> String {
+        match self {
+            Self::Int32 => "int32".to_string(),
+            Self::UInt64 => "uint64".to_string(),
+            Self::Bool => "bool".to_string(),
+            Self::Float32 => "float32".to_string(),
+            Self::String => "CUtlString".to_string(),
+            Self::Int32Array(length) => format!("int32[{length}]"),
+            Self::UInt64Array(length) => format!("uint64[{length}]"),
+            Self::Pointer(class) => format!("{}*", class.name),
+        }
+    }
+
+    /// default value of the field (of each element for arrays). pointers are set.
+    fn default_value(&self) -> FieldValue {
+        match self {
+            Self::Int32 | Self::Int32Array(_) => FieldValue::I64(0),
+            Self::UInt64 | Self::UInt64Array(_) => FieldValue::U64(0),
+            Self::Bool => FieldValue::Bool(false),
+            Self::Pointer(_) => FieldValue::Bool(true),
+            Self::Float32 => FieldValue::F32(0.0),
+            Self::String => FieldValue::String("".into()),
+        }
+    }
+}
+
+/// serializer and class of the same (network) name. classes that are only pointed to (see
+/// [`SyntheticFieldType::Pointer`]) get a serializer, but no class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticClass {
+    name: String,
+    fields: Vec<(String, SyntheticFieldType)>,
+}
+
+impl SyntheticClass {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn field(mut self, name: impl Into<String>, field_type: SyntheticFieldType) -> Self {
+        self.fields.push((name.into(), field_type));
+        self
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self) -> Result<(), SyntheticDemoError> {
+        if self.fields.len() > u8::MAX as usize {
+            return Err(SyntheticDemoError::TooManyFields(self.name.clone()));
+        }
+        if self
+            .baseline_fields()
+            .keys()
+            .any(|key| key.len() > fieldpath::MAX_COMPONENTS)
+        {
+            return Err(SyntheticDemoError::TooDeep(self.name.clone()));
+        }
+        for (_, field_type) in self.fields.iter() {
+            if let SyntheticFieldType::Pointer(class) = field_type {
+                class.validate()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// default values of all fields (and elements, and fields of nested classes).
+    fn baseline_fields(&self) -> BTreeMap<FieldKey, FieldValue> {
+        let mut fields = BTreeMap::new();
+        for (field_index, (_, field_type)) in self.fields.iter().enumerate() {
+            match field_type {
+                SyntheticFieldType::Int32Array(length)
+                | SyntheticFieldType::UInt64Array(length) => {
+                    for element_index in 0..*length as usize {
+                        fields.insert(vec![field_index, element_index], field_type.default_value());
+                    }
+                }
+                SyntheticFieldType::Pointer(class) => {
+                    fields.insert(vec![field_index], field_type.default_value());
+                    for (mut key, value) in class.baseline_fields() {
+                        key.insert(0, field_index);
+                        fields.insert(key, value);
+                    }
+                }
+                _ => {
+                    fields.insert(vec![field_index], field_type.default_value());
+                }
+            }
+        }
+        fields
+    }
+
+    /// key and type of the field (or of the element) with the given dotted name; types of
+    /// elements are types of their arrays.
+    fn resolve_name(&self, name: &str) -> Option<(FieldKey, &SyntheticFieldType)> {
+        let (field_name, rest) = match name.split_once('.') {
+            Some((field_name, rest)) => (field_name, Some(rest)),
+            None => (name, None),
+        };
+        let (field_index, (_, field_type)) = self
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(_, (other_name, _))| other_name == field_name)?;
+        match (field_type, rest) {
+            (
+                SyntheticFieldType::Int32Array(length) | SyntheticFieldType::UInt64Array(length),
+                Some(rest),
+            ) => {
+                let element_index = rest.parse::<usize>().ok()?;
+                (element_index < *length as usize)
+                    .then(|| (vec![field_index, element_index], field_type))
+            }
+            (SyntheticFieldType::Int32Array(_) | SyntheticFieldType::UInt64Array(_), None) => None,
+            (SyntheticFieldType::Pointer(class), Some(rest)) => {
+                let (mut key, field_type) = class.resolve_name(rest)?;
+                key.insert(0, field_index);
+                Some((key, field_type))
+            }
+            (_, Some(_)) => None,
+            (_, None) => Some((vec![field_index], field_type)),
+        }
+    }
+
+    /// field values must be sorted by field path and encoded in that order.
+    fn resolve_fields(
+        &self,
+        fields: &[(&str, FieldValue)],
+    ) -> Result<BTreeMap<FieldKey, FieldValue>, SyntheticDemoError> {
+        let mut resolved = BTreeMap::new();
+        for (name, value) in fields {
+            let (key, field_type) =
+                self.resolve_name(name)
+                    .ok_or_else(|| SyntheticDemoError::UnknownField {
+                        class: self.name.clone(),
+                        field: name.to_string(),
+                    })?;
+            if std::mem::discriminant(value) != std::mem::discriminant(&field_type.default_value())
+            {
+                return Err(SyntheticDemoError::ValueTypeMismatch {
+                    field: name.to_string(),
+                    field_type: field_type.clone(),
+                    value: value.clone(),
+                });
+            }
+            resolved.insert(key, value.clone());
+        }
+        Ok(resolved)
+    }
+}
+
+/// field paths followed by values; see `Entity::parse`.
+fn write_fields(bw: &mut BitWriter, fields: &BTreeMap<FieldKey, FieldValue>) {
+    let fps: Vec<FieldPath> = fields
+        .keys()
+        // NOTE: start_writing makes sure that there are no more than 255 fields and that keys
+        // are not longer than field paths can be; array lengths are u8.
+        .filter_map(|key| {
+            let components: Vec<u8> = key.iter().map(|component| *component as u8).collect();
+            FieldPath::from_components(&components)
+        })
+        .collect();
+    fieldpath::write_field_paths(bw, &fps);
+
+    for value in fields.values() {
+        match value {
+            FieldValue::I64(v) => bw.write_varint64(*v),
+            FieldValue::U64(v) => bw.write_uvarint64(*v),
+            FieldValue::Bool(v) => bw.write_bool(*v),
+            FieldValue::F32(v) => bw.write_bitfloat(*v),
+            FieldValue::String(v) => bw.write_string(v.as_bytes()),
+            // NOTE: other variants are rejected by resolve_fields.
+            _ => {}
+        }
+    }
+}
+
+// NOTE: see DeltaHeader::from_bit_reader.
+fn write_delta_header(bw: &mut BitWriter, delta_header: DeltaHeader) {
+    let bits = match delta_header {
+        DeltaHeader::CREATE => 0b10,
+        DeltaHeader::LEAVE => 0b01,
+        DeltaHeader::DELETE => 0b11,
+        _ => 0b00,
+    };
+    bw.write_ubit64(bits, 2);
+}
+
+/// string table entries are written one after another (without key history references);
+/// see `StringTable::parse_update`.
+fn write_string_table_entries(bw: &mut BitWriter, entries: &[(String, Vec<u8>)]) {
+    for (string, user_data) in entries {
+        // NOTE: increment entry index
+        bw.write_bool(true);
+        // NOTE: has string, does not reference history
+        bw.write_bool(true);
+        bw.write_bool(false);
+        bw.write_string(string.as_bytes());
+        // NOTE: has user data; size is ubitvar because of using_varint_bitcounts
+        bw.write_bool(true);
+        bw.write_ubitvar(user_data.len() as u32);
+        bw.write_bytes(user_data);
+    }
+}
+
+/// packet messages are prefixed with their type and size; see [`crate::packetmessages`].
+fn write_packet_message<M: ProtoMessage>(bw: &mut BitWriter, packet_type: u32, msg: &M) {
+    write_raw_packet_message(bw, packet_type, &msg.encode_message_to_vec());
+}
+
+fn write_raw_packet_message(bw: &mut BitWriter, packet_type: u32, data: &[u8]) {
+    bw.write_ubitvar(packet_type);
+    bw.write_uvarint32(data.len() as u32);
+    bw.write_bytes(data);
+}
+
+/// index of the symbol, symbol is added if it's not there yet.
+fn symbol(msg: &mut CsvcMsgFlattenedSerializer, name: &str) -> i32 {
+    let i = msg
+        .symbols
+        .iter()
+        .position(|symbol| symbol == name)
+        .unwrap_or_else(|| {
+            msg.symbols.push(name.to_string());
+            msg.symbols.len() - 1
+        });
+    i as i32
+}
+
+/// serializers of nested classes are added before the serializer of the class (parser resolves
+/// them by name among serializers that came before); serializers that are there already are
+/// skipped.
+fn add_serializer(msg: &mut CsvcMsgFlattenedSerializer, class: &SyntheticClass) {
+    for (_, field_type) in class.fields.iter() {
+        if let SyntheticFieldType::Pointer(nested_class) = field_type {
+            add_serializer(msg, nested_class);
+        }
+    }
+
+    let serializer_name_sym = symbol(msg, &class.name);
+    if msg
+        .serializers
+        .iter()
+        .any(|serializer| serializer.serializer_name_sym == Some(serializer_name_sym))
+    {
+        return;
+    }
+
+    let mut fields_index = Vec::with_capacity(class.fields.len());
+    for (name, field_type) in class.fields.iter() {
+        let field_serializer_name_sym = match field_type {
+            SyntheticFieldType::Pointer(nested_class) => Some(symbol(msg, &nested_class.name)),
+            _ => None,
+        };
+        let field = ProtoFlattenedSerializerFieldT {
+            var_type_sym: Some(symbol(msg, &field_type.var_type())),
+            var_name_sym: Some(symbol(msg, name)),
+            field_serializer_name_sym,
+            ..Default::default()
+        };
+        fields_index.push(msg.fields.len() as i32);
+        msg.fields.push(field);
+    }
+    msg.serializers.push(ProtoFlattenedSerializerT {
+        serializer_name_sym: Some(serializer_name_sym),
+        serializer_version: Some(0),
+        fields_index,
+    });
+}
+
+enum PendingUpdate {
+    Create {
+        class_id: usize,
+        fields: BTreeMap<FieldKey, FieldValue>,
+    },
+    Update {
+        fields: BTreeMap<FieldKey, FieldValue>,
+    },
+    Leave,
+    Delete,
+}
+
+pub struct SyntheticDemoWriter {
+    wtr: DemoWriter<Cursor<Vec<u8>>>,
+    classes: Vec<SyntheticClass>,
+    class_bits: usize,
+    serial_bits: usize,
+    // NOTE: class ids of entities that exist (entities that left pvs too).
+    entities: BTreeMap<i32, usize>,
+    pending: BTreeMap<i32, PendingUpdate>,
+    pending_messages: Vec<(u32, Vec<u8>)>,
+    tick: i32,
+}
+
+impl SyntheticDemoWriter {
+    /// writes everything that comes before the first tick. class ids are positions of classes.
+    pub fn start_writing(classes: Vec<SyntheticClass>) -> Result<Self, SyntheticDemoError> {
+        for class in classes.iter() {
+            class.validate()?;
+        }
+
+        let mut wtr = DemoWriter::start_writing(Cursor::new(Vec::new()))?;
+        // NOTE: init cmds are sent at tick -1 (u32::MAX on the wire).
+        let tick = -1;
+
+        wtr.write_cmd_msg(
+            EDemoCommands::DemFileHeader,
+            tick,
+            &CDemoFileHeader::default(),
+            false,
+        )?;
+
+        // instance baselines
+        let baselines: Vec<(String, Vec<u8>)> = classes
+            .iter()
+            .enumerate()
+            .map(|(class_id, class)| {
+                let mut bw = BitWriter::new();
+                write_fields(&mut bw, &class.baseline_fields());
+                (class_id.to_string(), bw.into_bytes())
+            })
+            .collect();
+        let mut bw = BitWriter::new();
+        write_string_table_entries(&mut bw, &baselines);
+        let create_string_table = CsvcMsgCreateStringTable {
+            name: Some(INSTANCE_BASELINE_TABLE_NAME.to_string()),
+            num_entries: Some(baselines.len() as i32),
+            string_data: Some(bw.into_bytes()),
+            using_varint_bitcounts: Some(true),
+            ..Default::default()
+        };
+        let mut bw = BitWriter::new();
+        write_packet_message(
+            &mut bw,
+            SvcMessages::SvcCreateStringTable as u32,
+            &create_string_table,
+        );
+        wtr.write_cmd_msg(
+            EDemoCommands::DemSignonPacket,
+            tick,
+            &CDemoPacket {
+                data: Some(bw.into_bytes()),
+            },
+            false,
+        )?;
+
+        // send tables
+        let mut msg = CsvcMsgFlattenedSerializer::default();
+        for class in classes.iter() {
+            add_serializer(&mut msg, class);
+        }
+        // NOTE: flattened serializer message is prefixed with its size; see
+        // FlattenedSerializerContainer::parse_with_options.
+        let data = msg.encode_message_to_vec();
+        let mut send_tables = Vec::with_capacity(data.len() + varint::MAX_VARINT64_BYTES);
+        varint::write_uvarint64(&mut send_tables, data.len() as u64)?;
+        send_tables.extend_from_slice(&data);
+        wtr.write_cmd_msg(
+            EDemoCommands::DemSendTables,
+            tick,
+            &CDemoSendTables {
+                data: Some(send_tables),
+            },
+            false,
+        )?;
+
+        // class info
+        let class_info = CDemoClassInfo {
+            classes: classes
+                .iter()
+                .enumerate()
+                .map(|(class_id, class)| c_demo_class_info::ClassT {
+                    class_id: Some(class_id as i32),
+                    network_name: Some(class.name.clone()),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        wtr.write_cmd_msg(EDemoCommands::DemClassInfo, tick, &class_info, false)?;
+
+        wtr.write_cmd_msg(EDemoCommands::DemSyncTick, tick, &CDemoSyncTick {}, false)?;
+
+        Ok(Self {
+            wtr,
+            // NOTE: see EntityClasses::parse
+            class_bits: (classes.len() as f32).log2().ceil() as usize,
+            serial_bits: Game::Unknown.engine_constants().num_serial_num_bits() as usize,
+            classes,
+            entities: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            pending_messages: Vec::new(),
+            tick,
+        })
+    }
+
+    fn class_of(&self, index: i32) -> Result<&SyntheticClass, SyntheticDemoError> {
+        self.entities
+            .get(&index)
+            .map(|class_id| &self.classes[*class_id])
+            .ok_or(SyntheticDemoError::UnknownEntity(index))
+    }
+
+    // entity updates
+    // ----
+    //
+    // NOTE: updates are queued until write_tick; each entity can have at most one update per
+    // tick (last one wins).
+
+    /// fields that are not given keep their baseline (default) values.
+    pub fn create(
+        &mut self,
+        index: i32,
+        class_name: &str,
+        fields: &[(&str, FieldValue)],
+    ) -> Result<(), SyntheticDemoError> {
+        let class_id = self
+            .classes
+            .iter()
+            .position(|class| class.name == class_name)
+            .ok_or_else(|| SyntheticDemoError::UnknownClass(class_name.to_string()))?;
+        let fields = self.classes[class_id].resolve_fields(fields)?;
+        self.entities.insert(index, class_id);
+        self.pending
+            .insert(index, PendingUpdate::Create { class_id, fields });
+        Ok(())
+    }
+
+    pub fn update(
+        &mut self,
+        index: i32,
+        fields: &[(&str, FieldValue)],
+    ) -> Result<(), SyntheticDemoError> {
+        let fields = self.class_of(index)?.resolve_fields(fields)?;
+        self.pending.insert(index, PendingUpdate::Update { fields });
+        Ok(())
+    }
+
+    /// entity leaves pvs; it still exists and can be updated afterwards.
+    pub fn leave(&mut self, index: i32) -> Result<(), SyntheticDemoError> {
+        self.class_of(index)?;
+        self.pending.insert(index, PendingUpdate::Leave);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, index: i32) -> Result<(), SyntheticDemoError> {
+        self.class_of(index)?;
+        self.entities.remove(&index);
+        self.pending.insert(index, PendingUpdate::Delete);
+        Ok(())
+    }
+
+    /// queues a packet message that is not an entity update (for example a user message); data is
+    /// written as is.
+    pub fn packet_message(&mut self, packet_type: u32, data: Vec<u8>) {
+        self.pending_messages.push((packet_type, data));
+    }
+
+    /// queues a baseline change of the class (fields that are not given get their default values);
+    /// it comes into effect at the next tick, before entity updates of that tick.
+    pub fn update_baseline(
+        &mut self,
+        class_name: &str,
+        fields: &[(&str, FieldValue)],
+    ) -> Result<(), SyntheticDemoError> {
+        let (class_id, class) = self
+            .classes
+            .iter()
+            .enumerate()
+            .find(|(_, class)| class.name == class_name)
+            .ok_or_else(|| SyntheticDemoError::UnknownClass(class_name.to_string()))?;
+        let mut baseline = class.baseline_fields();
+        baseline.extend(class.resolve_fields(fields)?);
+
+        let mut bw = BitWriter::new();
+        write_fields(&mut bw, &baseline);
+        let user_data = bw.into_bytes();
+
+        let mut bw = BitWriter::new();
+        // NOTE: entry index; see StringTable::parse_update.
+        if class_id == 0 {
+            bw.write_bool(true);
+        } else {
+            bw.write_bool(false);
+            bw.write_uvarint32(class_id as u32 - 1);
+        }
+        bw.write_bool(false);
+        bw.write_bool(true);
+        bw.write_ubitvar(user_data.len() as u32);
+        bw.write_bytes(&user_data);
+        let update_string_table = CsvcMsgUpdateStringTable {
+            // NOTE: instancebaseline is the only table.
+            table_id: Some(0),
+            num_changed_entries: Some(1),
+            string_data: Some(bw.into_bytes()),
+        };
+        self.packet_message(
+            SvcMessages::SvcUpdateStringTable as u32,
+            update_string_table.encode_message_to_vec(),
+        );
+        Ok(())
+    }
+
+    /// writes queued entity updates (preceded by queued packet messages) as a single packet at the
+    /// given tick.
+    pub fn write_tick(&mut self, tick: i32) -> Result<(), SyntheticDemoError> {
+        let packet = self.take_packet(tick)?;
+        self.wtr.write_cmd_msg(
+            EDemoCommands::DemPacket,
+            tick,
+            &CDemoPacket { data: Some(packet) },
+            false,
+        )?;
+        self.tick = tick;
+        Ok(())
+    }
+
+    /// same as [`Self::write_tick`], but the packet is written as a full packet (without string
+    /// tables). parser ignores full packets unless it seeks (see
+    /// [`crate::parser::Parser::run_to_tick`]), thus queued updates must describe entire state:
+    /// queue creates of all entities.
+    pub fn write_full_packet(&mut self, tick: i32) -> Result<(), SyntheticDemoError> {
+        let packet = self.take_packet(tick)?;
+        self.wtr.write_cmd_msg(
+            EDemoCommands::DemFullPacket,
+            tick,
+            &CDemoFullPacket {
+                string_table: None,
+                packet: Some(CDemoPacket { data: Some(packet) }),
+            },
+            false,
+        )?;
+        self.tick = tick;
+        Ok(())
+    }
+
+    fn take_packet(&mut self, tick: i32) -> Result<Vec<u8>, SyntheticDemoError> {
+        if tick <= self.tick {
+            return Err(SyntheticDemoError::NonIncreasingTick {
+                tick,
+                prev_tick: self.tick,
+            });
+        }
+
+        let mut bw = BitWriter::new();
+        let mut prev_index = -1;
+        for (index, pending_update) in self.pending.iter() {
+            // NOTE: see ReadPacketEntities in parser; indices are deltas.
+            bw.write_ubitvar((index - prev_index - 1) as u32);
+            prev_index = *index;
+
+            match pending_update {
+                PendingUpdate::Create { class_id, fields } => {
+                    write_delta_header(&mut bw, DeltaHeader::CREATE);
+                    bw.write_ubit64(*class_id as u64, self.class_bits);
+                    // NOTE: serial number and unknown uvarint; see EntityContainer::handle_create
+                    bw.write_ubit64(0, self.serial_bits);
+                    bw.write_uvarint32(0);
+                    write_fields(&mut bw, fields);
+                }
+                PendingUpdate::Update { fields } => {
+                    write_delta_header(&mut bw, DeltaHeader::UPDATE);
+                    write_fields(&mut bw, fields);
+                }
+                PendingUpdate::Leave => write_delta_header(&mut bw, DeltaHeader::LEAVE),
+                PendingUpdate::Delete => write_delta_header(&mut bw, DeltaHeader::DELETE),
+            }
+        }
+
+        let packet_entities = CsvcMsgPacketEntities {
+            max_entries: Some(self.entities.len() as i32),
+            updated_entries: Some(self.pending.len() as i32),
+            entity_data: Some(bw.into_bytes()),
+            ..Default::default()
+        };
+        let mut bw = BitWriter::new();
+        for (packet_type, data) in self.pending_messages.drain(..) {
+            write_raw_packet_message(&mut bw, packet_type, &data);
+        }
+        write_packet_message(
+            &mut bw,
+            SvcMessages::SvcPacketEntities as u32,
+            &packet_entities,
+        );
+
+        self.pending.clear();
+        Ok(bw.into_bytes())
+    }
+
+    /// writes stop and file info cmds; updates (and packet messages) that were queued after the
+    /// last [`Self::write_tick`] are dropped.
+    pub fn finish(self) -> Result<Vec<u8>, SyntheticDemoError> {
+        let file_info = CDemoFileInfo {
+            playback_ticks: Some(self.tick.max(0)),
+            ..Default::default()
+        };
+        Ok(self.wtr.finish(&file_info)?.into_inner())
+    }
+
+    pub fn finish_into_demo_file(self) -> Result<DemoFile<Cursor<Vec<u8>>>, SyntheticDemoError> {
+        Ok(DemoFile::start_reading(Cursor::new(self.finish()?))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entities::{fkey_from_dotted_path, fkey_from_path};
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_parse_synthetic_demo() -> anyhow::Result<()> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_bAlive", SyntheticFieldType::Bool)
+            .field("m_flSpeed", SyntheticFieldType::Float32)
+            .field("m_iszName", SyntheticFieldType::String)];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+
+        wtr.create(1, "CToyEntity", &[("m_iHealth", FieldValue::I64(100))])?;
+        wtr.create(
+            3,
+            "CToyEntity",
+            &[("m_iszName", FieldValue::String("toy".into()))],
+        )?;
+        wtr.write_tick(1)?;
+        wtr.update(
+            1,
+            &[
+                ("m_iHealth", FieldValue::I64(-20)),
+                ("m_bAlive", FieldValue::Bool(true)),
+                ("m_flSpeed", FieldValue::F32(1.5)),
+            ],
+        )?;
+        wtr.delete(3)?;
+        wtr.write_tick(2)?;
+
+        let mut parser = Parser::from_stream(wtr.finish_into_demo_file()?)?;
+        parser.run_to_end()?;
+
+        let entities = parser
+            .context()
+            .entities()
+            .ok_or_else(|| anyhow::anyhow!("no entities"))?;
+        assert!(entities.get(&3).is_none());
+        let entity = entities
+            .get(&1)
+            .ok_or_else(|| anyhow::anyhow!("no entity #1"))?;
+        assert!(matches!(
+            entity.get(&fkey_from_path(&["m_iHealth"])),
+            Some(FieldValue::I64(-20))
+        ));
+        assert!(matches!(
+            entity.get(&fkey_from_path(&["m_bAlive"])),
+            Some(FieldValue::Bool(true))
+        ));
+        assert!(matches!(
+            entity.get(&fkey_from_path(&["m_flSpeed"])),
+            Some(FieldValue::F32(v)) if *v == 1.5
+        ));
+        assert!(matches!(
+            entity.get(&fkey_from_path(&["m_iszName"])),
+            Some(FieldValue::String(v)) if v.is_empty()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_array_element_keys() -> anyhow::Result<()> {
+        let classes = vec![SyntheticClass::new("CToyEntity")
+            .field("m_iHealth", SyntheticFieldType::Int32)
+            .field("m_iValues", SyntheticFieldType::Int32Array(3))];
+        let mut wtr = SyntheticDemoWriter::start_writing(classes)?;
+        wtr.create(
+            1,
+            "CToyEntity",
+            &[
+                ("m_iValues.0", FieldValue::I64(10)),
+                ("m_iValues.2", FieldValue::I64(30)),
+            ],
+        )?;
+        wtr.write_tick(1)?;
+
+        let mut parser = Parser::from_stream(wtr.finish_into_demo_file()?)?;
+        parser.run_to_end()?;
+
+        let entity = parser
+            .context()
+            .entities()
+            .and_then(|entities| entities.get(&1))
+            .ok_or_else(|| anyhow::anyhow!("no entity #1"))?;
+        // NOTE: elements are keyed by index, same as elements of dynamic arrays.
+        assert_eq!(
+            entity.get_value::<i32>(&fkey_from_dotted_path("m_iValues.0")),
+            Some(10)
+        );
+        assert_eq!(
+            entity.get_value::<i32>(&fkey_from_dotted_path("m_iValues.1")),
+            Some(0)
+        );
+        assert_eq!(
+            entity.get_value::<i32>(&fkey_from_dotted_path("m_iValues.2")),
+            Some(30)
+        );
+        // NOTE: elements used to share a single key (var name of the array twice); it is gone.
+        assert!(entity
+            .get(&fkey_from_path(&["m_iValues", "m_iValues"]))
+            .is_none());
+        assert!(entity.get(&fkey_from_path(&["m_iValues"])).is_none());
+        assert_eq!(
+            entity.get_value::<i32>(&fkey_from_path(&["m_iHealth"])),
+            Some(0)
+        );
+
+        Ok(())
+    }
+}
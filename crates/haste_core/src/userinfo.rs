@@ -0,0 +1,148 @@
+use valveprotos::common::CMsgPlayerInfo;
+
+use crate::protobackend::{DecodeError, ProtoMessage};
+use crate::stringtables::{StringTable, StringTableContainer};
+
+pub const USERINFO_TABLE_NAME: &str = "userinfo";
+
+// NOTE: steam ids (64 bit) are composed of universe, account type, instance and account id; the
+// lower 32 bits are the account id. see https://developer.valvesoftware.com/wiki/SteamID
+const STEAMID_ACCOUNT_ID_MASK: u64 = 0xFFFF_FFFF;
+
+/// player record decoded from user data of `userinfo` string table entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerInfo {
+    /// index of the entry within `userinfo` string table. player controller entity's index is
+    /// `slot + 1`.
+    pub slot: i32,
+    pub name: String,
+    pub steamid: u64,
+    pub xuid: u64,
+    /// server assigned user id; game events refer to players by it.
+    pub userid: i32,
+    pub is_bot: bool,
+    pub is_hltv: bool,
+}
+
+impl PlayerInfo {
+    pub fn decode(slot: i32, user_data: &[u8]) -> Result<Self, DecodeError> {
+        let msg = CMsgPlayerInfo::decode_message(user_data)?;
+        Ok(Self {
+            slot,
+            name: msg.name().to_string(),
+            steamid: msg.steamid(),
+            xuid: msg.xuid(),
+            userid: msg.userid(),
+            is_bot: msg.fakeplayer(),
+            is_hltv: msg.ishltv(),
+        })
+    }
+
+    /// index of player controller entity of the player.
+    #[inline]
+    pub fn controller_index(&self) -> i32 {
+        self.slot + 1
+    }
+
+    /// 32 bit account id (as used by dota 2 / deadlock apis, opendota, etc.).
+    #[inline]
+    pub fn account_id(&self) -> u32 {
+        (self.steamid & STEAMID_ACCOUNT_ID_MASK) as u32
+    }
+}
+
+/// decodes all entries of `userinfo` string table; entries without user data or with user data
+/// that can't be decoded are skipped. players are sorted by slot.
+pub fn players_from_table(string_table: &StringTable) -> Vec<PlayerInfo> {
+    let mut players: Vec<PlayerInfo> = string_table
+        .items()
+        .filter_map(|(slot, item)| {
+            let user_data = item.user_data()?;
+            if user_data.is_empty() {
+                return None;
+            }
+            PlayerInfo::decode(*slot, user_data).ok()
+        })
+        .collect();
+    players.sort_unstable_by_key(|player| player.slot);
+    players
+}
+
+/// see [`players_from_table`]; returns empty vec if `userinfo` table does not exist (yet).
+pub fn players(string_tables: &StringTableContainer) -> Vec<PlayerInfo> {
+    string_tables
+        .find_table(USERINFO_TABLE_NAME)
+        .map(players_from_table)
+        .unwrap_or_default()
+}
+
+/// finds player by user id that game events carry (for example in `userid` key); returns none if
+/// no player has the given user id.
+///
+/// NOTE: not all games / events put server assigned user id there, some send player slot
+/// instead; see [`find_player_by_slot`] for those.
+pub fn find_player_by_userid(players: &[PlayerInfo], userid: i32) -> Option<&PlayerInfo> {
+    players.iter().find(|player| player.userid == userid)
+}
+
+/// finds player by slot that is stored in the low byte of the value (some events send it in place
+/// of user id).
+pub fn find_player_by_slot(players: &[PlayerInfo], value: i32) -> Option<&PlayerInfo> {
+    players.iter().find(|player| player.slot == value & 0xFF)
+}
+
+/// see [`find_player_by_userid`]; returns index of player controller entity.
+pub fn controller_index_from_userid(
+    string_tables: &StringTableContainer,
+    userid: i32,
+) -> Option<i32> {
+    find_player_by_userid(&players(string_tables), userid).map(PlayerInfo::controller_index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn player(slot: i32, steamid: u64, userid: i32) -> Result<PlayerInfo, DecodeError> {
+        let user_data = CMsgPlayerInfo {
+            name: Some(format!("player {slot}")),
+            steamid: Some(steamid),
+            userid: Some(userid),
+            ..Default::default()
+        }
+        .encode_message_to_vec();
+        PlayerInfo::decode(slot, &user_data)
+    }
+
+    #[test]
+    fn test_decode() -> Result<(), DecodeError> {
+        let player = player(3, 76561198012345678, 1234)?;
+        assert_eq!(player.name, "player 3");
+        assert_eq!(player.steamid, 76561198012345678);
+        assert_eq!(player.userid, 1234);
+        assert!(!player.is_bot);
+        // NOTE: upper 32 bits (universe, account type, instance) are masked off.
+        assert_eq!(player.account_id(), 52079950);
+        assert_eq!(player.controller_index(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_player() -> Result<(), DecodeError> {
+        let players = vec![
+            player(0, 76561198000000001, 1000)?,
+            player(1, 76561198000000002, 1001)?,
+        ];
+        assert_eq!(
+            find_player_by_userid(&players, 1001).map(|player| player.slot),
+            Some(1)
+        );
+        // NOTE: unknown user id is not mistaken for a slot.
+        assert!(find_player_by_userid(&players, 0x0100).is_none());
+        assert_eq!(
+            find_player_by_slot(&players, 0x0100).map(|player| player.slot),
+            Some(0)
+        );
+        Ok(())
+    }
+}
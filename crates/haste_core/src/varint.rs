@@ -0,0 +1,196 @@
+//! protobuf-style base 128 varints (as used in demo cmd headers, send tables and various
+//! user_data blobs) and zigzag encoding of signed values.
+//!
+//! unlike protobuf decoders that silently truncate, reads fail on varints that are longer than
+//! the max length of the target type or carry bits that do not fit into it.
+
+use std::io::{self, Read, Write};
+
+pub const MAX_VARINT32_BYTES: usize = 5;
+pub const MAX_VARINT64_BYTES: usize = 10;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadVarintError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("malformed varint (does not fit into {bits} bits)")]
+    MalformedVarint { bits: usize },
+}
+
+// zigzag
+// ----
+
+#[inline]
+pub const fn zigzag_encode32(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+#[inline]
+pub const fn zigzag_decode32(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+#[inline]
+pub const fn zigzag_encode64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[inline]
+pub const fn zigzag_decode64(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+// read
+// ----
+
+// NOTE: last byte of a varint can only carry `bits - (max_bytes - 1) * 7` bits of value and must
+// not have the continuation bit set.
+#[inline]
+fn read_uvarint<R: Read>(mut rdr: R, bits: usize) -> Result<(u64, usize), ReadVarintError> {
+    let max_bytes = bits.div_ceil(7);
+    let last_byte_max = (1u8 << (bits - (max_bytes - 1) * 7)) - 1;
+
+    let mut value = 0u64;
+    let mut buf = [0u8; 1];
+    for i in 0..max_bytes {
+        rdr.read_exact(&mut buf)?;
+        let byte = buf[0];
+        if i == max_bytes - 1 && byte > last_byte_max {
+            break;
+        }
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(ReadVarintError::MalformedVarint { bits })
+}
+
+/// returns the value and the number of bytes that were read.
+#[inline]
+pub fn read_uvarint32<R: Read>(rdr: R) -> Result<(u32, usize), ReadVarintError> {
+    read_uvarint(rdr, 32).map(|(value, n)| (value as u32, n))
+}
+
+/// returns the value and the number of bytes that were read.
+#[inline]
+pub fn read_uvarint64<R: Read>(rdr: R) -> Result<(u64, usize), ReadVarintError> {
+    read_uvarint(rdr, 64)
+}
+
+/// zigzag encoded; returns the value and the number of bytes that were read.
+#[inline]
+pub fn read_varint32<R: Read>(rdr: R) -> Result<(i32, usize), ReadVarintError> {
+    read_uvarint32(rdr).map(|(value, n)| (zigzag_decode32(value), n))
+}
+
+/// zigzag encoded; returns the value and the number of bytes that were read.
+#[inline]
+pub fn read_varint64<R: Read>(rdr: R) -> Result<(i64, usize), ReadVarintError> {
+    read_uvarint64(rdr).map(|(value, n)| (zigzag_decode64(value), n))
+}
+
+// write
+// ----
+
+/// returns the number of bytes that were written.
+#[inline]
+pub fn write_uvarint32<W: Write>(wtr: W, value: u32) -> Result<usize, io::Error> {
+    write_uvarint64(wtr, value as u64)
+}
+
+/// returns the number of bytes that were written.
+pub fn write_uvarint64<W: Write>(mut wtr: W, mut value: u64) -> Result<usize, io::Error> {
+    let mut buf = [0u8; MAX_VARINT64_BYTES];
+    let mut n = 0;
+    while value >= 0x80 {
+        buf[n] = (value as u8) | 0x80;
+        value >>= 7;
+        n += 1;
+    }
+    buf[n] = value as u8;
+    wtr.write_all(&buf[..=n])?;
+    Ok(n + 1)
+}
+
+/// zigzag encoded; returns the number of bytes that were written.
+#[inline]
+pub fn write_varint32<W: Write>(wtr: W, value: i32) -> Result<usize, io::Error> {
+    write_uvarint32(wtr, zigzag_encode32(value))
+}
+
+/// zigzag encoded; returns the number of bytes that were written.
+#[inline]
+pub fn write_varint64<W: Write>(wtr: W, value: i64) -> Result<usize, io::Error> {
+    write_uvarint64(wtr, zigzag_encode64(value))
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), ReadVarintError> {
+        let mut buf = Vec::new();
+        assert_eq!(write_uvarint32(&mut buf, 300)?, 2);
+        assert_eq!(write_uvarint32(&mut buf, u32::MAX)?, MAX_VARINT32_BYTES);
+        assert_eq!(write_uvarint64(&mut buf, u64::MAX)?, MAX_VARINT64_BYTES);
+        write_varint32(&mut buf, i32::MIN)?;
+        write_varint64(&mut buf, -1)?;
+
+        let mut rdr = &buf[..];
+        assert_eq!(read_uvarint32(&mut rdr)?, (300, 2));
+        assert_eq!(read_uvarint32(&mut rdr)?, (u32::MAX, MAX_VARINT32_BYTES));
+        assert_eq!(read_uvarint64(&mut rdr)?, (u64::MAX, MAX_VARINT64_BYTES));
+        assert_eq!(read_varint32(&mut rdr)?.0, i32::MIN);
+        assert_eq!(read_varint64(&mut rdr)?, (-1, 1));
+        assert!(rdr.is_empty());
+
+        assert_eq!(zigzag_encode64(-2), 3);
+        assert_eq!(zigzag_decode64(3), -2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed() {
+        // 6 bytes
+        let buf = [0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(matches!(
+            read_uvarint32(&buf[..]),
+            Err(ReadVarintError::MalformedVarint { bits: 32 })
+        ));
+        // 5 bytes, but value is wider than 32 bits
+        let buf = [0xff, 0xff, 0xff, 0xff, 0x1f];
+        assert!(matches!(
+            read_uvarint32(&buf[..]),
+            Err(ReadVarintError::MalformedVarint { bits: 32 })
+        ));
+        // truncated
+        let buf = [0xff];
+        assert!(matches!(
+            read_uvarint64(&buf[..]),
+            Err(ReadVarintError::IoError(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_round_trip(a: u32, b: u64, c: i32, d: i64) {
+            let mut buf = Vec::new();
+            let a_size = write_uvarint32(&mut buf, a)?;
+            let b_size = write_uvarint64(&mut buf, b)?;
+            let c_size = write_varint32(&mut buf, c)?;
+            let d_size = write_varint64(&mut buf, d)?;
+            prop_assert_eq!(buf.len(), a_size + b_size + c_size + d_size);
+
+            let mut rdr = &buf[..];
+            prop_assert_eq!(read_uvarint32(&mut rdr)?, (a, a_size));
+            prop_assert_eq!(read_uvarint64(&mut rdr)?, (b, b_size));
+            prop_assert_eq!(read_varint32(&mut rdr)?, (c, c_size));
+            prop_assert_eq!(read_varint64(&mut rdr)?, (d, d_size));
+            prop_assert!(rdr.is_empty());
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! typed accessors for some of the standard string tables. each table is behind its own feature
+//! flag (`econitems`, `downloadables`, `server-query-info`).
+//!
+//! see also [`crate::userinfo`] and `crate::modifiers` for `userinfo` and `ActiveModifiers`
+//! tables.
+
+#[cfg(any(feature = "downloadables", feature = "server-query-info"))]
+use crate::stringtables::StringTableContainer;
+
+// econ items
+// ----
+
+#[cfg(feature = "econitems")]
+pub use econitems::*;
+
+#[cfg(feature = "econitems")]
+mod econitems {
+    use valveprotos::dota2::CsoEconItem;
+
+    use crate::protobackend::{DecodeError, ProtoMessage};
+    use crate::stringtables::StringTableContainer;
+
+    pub const ECON_ITEMS_TABLE_NAME: &str = "EconItems";
+
+    /// cosmetic item; decoded from user data (`CSOEconItem`) of `EconItems` table entry.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct EconItem {
+        pub id: u64,
+        /// 32 bit account id of the owner.
+        pub account_id: u32,
+        pub def_index: u32,
+        pub quality: u32,
+        pub level: u32,
+        pub style: u32,
+    }
+
+    impl EconItem {
+        pub fn decode(user_data: &[u8]) -> Result<Self, DecodeError> {
+            let msg = CsoEconItem::decode_message(user_data)?;
+            Ok(Self {
+                id: msg.id(),
+                account_id: msg.account_id(),
+                def_index: msg.def_index(),
+                quality: msg.quality(),
+                level: msg.level(),
+                style: msg.style(),
+            })
+        }
+    }
+
+    /// entries that can't be decoded are skipped.
+    pub fn econ_items(string_tables: &StringTableContainer) -> Vec<EconItem> {
+        let Some(string_table) = string_tables.find_table(ECON_ITEMS_TABLE_NAME) else {
+            return Vec::new();
+        };
+        string_table
+            .items()
+            .filter_map(|(_, item)| EconItem::decode(item.user_data()?).ok())
+            .collect()
+    }
+}
+
+// downloadables
+// ----
+
+#[cfg(feature = "downloadables")]
+pub const DOWNLOADABLES_TABLE_NAME: &str = "downloadables";
+
+/// paths of files that clients are supposed to download; stored as keys of `downloadables` table
+/// entries.
+#[cfg(feature = "downloadables")]
+pub fn downloadables(string_tables: &StringTableContainer) -> Vec<&str> {
+    let Some(string_table) = string_tables.find_table(DOWNLOADABLES_TABLE_NAME) else {
+        return Vec::new();
+    };
+    let mut items: Vec<(&i32, &str)> = string_table
+        .items()
+        .filter_map(|(index, item)| Some((index, item.key()?)))
+        .collect();
+    items.sort_unstable_by_key(|(index, _)| **index);
+    items.into_iter().map(|(_, path)| path).collect()
+}
+
+// server query info
+// ----
+
+#[cfg(feature = "server-query-info")]
+pub const SERVER_QUERY_INFO_TABLE_NAME: &str = "server_query_info";
+
+/// key / value pairs of `server_query_info` table.
+///
+/// NOTE: user data of this table does not seem to be a protobuf message; values are returned as
+/// (lossy) utf-8 strings with trailing nul bytes trimmed.
+#[cfg(feature = "server-query-info")]
+pub fn server_query_info(string_tables: &StringTableContainer) -> Vec<(&str, String)> {
+    let Some(string_table) = string_tables.find_table(SERVER_QUERY_INFO_TABLE_NAME) else {
+        return Vec::new();
+    };
+    let mut items: Vec<(&i32, &str, String)> = string_table
+        .items()
+        .filter_map(|(index, item)| {
+            let key = item.key()?;
+            let value = item.user_data().map_or_else(String::new, |user_data| {
+                String::from_utf8_lossy(user_data)
+                    .trim_end_matches('\0')
+                    .to_string()
+            });
+            Some((index, key, value))
+        })
+        .collect();
+    items.sort_unstable_by_key(|(index, _, _)| **index);
+    items
+        .into_iter()
+        .map(|(_, key, value)| (key, value))
+        .collect()
+}
@@ -0,0 +1,71 @@
+//! golden-file regression tests.
+//!
+//! every `*.dem` file in `tests/fixtures` (or in the directory specified by `HASTE_FIXTURES`
+//! environment variable) is digested and compared against `<name>.digest` file that sits next to
+//! it. demo files are not checked in (they are large), digests are. the directory must exist;
+//! it always holds the golden of the synthetic demo which is built from code (see tests of
+//! `haste_core::digest`), thus a missing directory means a broken checkout.
+//!
+//! to (re)generate digests run `UPDATE_GOLDENS=1 cargo test -p haste_core --test golden`.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use haste_core::demofile::DemoFile;
+use haste_core::digest::ReplayDigest;
+
+fn fixtures_dir() -> PathBuf {
+    env::var_os("HASTE_FIXTURES")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"))
+}
+
+#[test]
+fn golden() -> anyhow::Result<()> {
+    let dir = fixtures_dir();
+    let mut demo_paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|err| anyhow::anyhow!("could not read fixtures in {}: {err}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dem"))
+        .collect();
+    demo_paths.sort();
+
+    if demo_paths.is_empty() {
+        eprintln!("no fixture demos in {}; skipping", dir.display());
+        return Ok(());
+    }
+
+    let update = env::var_os("UPDATE_GOLDENS").is_some();
+    let mut failures = Vec::new();
+    for demo_path in demo_paths.iter() {
+        let demo_file = DemoFile::start_reading(BufReader::new(File::open(demo_path)?))?;
+        let digest = ReplayDigest::compute(demo_file)?;
+
+        let golden_path = demo_path.with_extension("digest");
+        if update {
+            digest.write_to(BufWriter::new(File::create(&golden_path)?))?;
+            eprintln!("wrote {}", golden_path.display());
+            continue;
+        }
+
+        let golden = match File::open(&golden_path) {
+            Ok(file) => ReplayDigest::read_from(BufReader::new(file))?,
+            Err(_) => {
+                failures.push(format!("{}: golden is missing", golden_path.display()));
+                continue;
+            }
+        };
+        if let Some(difference) = golden.first_difference(&digest) {
+            failures.push(format!("{}: {difference}", demo_path.display()));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "digests do not match goldens (run with UPDATE_GOLDENS=1 to accept changes):\n{}",
+        failures.join("\n")
+    );
+    Ok(())
+}
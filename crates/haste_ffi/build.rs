@@ -0,0 +1,39 @@
+use std::env;
+use std::path::PathBuf;
+
+// NOTE: header is written into OUT_DIR (target/<profile>/build/haste_ffi-<hash>/out/haste_ffi.h);
+// source tree may be read-only. failure to generate it must not fail the build of the library
+// itself.
+fn main() {
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+
+    let (Some(crate_dir), Some(out_dir)) = (
+        env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from),
+        env::var_os("OUT_DIR").map(PathBuf::from),
+    ) else {
+        println!("cargo:warning=CARGO_MANIFEST_DIR or OUT_DIR is not set, skipping header");
+        return;
+    };
+
+    let config = match cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("cargo:warning=could not read cbindgen.toml, skipping header: {err}");
+            return;
+        }
+    };
+
+    match cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+    {
+        Ok(bindings) => {
+            let header = out_dir.join("haste_ffi.h");
+            // NOTE: false means that the header did not change.
+            let _ = bindings.write_to_file(header);
+        }
+        Err(err) => println!("cargo:warning=could not generate haste_ffi.h: {err}"),
+    }
+}
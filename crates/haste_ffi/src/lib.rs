@@ -0,0 +1,375 @@
+//! c api for haste.
+//!
+//! general rules:
+//! - all functions that can fail return [`HasteError`]; message of the last error that occurred on
+//! the calling thread can be obtained with [`haste_last_error_message`].
+//! - pointers that are returned from the api (for example field string values) remain valid
+//! until the next call that advances or frees the parser.
+//! - parser is not thread safe; it must only be used from the thread that created it.
+
+// NOTE: safety requirements are the same for all functions and are described above.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fs::File;
+use std::io::BufReader;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use haste_core::demofile::DemoFile;
+use haste_core::entities::fkey_from_dotted_path;
+use haste_core::fieldvalue::FieldValue;
+use haste_core::fxhash;
+use haste_core::parser::{NopVisitor, Parser};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HasteError {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    Io = 3,
+    Parse = 4,
+    NotFound = 5,
+    Panic = 6,
+}
+
+thread_local! {
+    static LAST_ERROR_MESSAGE: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error_message(message: impl ToString) {
+    // NOTE: interior nul bytes can't be represented in c strings.
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR_MESSAGE.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+/// invokes f catching panics (unwinding across ffi boundary is undefined behavior).
+fn guard<F>(f: F) -> HasteError
+where
+    F: FnOnce() -> Result<(), HasteError>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => HasteError::Ok,
+        Ok(Err(err)) => err,
+        Err(_) => {
+            set_last_error_message("panic");
+            HasteError::Panic
+        }
+    }
+}
+
+fn fail(err: HasteError, message: impl ToString) -> HasteError {
+    set_last_error_message(message);
+    err
+}
+
+/// returns message of the last error that occurred on the calling thread, or null.
+#[no_mangle]
+pub extern "C" fn haste_last_error_message() -> *const c_char {
+    LAST_ERROR_MESSAGE.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+unsafe fn str_from_ptr<'a>(s: *const c_char) -> Result<&'a str, HasteError> {
+    if s.is_null() {
+        return Err(fail(HasteError::NullPointer, "string is null"));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|err| fail(HasteError::InvalidArgument, err))
+}
+
+/// hashes the given string; can be used to compare entity class names with
+/// [`haste_parser_entity_class_hash`].
+#[no_mangle]
+pub unsafe extern "C" fn haste_hash(s: *const c_char) -> u64 {
+    if s.is_null() {
+        return 0;
+    }
+    fxhash::hash_bytes(CStr::from_ptr(s).to_bytes())
+}
+
+/// computes entity field key from dot separated path (for example `CBodyComponent.m_cellX`).
+#[no_mangle]
+pub unsafe extern "C" fn haste_fkey_from_path(path: *const c_char) -> u64 {
+    match str_from_ptr(path) {
+        Ok(path) => fkey_from_dotted_path(path),
+        Err(_) => 0,
+    }
+}
+
+// parser
+// ------
+
+/// opaque parser handle.
+pub struct HasteParser {
+    parser: Parser<DemoFile<BufReader<File>>, NopVisitor>,
+}
+
+unsafe fn parser_ref<'a>(parser: *const HasteParser) -> Result<&'a HasteParser, HasteError> {
+    parser
+        .as_ref()
+        .ok_or_else(|| fail(HasteError::NullPointer, "parser is null"))
+}
+
+unsafe fn parser_mut<'a>(parser: *mut HasteParser) -> Result<&'a mut HasteParser, HasteError> {
+    parser
+        .as_mut()
+        .ok_or_else(|| fail(HasteError::NullPointer, "parser is null"))
+}
+
+/// opens demo file at the given path. on success parser is written into `out_parser`; it must be
+/// freed with [`haste_parser_free`].
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_open(
+    path: *const c_char,
+    out_parser: *mut *mut HasteParser,
+) -> HasteError {
+    guard(|| {
+        if out_parser.is_null() {
+            return Err(fail(HasteError::NullPointer, "out_parser is null"));
+        }
+        let path = str_from_ptr(path)?;
+        let file = File::open(path).map_err(|err| fail(HasteError::Io, err))?;
+        let demo_file = DemoFile::start_reading(BufReader::new(file))
+            .map_err(|err| fail(HasteError::Parse, err))?;
+        let parser = Parser::from_stream(demo_file).map_err(|err| fail(HasteError::Parse, err))?;
+        *out_parser = Box::into_raw(Box::new(HasteParser { parser }));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_free(parser: *mut HasteParser) {
+    if !parser.is_null() {
+        drop(Box::from_raw(parser));
+    }
+}
+
+/// handles all cmds of the next tick. `out_has_more` (optional) is set to false when the end of
+/// the demo is reached.
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_step(
+    parser: *mut HasteParser,
+    out_has_more: *mut bool,
+) -> HasteError {
+    guard(|| {
+        let parser = parser_mut(parser)?;
+        let has_more = parser
+            .parser
+            .run_to_next_tick()
+            .map_err(|err| fail(HasteError::Parse, err))?;
+        if !out_has_more.is_null() {
+            *out_has_more = has_more;
+        }
+        Ok(())
+    })
+}
+
+/// seeks to the given tick; backwards too.
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_run_to_tick(
+    parser: *mut HasteParser,
+    tick: i32,
+) -> HasteError {
+    guard(|| {
+        let parser = parser_mut(parser)?;
+        parser
+            .parser
+            .run_to_tick(tick)
+            .map_err(|err| fail(HasteError::Parse, err))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_run_to_end(parser: *mut HasteParser) -> HasteError {
+    guard(|| {
+        let parser = parser_mut(parser)?;
+        parser
+            .parser
+            .run_to_end()
+            .map_err(|err| fail(HasteError::Parse, err))
+    })
+}
+
+/// returns current tick, or -1 if the parser is null or no ticks were handled yet.
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_tick(parser: *const HasteParser) -> i32 {
+    parser_ref(parser).map_or(-1, |parser| parser.parser.context().tick())
+}
+
+// entities
+// --------
+
+/// writes indices of existing entities into `buf` (up to `buf_len`); `out_len` is set to the total
+/// number of entities, which may be greater then `buf_len`. `buf` may be null if `buf_len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_entity_indices(
+    parser: *const HasteParser,
+    buf: *mut i32,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> HasteError {
+    guard(|| {
+        let parser = parser_ref(parser)?;
+        if out_len.is_null() || (buf.is_null() && buf_len > 0) {
+            return Err(fail(HasteError::NullPointer, "buf or out_len is null"));
+        }
+        let Some(entities) = parser.parser.context().entities() else {
+            *out_len = 0;
+            return Ok(());
+        };
+        for (i, (index, _)) in entities.iter().take(buf_len).enumerate() {
+            *buf.add(i) = *index;
+        }
+        *out_len = entities.iter().count();
+        Ok(())
+    })
+}
+
+/// writes hash of entity's class name into `out_hash`; see [`haste_hash`].
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_entity_class_hash(
+    parser: *const HasteParser,
+    index: i32,
+    out_hash: *mut u64,
+) -> HasteError {
+    guard(|| {
+        let parser = parser_ref(parser)?;
+        if out_hash.is_null() {
+            return Err(fail(HasteError::NullPointer, "out_hash is null"));
+        }
+        let entity = parser
+            .parser
+            .context()
+            .entities()
+            .and_then(|entities| entities.get(&index))
+            .ok_or_else(|| {
+                fail(
+                    HasteError::NotFound,
+                    format!("entity {index} does not exist"),
+                )
+            })?;
+        *out_hash = entity.serializer().serializer_name.hash;
+        Ok(())
+    })
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HasteFieldValueKind {
+    I64,
+    U64,
+    F32,
+    Bool,
+    Vector2,
+    Vector3,
+    Vector4,
+    QAngle,
+    String,
+    // NOTE: appended to keep values of existing kinds stable.
+    QAnglePitchYaw,
+}
+
+/// tagged field value; only members that correspond to `kind` are meaningful. vectors (and qangle)
+/// are stored in `f32_values`. `string_ptr` is not nul terminated.
+#[repr(C)]
+pub struct HasteFieldValue {
+    pub kind: HasteFieldValueKind,
+    pub i64_value: i64,
+    pub u64_value: u64,
+    pub f32_values: [f32; 4],
+    pub bool_value: bool,
+    pub string_ptr: *const u8,
+    pub string_len: usize,
+}
+
+impl HasteFieldValue {
+    fn new(kind: HasteFieldValueKind) -> Self {
+        Self {
+            kind,
+            i64_value: 0,
+            u64_value: 0,
+            f32_values: [0.0; 4],
+            bool_value: false,
+            string_ptr: ptr::null(),
+            string_len: 0,
+        }
+    }
+
+    fn from_vector(kind: HasteFieldValueKind, values: &[f32]) -> Self {
+        let mut ret = Self::new(kind);
+        ret.f32_values[..values.len()].copy_from_slice(values);
+        ret
+    }
+}
+
+impl From<&FieldValue> for HasteFieldValue {
+    fn from(value: &FieldValue) -> Self {
+        match value {
+            FieldValue::I64(v) => Self {
+                i64_value: *v,
+                ..Self::new(HasteFieldValueKind::I64)
+            },
+            FieldValue::U64(v) => Self {
+                u64_value: *v,
+                ..Self::new(HasteFieldValueKind::U64)
+            },
+            FieldValue::F32(v) => Self::from_vector(HasteFieldValueKind::F32, &[*v]),
+            FieldValue::Bool(v) => Self {
+                bool_value: *v,
+                ..Self::new(HasteFieldValueKind::Bool)
+            },
+            FieldValue::Vector2(v) => Self::from_vector(HasteFieldValueKind::Vector2, v),
+            FieldValue::Vector3(v) => Self::from_vector(HasteFieldValueKind::Vector3, v),
+            FieldValue::Vector4(v) => Self::from_vector(HasteFieldValueKind::Vector4, v),
+            FieldValue::QAngle(v) => Self::from_vector(HasteFieldValueKind::QAngle, v),
+            FieldValue::QAnglePitchYaw(v) => {
+                Self::from_vector(HasteFieldValueKind::QAnglePitchYaw, v)
+            }
+            FieldValue::String(v) => Self {
+                string_ptr: v.as_ptr(),
+                string_len: v.len(),
+                ..Self::new(HasteFieldValueKind::String)
+            },
+        }
+    }
+}
+
+/// reads the value of entity's field by key; see [`haste_fkey_from_path`]. returns
+/// [`HasteError::NotFound`] if either entity or field does not exist.
+#[no_mangle]
+pub unsafe extern "C" fn haste_parser_entity_field(
+    parser: *const HasteParser,
+    index: i32,
+    key: u64,
+    out_value: *mut HasteFieldValue,
+) -> HasteError {
+    guard(|| {
+        let parser = parser_ref(parser)?;
+        if out_value.is_null() {
+            return Err(fail(HasteError::NullPointer, "out_value is null"));
+        }
+        let entity = parser
+            .parser
+            .context()
+            .entities()
+            .and_then(|entities| entities.get(&index))
+            .ok_or_else(|| {
+                fail(
+                    HasteError::NotFound,
+                    format!("entity {index} does not exist"),
+                )
+            })?;
+        let value = entity
+            .get(&key)
+            .ok_or_else(|| fail(HasteError::NotFound, format!("field {key} does not exist")))?;
+        *out_value = HasteFieldValue::from(value);
+        Ok(())
+    })
+}
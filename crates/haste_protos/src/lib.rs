@@ -0,0 +1,21 @@
+//! protobuf message types that haste is built against: demo, network and user messages that are
+//! common to source 2 games (`common`), plus dota 2 (`dota2` feature) and deadlock (`deadlock`
+//! feature) ones.
+//!
+//! meant for consumers that handle raw messages themselves (for example raw packets that are
+//! handed over through `Visitor::on_packet`): depending on this crate (instead of generating
+//! protos on their own, or depending on `valveprotos` directly) guarantees that messages are
+//! decoded with exactly the same schema and the same [`prost`] version that haste uses.
+//!
+//! ```ignore
+//! use haste_protos::prost::Message;
+//! use haste_protos::common::CMsgSource1LegacyGameEvent;
+//!
+//! let msg = CMsgSource1LegacyGameEvent::decode(data)?;
+//! ```
+//!
+//! NOTE: types are generated by `valveprotos` at build time; building requires `protoc`, or
+//! `protobuf-src` feature.
+
+pub use prost;
+pub use valveprotos::*;
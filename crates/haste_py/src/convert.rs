@@ -0,0 +1,46 @@
+use haste_core::fieldvalue::FieldValue;
+use haste_core::gameevents::EventValue;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+pub(crate) fn field_value_to_py(py: Python<'_>, value: &FieldValue) -> PyObject {
+    match value {
+        FieldValue::I64(v) => v.to_object(py),
+        FieldValue::U64(v) => v.to_object(py),
+        FieldValue::F32(v) => v.to_object(py),
+        FieldValue::Bool(v) => v.to_object(py),
+        FieldValue::Vector2([x, y]) | FieldValue::QAnglePitchYaw([x, y]) => (x, y).to_object(py),
+        FieldValue::Vector3([x, y, z]) | FieldValue::QAngle([x, y, z]) => (x, y, z).to_object(py),
+        FieldValue::Vector4([x, y, z, w]) => (x, y, z, w).to_object(py),
+        FieldValue::String(v) => v.as_ref().to_object(py),
+    }
+}
+
+pub(crate) fn event_value_to_py(py: Python<'_>, value: &EventValue) -> PyObject {
+    match value {
+        EventValue::String(v) => v.as_ref().to_object(py),
+        EventValue::F32(v) => v.to_object(py),
+        EventValue::I32(v) => v.to_object(py),
+        EventValue::Bool(v) => v.to_object(py),
+        EventValue::U64(v) => v.to_object(py),
+    }
+}
+
+/// builds `{"tick": .., "name": .., "data": {..}}` dict; shape is shared by game events and
+/// combat log entries.
+pub(crate) fn record_to_py<'py>(
+    py: Python<'py>,
+    tick: i32,
+    name: &str,
+    data: &[(Box<str>, EventValue)],
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("tick", tick)?;
+    dict.set_item("name", name)?;
+    let data_dict = PyDict::new_bound(py);
+    for (key, value) in data.iter() {
+        data_dict.set_item(key.as_ref(), event_value_to_py(py, value))?;
+    }
+    dict.set_item("data", data_dict)?;
+    Ok(dict)
+}
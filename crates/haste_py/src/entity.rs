@@ -0,0 +1,60 @@
+use haste_core::entities::{self, fkey_from_dotted_path};
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+
+use crate::convert::field_value_to_py;
+
+/// snapshot of an entity's state at the moment when it was requested. parser does not need to be
+/// kept alive for snapshot to remain accessible.
+//
+// NOTE: entity holds Rc of serializer, it can't be sent across threads.
+#[pyclass(unsendable, module = "haste")]
+pub(crate) struct Entity {
+    inner: entities::Entity,
+    class_name: Option<String>,
+}
+
+impl Entity {
+    pub(crate) fn new(inner: entities::Entity, class_name: Option<String>) -> Self {
+        Self { inner, class_name }
+    }
+}
+
+#[pymethods]
+impl Entity {
+    #[getter]
+    fn index(&self) -> i32 {
+        self.inner.index()
+    }
+
+    #[getter]
+    fn class_name(&self) -> Option<&str> {
+        self.class_name.as_deref()
+    }
+
+    /// get the value of the field by dot separated path (for example
+    /// `CBodyComponent.m_cellX` or `m_vecPlayerData.3.m_iszPlayerName`), returns None if the
+    /// field does not exist.
+    fn get(&self, py: Python<'_>, path: &str) -> Option<PyObject> {
+        self.inner
+            .get(&fkey_from_dotted_path(path))
+            .map(|value| field_value_to_py(py, value))
+    }
+
+    fn __getitem__(&self, py: Python<'_>, path: &str) -> PyResult<PyObject> {
+        self.get(py, path)
+            .ok_or_else(|| PyKeyError::new_err(path.to_string()))
+    }
+
+    fn __contains__(&self, path: &str) -> bool {
+        self.inner.get(&fkey_from_dotted_path(path)).is_some()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Entity(index={}, class_name={:?})",
+            self.inner.index(),
+            self.class_name.as_deref().unwrap_or("?")
+        )
+    }
+}
@@ -0,0 +1,14 @@
+use pyo3::prelude::*;
+
+mod convert;
+mod entity;
+mod projection;
+mod replay;
+
+/// python bindings for haste - dota 2 and deadlock replay parser.
+#[pymodule]
+fn haste(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<entity::Entity>()?;
+    m.add_class::<replay::Replay>()?;
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use haste_core::entities::{fkey_from_dotted_path, Entity};
+use haste_core::fieldvalue::FieldValue;
+use haste_core::fxhash;
+use haste_core::parser::Context;
+use numpy::{PyArray1, PyArrayMethods};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// single projected field. values are stored row-major, `width` values per row.
+struct Column {
+    path: String,
+    key: u64,
+    // NOTE: width is not known until the first value is seen (scalars are 1 wide, vectors are 2, 3
+    // or 4 wide).
+    width: Option<usize>,
+    // number of rows that were pushed before width was known.
+    pending: usize,
+    data: Vec<f64>,
+}
+
+impl Column {
+    fn new(path: String) -> Self {
+        Self {
+            key: fkey_from_dotted_path(&path),
+            path,
+            width: None,
+            pending: 0,
+            data: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, value: Option<&FieldValue>) -> Result<()> {
+        let Some(value) = value else {
+            match self.width {
+                Some(width) => self.data.extend(std::iter::repeat(f64::NAN).take(width)),
+                None => self.pending += 1,
+            }
+            return Ok(());
+        };
+
+        let mut buf = [0f64; 4];
+        let values: &[f64] = match value {
+            FieldValue::I64(v) => {
+                buf[0] = *v as f64;
+                &buf[..1]
+            }
+            // NOTE: large u64s (for example steam ids) lose precision.
+            FieldValue::U64(v) => {
+                buf[0] = *v as f64;
+                &buf[..1]
+            }
+            FieldValue::F32(v) => {
+                buf[0] = *v as f64;
+                &buf[..1]
+            }
+            FieldValue::Bool(v) => {
+                buf[0] = *v as u8 as f64;
+                &buf[..1]
+            }
+            FieldValue::Vector2(v) | FieldValue::QAnglePitchYaw(v) => {
+                v.iter()
+                    .zip(buf.iter_mut())
+                    .for_each(|(v, b)| *b = *v as f64);
+                &buf[..2]
+            }
+            FieldValue::Vector3(v) | FieldValue::QAngle(v) => {
+                v.iter()
+                    .zip(buf.iter_mut())
+                    .for_each(|(v, b)| *b = *v as f64);
+                &buf[..3]
+            }
+            FieldValue::Vector4(v) => {
+                v.iter()
+                    .zip(buf.iter_mut())
+                    .for_each(|(v, b)| *b = *v as f64);
+                &buf[..4]
+            }
+            FieldValue::String(_) => {
+                return Err(anyhow!("field {} is not numeric", self.path));
+            }
+        };
+
+        match self.width {
+            Some(width) if width != values.len() => {
+                return Err(anyhow!(
+                    "field {} changed width (from {width} to {})",
+                    self.path,
+                    values.len()
+                ));
+            }
+            Some(_) => {}
+            None => {
+                self.width = Some(values.len());
+                self.fill_pending(values.len());
+            }
+        }
+        self.data.extend_from_slice(values);
+        Ok(())
+    }
+
+    fn fill_pending(&mut self, width: usize) {
+        self.data
+            .extend(std::iter::repeat(f64::NAN).take(self.pending * width));
+        self.pending = 0;
+    }
+}
+
+/// collects values of the given fields of all entities of the given class at the end of each
+/// tick.
+pub(crate) struct Projection {
+    class_hash: u64,
+    ticks: Vec<i32>,
+    indices: Vec<i32>,
+    columns: Vec<Column>,
+}
+
+impl Projection {
+    pub(crate) fn new(class_name: &str, paths: Vec<String>) -> Self {
+        Self {
+            class_hash: fxhash::hash_bytes(class_name.as_bytes()),
+            ticks: Vec::new(),
+            indices: Vec::new(),
+            columns: paths.into_iter().map(Column::new).collect(),
+        }
+    }
+
+    pub(crate) fn push_tick(&mut self, ctx: &Context) -> Result<()> {
+        let Some(entities) = ctx.entities() else {
+            return Ok(());
+        };
+        let tick = ctx.tick();
+        for (_, entity) in entities.iter() {
+            if entity.serializer_name_heq(self.class_hash) {
+                self.push_entity(tick, entity)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn push_entity(&mut self, tick: i32, entity: &Entity) -> Result<()> {
+        self.ticks.push(tick);
+        self.indices.push(entity.index());
+        for column in self.columns.iter_mut() {
+            column.push(entity.get(&column.key))?;
+        }
+        Ok(())
+    }
+
+    /// moves collected data into numpy arrays without copying. returns dict with `tick` and
+    /// `index` columns, and a column for each field; vector fields are 2d arrays.
+    pub(crate) fn into_py_dict(self, py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        let rows = self.ticks.len();
+        dict.set_item("tick", PyArray1::from_vec_bound(py, self.ticks))?;
+        dict.set_item("index", PyArray1::from_vec_bound(py, self.indices))?;
+        for mut column in self.columns.into_iter() {
+            let width = column.width.unwrap_or(1);
+            column.fill_pending(width);
+            let array = PyArray1::from_vec_bound(py, column.data);
+            if width == 1 {
+                dict.set_item(column.path, array)?;
+            } else {
+                // NOTE: reshaping contiguous array produces a view, data is not copied.
+                dict.set_item(column.path, array.reshape([rows, width])?)?;
+            }
+        }
+        Ok(dict)
+    }
+}
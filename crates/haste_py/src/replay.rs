@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use haste_core::demofile::DemoFile;
+use haste_core::demostream::CmdHeader;
+use haste_core::fxhash;
+use haste_core::gameevents::EventValue;
+use haste_core::parser::{Context, Parser, Visitor};
+use haste_core::protobackend::ProtoMessage;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use valveprotos::common::{
+    CDemoClassInfo, CMsgSource1LegacyGameEvent, EBaseGameEvents, EDemoCommands,
+};
+use valveprotos::dota2::{CMsgDotaCombatLogEntry, EDotaUserMessages};
+
+use crate::convert::record_to_py;
+use crate::entity::Entity;
+use crate::projection::Projection;
+
+const COMBAT_LOG_NAMES_TABLE_NAME: &str = "CombatLogNames";
+
+struct Record {
+    tick: i32,
+    name: Box<str>,
+    data: Vec<(Box<str>, EventValue)>,
+}
+
+#[derive(Default)]
+struct ReplayVisitor {
+    // NOTE: haste_core is built with preserve-metadata here, so serializer names are also
+    // available on the serializer itself; they match network names of entity classes which arrive
+    // in DemClassInfo cmd, and resolving them from there keeps parity with the wasm bindings.
+    class_names: HashMap<u64, Box<str>>,
+
+    collect_game_events: bool,
+    game_events: Vec<Record>,
+    collect_combat_log: bool,
+    combat_log: Vec<Record>,
+
+    projection: Option<Projection>,
+}
+
+impl ReplayVisitor {
+    fn class_name(&self, hash: u64) -> Option<&str> {
+        self.class_names.get(&hash).map(AsRef::as_ref)
+    }
+
+    fn handle_game_event(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let Some(game_event_list) = ctx.game_event_list() else {
+            return Ok(());
+        };
+        let msg = CMsgSource1LegacyGameEvent::decode_message(data)?;
+        let Some(event) = game_event_list.decode(&msg) else {
+            return Ok(());
+        };
+        self.game_events.push(Record {
+            tick: ctx.tick(),
+            name: event.name().into(),
+            data: event
+                .iter()
+                .map(|(key, value)| ((*key).into(), value.clone()))
+                .collect(),
+        });
+        Ok(())
+    }
+
+    fn handle_combat_log(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let msg = CMsgDotaCombatLogEntry::decode_message(data)?;
+
+        // names are indices into CombatLogNames string table.
+        let name = |index: u32| -> Option<EventValue> {
+            ctx.string_tables()
+                .and_then(|string_tables| string_tables.find_table(COMBAT_LOG_NAMES_TABLE_NAME))
+                .and_then(|string_table| string_table.get_item(&(index as i32)))
+                .and_then(|item| item.string.as_ref())
+                .map(|string| EventValue::String(String::from_utf8_lossy(string).into()))
+        };
+
+        let mut data: Vec<(Box<str>, EventValue)> = vec![
+            (
+                "type".into(),
+                EventValue::String(msg.r#type().as_str_name().into()),
+            ),
+            ("timestamp".into(), EventValue::F32(msg.timestamp())),
+            (
+                "is_attacker_hero".into(),
+                EventValue::Bool(msg.is_attacker_hero()),
+            ),
+            (
+                "is_attacker_illusion".into(),
+                EventValue::Bool(msg.is_attacker_illusion()),
+            ),
+            (
+                "is_target_hero".into(),
+                EventValue::Bool(msg.is_target_hero()),
+            ),
+            (
+                "is_target_illusion".into(),
+                EventValue::Bool(msg.is_target_illusion()),
+            ),
+            ("value".into(), EventValue::U64(msg.value() as u64)),
+            ("health".into(), EventValue::I32(msg.health())),
+        ];
+        let names = [
+            ("attacker", msg.attacker_name()),
+            ("target", msg.target_name()),
+            ("target_source", msg.target_source_name()),
+            ("inflictor", msg.inflictor_name()),
+            ("damage_source", msg.damage_source_name()),
+        ];
+        for (key, index) in names {
+            if let Some(value) = name(index) {
+                data.push((key.into(), value));
+            }
+        }
+
+        self.combat_log.push(Record {
+            tick: ctx.tick(),
+            name: "dota_combatlog".into(),
+            data,
+        });
+        Ok(())
+    }
+}
+
+impl Visitor for ReplayVisitor {
+    fn on_cmd(&mut self, _ctx: &Context, cmd_header: &CmdHeader, data: &[u8]) -> Result<()> {
+        if cmd_header.cmd == EDemoCommands::DemClassInfo {
+            let cmd = CDemoClassInfo::decode_message(data)?;
+            self.class_names = cmd
+                .classes
+                .iter()
+                .map(|class| {
+                    let name = class.network_name();
+                    (fxhash::hash_bytes(name.as_bytes()), name.into())
+                })
+                .collect();
+        }
+        Ok(())
+    }
+
+    fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        match packet_type {
+            t if t == EBaseGameEvents::GeSource1LegacyGameEvent as u32
+                && self.collect_game_events =>
+            {
+                self.handle_game_event(ctx, data)
+            }
+            t if t == EDotaUserMessages::DotaUmCombatLogDataHltv as u32
+                && self.collect_combat_log =>
+            {
+                self.handle_combat_log(ctx, data)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+        if let Some(projection) = self.projection.as_mut() {
+            projection.push_tick(ctx)?;
+        }
+        Ok(())
+    }
+}
+
+/// replay parser.
+///
+/// game events and combat log entries are only collected if requested; collected entries are
+/// drained by [`Replay::game_events`] and [`Replay::combat_log`].
+#[pyclass(unsendable, module = "haste")]
+pub(crate) struct Replay {
+    parser: Parser<DemoFile<BufReader<File>>, ReplayVisitor>,
+}
+
+#[pymethods]
+impl Replay {
+    #[new]
+    #[pyo3(signature = (path, game_events = false, combat_log = false))]
+    fn new(path: PathBuf, game_events: bool, combat_log: bool) -> Result<Self> {
+        let file = File::open(path)?;
+        let buf_reader = BufReader::new(file);
+        let demo_file = DemoFile::start_reading(buf_reader)?;
+        let visitor = ReplayVisitor {
+            collect_game_events: game_events,
+            collect_combat_log: combat_log,
+            ..Default::default()
+        };
+        let parser = Parser::from_stream_with_visitor(demo_file, visitor)?;
+        Ok(Self { parser })
+    }
+
+    #[getter]
+    fn tick(&self) -> i32 {
+        self.parser.context().tick()
+    }
+
+    fn run_to_end(&mut self) -> Result<()> {
+        self.parser.run_to_end()
+    }
+
+    /// seeks to the given tick; backwards too.
+    fn run_to_tick(&mut self, tick: i32) -> Result<()> {
+        self.parser.run_to_tick(tick)
+    }
+
+    /// snapshots of all entities, or only entities of the given class (for example
+    /// `CDOTA_Unit_Hero_Axe`).
+    #[pyo3(signature = (class_name = None))]
+    fn entities(&self, class_name: Option<&str>) -> Vec<Entity> {
+        let Some(entities) = self.parser.context().entities() else {
+            return Vec::new();
+        };
+        let class_hash = class_name.map(|name| fxhash::hash_bytes(name.as_bytes()));
+        entities
+            .iter()
+            .filter(|(_, entity)| class_hash.map_or(true, |hash| entity.serializer_name_heq(hash)))
+            .map(|(_, entity)| self.snapshot(entity))
+            .collect()
+    }
+
+    fn entity(&self, index: i32) -> Option<Entity> {
+        self.parser
+            .context()
+            .entities()
+            .and_then(|entities| entities.get(&index))
+            .map(|entity| self.snapshot(entity))
+    }
+
+    /// drains collected game events; each event is a dict of `tick`, `name` and `data`.
+    fn game_events<'py>(&mut self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let records = std::mem::take(&mut self.parser.visitor_mut().game_events);
+        records_to_py(py, records)
+    }
+
+    /// drains collected combat log entries (dota 2 only); same shape as game events.
+    fn combat_log<'py>(&mut self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let records = std::mem::take(&mut self.parser.visitor_mut().combat_log);
+        records_to_py(py, records)
+    }
+
+    /// runs parser from the current position to the end and collects values of the given fields
+    /// (dot separated paths) of all entities of the given class at the end of each tick.
+    ///
+    /// returns dict of numpy arrays: `tick`, `index` (entity index) and a column per field. missing
+    /// values are NaN.
+    fn project<'py>(
+        &mut self,
+        py: Python<'py>,
+        class_name: &str,
+        fields: Vec<String>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        self.parser.visitor_mut().projection = Some(Projection::new(class_name, fields));
+        let result = self.parser.run_to_end();
+        let projection = self.parser.visitor_mut().projection.take();
+        result?;
+        match projection {
+            Some(projection) => projection.into_py_dict(py),
+            None => Ok(PyDict::new_bound(py)),
+        }
+    }
+}
+
+impl Replay {
+    fn snapshot(&self, entity: &haste_core::entities::Entity) -> Entity {
+        let class_name = self
+            .parser
+            .visitor()
+            .class_name(entity.serializer().serializer_name.hash)
+            .map(String::from);
+        Entity::new(entity.clone(), class_name)
+    }
+}
+
+fn records_to_py(py: Python<'_>, records: Vec<Record>) -> PyResult<Vec<Bound<'_, PyDict>>> {
+    records
+        .iter()
+        .map(|record| record_to_py(py, record.tick, &record.name, &record.data))
+        .collect()
+}
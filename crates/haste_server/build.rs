@@ -0,0 +1,4 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto/haste.proto");
+    tonic_build::compile_protos("proto/haste.proto").unwrap();
+}
@@ -0,0 +1,77 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use haste_core::limits::ResourceLimits;
+use tonic::transport::Server;
+
+mod pb {
+    tonic::include_proto!("haste");
+}
+mod service;
+mod visitor;
+
+/// haste-server - grpc service that parses dota 2 and deadlock replays
+#[derive(argh::FromArgs)]
+struct Args {
+    /// address to listen on
+    #[argh(option, default = "\"127.0.0.1:50051\".parse().unwrap()")]
+    listen: SocketAddr,
+    /// maximum size of demo (in megabytes) that can be uploaded or fetched
+    #[argh(option, default = "512")]
+    max_demo_size: usize,
+    /// maximum number of tick events that can be buffered per request before parsing is paused
+    #[argh(option, default = "1024")]
+    channel_capacity: usize,
+    /// allow clients to submit demo urls; server fetches them as is, enable only if the server is
+    /// not exposed to untrusted clients or can't reach anything it should not.
+    #[argh(switch)]
+    allow_url_fetch: bool,
+    /// maximum time (in seconds) that parsing of a single demo may take
+    #[argh(option, default = "300")]
+    max_parse_time: u64,
+    /// maximum size (in megabytes) of a single cmd or packet message of a demo
+    #[argh(option, default = "2")]
+    max_message_size: usize,
+    /// maximum number of entities that a demo may have at once
+    #[argh(option, default = "16384")]
+    max_entities: usize,
+    /// maximum size (in megabytes, approximately) of entity field state of a single demo
+    #[argh(option, default = "1024")]
+    max_field_state_size: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let args: Args = argh::from_env();
+    let max_demo_size = args.max_demo_size * 1024 * 1024;
+
+    // NOTE: demos come from untrusted clients; a crafted one must not be able to take the server
+    // down or make it spin forever.
+    let resource_limits = ResourceLimits {
+        max_entities: Some(args.max_entities),
+        max_field_state_bytes: Some(args.max_field_state_size * 1024 * 1024),
+        max_wall_time: Some(Duration::from_secs(args.max_parse_time)),
+        max_message_size: Some(args.max_message_size * 1024 * 1024),
+    };
+
+    let service = service::HasteService::new(
+        max_demo_size,
+        args.channel_capacity,
+        args.allow_url_fetch,
+        resource_limits,
+    );
+    let server = pb::haste_server::HasteServer::new(service)
+        // NOTE: demo uploads are much bigger then tonic's default limit of 4mb.
+        .max_decoding_message_size(max_demo_size);
+
+    log::info!("listening on {}", args.listen);
+    Server::builder()
+        .add_service(server)
+        .serve(args.listen)
+        .await?;
+
+    Ok(())
+}
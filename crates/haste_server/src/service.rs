@@ -0,0 +1,246 @@
+use std::io::Cursor;
+
+use haste_arrow::{GameEventRecorder, ProjectionRecorder};
+use haste_core::demofile::DemoFile;
+use haste_core::limits::{ResourceLimitError, ResourceLimits};
+use haste_core::parser::{Parser, ParserOptions, Visitor};
+use haste_core::sink::SinkVisitor;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::pb::haste_server::Haste;
+use crate::pb::{
+    export_request, parse_request, ArtifactChunk, ExportRequest, ParseRequest, TickEvents,
+};
+use crate::visitor::StreamVisitor;
+
+/// artifacts are sent in chunks of this size; tonic clients refuse messages bigger than 4mb by
+/// default.
+const ARTIFACT_CHUNK_SIZE: usize = 1024 * 1024;
+
+pub(crate) struct HasteService {
+    http_client: reqwest::Client,
+    max_demo_size: usize,
+    channel_capacity: usize,
+    allow_url_fetch: bool,
+    resource_limits: ResourceLimits,
+}
+
+impl HasteService {
+    pub(crate) fn new(
+        max_demo_size: usize,
+        channel_capacity: usize,
+        allow_url_fetch: bool,
+        resource_limits: ResourceLimits,
+    ) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            max_demo_size,
+            channel_capacity,
+            allow_url_fetch,
+            resource_limits,
+        }
+    }
+
+    /// demos are untrusted; they are parsed in safe mode and within resource limits.
+    fn parser_options(&self) -> ParserOptions {
+        ParserOptions {
+            safe_mode: true,
+            resource_limits: self.resource_limits,
+            ..Default::default()
+        }
+    }
+
+    /// NOTE: url comes from the client as is; server will fetch whatever it can reach (including
+    /// hosts on its private network), that's why fetching must be enabled explicitly.
+    async fn fetch_demo(&self, url: &str) -> Result<Vec<u8>, Status> {
+        if !self.allow_url_fetch {
+            return Err(Status::permission_denied(
+                "fetching demos by url is disabled",
+            ));
+        }
+
+        let mut response = self
+            .http_client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| Status::unavailable(format!("could not fetch demo: {err}")))?;
+
+        if response
+            .content_length()
+            .is_some_and(|content_length| content_length as usize > self.max_demo_size)
+        {
+            return Err(Status::invalid_argument("demo is too large"));
+        }
+
+        // NOTE: content length is not always known (chunked transfer encoding, etc.); body is read
+        // chunk by chunk so that nothing beyond the limit would be buffered.
+        let mut demo = Vec::with_capacity(response.content_length().unwrap_or(0) as usize);
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|err| Status::unavailable(format!("could not fetch demo: {err}")))?
+        {
+            if demo.len() + chunk.len() > self.max_demo_size {
+                return Err(Status::invalid_argument("demo is too large"));
+            }
+            demo.extend_from_slice(&chunk);
+        }
+        Ok(demo)
+    }
+
+    async fn load_demo(
+        &self,
+        source: Option<parse_request::Source>,
+    ) -> Result<DemoFile<Cursor<Vec<u8>>>, Status> {
+        let demo = match source {
+            Some(parse_request::Source::Demo(demo)) => demo,
+            Some(parse_request::Source::Url(url)) => self.fetch_demo(&url).await?,
+            None => return Err(Status::invalid_argument("source is missing")),
+        };
+        DemoFile::start_reading(Cursor::new(demo))
+            .map_err(|err| Status::invalid_argument(err.to_string()))
+    }
+}
+
+/// exceeded resource limits are reported as such; anything else that makes parsing fail is a
+/// problem of the demo.
+fn parse_error_status(err: &anyhow::Error) -> Status {
+    match err
+        .chain()
+        .find_map(|err| err.downcast_ref::<ResourceLimitError>())
+    {
+        Some(err) => Status::resource_exhausted(err.to_string()),
+        None => Status::invalid_argument(err.to_string()),
+    }
+}
+
+/// parses the demo to the end and hands over the visitor.
+fn run_parser<V: Visitor>(
+    demo_file: DemoFile<Cursor<Vec<u8>>>,
+    visitor: V,
+    options: ParserOptions,
+) -> Result<V, Status> {
+    let mut parser = Parser::from_stream_with_visitor_and_options(demo_file, visitor, options)
+        .map_err(|err| Status::invalid_argument(err.to_string()))?;
+    parser
+        .run_to_end()
+        .map_err(|err| parse_error_status(&err))?;
+    Ok(parser.into_visitor())
+}
+
+fn export_parquet(
+    demo_file: DemoFile<Cursor<Vec<u8>>>,
+    table: export_request::Table,
+    options: ParserOptions,
+) -> Result<Vec<u8>, Status> {
+    let batch = match table {
+        export_request::Table::Projection(projection) => {
+            let visitor = ProjectionRecorder::new(&projection.class_name, projection.fields);
+            run_parser(demo_file, visitor, options)?
+                .finish()
+                .map_err(anyhow::Error::from)
+        }
+        export_request::Table::Events(_) => {
+            let visitor = SinkVisitor::new(GameEventRecorder::new());
+            run_parser(demo_file, visitor, options)?
+                .finish()
+                .and_then(|recorder| recorder.finish().map_err(anyhow::Error::from))
+        }
+    }
+    .map_err(|err| Status::internal(err.to_string()))?;
+
+    let mut artifact = Vec::new();
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    ArrowWriter::try_new(&mut artifact, batch.schema(), Some(props))
+        .and_then(|mut writer| {
+            writer.write(&batch)?;
+            writer.close()
+        })
+        .map_err(|err| Status::internal(err.to_string()))?;
+    Ok(artifact)
+}
+
+#[tonic::async_trait]
+impl Haste for HasteService {
+    type ParseStream = ReceiverStream<Result<TickEvents, Status>>;
+    type ExportStream = ReceiverStream<Result<ArtifactChunk, Status>>;
+
+    async fn parse(
+        &self,
+        request: Request<ParseRequest>,
+    ) -> Result<Response<Self::ParseStream>, Status> {
+        let request = request.into_inner();
+        let demo_file = self.load_demo(request.source).await?;
+
+        let (tx, rx) = mpsc::channel(self.channel_capacity);
+        let visitor = StreamVisitor::new(tx.clone(), &request.projections, request.events);
+        let options = self.parser_options();
+
+        // NOTE: parsing is cpu bound, it must not block async runtime's threads.
+        tokio::task::spawn_blocking(move || {
+            let result = Parser::from_stream_with_visitor_and_options(demo_file, visitor, options)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut parser| parser.run_to_end());
+            if let Err(err) = result {
+                // NOTE: client might be gone already; there's nobody to report to then.
+                if !tx.is_closed() {
+                    let _ = tx.blocking_send(Err(parse_error_status(&err)));
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn export(
+        &self,
+        request: Request<ExportRequest>,
+    ) -> Result<Response<Self::ExportStream>, Status> {
+        let request = request.into_inner();
+        let table = match request.table {
+            Some(export_request::Table::Events(false)) | None => {
+                return Err(Status::invalid_argument("table is missing"));
+            }
+            Some(table) => table,
+        };
+        let source = request.source.map(|source| match source {
+            export_request::Source::Demo(demo) => parse_request::Source::Demo(demo),
+            export_request::Source::Url(url) => parse_request::Source::Url(url),
+        });
+        let demo_file = self.load_demo(source).await?;
+
+        let (tx, rx) = mpsc::channel(self.channel_capacity);
+        let options = self.parser_options();
+
+        // NOTE: parsing is cpu bound, it must not block async runtime's threads.
+        tokio::task::spawn_blocking(move || {
+            let artifact = match export_parquet(demo_file, table, options) {
+                Ok(artifact) => artifact,
+                Err(status) => {
+                    let _ = tx.blocking_send(Err(status));
+                    return;
+                }
+            };
+            for chunk in artifact.chunks(ARTIFACT_CHUNK_SIZE) {
+                let chunk = ArtifactChunk {
+                    data: chunk.to_vec(),
+                };
+                // NOTE: client is gone if send fails.
+                if tx.blocking_send(Ok(chunk)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use haste_core::entities::fkey_from_dotted_path;
+use haste_core::fieldvalue::FieldValue;
+use haste_core::fxhash;
+use haste_core::gameevents::EventValue;
+use haste_core::parser::{Context, Visitor};
+use haste_core::protobackend::ProtoMessage;
+use tokio::sync::mpsc;
+use tonic::Status;
+use valveprotos::common::{CMsgSource1LegacyGameEvent, EBaseGameEvents};
+
+use crate::pb::{self, value};
+
+struct Projection {
+    class_name: String,
+    class_hash: u64,
+    keys: Vec<u64>,
+}
+
+enum EventFilter {
+    None,
+    All,
+    Names(Vec<String>),
+}
+
+impl EventFilter {
+    fn new(events: Vec<String>) -> Self {
+        if events.is_empty() {
+            Self::None
+        } else if events.iter().any(|event| event.eq("*")) {
+            Self::All
+        } else {
+            Self::Names(events)
+        }
+    }
+
+    #[inline]
+    fn wants(&self, name: &str) -> bool {
+        match self {
+            Self::None => false,
+            Self::All => true,
+            Self::Names(names) => names.iter().any(|n| n.eq(name)),
+        }
+    }
+}
+
+fn field_value_to_pb(value: &FieldValue) -> pb::Value {
+    let kind = match value {
+        FieldValue::I64(v) => value::Kind::I64(*v),
+        FieldValue::U64(v) => value::Kind::U64(*v),
+        FieldValue::F32(v) => value::Kind::F32(*v),
+        FieldValue::Bool(v) => value::Kind::Bool(*v),
+        FieldValue::Vector2(v) | FieldValue::QAnglePitchYaw(v) => {
+            value::Kind::Vector(pb::Vector { values: v.to_vec() })
+        }
+        FieldValue::Vector3(v) | FieldValue::QAngle(v) => {
+            value::Kind::Vector(pb::Vector { values: v.to_vec() })
+        }
+        FieldValue::Vector4(v) => value::Kind::Vector(pb::Vector { values: v.to_vec() }),
+        FieldValue::String(v) => value::Kind::String(v.to_string()),
+    };
+    pb::Value { kind: Some(kind) }
+}
+
+fn event_value_to_pb(value: &EventValue) -> pb::Value {
+    let kind = match value {
+        EventValue::String(v) => value::Kind::String(v.to_string()),
+        EventValue::F32(v) => value::Kind::F32(*v),
+        EventValue::I32(v) => value::Kind::I64(*v as i64),
+        EventValue::Bool(v) => value::Kind::Bool(*v),
+        EventValue::U64(v) => value::Kind::U64(*v),
+    };
+    pb::Value { kind: Some(kind) }
+}
+
+/// sends selected entity fields and game events of each tick into the channel.
+pub(crate) struct StreamVisitor {
+    tx: mpsc::Sender<Result<pb::TickEvents, Status>>,
+    projections: Vec<Projection>,
+    event_filter: EventFilter,
+    pending_events: Vec<pb::GameEvent>,
+}
+
+impl StreamVisitor {
+    pub(crate) fn new(
+        tx: mpsc::Sender<Result<pb::TickEvents, Status>>,
+        projections: &[pb::Projection],
+        events: Vec<String>,
+    ) -> Self {
+        Self {
+            tx,
+            projections: projections
+                .iter()
+                .map(|projection| Projection {
+                    class_name: projection.class_name.clone(),
+                    class_hash: fxhash::hash_bytes(projection.class_name.as_bytes()),
+                    keys: projection
+                        .fields
+                        .iter()
+                        .map(|field| fkey_from_dotted_path(field))
+                        .collect(),
+                })
+                .collect(),
+            event_filter: EventFilter::new(events),
+            pending_events: Vec::new(),
+        }
+    }
+
+    fn handle_game_event(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let Some(game_event_list) = ctx.game_event_list() else {
+            return Ok(());
+        };
+        let msg = CMsgSource1LegacyGameEvent::decode_message(data)?;
+        let Some(event) = game_event_list.decode(&msg) else {
+            return Ok(());
+        };
+        if self.event_filter.wants(event.name()) {
+            self.pending_events.push(pb::GameEvent {
+                name: event.name().to_string(),
+                data: event
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), event_value_to_pb(value)))
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Visitor for StreamVisitor {
+    fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        if packet_type == EBaseGameEvents::GeSource1LegacyGameEvent as u32
+            && !matches!(self.event_filter, EventFilter::None)
+        {
+            self.handle_game_event(ctx, data)?;
+        }
+        Ok(())
+    }
+
+    fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+        let mut rows = Vec::new();
+        if let Some(entities) = ctx.entities() {
+            for projection in self.projections.iter() {
+                for (_, entity) in entities.iter() {
+                    if !entity.serializer_name_heq(projection.class_hash) {
+                        continue;
+                    }
+                    rows.push(pb::EntityRow {
+                        index: entity.index(),
+                        class_name: projection.class_name.clone(),
+                        values: projection
+                            .keys
+                            .iter()
+                            .map(|key| {
+                                entity
+                                    .get(key)
+                                    .map_or(pb::Value { kind: None }, field_value_to_pb)
+                            })
+                            .collect(),
+                    });
+                }
+            }
+        }
+
+        if rows.is_empty() && self.pending_events.is_empty() {
+            return Ok(());
+        }
+
+        let tick_events = pb::TickEvents {
+            tick: ctx.tick(),
+            entities: rows,
+            events: std::mem::take(&mut self.pending_events),
+        };
+        // NOTE: blocking_send will wait if client is slower then parser; error means that client is
+        // gone and there's no need to continue.
+        self.tx
+            .blocking_send(Ok(tick_events))
+            .map_err(|_| anyhow!("client disconnected"))
+    }
+}
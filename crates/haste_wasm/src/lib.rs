@@ -0,0 +1,149 @@
+//! wasm-bindgen wrapper around haste's parser. demo data is fed incrementally (for example with
+//! chunks of a `fetch()` response body) and is parsed as soon as complete cmds arrive.
+//!
+//! ```js
+//! const parser = new Parser();
+//! const reader = (await fetch(url)).body.getReader();
+//! for (;;) {
+//!   const { done, value } = await reader.read();
+//!   if (done) break;
+//!   parser.feed(value);
+//! }
+//! // NOTE: the last tick is not handled until parser knows that nothing else will arrive.
+//! parser.finish();
+//! ```
+
+use std::collections::HashMap;
+
+use haste_core::demobuffer::DemoBuffer;
+use haste_core::demostream::CmdHeader;
+use haste_core::entities::fkey_from_dotted_path;
+use haste_core::fieldvalue::FieldValue;
+use haste_core::fxhash;
+use haste_core::parser::{self, Context, Visitor};
+use haste_core::protobackend::ProtoMessage;
+use valveprotos::common::{CDemoClassInfo, EDemoCommands};
+use wasm_bindgen::prelude::*;
+
+#[derive(Default)]
+struct WasmVisitor {
+    // NOTE: haste_core is built without preserve-metadata here, so serializer names are not
+    // preserved; they match network names of entity classes which arrive in DemClassInfo cmd.
+    class_names: HashMap<u64, Box<str>>,
+}
+
+impl Visitor for WasmVisitor {
+    fn on_cmd(
+        &mut self,
+        _ctx: &Context,
+        cmd_header: &CmdHeader,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        if cmd_header.cmd == EDemoCommands::DemClassInfo {
+            let cmd = CDemoClassInfo::decode_message(data)?;
+            self.class_names = cmd
+                .classes
+                .iter()
+                .map(|class| {
+                    let name = class.network_name();
+                    (fxhash::hash_bytes(name.as_bytes()), name.into())
+                })
+                .collect();
+        }
+        Ok(())
+    }
+}
+
+fn js_error(err: impl ToString) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+fn field_value_to_js(value: &FieldValue) -> JsValue {
+    match value {
+        // NOTE: 64 bit integers become BigInts.
+        FieldValue::I64(v) => JsValue::from(*v),
+        FieldValue::U64(v) => JsValue::from(*v),
+        FieldValue::F32(v) => JsValue::from(*v),
+        FieldValue::Bool(v) => JsValue::from(*v),
+        FieldValue::Vector2(v) | FieldValue::QAnglePitchYaw(v) => {
+            js_sys::Float32Array::from(&v[..]).into()
+        }
+        FieldValue::Vector3(v) | FieldValue::QAngle(v) => js_sys::Float32Array::from(&v[..]).into(),
+        FieldValue::Vector4(v) => js_sys::Float32Array::from(&v[..]).into(),
+        FieldValue::String(v) => JsValue::from_str(v),
+    }
+}
+
+#[wasm_bindgen]
+pub struct Parser {
+    parser: parser::Parser<DemoBuffer, WasmVisitor>,
+}
+
+#[wasm_bindgen]
+impl Parser {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Parser, JsError> {
+        let parser =
+            parser::Parser::from_stream_with_visitor(DemoBuffer::new(), WasmVisitor::default())
+                .map_err(js_error)?;
+        Ok(Self { parser })
+    }
+
+    /// appends the chunk and parses all cmds that became complete.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), JsError> {
+        self.parser
+            .demo_stream_mut()
+            .feed(chunk)
+            .map_err(js_error)?;
+        self.parser.run_to_end().map_err(js_error)
+    }
+
+    /// marks that nothing will be fed anymore and parses what is left (the last tick stays
+    /// pending until then).
+    pub fn finish(&mut self) -> Result<(), JsError> {
+        self.parser.demo_stream_mut().finish();
+        self.parser.run_to_end().map_err(js_error)
+    }
+
+    /// seeks to the given tick; backwards too. only data that was fed so far is available.
+    #[wasm_bindgen(js_name = runToTick)]
+    pub fn run_to_tick(&mut self, tick: i32) -> Result<(), JsError> {
+        self.parser.run_to_tick(tick).map_err(js_error)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tick(&self) -> i32 {
+        self.parser.context().tick()
+    }
+
+    #[wasm_bindgen(js_name = entityIndices)]
+    pub fn entity_indices(&self) -> Vec<i32> {
+        self.parser
+            .context()
+            .entities()
+            .map(|entities| entities.iter().map(|(index, _)| *index).collect())
+            .unwrap_or_default()
+    }
+
+    #[wasm_bindgen(js_name = entityClassName)]
+    pub fn entity_class_name(&self, index: i32) -> Option<String> {
+        let entity = self.parser.context().entities()?.get(&index)?;
+        self.parser
+            .visitor()
+            .class_names
+            .get(&entity.serializer().serializer_name.hash)
+            .map(|name| name.to_string())
+    }
+
+    /// returns the value of entity's field by dot separated path (for example
+    /// `CBodyComponent.m_cellX`), or undefined if either entity or field does not exist.
+    #[wasm_bindgen(js_name = entityField)]
+    pub fn entity_field(&self, index: i32, path: &str) -> JsValue {
+        self.parser
+            .context()
+            .entities()
+            .and_then(|entities| entities.get(&index))
+            .and_then(|entity| entity.get(&fkey_from_dotted_path(path)))
+            .map_or(JsValue::UNDEFINED, field_value_to_js)
+    }
+}
@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::sync::broadcast;
+
+mod server;
+mod source;
+mod visitor;
+
+use visitor::{Projection, PublishVisitor};
+
+/// haste-ws - parses live broadcast (or a demo file that is being recorded) and pushes selected
+/// entity fields and game events of each tick to websocket subscribers. subscribers choose frame
+/// format with `?format=json` (default) or `?format=msgpack`.
+#[derive(argh::FromArgs)]
+struct Args {
+    /// address to listen on
+    #[argh(option, default = "\"127.0.0.1:8080\".parse().unwrap()")]
+    listen: SocketAddr,
+    /// broadcast url (for example http://dist1-ord1.steamcontent.com/tv/18895867)
+    #[argh(option)]
+    url: Option<String>,
+    /// demo file to tail
+    #[argh(option)]
+    file: Option<PathBuf>,
+    /// entity fields to publish, `<class name>:<dotted field path>,..`; can be repeated
+    #[argh(option)]
+    projection: Vec<Projection>,
+    /// names of game events to publish, "*" publishes all; can be repeated
+    #[argh(option)]
+    event: Vec<String>,
+    /// how often to check the tailed file for new data, in milliseconds
+    #[argh(option, default = "250")]
+    poll_interval: u64,
+    /// maximum number of ticks that are buffered per subscriber before it starts to miss them
+    #[argh(option, default = "1024")]
+    channel_capacity: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let args: Args = argh::from_env();
+
+    let (tx, _) = broadcast::channel(args.channel_capacity);
+    let visitor = PublishVisitor::new(tx.clone(), args.projection, args.event);
+
+    let source = async {
+        match (args.url, args.file) {
+            (Some(url), None) => source::run_broadcast(&url, visitor).await,
+            (None, Some(file)) => {
+                let poll_interval = Duration::from_millis(args.poll_interval);
+                // NOTE: parsing is cpu bound, it must not block async runtime's threads.
+                tokio::task::spawn_blocking(move || {
+                    source::tail_file(&file, poll_interval, visitor)
+                })
+                .await?
+            }
+            _ => bail!("either --url or --file must be specified"),
+        }
+    };
+
+    tokio::select! {
+        result = server::serve(args.listen, tx) => result,
+        result = source => {
+            log::info!("source ended");
+            result
+        }
+    }
+}
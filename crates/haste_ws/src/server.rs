@@ -0,0 +1,100 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::visitor::TickEvents;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Format {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Format {
+    /// `?format=json` (default) or `?format=msgpack`.
+    fn from_query(query: Option<&str>) -> Self {
+        let is_msgpack = query.is_some_and(|query| {
+            query
+                .split('&')
+                .any(|pair| pair == "format=msgpack" || pair == "format=messagepack")
+        });
+        if is_msgpack {
+            Self::MessagePack
+        } else {
+            Self::Json
+        }
+    }
+
+    fn encode(&self, tick_events: &TickEvents) -> Result<Message> {
+        Ok(match self {
+            Self::Json => Message::Text(serde_json::to_string(tick_events)?),
+            Self::MessagePack => Message::Binary(rmp_serde::to_vec_named(tick_events)?),
+        })
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    mut rx: broadcast::Receiver<Arc<TickEvents>>,
+) -> Result<()> {
+    let mut format = Format::default();
+    let ws = tokio_tungstenite::accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            format = Format::from_query(request.uri().query());
+            Ok(response)
+        },
+    )
+    .await?;
+    let (mut sink, mut stream) = ws.split();
+
+    loop {
+        tokio::select! {
+            tick_events = rx.recv() => match tick_events {
+                Ok(tick_events) => sink.send(format.encode(&tick_events)?).await?,
+                // NOTE: subscriber that can't keep up misses ticks instead of slowing down
+                // everybody else.
+                Err(RecvError::Lagged(n)) => log::warn!("subscriber lagged behind by {n} ticks"),
+                Err(RecvError::Closed) => {
+                    sink.send(Message::Close(None)).await?;
+                    return Ok(());
+                }
+            },
+            // NOTE: subscribers are not expected to send anything; reading is needed to notice
+            // disconnects (pings are answered by tungstenite).
+            msg = stream.next() => match msg {
+                None | Some(Ok(Message::Close(_))) => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+            },
+        }
+    }
+}
+
+/// accepts websocket subscribers; each gets every tick that is published after it connected.
+pub(crate) async fn serve(
+    listen: SocketAddr,
+    tx: broadcast::Sender<Arc<TickEvents>>,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    log::info!("listening on {}", listen);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let rx = tx.subscribe();
+        tokio::spawn(async move {
+            log::debug!("subscriber {} connected", addr);
+            match handle_connection(stream, rx).await {
+                Ok(()) => log::debug!("subscriber {} disconnected", addr),
+                Err(err) => log::debug!("subscriber {} disconnected: {}", addr, err),
+            }
+        });
+    }
+}
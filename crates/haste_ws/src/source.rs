@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use haste_broadcast::{BroadcastHttp, BroadcastHttpOptions, GapPolicy};
+use haste_core::demobuffer::DemoBuffer;
+use haste_core::parser::Parser;
+
+use crate::visitor::PublishVisitor;
+
+/// parses live broadcast until it ends.
+pub(crate) async fn run_broadcast(url: &str, visitor: PublishVisitor) -> Result<()> {
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()?;
+    // NOTE: dashboards want to stay live; missing fragments are skipped instead of stalling.
+    let options = BroadcastHttpOptions {
+        max_request_retries: 3,
+        gap_policy: GapPolicy::Skip,
+        ..Default::default()
+    };
+    let demo_stream =
+        BroadcastHttp::start_streaming_with_options(http_client, url, options).await?;
+    let mut parser = Parser::from_stream_with_visitor(demo_stream, visitor)?;
+
+    loop {
+        match parser.demo_stream_mut().next_packet().await {
+            // NOTE: run_to_end continues from where it stopped, see tools/broadcast.
+            Some(Ok(_)) => parser.run_to_end()?,
+            Some(Err(err)) => return Err(err.into()),
+            None => return Ok(()),
+        }
+    }
+}
+
+/// parses demo file that is being recorded (or a finished one) as it grows; returns once
+/// `CDemoStop` was parsed.
+///
+/// NOTE: blocks; must be run outside of async runtime's threads.
+pub(crate) fn tail_file(
+    path: &Path,
+    poll_interval: Duration,
+    visitor: PublishVisitor,
+) -> Result<()> {
+    let mut file = File::open(path)?;
+    let mut parser = Parser::from_stream_with_visitor(DemoBuffer::new(), visitor)?;
+
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut chunk)?;
+        if n == 0 {
+            if parser.visitor().did_stop() {
+                return Ok(());
+            }
+            std::thread::sleep(poll_interval);
+            continue;
+        }
+
+        parser.demo_stream_mut().feed(&chunk[..n])?;
+        // NOTE: demo buffer reports eof when there's no complete cmd; whatever is incomplete is
+        // parsed after more data arrives.
+        parser.run_to_end()?;
+    }
+}
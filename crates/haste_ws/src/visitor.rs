@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use haste_core::demostream::CmdHeader;
+use haste_core::entities::fkey_from_dotted_path;
+use haste_core::fieldvalue::FieldValue;
+use haste_core::fxhash;
+use haste_core::gameevents::EventValue;
+use haste_core::parser::{Context, Visitor};
+use haste_core::protobackend::ProtoMessage;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use valveprotos::common::{CMsgSource1LegacyGameEvent, EBaseGameEvents, EDemoCommands};
+
+// frames
+// ----
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum Value {
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    Bool(bool),
+    Vector(Vec<f32>),
+    String(String),
+}
+
+impl From<&FieldValue> for Value {
+    fn from(value: &FieldValue) -> Self {
+        match value {
+            FieldValue::I64(v) => Self::I64(*v),
+            FieldValue::U64(v) => Self::U64(*v),
+            FieldValue::F32(v) => Self::F32(*v),
+            FieldValue::Bool(v) => Self::Bool(*v),
+            FieldValue::Vector2(v) | FieldValue::QAnglePitchYaw(v) => Self::Vector(v.to_vec()),
+            FieldValue::Vector3(v) | FieldValue::QAngle(v) => Self::Vector(v.to_vec()),
+            FieldValue::Vector4(v) => Self::Vector(v.to_vec()),
+            FieldValue::String(v) => Self::String(v.to_string()),
+        }
+    }
+}
+
+impl From<&EventValue> for Value {
+    fn from(value: &EventValue) -> Self {
+        match value {
+            EventValue::String(v) => Self::String(v.to_string()),
+            EventValue::F32(v) => Self::F32(*v),
+            EventValue::I32(v) => Self::I64(*v as i64),
+            EventValue::Bool(v) => Self::Bool(*v),
+            EventValue::U64(v) => Self::U64(*v),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EntityRow {
+    pub(crate) index: i32,
+    pub(crate) class_name: String,
+    /// keyed by dotted field path; missing fields are omitted.
+    pub(crate) fields: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GameEvent {
+    pub(crate) name: String,
+    pub(crate) data: BTreeMap<String, Value>,
+}
+
+/// what subscribers receive, once per tick that has either entity rows or events.
+#[derive(Debug, Serialize)]
+pub(crate) struct TickEvents {
+    pub(crate) tick: i32,
+    pub(crate) entities: Vec<EntityRow>,
+    pub(crate) events: Vec<GameEvent>,
+}
+
+// selection
+// ----
+
+/// `<class name>:<dotted field path>,<dotted field path>,..`, for example
+/// `CDOTA_Unit_Hero_Axe:m_iHealth,CBodyComponent.m_cellX`.
+pub(crate) struct Projection {
+    class_name: String,
+    class_hash: u64,
+    fields: Vec<(String, u64)>,
+}
+
+impl std::str::FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((class_name, fields)) = s.split_once(':') else {
+            return Err(format!(
+                "invalid projection {s:?} (want <class name>:<fields>)"
+            ));
+        };
+        Ok(Self {
+            class_name: class_name.to_string(),
+            class_hash: fxhash::hash_bytes(class_name.as_bytes()),
+            fields: fields
+                .split(',')
+                .filter(|field| !field.is_empty())
+                .map(|field| (field.to_string(), fkey_from_dotted_path(field)))
+                .collect(),
+        })
+    }
+}
+
+enum EventFilter {
+    None,
+    All,
+    Names(Vec<String>),
+}
+
+impl EventFilter {
+    fn new(events: Vec<String>) -> Self {
+        if events.is_empty() {
+            Self::None
+        } else if events.iter().any(|event| event.eq("*")) {
+            Self::All
+        } else {
+            Self::Names(events)
+        }
+    }
+
+    #[inline]
+    fn wants(&self, name: &str) -> bool {
+        match self {
+            Self::None => false,
+            Self::All => true,
+            Self::Names(names) => names.iter().any(|n| n.eq(name)),
+        }
+    }
+}
+
+// visitor
+// ----
+
+/// publishes selected entity fields and game events of each tick to all subscribers.
+pub(crate) struct PublishVisitor {
+    tx: broadcast::Sender<Arc<TickEvents>>,
+    projections: Vec<Projection>,
+    event_filter: EventFilter,
+    pending_events: Vec<GameEvent>,
+    did_stop: bool,
+}
+
+impl PublishVisitor {
+    pub(crate) fn new(
+        tx: broadcast::Sender<Arc<TickEvents>>,
+        projections: Vec<Projection>,
+        events: Vec<String>,
+    ) -> Self {
+        Self {
+            tx,
+            projections,
+            event_filter: EventFilter::new(events),
+            pending_events: Vec::new(),
+            did_stop: false,
+        }
+    }
+
+    /// true once `CDemoStop` was seen.
+    pub(crate) fn did_stop(&self) -> bool {
+        self.did_stop
+    }
+
+    fn handle_game_event(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let Some(game_event_list) = ctx.game_event_list() else {
+            return Ok(());
+        };
+        let msg = CMsgSource1LegacyGameEvent::decode_message(data)?;
+        let Some(event) = game_event_list.decode(&msg) else {
+            return Ok(());
+        };
+        if self.event_filter.wants(event.name()) {
+            self.pending_events.push(GameEvent {
+                name: event.name().to_string(),
+                data: event
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), Value::from(value)))
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Visitor for PublishVisitor {
+    fn on_cmd(&mut self, _ctx: &Context, cmd_header: &CmdHeader, _data: &[u8]) -> Result<()> {
+        self.did_stop |= cmd_header.cmd == EDemoCommands::DemStop;
+        Ok(())
+    }
+
+    fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        if packet_type == EBaseGameEvents::GeSource1LegacyGameEvent as u32
+            && !matches!(self.event_filter, EventFilter::None)
+        {
+            self.handle_game_event(ctx, data)?;
+        }
+        Ok(())
+    }
+
+    fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+        let mut rows = Vec::new();
+        if let Some(entities) = ctx.entities() {
+            for projection in self.projections.iter() {
+                for (_, entity) in entities.iter() {
+                    if !entity.serializer_name_heq(projection.class_hash) {
+                        continue;
+                    }
+                    rows.push(EntityRow {
+                        index: entity.index(),
+                        class_name: projection.class_name.clone(),
+                        fields: projection
+                            .fields
+                            .iter()
+                            .filter_map(|(path, key)| {
+                                entity
+                                    .get(key)
+                                    .map(|value| (path.clone(), Value::from(value)))
+                            })
+                            .collect(),
+                    });
+                }
+            }
+        }
+
+        if rows.is_empty() && self.pending_events.is_empty() {
+            return Ok(());
+        }
+
+        let tick_events = TickEvents {
+            tick: ctx.tick(),
+            entities: rows,
+            events: std::mem::take(&mut self.pending_events),
+        };
+        // NOTE: error means that there are no subscribers at the moment; that is fine, live
+        // parsing goes on for those who will connect later.
+        let _ = self.tx.send(Arc::new(tick_events));
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::io::Cursor;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use haste::bitreader::BitReader;
+use haste::demofile::DemoFile;
+use haste::demostream::DemoStream;
+use haste::parser::{NopVisitor, Parser, ParserOptions};
+use haste::valveprotos::common::EDemoCommands;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BenchMode {
+    /// full parse; entities included.
+    Entities,
+    /// reads cmds and splits packets into messages; does not decode anything.
+    MessagesOnly,
+}
+
+impl FromStr for BenchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "entities" => Ok(Self::Entities),
+            "messages-only" => Ok(Self::MessagesOnly),
+            _ => Err(format!(
+                "invalid mode {s:?}; expected entities or messages-only"
+            )),
+        }
+    }
+}
+
+/// measure parsing performance
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "bench")]
+pub(crate) struct BenchCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// number of iterations (defaults to 5)
+    #[argh(option, default = "5")]
+    iterations: usize,
+    /// entities (full parse; default) or messages-only
+    #[argh(option, default = "BenchMode::Entities")]
+    mode: BenchMode,
+    /// parse in safe mode (checked reads, validation of data from the wire); compare with a
+    /// regular run to see the cost
+    #[argh(switch)]
+    safe_mode: bool,
+    /// back large parser buffers with huge pages (linux only; falls back silently)
+    #[argh(switch)]
+    hugepages: bool,
+}
+
+fn run_entities(data: &[u8], safe_mode: bool, hugepages: bool) -> Result<i32> {
+    let demo_file = DemoFile::start_reading(Cursor::new(data))?;
+    let mut parser = Parser::from_stream_with_visitor_and_options(
+        demo_file,
+        NopVisitor,
+        ParserOptions {
+            safe_mode,
+            hugepages,
+            ..Default::default()
+        },
+    )?;
+    parser.run_to_end()?;
+    Ok(parser.context().tick())
+}
+
+fn run_messages_only(data: &[u8]) -> Result<i32> {
+    let mut demo_file = DemoFile::start_reading(Cursor::new(data))?;
+    let mut last_tick = -1;
+    loop {
+        let cmd_header = match demo_file.read_cmd_header() {
+            Ok(cmd_header) => cmd_header,
+            Err(_) if demo_file.is_at_eof().unwrap_or_default() => break,
+            Err(err) => return Err(err.into()),
+        };
+        last_tick = last_tick.max(cmd_header.tick);
+
+        match cmd_header.cmd {
+            EDemoCommands::DemPacket | EDemoCommands::DemSignonPacket => {
+                let cmd_body = demo_file.read_cmd(&cmd_header)?;
+                let cmd = DemoFile::<Cursor<&[u8]>>::decode_cmd_packet(cmd_body)?;
+                let data = cmd.data.unwrap_or_default();
+                let mut br = BitReader::new(&data);
+                while br.num_bits_left() > 8 {
+                    let _command = br.read_ubitvar();
+                    let size = br.read_uvarint32() as usize;
+                    // NOTE: same check as in parser; size comes from the wire.
+                    if size.saturating_mul(8) > br.num_bits_left() {
+                        bail!(
+                            "message of {size} bytes does not fit into remaining {} bits of packet",
+                            br.num_bits_left()
+                        );
+                    }
+                    br.skip_bits(size * 8);
+                }
+                br.is_overflowed()?;
+            }
+            _ => demo_file.skip_cmd(&cmd_header)?,
+        }
+    }
+    Ok(last_tick)
+}
+
+impl BenchCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        // NOTE: read the whole thing into memory to keep io out of measurements.
+        let data = std::fs::read(&self.filepath)?;
+        let megabytes = data.len() as f64 / (1024.0 * 1024.0);
+
+        let mut durations: Vec<Duration> = Vec::with_capacity(self.iterations);
+        let mut ticks = 0;
+        for i in 0..self.iterations.max(1) {
+            let start = Instant::now();
+            ticks = match self.mode {
+                BenchMode::Entities => run_entities(&data, self.safe_mode, self.hugepages)?,
+                BenchMode::MessagesOnly => run_messages_only(&data)?,
+            };
+            let elapsed = start.elapsed();
+            eprintln!("iteration {}: {elapsed:?}", i + 1);
+            durations.push(elapsed);
+        }
+
+        let total: Duration = durations.iter().sum();
+        let mean = total.as_secs_f64() / durations.len() as f64;
+        let min = durations.iter().min().map_or(0.0, |d| d.as_secs_f64());
+        let max = durations.iter().max().map_or(0.0, |d| d.as_secs_f64());
+
+        println!("file:       {} ({megabytes:.2} MB)", self.filepath);
+        println!("mode:       {:?}", self.mode);
+        println!("safe mode:  {}", self.safe_mode);
+        println!("hugepages:  {}", self.hugepages);
+        println!("iterations: {}", durations.len());
+        println!(
+            "time:       mean {:.2} ms (min {:.2} ms; max {:.2} ms)",
+            mean * 1000.0,
+            min * 1000.0,
+            max * 1000.0
+        );
+        println!("throughput: {:.2} MB/s", megabytes / mean);
+        println!("ticks:      {ticks} ({:.0} ticks/s)", ticks as f64 / mean);
+
+        Ok(())
+    }
+}
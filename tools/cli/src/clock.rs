@@ -0,0 +1,120 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::str::FromStr;
+
+use anyhow::{Context as _, Result};
+use haste::demofile::DemoFile;
+use haste::entities::{DeltaHeader, Entity};
+use haste::gameclock::GameClock;
+use haste::parser::{Context, Parser, Visitor};
+
+/// either a tick or a game clock time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Position {
+    Tick(i32),
+    /// seconds relative to game start (can be negative).
+    Clock(f32),
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(':') {
+            return s
+                .parse::<i32>()
+                .map(Position::Tick)
+                .map_err(|err| format!("invalid tick {s:?}: {err}"));
+        }
+
+        let (negative, rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+
+        // [hh:]mm:ss
+        let mut seconds = 0.0;
+        for part in rest.split(':') {
+            let value = part
+                .parse::<f32>()
+                .map_err(|err| format!("invalid game clock time {s:?}: {err}"))?;
+            seconds = seconds * 60.0 + value;
+        }
+
+        Ok(Position::Clock(if negative { -seconds } else { seconds }))
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Position::Tick(tick) => write!(f, "tick {tick}"),
+            Position::Clock(seconds) => {
+                let sign = if *seconds < 0.0 { "-" } else { "" };
+                let seconds = seconds.abs() as u32;
+                write!(f, "{sign}{}:{:02}", seconds / 60, seconds % 60)
+            }
+        }
+    }
+}
+
+/// collects game time at the end of each tick; see [`GameClock`].
+#[derive(Default)]
+struct GameClockVisitor {
+    clock: GameClock,
+    /// (tick, game time)
+    samples: Vec<(i32, f32)>,
+}
+
+impl Visitor for GameClockVisitor {
+    fn on_packet(&mut self, _ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        self.clock.update_from_packet(packet_type, data)?;
+        Ok(())
+    }
+
+    fn on_entity(
+        &mut self,
+        _ctx: &Context,
+        _delta_header: DeltaHeader,
+        entity: &Entity,
+    ) -> Result<()> {
+        self.clock.update_from_entity(entity);
+        Ok(())
+    }
+
+    fn on_tick_end(&mut self, ctx: &Context) -> Result<()> {
+        self.clock.set_tick_interval(ctx.tick_interval());
+        if let Some(game_time) = self.clock.game_time() {
+            self.samples.push((ctx.tick(), game_time));
+        }
+        Ok(())
+    }
+}
+
+/// resolves positions into ticks. game clock times require a full pass over the demo.
+pub(crate) fn resolve_ticks(filepath: &str, positions: &[Position]) -> Result<Vec<i32>> {
+    let samples = if positions
+        .iter()
+        .any(|position| matches!(position, Position::Clock(_)))
+    {
+        let file = File::open(filepath)?;
+        let demo_file = DemoFile::start_reading(BufReader::new(file))?;
+        let mut parser = Parser::from_stream_with_visitor(demo_file, GameClockVisitor::default())?;
+        parser.run_to_end()?;
+        std::mem::take(&mut parser.visitor_mut().samples)
+    } else {
+        Vec::new()
+    };
+
+    positions
+        .iter()
+        .map(|position| match position {
+            Position::Tick(tick) => Ok(*tick),
+            Position::Clock(seconds) => samples
+                .iter()
+                .find_map(|(tick, game_time)| (game_time >= seconds).then_some(*tick))
+                .with_context(|| format!("could not resolve {position} into a tick")),
+        })
+        .collect()
+}
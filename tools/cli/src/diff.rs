@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use anyhow::{bail, Result};
+use haste::demofile::DemoFile;
+use haste::parser::{NopVisitor, Parser};
+use haste::replaydiff::{self, DiffOptions};
+
+/// compare entity state of two demos tick by tick
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "diff")]
+pub(crate) struct DiffCommand {
+    /// path to the first demo file
+    #[argh(positional)]
+    left: String,
+    /// path to the second demo file
+    #[argh(positional)]
+    right: String,
+    /// maximum absolute difference at which floats are considered equal (defaults to 0)
+    #[argh(option, default = "0.0")]
+    epsilon: f32,
+    /// stop after this many divergences (defaults to 1000)
+    #[argh(option, default = "1000")]
+    max_divergences: usize,
+}
+
+fn open_parser(filepath: &str) -> Result<Parser<DemoFile<BufReader<File>>, NopVisitor>> {
+    let file = File::open(filepath)?;
+    let demo_file = DemoFile::start_reading(BufReader::new(file))?;
+    Ok(Parser::from_stream(demo_file)?)
+}
+
+impl DiffCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let mut left = open_parser(&self.left)?;
+        let mut right = open_parser(&self.right)?;
+
+        let opts = DiffOptions {
+            f32_epsilon: self.epsilon,
+            max_divergences: self.max_divergences,
+        };
+        let report = replaydiff::diff_parsers(&mut left, &mut right, &opts)?;
+
+        let mut out = BufWriter::new(io::stdout().lock());
+        for divergence in report.divergences.iter() {
+            writeln!(out, "{divergence}")?;
+        }
+        out.flush()?;
+
+        eprintln!("compared {} ticks", report.ticks);
+        if !report.is_ok() {
+            bail!(
+                "found {}{} divergences",
+                report.divergences.len(),
+                if report.truncated { "+" } else { "" }
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use anyhow::Result;
+use haste::demofile::DemoFile;
+use haste::gameevents::{EventValue, GameEvent};
+use haste::parser::{Context, Parser, Visitor};
+use haste::protobackend::ProtoMessage;
+use haste::valveprotos::common::{CMsgSource1LegacyGameEvent, EBaseGameEvents};
+use haste::valveprotos::deadlock::{CCitadelUserMsgChatMsg, CitadelUserMessageIds};
+use haste::valveprotos::dota2::{
+    CMsgDotaCombatLogEntry, CdotaUserMsgChatMessage, EDotaUserMessages,
+};
+use serde_json::{json, Map, Value};
+
+const COMBAT_LOG_NAMES_TABLE_NAME: &str = "CombatLogNames";
+
+/// print game events, chat and combat log
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "events")]
+pub(crate) struct EventsCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// output newline-delimited json (one event per line)
+    #[argh(switch)]
+    json: bool,
+    /// comma separated list of events to print (for example `dota_combatlog,chat`); event names
+    /// are game event names, `chat` and `dota_combatlog`
+    #[argh(option)]
+    filter: Option<String>,
+}
+
+impl EventsCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let file = File::open(&self.filepath)?;
+        let buf_reader = BufReader::new(file);
+        let demo_file = DemoFile::start_reading(buf_reader)?;
+
+        let visitor = EventsVisitor {
+            out: BufWriter::new(io::stdout().lock()),
+            json: self.json,
+            filter: self
+                .filter
+                .map(|filter| filter.split(',').map(|s| s.trim().to_string()).collect()),
+        };
+        let mut parser = Parser::from_stream_with_visitor(demo_file, visitor)?;
+        parser.run_to_end()
+    }
+}
+
+struct EventsVisitor<W: Write> {
+    out: W,
+    json: bool,
+    filter: Option<Vec<String>>,
+}
+
+impl<W: Write> EventsVisitor<W> {
+    #[inline]
+    fn wants(&self, name: &str) -> bool {
+        self.filter
+            .as_ref()
+            .map_or(true, |filter| filter.iter().any(|f| f.eq(name)))
+    }
+
+    fn emit(&mut self, tick: i32, name: &str, data: Map<String, Value>) -> Result<()> {
+        if self.json {
+            let line = json!({ "tick": tick, "event": name, "data": data });
+            serde_json::to_writer(&mut self.out, &line)?;
+            writeln!(self.out)?;
+        } else {
+            write!(self.out, "[{tick}] {name}")?;
+            for (key, value) in data.iter() {
+                write!(self.out, " {key}={value}")?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    fn handle_game_event(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let Some(game_event_list) = ctx.game_event_list() else {
+            return Ok(());
+        };
+        let msg = CMsgSource1LegacyGameEvent::decode_message(data)?;
+        let Some(event) = game_event_list.decode(&msg) else {
+            return Ok(());
+        };
+        if self.wants(event.name()) {
+            self.emit(ctx.tick(), event.name(), game_event_to_json(&event))?;
+        }
+        Ok(())
+    }
+
+    fn handle_dota2_chat(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let msg = CdotaUserMsgChatMessage::decode_message(data)?;
+        let mut map = Map::new();
+        map.insert("source_player_id".into(), json!(msg.source_player_id()));
+        map.insert("channel_type".into(), json!(msg.channel_type()));
+        map.insert("text".into(), json!(msg.message_text()));
+        self.emit(ctx.tick(), "chat", map)
+    }
+
+    fn handle_deadlock_chat(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let msg = CCitadelUserMsgChatMsg::decode_message(data)?;
+        let mut map = Map::new();
+        map.insert("player_slot".into(), json!(msg.player_slot()));
+        map.insert("all_chat".into(), json!(msg.all_chat()));
+        map.insert("text".into(), json!(msg.text()));
+        self.emit(ctx.tick(), "chat", map)
+    }
+
+    fn handle_dota2_combat_log(&mut self, ctx: &Context, data: &[u8]) -> Result<()> {
+        let msg = CMsgDotaCombatLogEntry::decode_message(data)?;
+
+        // names are indices into CombatLogNames string table.
+        let name = |index: u32| -> Value {
+            ctx.string_tables()
+                .and_then(|string_tables| string_tables.find_table(COMBAT_LOG_NAMES_TABLE_NAME))
+                .and_then(|string_table| string_table.get_item(&(index as i32)))
+                .and_then(|item| item.string.as_ref())
+                .map_or(Value::Null, |string| {
+                    Value::String(String::from_utf8_lossy(string).into_owned())
+                })
+        };
+
+        let mut map = Map::new();
+        map.insert("type".into(), json!(msg.r#type().as_str_name()));
+        map.insert("timestamp".into(), json!(msg.timestamp()));
+        map.insert("attacker".into(), name(msg.attacker_name()));
+        map.insert("target".into(), name(msg.target_name()));
+        map.insert("target_source".into(), name(msg.target_source_name()));
+        map.insert("inflictor".into(), name(msg.inflictor_name()));
+        map.insert("damage_source".into(), name(msg.damage_source_name()));
+        map.insert("is_attacker_hero".into(), json!(msg.is_attacker_hero()));
+        map.insert(
+            "is_attacker_illusion".into(),
+            json!(msg.is_attacker_illusion()),
+        );
+        map.insert("is_target_hero".into(), json!(msg.is_target_hero()));
+        map.insert("is_target_illusion".into(), json!(msg.is_target_illusion()));
+        map.insert("value".into(), json!(msg.value()));
+        map.insert("health".into(), json!(msg.health()));
+        self.emit(ctx.tick(), "dota_combatlog", map)
+    }
+}
+
+impl<W: Write> Visitor for EventsVisitor<W> {
+    fn on_packet(&mut self, ctx: &Context, packet_type: u32, data: &[u8]) -> Result<()> {
+        match packet_type {
+            t if t == EBaseGameEvents::GeSource1LegacyGameEvent as u32 => {
+                self.handle_game_event(ctx, data)
+            }
+            t if t == EDotaUserMessages::DotaUmChatMessage as u32 && self.wants("chat") => {
+                self.handle_dota2_chat(ctx, data)
+            }
+            t if t == CitadelUserMessageIds::KEUserMsgChatMsg as u32 && self.wants("chat") => {
+                self.handle_deadlock_chat(ctx, data)
+            }
+            t if t == EDotaUserMessages::DotaUmCombatLogDataHltv as u32
+                && self.wants("dota_combatlog") =>
+            {
+                self.handle_dota2_combat_log(ctx, data)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_tick_end(&mut self, _ctx: &Context) -> Result<()> {
+        // NOTE: flush once per tick so that output can be consumed while the demo is being
+        // parsed (for example when piping into jq).
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn game_event_to_json(event: &GameEvent) -> Map<String, Value> {
+    event
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                EventValue::String(v) => json!(v),
+                EventValue::F32(v) => json!(v),
+                EventValue::I32(v) => json!(v),
+                EventValue::Bool(v) => json!(v),
+                EventValue::U64(v) => json!(v),
+            };
+            (key.to_string(), value)
+        })
+        .collect()
+}
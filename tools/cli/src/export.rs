@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use haste::demofile::DemoFile;
+use haste::parser::Parser;
+use haste::sink::SinkVisitor;
+use haste_arrow::{GameEventRecorder, ProjectionRecorder};
+
+pub(crate) enum ExportFormat {
+    /// arrow ipc file (feather v2)
+    File,
+    /// arrow ipc stream
+    Stream,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(Self::File),
+            "stream" => Ok(Self::Stream),
+            _ => Err(format!("unknown format {s:?} (want file or stream)")),
+        }
+    }
+}
+
+/// export projected entity fields or game events as arrow ipc
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "export")]
+pub(crate) struct ExportCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// path to the output file
+    #[argh(positional)]
+    output: String,
+    /// entity class to project (for example `CCitadelPlayerPawn`)
+    #[argh(option)]
+    class: Option<String>,
+    /// comma separated list of dot separated field paths (for example
+    /// `CBodyComponent.m_cellX,m_iHealth`)
+    #[argh(option)]
+    fields: Option<String>,
+    /// export game events instead of entity fields
+    #[argh(switch)]
+    events: bool,
+    /// output format: file (default; feather v2) or stream
+    #[argh(option, default = "ExportFormat::File")]
+    format: ExportFormat,
+}
+
+impl ExportCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let file = File::open(&self.filepath)?;
+        let demo_file = DemoFile::start_reading(BufReader::new(file))?;
+
+        let batch = if self.events {
+            let visitor = SinkVisitor::new(GameEventRecorder::new());
+            let mut parser = Parser::from_stream_with_visitor(demo_file, visitor)?;
+            parser.run_to_end()?;
+            parser.into_visitor().finish()?.finish()?
+        } else {
+            let (Some(class), Some(fields)) = (self.class.as_ref(), self.fields.as_ref()) else {
+                bail!("either --events, or --class and --fields must be provided");
+            };
+            let visitor = ProjectionRecorder::new(class, fields.split(',').map(str::trim));
+            let mut parser = Parser::from_stream_with_visitor(demo_file, visitor)?;
+            parser.run_to_end()?;
+            parser.into_visitor().finish()?
+        };
+
+        let output = BufWriter::new(File::create(&self.output)?);
+        match self.format {
+            ExportFormat::File => haste_arrow::write_ipc_file(output, &batch)?,
+            ExportFormat::Stream => haste_arrow::write_ipc_stream(output, &batch)?,
+        }
+
+        eprintln!("wrote {} rows into {}", batch.num_rows(), self.output);
+        Ok(())
+    }
+}
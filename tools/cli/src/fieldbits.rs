@@ -0,0 +1,98 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use anyhow::Result;
+use haste::demofile::DemoFile;
+use haste::parser::{NopVisitor, Parser, ParserOptions};
+
+/// print fields that consume the most bits over the whole demo
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "fieldbits")]
+pub(crate) struct FieldBitsCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// number of fields to print (defaults to 50)
+    #[argh(option, default = "50")]
+    top: usize,
+    /// only print fields of the class (serializer name, for example `CCitadelPlayerPawn`)
+    #[argh(option)]
+    class: Option<String>,
+}
+
+impl FieldBitsCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let file = File::open(&self.filepath)?;
+        let demo_file = DemoFile::start_reading(BufReader::new(file))?;
+        let mut parser = Parser::from_stream_with_visitor_and_options(
+            demo_file,
+            NopVisitor,
+            ParserOptions {
+                measure_field_bits: true,
+                ..Default::default()
+            },
+        )?;
+        parser.run_to_end()?;
+
+        let (Some(field_bits), Some(serializers)) =
+            (parser.field_bits(), parser.context().serializers())
+        else {
+            return Ok(());
+        };
+
+        let total_bits = field_bits.field_bits() + field_bits.field_path_bits();
+        let percent = |bits: u64| {
+            if total_bits == 0 {
+                0.0
+            } else {
+                bits as f64 * 100.0 / total_bits as f64
+            }
+        };
+
+        let mut out = BufWriter::new(io::stdout().lock());
+        writeln!(
+            out,
+            "{:>12} {:>6} {:>10} {:>8}  field",
+            "bits", "%", "decodes", "bits/dec"
+        )?;
+        let rows = field_bits
+            .sorted_by_bits()
+            .into_iter()
+            .filter_map(|stats| {
+                let serializer = serializers.by_name_hash(stats.serializer_name_hash)?;
+                let class_name = serializer.serializer_name.as_str();
+                if self.class.as_ref().is_some_and(|class| class != class_name) {
+                    return None;
+                }
+                Some((
+                    stats,
+                    format!("{class_name}.{}", serializer.dotted_path(&stats.path)),
+                ))
+            })
+            .take(self.top);
+        for (stats, name) in rows {
+            writeln!(
+                out,
+                "{:>12} {:>6.2} {:>10} {:>8.2}  {name}",
+                stats.bits,
+                percent(stats.bits),
+                stats.count,
+                stats.bits_per_decode(),
+            )?;
+        }
+        writeln!(
+            out,
+            "{:>12} {:>6.2} {:>10} {:>8}  (field paths)",
+            field_bits.field_path_bits(),
+            percent(field_bits.field_path_bits()),
+            "",
+            "",
+        )?;
+        writeln!(
+            out,
+            "{total_bits:>12} {:>6.2} {:>10} {:>8}  (total)",
+            100.0, "", ""
+        )?;
+        Ok(())
+    }
+}
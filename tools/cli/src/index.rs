@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use anyhow::Result;
+use haste::demofile::DemoFile;
+use haste::demoindex::DemoIndex;
+
+/// build the sidecar tick index (or print it)
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "index")]
+pub(crate) struct IndexCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// print full packet ticks and byte offsets instead of only writing the index; the index is
+    /// built if it does not exist or is stale
+    #[argh(switch)]
+    show: bool,
+}
+
+impl IndexCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let file = File::open(&self.filepath)?;
+        let mut demo_file = DemoFile::start_reading(BufReader::new(file))?;
+
+        let sidecar_path = DemoIndex::sidecar_path(&self.filepath);
+        let existing = match File::open(&sidecar_path) {
+            Ok(file) => DemoIndex::read_from(BufReader::new(file)).ok(),
+            Err(_) => None,
+        };
+
+        let demo_index = match existing {
+            Some(demo_index) if self.show && demo_index.is_fresh(&mut demo_file)? => demo_index,
+            _ => {
+                let demo_index = DemoIndex::build(&mut demo_file)?;
+                demo_index.write_to(BufWriter::new(File::create(&sidecar_path)?))?;
+                eprintln!("wrote {}", sidecar_path.display());
+                demo_index
+            }
+        };
+
+        if self.show {
+            let mut out = BufWriter::new(io::stdout().lock());
+            writeln!(out, "total ticks: {}", demo_index.total_ticks())?;
+            writeln!(out, "{:>10} {:>12}", "tick", "offset")?;
+            for entry in demo_index.entries() {
+                writeln!(out, "{:>10} {:>12}", entry.tick, entry.position)?;
+            }
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufReader;
+
+use anyhow::Result;
+use haste::demofile::DemoFile;
+use haste::demoindex::DemoIndex;
+use haste::entities::Entity;
+use haste::fieldpath::FieldPath;
+use haste::flattenedserializers::FlattenedSerializerField;
+use haste::parser::{NopVisitor, Parser};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+// NOTE: forward seeks shorter than this are made by parsing ticks in between; longer ones (and
+// all backward seeks) go through run_to_tick which restores state from the closest full packet.
+const MAX_FORWARD_RUN_TICKS: i32 = 1800;
+const BIG_STEP_TICKS: i32 = 1000;
+const PAGE_SIZE: isize = 20;
+
+const HELP: &str = "←/→ tick  H/L ±1000 ticks  g go to tick  tab switch pane  ↑/↓ select  q quit";
+
+type DemoParser = Parser<DemoFile<BufReader<File>>, NopVisitor>;
+
+/// browse entities by class and inspect their fields tick by tick
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "inspect")]
+pub(crate) struct InspectCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// tick to start at (defaults to 0)
+    #[argh(option, default = "0")]
+    tick: i32,
+}
+
+/// dotted name of the field (for example `m_vecPlayerData.3.m_iszPlayerName`); components that
+/// can't be resolved are printed as indices.
+fn field_name(entity: &Entity, path: &FieldPath) -> String {
+    let mut name = String::new();
+    let mut parent: Option<&FlattenedSerializerField> = None;
+    for (depth, i) in path.iter().enumerate() {
+        let i = *i as usize;
+        let field = match parent {
+            None if depth == 0 => entity.serializer().get_child(i),
+            None => None,
+            Some(parent) => parent.get_child(i),
+        };
+
+        if depth > 0 {
+            name.push('.');
+        }
+        let is_element = parent.is_some_and(|parent| {
+            parent.is_dynamic_array() || parent.fixed_array_length().is_some()
+        });
+        match field {
+            Some(field) if !is_element => name.push_str(field.var_name.as_str()),
+            _ => {
+                let _ = write!(name, "{i}");
+            }
+        }
+
+        parent = field;
+    }
+    name
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Classes,
+    Entities,
+    Fields,
+}
+
+struct ClassRow {
+    name: String,
+    serializer_name_hash: u64,
+    indices: Vec<i32>,
+}
+
+struct App {
+    parser: DemoParser,
+    filepath: String,
+    total_ticks: i32,
+    focus: Pane,
+    classes: Vec<ClassRow>,
+    classes_state: ListState,
+    entities_state: ListState,
+    fields: Vec<(String, String)>,
+    fields_state: ListState,
+    goto: Option<String>,
+    status: Option<String>,
+}
+
+impl App {
+    fn new(parser: DemoParser, filepath: String, total_ticks: i32) -> Self {
+        Self {
+            parser,
+            filepath,
+            total_ticks,
+            focus: Pane::Classes,
+            classes: Vec::new(),
+            classes_state: ListState::default(),
+            entities_state: ListState::default(),
+            fields: Vec::new(),
+            fields_state: ListState::default(),
+            goto: None,
+            status: None,
+        }
+    }
+
+    fn selected_class(&self) -> Option<&ClassRow> {
+        self.classes_state
+            .selected()
+            .and_then(|pos| self.classes.get(pos))
+    }
+
+    fn selected_entity_index(&self) -> Option<i32> {
+        let class = self.selected_class()?;
+        self.entities_state
+            .selected()
+            .and_then(|pos| class.indices.get(pos).copied())
+    }
+
+    // state
+    // ----
+
+    fn seek(&mut self, target_tick: i32) {
+        let target_tick = target_tick.clamp(-1, self.total_ticks);
+        let tick = self.parser.context().tick();
+        let result = if target_tick > tick && target_tick - tick <= MAX_FORWARD_RUN_TICKS {
+            self.parser
+                .run_until(|ctx| ctx.tick() >= target_tick)
+                .map(|_| ())
+        } else {
+            self.parser.run_to_tick(target_tick)
+        };
+        self.status = result.err().map(|err| err.to_string());
+        self.refresh();
+    }
+
+    /// rebuilds lists from the current state; selection sticks to the same class and entity as
+    /// long as they exist.
+    fn refresh(&mut self) {
+        let selected_class = self
+            .selected_class()
+            .map(|class| class.serializer_name_hash);
+        let selected_entity_index = self.selected_entity_index();
+
+        let mut classes: HashMap<u64, ClassRow> = HashMap::new();
+        if let Some(entities) = self.parser.context().entities() {
+            for (index, entity) in entities.iter() {
+                let serializer_name = &entity.serializer().serializer_name;
+                classes
+                    .entry(serializer_name.hash)
+                    .or_insert_with(|| ClassRow {
+                        name: serializer_name.as_str().to_string(),
+                        serializer_name_hash: serializer_name.hash,
+                        indices: Vec::new(),
+                    })
+                    .indices
+                    .push(*index);
+            }
+        }
+        let mut classes: Vec<ClassRow> = classes.into_values().collect();
+        classes.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        for class in classes.iter_mut() {
+            class.indices.sort_unstable();
+        }
+        self.classes = classes;
+
+        let class_pos = selected_class
+            .and_then(|hash| {
+                self.classes
+                    .iter()
+                    .position(|class| class.serializer_name_hash == hash)
+            })
+            .or((!self.classes.is_empty()).then_some(0));
+        self.classes_state.select(class_pos);
+
+        let entity_pos = self.selected_class().and_then(|class| {
+            selected_entity_index
+                .and_then(|index| class.indices.iter().position(|i| *i == index))
+                .or((!class.indices.is_empty()).then_some(0))
+        });
+        self.entities_state.select(entity_pos);
+
+        self.refresh_fields();
+    }
+
+    fn refresh_fields(&mut self) {
+        let entity = self.selected_entity_index().and_then(|index| {
+            self.parser
+                .context()
+                .entities()
+                .and_then(|entities| entities.get(&index))
+        });
+
+        let mut fields: Vec<(String, String)> = entity
+            .map(|entity| {
+                entity
+                    .iter()
+                    .map(|(key, value)| {
+                        let name = entity.get_path(key).map_or_else(
+                            || format!("{key:#018x}"),
+                            |path| field_name(entity, path),
+                        );
+                        (name, value.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        fields.sort_unstable();
+        self.fields = fields;
+
+        let field_pos = match self.fields_state.selected() {
+            _ if self.fields.is_empty() => None,
+            Some(pos) => Some(pos.min(self.fields.len() - 1)),
+            None => Some(0),
+        };
+        self.fields_state.select(field_pos);
+    }
+
+    // input
+    // ----
+
+    fn move_selection(&mut self, delta: isize) {
+        let (state, len) = match self.focus {
+            Pane::Classes => (&mut self.classes_state, self.classes.len()),
+            Pane::Entities => (
+                &mut self.entities_state,
+                self.classes_state
+                    .selected()
+                    .and_then(|pos| self.classes.get(pos))
+                    .map_or(0, |class| class.indices.len()),
+            ),
+            Pane::Fields => (&mut self.fields_state, self.fields.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let pos = state.selected().unwrap_or(0) as isize + delta;
+        state.select(Some(pos.clamp(0, len as isize - 1) as usize));
+
+        match self.focus {
+            Pane::Classes => {
+                self.entities_state.select(Some(0));
+                self.fields_state.select(Some(0));
+                self.refresh_fields();
+            }
+            Pane::Entities => {
+                self.fields_state.select(Some(0));
+                self.refresh_fields();
+            }
+            Pane::Fields => {}
+        }
+    }
+
+    /// returns true if app should quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if let Some(goto) = self.goto.as_mut() {
+            match code {
+                KeyCode::Char(c) if c.is_ascii_digit() || c == '-' => goto.push(c),
+                KeyCode::Backspace => {
+                    goto.pop();
+                }
+                KeyCode::Enter => {
+                    if let Some(Ok(target_tick)) = self.goto.take().map(|goto| goto.parse()) {
+                        self.seek(target_tick);
+                    }
+                }
+                KeyCode::Esc => self.goto = None,
+                _ => {}
+            }
+            return false;
+        }
+
+        let tick = self.parser.context().tick();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Left | KeyCode::Char('h') => self.seek(tick - 1),
+            KeyCode::Right | KeyCode::Char('l') => self.seek(tick + 1),
+            KeyCode::Char('H') => self.seek(tick - BIG_STEP_TICKS),
+            KeyCode::Char('L') => self.seek(tick + BIG_STEP_TICKS),
+            KeyCode::Char('g') => self.goto = Some(String::new()),
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Pane::Classes => Pane::Entities,
+                    Pane::Entities => Pane::Fields,
+                    Pane::Fields => Pane::Classes,
+                }
+            }
+            KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Pane::Classes => Pane::Fields,
+                    Pane::Entities => Pane::Classes,
+                    Pane::Fields => Pane::Entities,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-PAGE_SIZE),
+            KeyCode::PageDown => self.move_selection(PAGE_SIZE),
+            _ => {}
+        }
+        false
+    }
+
+    // rendering
+    // ----
+
+    fn pane_block(&self, pane: Pane, title: String) -> Block<'static> {
+        let block = Block::bordered().title(title);
+        if self.focus == pane {
+            block.border_style(Style::new().add_modifier(Modifier::BOLD))
+        } else {
+            block.border_style(Style::new().add_modifier(Modifier::DIM))
+        }
+    }
+
+    fn draw_lists(&mut self, frame: &mut Frame, area: Rect) {
+        let [classes_area, entities_area, fields_area] = Layout::horizontal([
+            Constraint::Percentage(30),
+            Constraint::Length(12),
+            Constraint::Min(0),
+        ])
+        .areas(area);
+        let highlight_style = Style::new().add_modifier(Modifier::REVERSED);
+
+        let classes = List::new(
+            self.classes
+                .iter()
+                .map(|class| format!("{} ({})", class.name, class.indices.len())),
+        )
+        .block(self.pane_block(Pane::Classes, format!("classes ({})", self.classes.len())))
+        .highlight_style(highlight_style);
+        frame.render_stateful_widget(classes, classes_area, &mut self.classes_state);
+
+        let indices = self
+            .selected_class()
+            .map(|class| class.indices.clone())
+            .unwrap_or_default();
+        let entities = List::new(indices.iter().map(|index| format!("#{index}")))
+            .block(self.pane_block(Pane::Entities, "entities".to_string()))
+            .highlight_style(highlight_style);
+        frame.render_stateful_widget(entities, entities_area, &mut self.entities_state);
+
+        let fields = List::new(
+            self.fields
+                .iter()
+                .map(|(name, value)| format!("{name} = {value}")),
+        )
+        .block(self.pane_block(Pane::Fields, format!("fields ({})", self.fields.len())))
+        .highlight_style(highlight_style);
+        frame.render_stateful_widget(fields, fields_area, &mut self.fields_state);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header_area, body_area, footer_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let header = format!(
+            "{}  tick {} / {}",
+            self.filepath,
+            self.parser.context().tick(),
+            self.total_ticks
+        );
+        frame.render_widget(Paragraph::new(header), header_area);
+
+        self.draw_lists(frame, body_area);
+
+        let footer = match (&self.goto, &self.status) {
+            (Some(goto), _) => format!("go to tick: {goto}"),
+            (None, Some(status)) => format!("error: {status}"),
+            (None, None) => HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(footer), footer_area);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && self.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// uses sidecar index if it's fresh, builds one in memory otherwise (see index subcommand).
+fn load_demo_index(filepath: &str, demo_file: &mut DemoFile<BufReader<File>>) -> Result<DemoIndex> {
+    if let Ok(file) = File::open(DemoIndex::sidecar_path(filepath)) {
+        if let Ok(demo_index) = DemoIndex::read_from(BufReader::new(file)) {
+            if demo_index.is_fresh(demo_file)? {
+                return Ok(demo_index);
+            }
+        }
+    }
+    Ok(DemoIndex::build(demo_file)?)
+}
+
+impl InspectCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let file = File::open(&self.filepath)?;
+        let mut demo_file = DemoFile::start_reading(BufReader::new(file))?;
+        let demo_index = load_demo_index(&self.filepath, &mut demo_file)?;
+
+        let parser = Parser::from_stream(demo_file)?;
+        let mut app = App::new(parser, self.filepath, demo_index.total_ticks());
+        app.seek(self.tick);
+
+        let mut terminal = ratatui::init();
+        let result = app.run(&mut terminal);
+        ratatui::restore();
+        result
+    }
+}
@@ -0,0 +1,66 @@
+mod bench;
+mod clock;
+mod diff;
+mod events;
+mod export;
+#[cfg(feature = "fieldbits")]
+mod fieldbits;
+mod index;
+#[cfg(feature = "inspect")]
+mod inspect;
+#[cfg(feature = "schema")]
+mod schema;
+mod trim;
+mod verify;
+
+use anyhow::Result;
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand)]
+enum SubCommands {
+    Events(events::EventsCommand),
+    Trim(trim::TrimCommand),
+    Index(index::IndexCommand),
+    Verify(verify::VerifyCommand),
+    Bench(bench::BenchCommand),
+    Export(export::ExportCommand),
+    Diff(diff::DiffCommand),
+    #[cfg(feature = "fieldbits")]
+    FieldBits(fieldbits::FieldBitsCommand),
+    #[cfg(feature = "schema")]
+    Schema(schema::SchemaCommand),
+    #[cfg(feature = "inspect")]
+    Inspect(inspect::InspectCommand),
+}
+
+impl SubCommands {
+    fn execute(self) -> Result<()> {
+        match self {
+            SubCommands::Events(events) => events.execute(),
+            SubCommands::Trim(trim) => trim.execute(),
+            SubCommands::Index(index) => index.execute(),
+            SubCommands::Verify(verify) => verify.execute(),
+            SubCommands::Bench(bench) => bench.execute(),
+            SubCommands::Export(export) => export.execute(),
+            SubCommands::Diff(diff) => diff.execute(),
+            #[cfg(feature = "fieldbits")]
+            SubCommands::FieldBits(fieldbits) => fieldbits.execute(),
+            #[cfg(feature = "schema")]
+            SubCommands::Schema(schema) => schema.execute(),
+            #[cfg(feature = "inspect")]
+            SubCommands::Inspect(inspect) => inspect.execute(),
+        }
+    }
+}
+
+/// haste - dota 2 and deadlock replay tools
+#[derive(argh::FromArgs)]
+struct Args {
+    #[argh(subcommand)]
+    sub_command: SubCommands,
+}
+
+fn main() -> Result<()> {
+    let args = argh::from_env::<Args>();
+    args.sub_command.execute()
+}
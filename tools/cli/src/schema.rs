@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use anyhow::{bail, Result};
+use haste::demofile::DemoFile;
+use haste::schema::{self, Schema};
+
+/// dump serializer schema of a demo, or diff schemas of two demos (or dumps)
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "schema")]
+pub(crate) struct SchemaCommand {
+    #[argh(subcommand)]
+    sub_command: SchemaSubCommands,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand)]
+enum SchemaSubCommands {
+    Dump(DumpCommand),
+    Diff(DiffCommand),
+}
+
+/// write schema of a demo
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "dump")]
+struct DumpCommand {
+    /// path to the demo file
+    #[argh(positional)]
+    filepath: String,
+    /// path to the output file (defaults to stdout)
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+}
+
+/// print added, removed and retyped fields per serializer
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "diff")]
+struct DiffCommand {
+    /// path to the old demo or schema dump
+    #[argh(positional)]
+    old: String,
+    /// path to the new demo or schema dump
+    #[argh(positional)]
+    new: String,
+    /// exit with an error if schemas differ
+    #[argh(switch)]
+    check: bool,
+}
+
+/// files that start with schema header are read as dumps, everything else as demos.
+fn load_schema(filepath: &str) -> Result<Schema> {
+    let mut rdr = BufReader::new(File::open(filepath)?);
+    if rdr.fill_buf()?.starts_with(b"# haste schema") {
+        return Ok(Schema::read_from(rdr)?);
+    }
+    Schema::from_demo(DemoFile::start_reading(rdr)?)
+}
+
+impl SchemaCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        match self.sub_command {
+            SchemaSubCommands::Dump(dump) => {
+                let schema = load_schema(&dump.filepath)?;
+                match dump.output {
+                    Some(output) => schema.write_to(BufWriter::new(File::create(output)?))?,
+                    None => schema.write_to(BufWriter::new(io::stdout().lock()))?,
+                }
+                Ok(())
+            }
+            SchemaSubCommands::Diff(diff) => {
+                let old = load_schema(&diff.old)?;
+                let new = load_schema(&diff.new)?;
+                let changes = schema::diff_schemas(&old, &new);
+
+                let mut out = BufWriter::new(io::stdout().lock());
+                for change in changes.iter() {
+                    writeln!(out, "{change}")?;
+                }
+                out.flush()?;
+
+                if diff.check && !changes.is_empty() {
+                    bail!("found {} changes", changes.len());
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use anyhow::Result;
+use haste::demofile::DemoFile;
+use haste::demowriter;
+
+use crate::clock::{self, Position};
+
+/// write a demo that covers only the given range
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "trim")]
+pub(crate) struct TrimCommand {
+    /// path to the input demo file
+    #[argh(positional)]
+    input: String,
+    /// path to the output demo file
+    #[argh(positional)]
+    output: String,
+    /// start of the range; either a tick (`36000`) or game clock time (`20:00`)
+    #[argh(option)]
+    from: Position,
+    /// end of the range (inclusive); either a tick or game clock time
+    #[argh(option)]
+    to: Position,
+}
+
+impl TrimCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let ticks = clock::resolve_ticks(&self.input, &[self.from, self.to])?;
+        let (from_tick, to_tick) = (ticks[0], ticks[1]);
+
+        let file = File::open(&self.input)?;
+        let mut demo_file = DemoFile::start_reading(BufReader::new(file))?;
+        let output = BufWriter::new(File::create(&self.output)?);
+        demowriter::trim(&mut demo_file, output, from_tick, to_tick)?;
+
+        eprintln!(
+            "wrote {} ({} - {}; ticks {from_tick} - {to_tick})",
+            self.output, self.from, self.to
+        );
+        Ok(())
+    }
+}
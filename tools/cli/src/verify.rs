@@ -0,0 +1,99 @@
+use std::any::Any;
+use std::fs::File;
+use std::io::BufReader;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use anyhow::{anyhow, bail, Result};
+use haste::demofile::DemoFile;
+use haste::demoverify::{self, VerifyReport};
+
+/// check integrity of demo files
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "verify")]
+pub(crate) struct VerifyCommand {
+    /// paths to demo files
+    #[argh(positional)]
+    filepaths: Vec<String>,
+    /// number of files to verify in parallel (defaults to number of available cpus)
+    #[argh(option)]
+    jobs: Option<usize>,
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+fn verify_file(filepath: &str) -> Result<VerifyReport> {
+    let file = File::open(filepath)?;
+    let mut demo_file = DemoFile::start_reading(BufReader::new(file))?;
+    Ok(demoverify::verify(&mut demo_file)?)
+}
+
+impl VerifyCommand {
+    pub(crate) fn execute(self) -> Result<()> {
+        let jobs = self
+            .jobs
+            .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1)
+            .clamp(1, self.filepaths.len().max(1));
+
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<(usize, Result<VerifyReport>)>> =
+            Mutex::new(Vec::with_capacity(self.filepaths.len()));
+
+        thread::scope(|s| {
+            for _ in 0..jobs {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(filepath) = self.filepaths.get(i) else {
+                        break;
+                    };
+                    // NOTE: a corrupt demo may make the parser panic; it is reported as an error
+                    // of that file, others are still verified.
+                    let result =
+                        panic::catch_unwind(|| verify_file(filepath)).unwrap_or_else(|payload| {
+                            Err(anyhow!("panicked: {}", panic_message(&*payload)))
+                        });
+                    results
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push((i, result));
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+        results.sort_by_key(|(i, _)| *i);
+        let mut failed = 0;
+        for (i, result) in results {
+            let filepath = &self.filepaths[i];
+            match result {
+                Ok(report) => {
+                    if !report.is_ok() {
+                        failed += 1;
+                    }
+                    println!("{filepath}: {report}");
+                }
+                Err(err) => {
+                    failed += 1;
+                    println!("{filepath}: error: {err}");
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!(
+                "{failed} of {} files did not pass verification",
+                self.filepaths.len()
+            );
+        }
+        Ok(())
+    }
+}
@@ -3,12 +3,12 @@ use std::fs::File;
 use std::io::BufReader;
 
 use anyhow::{Context as _, Result};
-use dungers::varint;
 use haste::demofile::DemoFile;
 use haste::demostream::DemoStream;
+use haste::protobackend::ProtoMessage;
 use haste::valveprotos::common::{CDemoSendTables, CsvcMsgFlattenedSerializer, EDemoCommands};
+use haste::varint;
 use haste_vartype::{TokenKind, Tokenizer};
-use prost::Message;
 
 fn resolve_sym(
     flattened_serializer: &CsvcMsgFlattenedSerializer,
@@ -31,7 +31,7 @@ fn main() -> Result<()> {
         assert!(cmd_header.tick <= 0);
         if cmd_header.cmd == EDemoCommands::DemSendTables {
             let cmd_body = demo_file.read_cmd(&cmd_header)?;
-            break CDemoSendTables::decode(cmd_body)?;
+            break CDemoSendTables::decode_message(cmd_body)?;
         } else {
             demo_file.skip_cmd(&cmd_header)?;
         }
@@ -40,7 +40,7 @@ fn main() -> Result<()> {
     let mut data = &send_tables.data.unwrap_or_default()[..];
     // skip useless size info
     let _ = varint::read_uvarint64(&mut data)?;
-    let flattened_serializer = CsvcMsgFlattenedSerializer::decode(data)?;
+    let flattened_serializer = CsvcMsgFlattenedSerializer::decode_message(data)?;
 
     let mut var_type_idents: HashSet<String> = HashSet::new();
     let mut var_types: HashSet<String> = HashSet::new();